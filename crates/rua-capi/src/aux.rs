@@ -5,7 +5,7 @@
 
 use core::ffi::{c_char, c_int};
 
-use rua_core::gc::{GcHandle, TableKey};
+use rua_core::gc::TableKey;
 use rua_core::value::Value as CoreValue;
 
 use crate::{
@@ -273,8 +273,8 @@ pub unsafe extern "C" fn luaL_argerror(
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn luaL_ref(s: *mut lua_State, t: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    let v = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
-    if matches!(v, CoreValue::Nil) {
+    let v = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
+    if v.is_nil() {
         return LUA_REFNIL;
     }
     let Some(tk) = table_key_at(cs, t) else {
@@ -297,7 +297,7 @@ pub unsafe extern "C" fn luaL_ref(s: *mut lua_State, t: c_int) -> c_int {
         (len + 1) as i64
     };
     if let Some(tbl) = cs.lua.global.heap.get_table_mut(tk) {
-        let _ = tbl.set(CoreValue::Number(r as f64), v);
+        let _ = tbl.set(CoreValue::number(r as f64), v);
     }
     r as c_int
 }
@@ -316,10 +316,10 @@ pub unsafe extern "C" fn luaL_unref(s: *mut lua_State, t: c_int, r: c_int) {
     // t[r] = t[0]（旧フリーヘッドを退避）、 t[0] = r。
     if let Some(tbl) = cs.lua.global.heap.get_table_mut(tk) {
         let _ = tbl.set(
-            CoreValue::Number(r as f64),
-            CoreValue::Number(free_head as f64),
+            CoreValue::number(r as f64),
+            CoreValue::number(free_head as f64),
         );
-        let _ = tbl.set(CoreValue::Number(0.0), CoreValue::Number(r as f64));
+        let _ = tbl.set(CoreValue::number(0.0), CoreValue::number(r as f64));
     }
 }
 
@@ -429,30 +429,24 @@ fn raise_arg_error(cs: &mut CapiState, narg: c_int, msg: &str) {
 
 /// インデックス位置のテーブルキーを得る。
 fn table_key_at(cs: &CapiState, idx: c_int) -> Option<TableKey> {
-    match cs.value_at(idx) {
-        CoreValue::GcRef(GcHandle::Table(k)) => Some(k),
-        _ => None,
-    }
+    let v = cs.value_at(idx);
+    v.as_table()
 }
 
 /// テーブルの整数キーの値を i64 として読む（無ければ 0）。
 fn num_field(cs: &CapiState, tk: TableKey, key: i64) -> i64 {
-    match cs
-        .lua
+    cs.lua
         .global
         .heap
         .get_table(tk)
-        .map(|t| t.get(&CoreValue::Number(key as f64)))
-    {
-        Some(CoreValue::Number(n)) => n as i64,
-        _ => 0,
-    }
+        .and_then(|t| t.get(&CoreValue::number(key as f64)).as_number())
+        .map_or(0, |n| n as i64)
 }
 
 /// テーブルの整数キーへ整数値を書く。
 fn set_num_key(cs: &mut CapiState, tk: TableKey, key: i64, val: i64) {
     if let Some(t) = cs.lua.global.heap.get_table_mut(tk) {
-        let _ = t.set(CoreValue::Number(key as f64), CoreValue::Number(val as f64));
+        let _ = t.set(CoreValue::number(key as f64), CoreValue::number(val as f64));
     }
 }
 
@@ -71,7 +71,10 @@ pub type lua_Reader = Option<
 pub type lua_Writer = Option<
     unsafe extern "C" fn(L: *mut lua_State, p: *const c_void, sz: usize, ud: *mut c_void) -> c_int,
 >;
-/// 本家 `lua_Alloc`（メモリ確保フック）。rua では未使用（既定アロケータを使う）。
+/// 本家 `lua_Alloc`（メモリ確保フック）。
+///
+/// rua の実体は Rust のアロケータで確保し、`lua_newstate` に渡されたアロケータへは
+/// 使用量の増減だけを反映する（`lua_newstate` 参照）。`NULL` を返すと確保失敗として扱う。
 pub type lua_Alloc = Option<
     unsafe extern "C" fn(
         ud: *mut c_void,
@@ -164,9 +167,10 @@ pub struct CapiState {
 }
 
 /// 登録済み C 関数の情報。
+///
+/// upvalue はクロージャ本体（`NativeClosure` の upvalue）に持たせ、GC のトレース対象にする。
 struct CFunc {
     f: unsafe extern "C" fn(*mut lua_State) -> c_int,
-    upvalues: Vec<CoreValue>,
 }
 
 thread_local! {
@@ -223,24 +227,22 @@ impl CapiState {
     /// インデックス位置の値を返す（無効なら `None`）。疑似インデックスにも対応。
     ///
     /// upvalue 疑似インデックス (`LUA_GLOBALSINDEX - i`, i >= 1) にも対応する。
-    /// 対応する upvalue は現在実行中の C 関数クロージャの upvalue（`c_functions` に登録済みのもの）。
+    /// 対応する upvalue は現在実行中の C 関数クロージャ（`NativeClosure`）の upvalue。
     fn value_at_opt(&self, idx: c_int) -> Option<CoreValue> {
         if idx > LUA_REGISTRYINDEX || idx > 0 {
             self.abs_stack(idx).map(|i| self.lua.stack[i])
         } else {
             match idx {
-                LUA_REGISTRYINDEX => Some(CoreValue::GcRef(self.lua.global.registry)),
-                LUA_GLOBALSINDEX => Some(CoreValue::GcRef(self.lua.global.globals)),
+                LUA_REGISTRYINDEX => Some(CoreValue::gc(self.lua.global.registry)),
+                LUA_GLOBALSINDEX => Some(CoreValue::gc(self.lua.global.globals)),
                 // ENVIRONINDEX は当面グローバルで近似（C 関数の env 未実装）。
-                LUA_ENVIRONINDEX => Some(CoreValue::GcRef(self.lua.global.globals)),
+                LUA_ENVIRONINDEX => Some(CoreValue::gc(self.lua.global.globals)),
                 // upvalue 疑似インデックス: LUA_GLOBALSINDEX - 1, -2, ... (本家 lua_upvalueindex)。
                 // 対応する 1-origin インデックスは (LUA_GLOBALSINDEX - idx) 。
                 _ if idx < LUA_GLOBALSINDEX => {
                     let upv_idx = (LUA_GLOBALSINDEX - idx) as usize; // 1-origin
-                    // コールスタック末尾フレームの native_closure キーを取得。
-                    let key = self.lua.current_native_closure()?;
-                    let upv = self.c_functions.get(&key)?.upvalues.get(upv_idx - 1)?;
-                    Some(*upv)
+                    // コールスタック末尾フレームの native_closure の upvalue を読む。
+                    self.lua.current_upvalue(upv_idx - 1)
                 }
                 _ => None,
             }
@@ -249,7 +251,7 @@ impl CapiState {
 
     /// インデックス位置の値（無効なら `nil`）。
     fn value_at(&self, idx: c_int) -> CoreValue {
-        self.value_at_opt(idx).unwrap_or(CoreValue::Nil)
+        self.value_at_opt(idx).unwrap_or(CoreValue::NIL)
     }
 
     /// 文字列キーに対応する NUL 終端バッファのポインタと長さを返す（安定ポインタ）。
@@ -279,6 +281,7 @@ fn error_code(e: &LuaError) -> c_int {
         LuaError::Syntax(_) => LUA_ERRSYNTAX,
         LuaError::Memory => LUA_ERRMEM,
         LuaError::ErrorInError => LUA_ERRERR,
+        LuaError::Interrupted(_) => LUA_ERRRUN,
         LuaError::Yield(_) => LUA_ERRRUN,
     }
 }
@@ -290,6 +293,7 @@ fn error_value(cs: &mut CapiState, e: LuaError) -> CoreValue {
         LuaError::Syntax(s) | LuaError::Internal(s) => cs.lua.new_string(s.as_bytes()),
         LuaError::Memory => cs.lua.new_string(b"not enough memory"),
         LuaError::ErrorInError => cs.lua.new_string(b"error in error handling"),
+        LuaError::Interrupted(r) => cs.lua.new_string(r.message().as_bytes()),
         LuaError::Yield(_) => cs
             .lua
             .new_string(b"attempt to yield across a C-call boundary"),
@@ -310,14 +314,79 @@ fn take_pending_error() -> Option<LuaError> {
 // 状態の生成・破棄（本家 lstate / lapi）
 // ============================================================================
 
-/// 新しい Lua 状態を作る（本家 `lua_newstate`）。`alloc`/`ud` は無視し既定アロケータを使う。
+/// [`AllocShadow`] がアロケータへ要求するブロックの粒度（バイト）。
+const ALLOC_GRANULE: usize = 1024;
+
+/// `lua_newstate` に渡された `lua_Alloc` へヒープ使用量を反映する影ブロック群。
+///
+/// 使用量が確保済み合計を超えたら差分（[`ALLOC_GRANULE`] 単位に切り上げ）を新しいブロックとして
+/// 要求し、減ったら末尾のブロックから返す。アロケータが `NULL` を返せば確保を拒否する。
+/// 組み込み側のアロケータが数える使用量は、こうして rua のヒープ使用量に追従する。
+struct AllocShadow {
+    f: unsafe extern "C" fn(*mut c_void, *mut c_void, usize, usize) -> *mut c_void,
+    ud: *mut c_void,
+    blocks: Vec<(*mut c_void, usize)>,
+    reserved: usize,
+}
+
+impl AllocShadow {
+    /// 確保済み合計を `used` に合わせる。アロケータが拒否したら false。
+    fn reserve(&mut self, used: usize) -> bool {
+        if used > self.reserved {
+            let size = (used - self.reserved).div_ceil(ALLOC_GRANULE) * ALLOC_GRANULE;
+            let ptr = unsafe { (self.f)(self.ud, std::ptr::null_mut(), 0, size) };
+            if ptr.is_null() {
+                return false;
+            }
+            self.blocks.push((ptr, size));
+            self.reserved += size;
+        } else {
+            while let Some(&(ptr, size)) = self.blocks.last() {
+                if self.reserved - size < used {
+                    break;
+                }
+                unsafe { (self.f)(self.ud, ptr, size, 0) };
+                self.blocks.pop();
+                self.reserved -= size;
+            }
+        }
+        true
+    }
+}
+
+impl Drop for AllocShadow {
+    fn drop(&mut self) {
+        self.reserve(0);
+    }
+}
+
+/// 新しい Lua 状態を作る（本家 `lua_newstate`）。
+///
+/// `alloc` が与えられた場合はヒープ使用量をそのアロケータへ反映し、`NULL` を返されたら
+/// 安全点で全回収を試みた後 `LUA_ERRMEM`（"not enough memory"）を送出する。
+/// 初期状態の分すら確保できなければ `NULL` を返す。
 ///
 /// # Safety
-/// 返ったポインタは [`lua_close`] で解放すること。
+/// 返ったポインタは [`lua_close`] で解放すること。`alloc` は `ud` と組で状態の破棄まで有効であること。
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn lua_newstate(_alloc: lua_Alloc, _ud: *mut c_void) -> *mut lua_State {
+pub unsafe extern "C" fn lua_newstate(alloc: lua_Alloc, ud: *mut c_void) -> *mut lua_State {
+    let mut lua = LuaState::new();
+    if let Some(f) = alloc {
+        let mut shadow = AllocShadow {
+            f,
+            ud,
+            blocks: Vec::new(),
+            reserved: 0,
+        };
+        if !shadow.reserve(lua.global.heap.bytes_in_use()) {
+            return std::ptr::null_mut();
+        }
+        lua.global
+            .heap
+            .set_alloc_hook(Some(Box::new(move |used| shadow.reserve(used))));
+    }
     let boxed = Box::new(CapiState {
-        lua: LuaState::new(),
+        lua,
         panic: None,
         cstr_cache: HashMap::new(),
         c_functions: HashMap::new(),
@@ -325,7 +394,7 @@ pub unsafe extern "C" fn lua_newstate(_alloc: lua_Alloc, _ud: *mut c_void) -> *m
     Box::into_raw(boxed) as *mut lua_State
 }
 
-/// Lua 状態を破棄する（本家 `lua_close`）。
+/// Lua 状態を破棄する（本家 `lua_close`）。残っている userdata の `__gc` を呼んでから解放する。
 ///
 /// # Safety
 /// `s` は本クレートが返した有効なポインタで、二重 close しないこと。
@@ -335,6 +404,7 @@ pub unsafe extern "C" fn lua_close(s: *mut lua_State) {
         return;
     }
     unsafe {
+        CapiState::from_ptr(s).lua.close();
         drop(Box::from_raw(s as *mut CapiState));
     }
 }
@@ -372,7 +442,7 @@ pub unsafe extern "C" fn lua_settop(s: *mut lua_State, idx: c_int) {
         (len as i64 + idx as i64 + 1).max(base as i64) as usize
     };
     if newlen > len {
-        cs.lua.stack.resize(newlen, CoreValue::Nil);
+        cs.lua.stack.resize(newlen, CoreValue::NIL);
     } else {
         cs.lua.stack.truncate(newlen);
     }
@@ -430,25 +500,25 @@ pub unsafe extern "C" fn lua_checkstack(_s: *mut lua_State, _extra: c_int) -> c_
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_pushnil(s: *mut lua_State) {
     let cs = unsafe { CapiState::from_ptr(s) };
-    cs.push(CoreValue::Nil);
+    cs.push(CoreValue::NIL);
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_pushboolean(s: *mut lua_State, b: c_int) {
     let cs = unsafe { CapiState::from_ptr(s) };
-    cs.push(CoreValue::Boolean(b != 0));
+    cs.push(CoreValue::boolean(b != 0));
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_pushnumber(s: *mut lua_State, n: lua_Number) {
     let cs = unsafe { CapiState::from_ptr(s) };
-    cs.push(CoreValue::Number(n));
+    cs.push(CoreValue::number(n));
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_pushinteger(s: *mut lua_State, n: lua_Integer) {
     let cs = unsafe { CapiState::from_ptr(s) };
-    cs.push(CoreValue::Number(n as f64));
+    cs.push(CoreValue::number(n as f64));
 }
 
 /// 長さ指定の文字列を積む（本家 `lua_pushlstring`）。`\0` を含みうる。
@@ -475,7 +545,7 @@ pub unsafe extern "C" fn lua_pushlstring(s: *mut lua_State, sp: *const c_char, l
 pub unsafe extern "C" fn lua_pushstring(s: *mut lua_State, sp: *const c_char) {
     let cs = unsafe { CapiState::from_ptr(s) };
     if sp.is_null() {
-        cs.push(CoreValue::Nil);
+        cs.push(CoreValue::NIL);
         return;
     }
     let bytes = unsafe { std::ffi::CStr::from_ptr(sp) }.to_bytes();
@@ -500,15 +570,17 @@ pub unsafe extern "C" fn lua_pushcclosure(s: *mut lua_State, f: lua_CFunction, n
         .lua
         .global
         .heap
-        .alloc_closure(Closure::Native(NativeClosure::new(c_trampoline)))
-    {
+        .alloc_closure(Closure::Native(NativeClosure::with_upvalues(
+            c_trampoline,
+            upvalues,
+        ))) {
         GcHandle::Closure(k) => k,
         _ => unreachable!(),
     };
     if let Some(func) = f {
-        cs.c_functions.insert(key, CFunc { f: func, upvalues });
+        cs.c_functions.insert(key, CFunc { f: func });
     }
-    cs.push(CoreValue::GcRef(GcHandle::Closure(key)));
+    cs.push(CoreValue::closure(key));
 }
 
 /// C 関数を upvalue 無しで積む（本家マクロ `lua_pushcfunction` 相当）。
@@ -521,7 +593,7 @@ pub unsafe extern "C" fn lua_pushcfunction(s: *mut lua_State, f: lua_CFunction)
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_pushlightuserdata(s: *mut lua_State, p: *mut c_void) {
     let cs = unsafe { CapiState::from_ptr(s) };
-    cs.push(CoreValue::LightUserData(p));
+    cs.push(CoreValue::light_userdata(p));
 }
 
 /// Lua スクリプト（VM）から push 済みの C 関数を呼ぶトランポリン（VM → C 経路）。
@@ -617,47 +689,46 @@ pub unsafe extern "C" fn lua_typename(_s: *mut lua_State, tp: c_int) -> *const c
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_isnumber(s: *mut lua_State, idx: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    match cs.value_at(idx) {
-        CoreValue::Number(_) => 1,
-        CoreValue::GcRef(GcHandle::Str(k)) => {
-            let ok = cs
-                .lua
-                .global
-                .heap
-                .get_str(k)
-                .map(|s| rua_core::value::convert::str_to_number(s.as_bytes()).is_some())
-                .unwrap_or(false);
-            ok as c_int
-        }
-        _ => 0,
+    let v = cs.value_at(idx);
+    if v.is_number() {
+        1
+    } else if let Some(k) = v.as_string() {
+        let ok = cs
+            .lua
+            .global
+            .heap
+            .get_str(k)
+            .map(|s| rua_core::value::convert::str_to_number(s.as_bytes()).is_some())
+            .unwrap_or(false);
+        ok as c_int
+    } else {
+        0
     }
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_isstring(s: *mut lua_State, idx: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    matches!(
-        cs.value_at(idx),
-        CoreValue::GcRef(GcHandle::Str(_)) | CoreValue::Number(_)
-    ) as c_int
+    let v = cs.value_at(idx);
+    (v.as_string().is_some() || v.is_number()) as c_int
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_iscfunction(s: *mut lua_State, idx: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    match cs.value_at(idx) {
-        CoreValue::GcRef(GcHandle::Closure(k)) => cs.c_functions.contains_key(&k) as c_int,
-        _ => 0,
+    let v = cs.value_at(idx);
+    if let Some(k) = v.as_closure() {
+        cs.c_functions.contains_key(&k) as c_int
+    } else {
+        0
     }
 }
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_isuserdata(s: *mut lua_State, idx: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    matches!(
-        cs.value_at(idx),
-        CoreValue::GcRef(GcHandle::Userdata(_)) | CoreValue::LightUserData(_)
-    ) as c_int
+    let v = cs.value_at(idx);
+    (v.as_userdata().is_some() || v.as_light_userdata().is_some()) as c_int
 }
 
 #[unsafe(no_mangle)]
@@ -670,16 +741,18 @@ pub unsafe extern "C" fn lua_toboolean(s: *mut lua_State, idx: c_int) -> c_int {
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_tonumber(s: *mut lua_State, idx: c_int) -> lua_Number {
     let cs = unsafe { CapiState::from_ptr(s) };
-    match cs.value_at(idx) {
-        CoreValue::Number(n) => n,
-        CoreValue::GcRef(GcHandle::Str(k)) => cs
-            .lua
+    let v = cs.value_at(idx);
+    if let Some(n) = v.as_number() {
+        n
+    } else if let Some(k) = v.as_string() {
+        cs.lua
             .global
             .heap
             .get_str(k)
             .and_then(|s| rua_core::value::convert::str_to_number(s.as_bytes()))
-            .unwrap_or(0.0),
-        _ => 0.0,
+            .unwrap_or(0.0)
+    } else {
+        0.0
     }
 }
 
@@ -704,21 +777,19 @@ pub unsafe extern "C" fn lua_tolstring(
 ) -> *const c_char {
     let cs = unsafe { CapiState::from_ptr(s) };
     // 数値はその場で文字列へ変換し、スタック上の値も差し替える（本家挙動）。
-    let key = match cs.value_at(idx) {
-        CoreValue::GcRef(GcHandle::Str(k)) => k,
-        CoreValue::Number(n) => {
+    let key = {
+        let v = cs.value_at(idx);
+        if let Some(k) = v.as_string() {
+            k
+        } else if let Some(n) = v.as_number() {
             let strv = cs
                 .lua
                 .new_string(rua_core::value::convert::number_to_string(n).as_bytes());
             if let Some(i) = cs.abs_stack(idx) {
                 cs.lua.stack[i] = strv;
             }
-            match strv {
-                CoreValue::GcRef(GcHandle::Str(k)) => k,
-                _ => unreachable!(),
-            }
-        }
-        _ => {
+            strv.as_string().unwrap()
+        } else {
             if !len.is_null() {
                 unsafe { *len = 0 };
             }
@@ -736,19 +807,20 @@ pub unsafe extern "C" fn lua_tolstring(
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_objlen(s: *mut lua_State, idx: c_int) -> usize {
     let cs = unsafe { CapiState::from_ptr(s) };
-    match cs.value_at(idx) {
-        CoreValue::GcRef(GcHandle::Str(k)) => {
-            cs.lua.global.heap.get_str(k).map(|s| s.len()).unwrap_or(0)
-        }
-        CoreValue::GcRef(GcHandle::Table(k)) => cs
-            .lua
+    let v = cs.value_at(idx);
+    if let Some(k) = v.as_string() {
+        cs.lua.global.heap.get_str(k).map(|s| s.len()).unwrap_or(0)
+    } else if let Some(k) = v.as_table() {
+        cs.lua
             .global
             .heap
             .get_table(k)
             .map(|t| t.length())
-            .unwrap_or(0),
-        CoreValue::Number(n) => rua_core::value::convert::number_to_string(n).len(),
-        _ => 0,
+            .unwrap_or(0)
+    } else if let Some(n) = v.as_number() {
+        rua_core::value::convert::number_to_string(n).len()
+    } else {
+        0
     }
 }
 
@@ -756,9 +828,11 @@ pub unsafe extern "C" fn lua_objlen(s: *mut lua_State, idx: c_int) -> usize {
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_tocfunction(s: *mut lua_State, idx: c_int) -> lua_CFunction {
     let cs = unsafe { CapiState::from_ptr(s) };
-    match cs.value_at(idx) {
-        CoreValue::GcRef(GcHandle::Closure(k)) => cs.c_functions.get(&k).map(|c| c.f),
-        _ => None,
+    let v = cs.value_at(idx);
+    if let Some(k) = v.as_closure() {
+        cs.c_functions.get(&k).map(|c| c.f)
+    } else {
+        None
     }
 }
 
@@ -766,9 +840,11 @@ pub unsafe extern "C" fn lua_tocfunction(s: *mut lua_State, idx: c_int) -> lua_C
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_touserdata(s: *mut lua_State, idx: c_int) -> *mut c_void {
     let cs = unsafe { CapiState::from_ptr(s) };
-    match cs.value_at(idx) {
-        CoreValue::LightUserData(p) => p,
-        _ => std::ptr::null_mut(),
+    let v = cs.value_at(idx);
+    if let Some(p) = v.as_light_userdata() {
+        p
+    } else {
+        std::ptr::null_mut()
     }
 }
 
@@ -776,14 +852,16 @@ pub unsafe extern "C" fn lua_touserdata(s: *mut lua_State, idx: c_int) -> *mut c
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_equal(s: *mut lua_State, idx1: c_int, idx2: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    (cs.value_at(idx1) == cs.value_at(idx2)) as c_int
+    let (a, b) = (cs.value_at(idx1), cs.value_at(idx2));
+    cs.lua.global.heap.raw_equal(a, b) as c_int
 }
 
 /// raw 等価（本家 `lua_rawequal`）。
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_rawequal(s: *mut lua_State, idx1: c_int, idx2: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    (cs.value_at(idx1) == cs.value_at(idx2)) as c_int
+    let (a, b) = (cs.value_at(idx1), cs.value_at(idx2));
+    cs.lua.global.heap.raw_equal(a, b) as c_int
 }
 
 // ============================================================================
@@ -796,14 +874,14 @@ pub unsafe extern "C" fn lua_createtable(s: *mut lua_State, narr: c_int, nrec: c
     let cs = unsafe { CapiState::from_ptr(s) };
     let t = CoreTable::with_capacity(narr.max(0) as usize, nrec.max(0) as usize);
     let h = cs.lua.global.heap.alloc_table(t);
-    cs.push(CoreValue::GcRef(h));
+    cs.push(CoreValue::gc(h));
 }
 
 /// テーブル `t[k]` を取得（raw, 本家 `lua_rawget`）。トップのキーを結果で置き換える。
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_rawget(s: *mut lua_State, idx: c_int) {
     let cs = unsafe { CapiState::from_ptr(s) };
-    let key = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
+    let key = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
     let v = table_raw_get(cs, cs.value_at(idx), key);
     cs.push(v);
 }
@@ -816,8 +894,8 @@ pub unsafe extern "C" fn lua_rawset(s: *mut lua_State, idx: c_int) {
     let cs = unsafe { CapiState::from_ptr(s) };
     // pop 前にテーブルを解決してから v, k を取り出す。
     let t = cs.value_at(idx);
-    let v = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
-    let k = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
+    let v = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
+    let k = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
     table_raw_set(cs, t, k, v);
 }
 
@@ -825,7 +903,7 @@ pub unsafe extern "C" fn lua_rawset(s: *mut lua_State, idx: c_int) {
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_rawgeti(s: *mut lua_State, idx: c_int, n: c_int) {
     let cs = unsafe { CapiState::from_ptr(s) };
-    let v = table_raw_get(cs, cs.value_at(idx), CoreValue::Number(n as f64));
+    let v = table_raw_get(cs, cs.value_at(idx), CoreValue::number(n as f64));
     cs.push(v);
 }
 
@@ -837,8 +915,8 @@ pub unsafe extern "C" fn lua_rawseti(s: *mut lua_State, idx: c_int, n: c_int) {
     let cs = unsafe { CapiState::from_ptr(s) };
     // pop 前にテーブルを解決してから値を取り出す（pop後にインデックスがずれるのを防ぐ）。
     let t = cs.value_at(idx);
-    let v = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
-    table_raw_set(cs, t, CoreValue::Number(n as f64), v);
+    let v = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
+    table_raw_set(cs, t, CoreValue::number(n as f64), v);
 }
 
 /// `t[k]`（本家 `lua_gettable`。メタメソッド未経由＝raw で近似）。トップのキーを結果で置換。
@@ -877,7 +955,7 @@ pub unsafe extern "C" fn lua_setfield(s: *mut lua_State, idx: c_int, k: *const c
     let cs = unsafe { CapiState::from_ptr(s) };
     // pop 前にテーブルを解決してから値を取り出す。
     let t = cs.value_at(idx);
-    let v = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
+    let v = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
     let key = unsafe { cstr_to_value(cs, k) };
     table_raw_set(cs, t, key, v);
 }
@@ -886,22 +964,25 @@ pub unsafe extern "C" fn lua_setfield(s: *mut lua_State, idx: c_int, k: *const c
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_getmetatable(s: *mut lua_State, idx: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    let mt = match cs.value_at(idx) {
-        CoreValue::GcRef(GcHandle::Table(k)) => {
+    let mt = {
+        let v = cs.value_at(idx);
+        if let Some(k) = v.as_table() {
             cs.lua.global.heap.get_table(k).and_then(|t| t.metatable())
+        } else if let Some(k) = v.as_userdata() {
+            cs.lua
+                .global
+                .heap
+                .get_userdata(k)
+                .and_then(|u| u.metatable())
+        } else if v.as_string().is_some() {
+            cs.lua.global.string_metatable
+        } else {
+            None
         }
-        CoreValue::GcRef(GcHandle::Userdata(k)) => cs
-            .lua
-            .global
-            .heap
-            .get_userdata(k)
-            .and_then(|u| u.metatable()),
-        CoreValue::GcRef(GcHandle::Str(_)) => cs.lua.global.string_metatable,
-        _ => None,
     };
     match mt {
         Some(h) => {
-            cs.push(CoreValue::GcRef(h));
+            cs.push(CoreValue::gc(h));
             1
         }
         None => 0,
@@ -912,23 +993,15 @@ pub unsafe extern "C" fn lua_getmetatable(s: *mut lua_State, idx: c_int) -> c_in
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_setmetatable(s: *mut lua_State, idx: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    let mtv = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
-    let mt = match mtv {
-        CoreValue::GcRef(h @ GcHandle::Table(_)) => Some(h),
-        _ => None,
-    };
-    match cs.value_at(idx) {
-        CoreValue::GcRef(GcHandle::Table(k)) => {
-            if let Some(t) = cs.lua.global.heap.get_table_mut(k) {
-                t.set_metatable(mt);
-            }
-        }
-        CoreValue::GcRef(GcHandle::Userdata(k)) => {
-            if let Some(u) = cs.lua.global.heap.get_userdata_mut(k) {
-                u.set_metatable(mt);
-            }
+    let mtv = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
+    let mt = mtv.as_table().map(GcHandle::Table);
+    let v = cs.value_at(idx);
+    if let Some(k) = v.as_table() {
+        if let Some(t) = cs.lua.global.heap.get_table_mut(k) {
+            t.set_metatable(mt);
         }
-        _ => {}
+    } else if let Some(k) = v.as_userdata() {
+        cs.lua.global.heap.set_userdata_metatable(k, mt);
     }
     1
 }
@@ -936,20 +1009,21 @@ pub unsafe extern "C" fn lua_setmetatable(s: *mut lua_State, idx: c_int) -> c_in
 // ---- テーブル raw ヘルパ -----------------------------------------------
 
 fn table_raw_get(cs: &CapiState, t: CoreValue, key: CoreValue) -> CoreValue {
-    match t {
-        CoreValue::GcRef(GcHandle::Table(k)) => cs
-            .lua
+    if let Some(k) = t.as_table() {
+        cs.lua
             .global
             .heap
             .get_table(k)
-            .map(|tbl| tbl.get(&key))
-            .unwrap_or(CoreValue::Nil),
-        _ => CoreValue::Nil,
+            .map(|tbl| tbl.get(&cs.lua.global.heap.table_key(key)))
+            .unwrap_or(CoreValue::NIL)
+    } else {
+        CoreValue::NIL
     }
 }
 
 fn table_raw_set(cs: &mut CapiState, t: CoreValue, key: CoreValue, value: CoreValue) {
-    if let CoreValue::GcRef(GcHandle::Table(k)) = t
+    let key = cs.lua.global.heap.intern_key(key);
+    if let Some(k) = t.as_table()
         && let Some(tbl) = cs.lua.global.heap.get_table_mut(k)
     {
         let _ = tbl.set(key, value);
@@ -962,7 +1036,7 @@ fn table_raw_set(cs: &mut CapiState, t: CoreValue, key: CoreValue, value: CoreVa
 /// `k` は NULL か NUL 終端 C 文字列。
 unsafe fn cstr_to_value(cs: &mut CapiState, k: *const c_char) -> CoreValue {
     if k.is_null() {
-        return CoreValue::Nil;
+        return CoreValue::NIL;
     }
     let bytes = unsafe { std::ffi::CStr::from_ptr(k) }.to_bytes();
     cs.lua.new_string(bytes)
@@ -1025,7 +1099,7 @@ pub unsafe extern "C" fn lua_cpcall(
     let cs = unsafe { CapiState::from_ptr(s) };
     let Some(f) = func else { return LUA_OK };
     // ud を軽量ユーザーデータとして 1 引数で積み、直接 C 関数を保護呼び出しする。
-    cs.push(CoreValue::LightUserData(ud));
+    cs.push(CoreValue::light_userdata(ud));
     let base = cs.lua.stack.len() - 1;
     match call_c_function(cs, f, &[], base) {
         Ok(_) => {
@@ -1049,7 +1123,7 @@ pub unsafe extern "C" fn lua_cpcall(
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn lua_error(s: *mut lua_State) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
-    let v = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
+    let v = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
     set_pending_error(LuaError::Runtime(v));
     0
 }
@@ -1077,7 +1151,7 @@ fn do_call(cs: &mut CapiState, nargs: c_int, nresults: c_int) -> Result<(), LuaE
     } else {
         let want = nresults.max(0) as usize;
         let mut r = results;
-        r.resize(want, CoreValue::Nil);
+        r.resize(want, CoreValue::NIL);
         r
     };
     cs.lua.stack.extend(results);
@@ -1090,7 +1164,7 @@ fn call_value(
     func: CoreValue,
     args: &[CoreValue],
 ) -> Result<Vec<CoreValue>, LuaError> {
-    if let CoreValue::GcRef(GcHandle::Closure(k)) = func
+    if let Some(k) = func.as_closure()
         && let Some(cf) = cs.c_functions.get(&k)
     {
         let f = cf.f;
@@ -1115,11 +1189,15 @@ fn call_c_function(
         base,
         func: base,
         expected_results: 0,
-        source: None,
         current_line: 0,
+        current_pc: 0,
         native_closure: None,
+        lua_closure: None,
+        nvarargs: 0,
         lua_frame: None,
         env: None,
+        tail_calls: 0,
+        continuation: None,
     });
     let _ = take_pending_error(); // 念のためクリア
     let p = cs.as_ptr();
@@ -1225,7 +1303,7 @@ pub unsafe extern "C" fn lua_concat(s: *mut lua_State, n: c_int) {
         });
         match result {
             Ok(r) => {
-                let v = r.into_iter().next().unwrap_or(CoreValue::Nil);
+                let v = r.into_iter().next().unwrap_or(CoreValue::NIL);
                 cs.push(v);
             }
             Err(e) => {
@@ -1243,12 +1321,12 @@ pub unsafe extern "C" fn lua_concat(s: *mut lua_State, n: c_int) {
 
 /// 値を連結可能なバイト列へ変換する（`string` または `number`）。それ以外は `None`。
 fn concat_to_bytes(cs: &CapiState, v: CoreValue) -> Option<Vec<u8>> {
-    match v {
-        CoreValue::Number(n) => Some(rua_core::value::convert::number_to_string(n).into_bytes()),
-        CoreValue::GcRef(GcHandle::Str(k)) => {
-            cs.lua.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
-        }
-        _ => None,
+    if let Some(n) = v.as_number() {
+        Some(rua_core::value::convert::number_to_string(n).into_bytes())
+    } else if let Some(k) = v.as_string() {
+        cs.lua.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
+    } else {
+        None
     }
 }
 
@@ -1262,28 +1340,29 @@ fn concat_via_vm(
     a: CoreValue,
     b: CoreValue,
 ) -> rua_core::error::LuaResult<CoreValue> {
-    let a_bytes = match a {
-        CoreValue::Number(n) => Some(rua_core::value::convert::number_to_string(n).into_bytes()),
-        CoreValue::GcRef(GcHandle::Str(k)) => {
-            state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
-        }
-        _ => None,
+    let a_bytes = if let Some(n) = a.as_number() {
+        Some(rua_core::value::convert::number_to_string(n).into_bytes())
+    } else if let Some(k) = a.as_string() {
+        state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
+    } else {
+        None
     };
-    let b_bytes = match b {
-        CoreValue::Number(n) => Some(rua_core::value::convert::number_to_string(n).into_bytes()),
-        CoreValue::GcRef(GcHandle::Str(k)) => {
-            state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
-        }
-        _ => None,
+    let b_bytes = if let Some(n) = b.as_number() {
+        Some(rua_core::value::convert::number_to_string(n).into_bytes())
+    } else if let Some(k) = b.as_string() {
+        state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
+    } else {
+        None
     };
     if let (Some(mut ab), Some(bb)) = (a_bytes, b_bytes) {
         ab.extend_from_slice(&bb);
-        return Ok(CoreValue::GcRef(state.global.heap.intern_str(&ab)));
+        return Ok(state.new_string_from(ab));
     }
     // 型エラー（`__concat` メタメソッド対応は TODO）。
-    let culprit = match (a, b) {
-        (CoreValue::GcRef(GcHandle::Str(_)), _) | (CoreValue::Number(_), _) => b,
-        _ => a,
+    let culprit = if a.as_string().is_some() || a.is_number() {
+        b
+    } else {
+        a
     };
     Err(rua_core::error::LuaError::Runtime(
         state.new_string(
@@ -1302,20 +1381,47 @@ fn concat_via_vm(
 
 /// GC 操作を行う（本家 `lua_gc`）。
 ///
-/// `LUA_GCCOLLECT` は実際に GC を起動する。その他の操作は未実装（0 を返す）。
+/// `LUA_GCCOLLECT`/`LUA_GCSTEP` は実際に GC を起動し、`LUA_GCSTOP`/`LUA_GCRESTART` は
+/// 自動 GC を停止/再開する。`LUA_GCSETPAUSE`/`LUA_GCSETSTEPMUL` は旧値を返す。
+/// `__gc` の実行が打ち切られたら、`lua_error` と同じく保留エラーとして呼び出し元へ送出する。
+/// その他の操作は未実装（0 を返す）。
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn lua_gc(s: *mut lua_State, what: c_int, _data: c_int) -> c_int {
+pub unsafe extern "C" fn lua_gc(s: *mut lua_State, what: c_int, data: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
     match what {
         LUA_GCCOLLECT => {
-            cs.lua.collect_garbage();
+            if let Err(e) = cs.lua.collect_garbage() {
+                set_pending_error(e);
+            }
+            0
+        }
+        LUA_GCSTOP => {
+            cs.lua.global.gc_config.enabled = false;
             0
         }
-        LUA_GCSTOP | LUA_GCRESTART => 0,
-        LUA_GCCOUNT => {
-            // 概算: ヒープの GC オブジェクト数（バイト数を返すのが本来だが近似で可）。
+        LUA_GCRESTART => {
+            cs.lua.global.gc_config.enabled = true;
             0
         }
+        LUA_GCSTEP => match cs.lua.gc_step_by(data.max(0) as usize) {
+            Ok(done) => done as c_int,
+            Err(e) => {
+                set_pending_error(e);
+                0
+            }
+        },
+        LUA_GCSETPAUSE => {
+            let old = cs.lua.global.gc_config.pause;
+            cs.lua.global.gc_config.pause = data.max(0) as u32;
+            old as c_int
+        }
+        LUA_GCSETSTEPMUL => {
+            let old = cs.lua.global.gc_config.stepmul;
+            cs.lua.global.gc_config.stepmul = data.max(0) as u32;
+            old as c_int
+        }
+        LUA_GCCOUNT => (cs.lua.global.heap.bytes_in_use() >> 10) as c_int,
+        LUA_GCCOUNTB => (cs.lua.global.heap.bytes_in_use() & 0x3ff) as c_int,
         _ => 0,
     }
 }
@@ -1336,9 +1442,10 @@ pub unsafe extern "C" fn lua_next(s: *mut lua_State, idx: c_int) -> c_int {
     let cs = unsafe { CapiState::from_ptr(s) };
     // テーブルは pop 前に解決する（pop 後にインデックスがずれる可能性があるため）。
     let tbl_val = cs.value_at(idx);
-    let key = cs.lua.stack.pop().unwrap_or(CoreValue::Nil);
+    let key = cs.lua.stack.pop().unwrap_or(CoreValue::NIL);
+    let key = cs.lua.global.heap.table_key(key);
 
-    let CoreValue::GcRef(GcHandle::Table(tk)) = tbl_val else {
+    let Some(tk) = tbl_val.as_table() else {
         return 0;
     };
 
@@ -1369,7 +1476,8 @@ pub unsafe extern "C" fn lua_next(s: *mut lua_State, idx: c_int) -> c_int {
 /// 内部: ソースをコンパイルし、関数値をトップへ積む。成功で `LUA_OK`、失敗でエラーコード。
 /// 失敗時はエラーメッセージ文字列をトップへ積む（本家 `lua_load` の契約）。
 pub(crate) fn load_buffer(cs: &mut CapiState, src: &[u8], chunkname: &str) -> c_int {
-    match rua_core::compiler::compile(&mut cs.lua.global.heap, src, chunkname) {
+    let lang = cs.lua.global.lang;
+    match rua_core::compiler::compile_with_lang(&mut cs.lua.global.heap, src, chunkname, lang) {
         Ok(proto) => {
             let env = cs.lua.global.globals;
             let h = cs
@@ -1377,7 +1485,7 @@ pub(crate) fn load_buffer(cs: &mut CapiState, src: &[u8], chunkname: &str) -> c_
                 .global
                 .heap
                 .alloc_closure(Closure::Lua(LuaClosure::new_with_env(Rc::new(proto), env)));
-            cs.push(CoreValue::GcRef(h));
+            cs.push(CoreValue::gc(h));
             LUA_OK
         }
         Err(e) => {
@@ -357,3 +357,80 @@ fn ref_unref_registry() {
         lua_close(l);
     }
 }
+
+#[test]
+fn newstate_allocator_limit_raises_errmem() {
+    /// 上限付きアロケータの状態（本家テストの `l_alloc` + 上限）。
+    struct Limit {
+        used: usize,
+        max: usize,
+    }
+    unsafe extern "C" fn limited_alloc(
+        ud: *mut c_void,
+        ptr: *mut c_void,
+        osize: usize,
+        nsize: usize,
+    ) -> *mut c_void {
+        // Safety: `ud` はテスト本体が渡した `Limit` を指す。
+        let limit = unsafe { &mut *(ud as *mut Limit) };
+        if nsize == 0 {
+            unsafe { libc_free(ptr) };
+            limit.used -= osize;
+            return std::ptr::null_mut();
+        }
+        if limit.used - osize + nsize > limit.max {
+            return std::ptr::null_mut();
+        }
+        limit.used = limit.used - osize + nsize;
+        unsafe { libc_realloc(ptr, nsize) }
+    }
+    unsafe extern "C" {
+        #[link_name = "realloc"]
+        fn libc_realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+        #[link_name = "free"]
+        fn libc_free(ptr: *mut c_void);
+    }
+
+    let mut limit = Limit {
+        used: 0,
+        max: 512 * 1024,
+    };
+    unsafe {
+        let l = lua_newstate(Some(limited_alloc), &mut limit as *mut Limit as *mut c_void);
+        assert!(!l.is_null());
+        aux::luaL_openlibs(l);
+        let src = cstr("local t = {} for i = 1, 1e6 do t[i] = tostring(i) end");
+        assert_eq!(aux::luaL_loadstring(l, src.as_ptr()), LUA_OK);
+        assert_eq!(lua_pcall(l, 0, 0, 0), LUA_ERRMEM);
+        let mut len = 0;
+        let p = lua_tolstring(l, -1, &mut len);
+        assert_eq!(
+            std::slice::from_raw_parts(p as *const u8, len),
+            b"not enough memory"
+        );
+        lua_settop(l, 0);
+        // 失敗した確保のゴミは回収され、状態は引き続き使える。
+        let src = cstr("return #string.rep('x', 1000)");
+        assert_eq!(aux::luaL_loadstring(l, src.as_ptr()), LUA_OK);
+        assert_eq!(lua_pcall(l, 0, 1, 0), LUA_OK);
+        assert_eq!(lua_tonumber(l, -1), 1000.0);
+        assert!(limit.used > 0);
+        lua_close(l);
+    }
+    assert_eq!(limit.used, 0, "close で影ブロックをすべて返す");
+}
+
+#[test]
+fn newstate_fails_when_allocator_refuses() {
+    unsafe extern "C" fn refuse(
+        _ud: *mut c_void,
+        _ptr: *mut c_void,
+        _osize: usize,
+        _nsize: usize,
+    ) -> *mut c_void {
+        std::ptr::null_mut()
+    }
+    unsafe {
+        assert!(lua_newstate(Some(refuse), std::ptr::null_mut()).is_null());
+    }
+}
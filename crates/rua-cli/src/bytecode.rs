@@ -9,7 +9,7 @@
 
 use std::rc::Rc;
 
-use rua_core::gc::{GcHandle, Heap};
+use rua_core::gc::Heap;
 use rua_core::value::Value;
 use rua_core::vm::Proto;
 use rua_core::vm::opcode::Instruction;
@@ -114,23 +114,21 @@ fn dump_proto(buf: &mut Vec<u8>, heap: &Heap, p: &Proto, strip: bool) {
 }
 
 fn dump_constant(buf: &mut Vec<u8>, heap: &Heap, v: &Value) {
-    match v {
-        Value::Nil => buf.push(TAG_NIL),
-        Value::Boolean(b) => {
-            buf.push(TAG_BOOL);
-            buf.push(*b as u8);
-        }
-        Value::Number(n) => {
-            buf.push(TAG_NUMBER);
-            write_u64(buf, n.to_bits());
-        }
-        Value::GcRef(GcHandle::Str(key)) => {
-            buf.push(TAG_STRING);
-            let bytes = heap.get_str(*key).map(|s| s.as_bytes()).unwrap_or(b"");
-            write_bytes(buf, bytes);
-        }
+    if v.is_nil() {
+        buf.push(TAG_NIL)
+    } else if let Some(b) = v.as_boolean() {
+        buf.push(TAG_BOOL);
+        buf.push(b as u8);
+    } else if let Some(n) = v.as_number() {
+        buf.push(TAG_NUMBER);
+        write_u64(buf, n.to_bits());
+    } else if let Some(key) = v.as_string() {
+        buf.push(TAG_STRING);
+        let bytes = heap.get_str(key).map(|s| s.as_bytes()).unwrap_or(b"");
+        write_bytes(buf, bytes);
+    } else {
         // 定数表にはこれら以外の値は現れない（codegen 契約）。
-        other => panic!("dump: 想定外の定数型 {:?}", other.type_of()),
+        panic!("dump: 想定外の定数型 {:?}", v.type_of())
     }
 }
 
@@ -289,12 +287,12 @@ fn undump_proto(heap: &mut Heap, r: &mut Reader) -> Result<Proto, UndumpError> {
 
 fn undump_constant(heap: &mut Heap, r: &mut Reader) -> Result<Value, UndumpError> {
     match r.u8()? {
-        TAG_NIL => Ok(Value::Nil),
-        TAG_BOOL => Ok(Value::Boolean(r.u8()? != 0)),
-        TAG_NUMBER => Ok(Value::Number(f64::from_bits(r.u64()?))),
+        TAG_NIL => Ok(Value::NIL),
+        TAG_BOOL => Ok(Value::boolean(r.u8()? != 0)),
+        TAG_NUMBER => Ok(Value::number(f64::from_bits(r.u64()?))),
         TAG_STRING => {
             let bytes = r.bytes()?;
-            Ok(Value::GcRef(heap.intern_str(bytes)))
+            Ok(Value::gc(heap.intern_str(bytes)))
         }
         other => Err(UndumpError(format!("不正な定数タグ {other}"))),
     }
@@ -7,8 +7,10 @@
 //! clap 4 の `styles` で色付きヘルプを提供する。
 
 use clap::builder::styling::{AnsiColor, Effects};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
 use clap::{Args, Parser, Subcommand, builder::Styles};
 use clap_complete::Shell;
+use rua_core::compiler::Lang;
 
 /// clap のヘルプ出力のカラースタイル（clap 4 の `Styles` API）。
 ///
@@ -32,6 +34,7 @@ Usage:
   rua script.lua [args...]   Run a script
   rua -                      Read from standard input and run
   rua                        Start the interactive interpreter (REPL)
+  rua --lang 5.2 script.lua  Also accept Lua 5.2 syntax (goto, \\x, \\z, \\u{...})
 
 Script arguments are available through the `arg` table and the `...` of the
 main chunk (same convention as the reference lua5.1):
@@ -112,6 +115,17 @@ pub struct Cli {
 /// `rua <file> [args...]` のデフォルト引数（本家 `lua [script [args]]` 相当）。
 #[derive(Debug, Args)]
 pub struct DefaultArgs {
+    /// Language level of the script, of chunks it loads and of the REPL.
+    #[arg(
+        long = "lang",
+        value_name = "VERSION",
+        default_value = "5.1",
+        value_parser = PossibleValuesParser::new(["5.1", "5.2"])
+            .map(|s| Lang::from_name(&s).expect("a possible value")),
+        long_help = "Language level of the script, of chunks it loads (load, dofile, require) and of the REPL.\n  5.1  Lua 5.1 (default)\n  5.2  Lua 5.1 plus goto / ::label::, \\x, \\z and \\u{...} string escapes"
+    )]
+    pub lang: Lang,
+
     /// Lua script to run (`-` for standard input). Starts the REPL when omitted.
     #[arg(value_name = "SCRIPT")]
     pub script: Option<String>,
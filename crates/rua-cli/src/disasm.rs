@@ -9,7 +9,7 @@
 use std::fmt::Write as _;
 
 use rua_core::compiler::chunk_id;
-use rua_core::gc::{GcHandle, Heap};
+use rua_core::gc::Heap;
 use rua_core::value::Value;
 use rua_core::value::convert::number_to_string;
 use rua_core::vm::Proto;
@@ -165,7 +165,8 @@ fn print_operands(out: &mut String, ins: Instruction, op: OpCode) {
             }
         }
         OpMode::AsBx => {
-            if op == OpCode::Jmp {
+            // 5.1 の JMP は A を使わない。A を持つのは 5.2 の goto（upvalue を閉じる）だけ。
+            if op == OpCode::Jmp && a == 0 {
                 let _ = write!(out, "{}", ins.sbx());
             } else {
                 let _ = write!(out, "{a} {}", ins.sbx());
@@ -205,10 +206,8 @@ fn print_comment(
         GetGlobal | SetGlobal => {
             let _ = write!(out, "\t; {}", const_str(heap, p, ins.bx() as usize));
         }
-        GetTable | SelfOp => {
-            if is_k(ins.c()) {
-                let _ = write!(out, "\t; {}", const_str(heap, p, index_k(ins.c()) as usize));
-            }
+        GetTable | SelfOp if is_k(ins.c()) => {
+            let _ = write!(out, "\t; {}", const_str(heap, p, index_k(ins.c()) as usize));
         }
         SetTable | Add | Sub | Mul | Div | Mod | Pow | Eq | Lt | Le => {
             let bk = is_k(ins.b());
@@ -260,15 +259,19 @@ fn const_str(heap: &Heap, p: &Proto, idx: usize) -> String {
 
 /// 定数値の `luac` 風表現（文字列はクォート・エスケープ）。
 fn value_repr(heap: &Heap, v: &Value) -> String {
-    match v {
-        Value::Nil => "nil".to_string(),
-        Value::Boolean(b) => b.to_string(),
-        Value::Number(n) => number_to_string(*n),
-        Value::GcRef(GcHandle::Str(key)) => match heap.get_str(*key) {
+    if v.is_nil() {
+        "nil".to_string()
+    } else if let Some(b) = v.as_boolean() {
+        b.to_string()
+    } else if let Some(n) = v.as_number() {
+        number_to_string(n)
+    } else if let Some(key) = v.as_string() {
+        match heap.get_str(key) {
             Some(s) => quote_string(s.as_bytes()),
             None => "\"?\"".to_string(),
-        },
-        other => format!("({} value)", other.type_of().name()),
+        }
+    } else {
+        format!("({} value)", v.type_of().name())
     }
 }
 
@@ -3,6 +3,7 @@
 //! コマンド構成:
 //!   - `rua <file> [args...]` / `rua -`  … スクリプト実行（`-` は標準入力）
 //!   - `rua`（引数なし）                 … 対話モード（REPL）
+//!   - `--lang 5.2`                      … Lua 5.2 の構文拡張を受理する（既定 5.1）
 //!   - `rua completions <shell>`         … シェル補完生成
 //!
 //! コンパイラは別バイナリ `ruac`（本家 `luac` 相当）として提供する。
@@ -18,6 +19,7 @@ use clap_complete::generate;
 
 use rua_cli::cli::{Cli, Command, CompletionsArgs};
 use rua_cli::{repl, run};
+use rua_core::compiler::Lang;
 
 fn main() -> ExitCode {
     let cli = Cli::parse();
@@ -25,19 +27,19 @@ fn main() -> ExitCode {
     match cli.command {
         Some(Command::Completions(args)) => completions(args),
         None => match cli.default.script {
-            Some(script) => dispatch_script(&script, &cli.default.args),
+            Some(script) => dispatch_script(&script, &cli.default.args, cli.default.lang),
             // 引数なし → REPL。
-            None => repl::main(),
+            None => repl::main(cli.default.lang),
         },
     }
 }
 
 /// スクリプト指定を実行する。`-` は標準入力。
-fn dispatch_script(script: &str, args: &[String]) -> ExitCode {
+fn dispatch_script(script: &str, args: &[String], lang: Lang) -> ExitCode {
     if script == "-" {
-        run::run_stdin(args)
+        run::run_stdin(args, lang)
     } else {
-        run::run_file(script, args)
+        run::run_file(script, args, lang)
     }
 }
 
@@ -24,7 +24,7 @@ use reedline::{
     ReedlineEvent, ReedlineMenu, Signal, Span, StyledText, Suggestion, default_emacs_keybindings,
 };
 
-use rua_core::compiler::compile;
+use rua_core::compiler::{Lang, compile_with_lang};
 use rua_core::error::LuaError;
 use rua_core::gc::GcHandle;
 use rua_core::state::{LuaState, call::pcall};
@@ -32,7 +32,7 @@ use rua_core::stdlib;
 use rua_core::value::Value;
 use rua_core::vm::{call as vm_call, run};
 
-use crate::run::render_error;
+use crate::run::{render_error, render_uncaught};
 
 // ============================================================================
 // REPL バージョン文字列
@@ -393,12 +393,12 @@ impl LuaCompleter {
         if let GcHandle::Table(gk) = state.global.globals
             && let Some(t) = state.global.heap.get_table(gk)
         {
-            let mut key = Value::Nil;
+            let mut key = Value::NIL;
             loop {
                 match t.next(&key) {
                     Ok(Some((k, _v))) => {
                         // 文字列キーのみ補完対象にする。
-                        if let Value::GcRef(GcHandle::Str(sk)) = k
+                        if let Some(sk) = k.as_string()
                             && let Some(s) = state.global.heap.get_str(sk)
                         {
                             let name = String::from_utf8_lossy(s.as_bytes()).into_owned();
@@ -492,20 +492,27 @@ fn is_incomplete(e: &LuaError) -> bool {
 fn eval_line(state: &mut LuaState, src: &str) -> Result<bool, LuaError> {
     // まず `return <src>` を試みる（式評価モード）。
     let return_src = format!("return {src}");
-    let expr_proto = compile(&mut state.global.heap, return_src.as_bytes(), "=stdin");
+    let lang = state.global.lang;
+    let expr_proto = compile_with_lang(
+        &mut state.global.heap,
+        return_src.as_bytes(),
+        "=stdin",
+        lang,
+    );
 
     let proto = match expr_proto {
         Ok(p) => p,
         Err(_) => {
             // 式として解釈できない: 文としてコンパイルする。
-            let stmt_proto = compile(&mut state.global.heap, src.as_bytes(), "=stdin")?;
+            let stmt_proto =
+                compile_with_lang(&mut state.global.heap, src.as_bytes(), "=stdin", lang)?;
             // 文として実行。
             let rc = Rc::new(stmt_proto);
             let exec_result = pcall(state, |s| run(s, rc, &[]));
             match exec_result {
                 Ok(_) => return Ok(true),
                 Err(e) => {
-                    let msg = render_error(state, &e);
+                    let msg = render_uncaught(state, &e);
                     eprintln!(
                         "{}",
                         Style::new().fg(Color::Red).paint(format!("rua: {msg}"))
@@ -527,7 +534,7 @@ fn eval_line(state: &mut LuaState, src: &str) -> Result<bool, LuaError> {
         }
         Ok(_) => Ok(true),
         Err(e) => {
-            let msg = render_error(state, &e);
+            let msg = render_uncaught(state, &e);
             eprintln!(
                 "{}",
                 Style::new().fg(Color::Red).paint(format!("rua: {msg}"))
@@ -546,12 +553,8 @@ fn print_results(state: &mut LuaState, values: Vec<Value>) {
     let print_key = state.global.heap.intern_str(b"print");
     let print_fn = if let GcHandle::Table(gk) = state.global.globals {
         if let Some(t) = state.global.heap.get_table(gk) {
-            let v = t.get(&Value::GcRef(print_key));
-            if !matches!(v, Value::Nil) {
-                Some(v)
-            } else {
-                None
-            }
+            let v = t.get(&Value::gc(print_key));
+            if !v.is_nil() { Some(v) } else { None }
         } else {
             None
         }
@@ -571,21 +574,19 @@ fn print_results(state: &mut LuaState, values: Vec<Value>) {
 
 /// 値を簡易表示用文字列に変換する（print のフォールバック用）。
 fn value_to_display(state: &LuaState, v: &Value) -> String {
-    match v {
-        Value::Nil => "nil".to_string(),
-        Value::Boolean(b) => b.to_string(),
-        Value::Number(n) => format_number(*n),
-        Value::GcRef(GcHandle::Str(k)) => state
+    if let Some(b) = v.as_boolean() {
+        b.to_string()
+    } else if let Some(n) = v.as_number() {
+        format_number(n)
+    } else if let Some(k) = v.as_string() {
+        state
             .global
             .heap
-            .get_str(*k)
+            .get_str(k)
             .map(|s| String::from_utf8_lossy(s.as_bytes()).into_owned())
-            .unwrap_or_else(|| "?".to_string()),
-        Value::GcRef(GcHandle::Table(_)) => "table".to_string(),
-        Value::GcRef(GcHandle::Closure(_)) => "function".to_string(),
-        Value::GcRef(GcHandle::Userdata(_)) => "userdata".to_string(),
-        Value::GcRef(GcHandle::Thread(_)) => "thread".to_string(),
-        Value::LightUserData(_) => "userdata".to_string(),
+            .unwrap_or_else(|| "?".to_string())
+    } else {
+        v.type_of().name().to_string()
     }
 }
 
@@ -758,14 +759,18 @@ fn run_pipe_mode(state: &mut LuaState) -> ExitCode {
 /// REPL のエントリポイント（`main.rs` から呼ばれる）。
 ///
 /// tty 接続時は reedline でリッチな対話インタプリタを提供する。
-/// パイプ（非 tty）入力時は行読み込みモードにフォールバックする。
-pub fn main() -> ExitCode {
+/// パイプ（非 tty）入力時は行読み込みモードにフォールバックする。`lang` は入力の言語水準（`--lang`）。
+pub fn main(lang: Lang) -> ExitCode {
     let mut state = LuaState::new();
+    state.global.lang = lang;
     stdlib::open_libs(&mut state);
+    state.traceback_on_error = true;
 
     // 非 tty（パイプ）チェック。
     if !is_tty() {
-        return run_pipe_mode(&mut state);
+        let code = run_pipe_mode(&mut state);
+        state.close();
+        return code;
     }
 
     print_banner();
@@ -851,6 +856,7 @@ pub fn main() -> ExitCode {
         }
     }
 
+    state.close();
     ExitCode::SUCCESS
 }
 
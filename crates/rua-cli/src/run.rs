@@ -6,7 +6,7 @@
 use std::process::ExitCode;
 use std::rc::Rc;
 
-use rua_core::compiler::compile;
+use rua_core::compiler::{Lang, compile_with_lang};
 use rua_core::error::LuaError;
 use rua_core::gc::GcHandle;
 use rua_core::state::LuaState;
@@ -17,7 +17,8 @@ use rua_core::vm::run;
 /// Lua スクリプトファイルを読み込んで実行する。
 ///
 /// `script_args` はスクリプトへ渡す引数（`arg` テーブルおよびメインチャンクの `...` に束ねる）。
-pub fn run_file(path: &str, script_args: &[String]) -> ExitCode {
+/// `lang` はスクリプトと、そこから `load`/`require` するソースの言語水準（`--lang`）。
+pub fn run_file(path: &str, script_args: &[String], lang: Lang) -> ExitCode {
     let source = match std::fs::read(path) {
         Ok(bytes) => bytes,
         Err(e) => {
@@ -26,18 +27,18 @@ pub fn run_file(path: &str, script_args: &[String]) -> ExitCode {
         }
     };
     // 本家同様、ファイル由来のチャンク名は `@` プレフィックス（エラー表示時に除去される）。
-    execute(&source, &format!("@{path}"), Some(path), script_args)
+    execute(&source, &format!("@{path}"), Some(path), script_args, lang)
 }
 
 /// 標準入力から読み込んで実行する。
-pub fn run_stdin(script_args: &[String]) -> ExitCode {
+pub fn run_stdin(script_args: &[String], lang: Lang) -> ExitCode {
     use std::io::Read;
     let mut source = Vec::new();
     if let Err(e) = std::io::stdin().read_to_end(&mut source) {
         eprintln!("rua: cannot read stdin: {e}");
         return ExitCode::from(1);
     }
-    execute(&source, "=stdin", None, script_args)
+    execute(&source, "=stdin", None, script_args, lang)
 }
 
 /// ソースをコンパイル→実行し、本家に寄せた終了コードを返す。
@@ -48,9 +49,12 @@ fn execute(
     chunkname: &str,
     script_name: Option<&str>,
     script_args: &[String],
+    lang: Lang,
 ) -> ExitCode {
     let mut state = LuaState::new();
+    state.global.lang = lang;
     stdlib::open_libs(&mut state);
+    state.traceback_on_error = true;
     setup_arg_table(&mut state, script_name, script_args);
 
     // rua バイナリチャンク（`luac -o` の出力）ならコンパイルせず逆シリアライズする。
@@ -63,7 +67,7 @@ fn execute(
             }
         }
     } else {
-        match compile(&mut state.global.heap, source, chunkname) {
+        match compile_with_lang(&mut state.global.heap, source, chunkname, lang) {
             Ok(p) => p,
             Err(e) => {
                 // 構文エラー: 本家は `lua: <chunk>:<line>: <msg>` 形式。
@@ -77,17 +81,20 @@ fn execute(
     let argv: Vec<Value> = script_args
         .iter()
         .map(|a| state.global.heap.intern_str(a.as_bytes()))
-        .map(Value::GcRef)
+        .map(Value::gc)
         .collect();
 
-    match run(&mut state, Rc::new(proto), &argv) {
+    let code = match run(&mut state, Rc::new(proto), &argv) {
         Ok(_) => ExitCode::SUCCESS,
         Err(e) => {
             // 未捕捉の実行時エラー: 本家標準インタプリタは終了コード 1 で stderr に出力。
-            eprintln!("rua: {}", render_error(&state, &e));
+            eprintln!("rua: {}", render_uncaught(&mut state, &e));
             ExitCode::from(1)
         }
-    }
+    };
+    // 本家 `lua.c` 同様、終了前に state を閉じて `__gc`（開いたままのファイル等）を処理する。
+    state.close();
+    code
 }
 
 /// 本家 `lua.c` 同様、グローバル `arg` テーブルを構築する。
@@ -98,43 +105,62 @@ fn setup_arg_table(state: &mut LuaState, script_name: Option<&str>, script_args:
 
     let mut t = Table::new();
     if let Some(name) = script_name {
-        let v = Value::GcRef(state.global.heap.intern_str(name.as_bytes()));
-        let _ = t.set(Value::Number(0.0), v);
+        let v = Value::gc(state.global.heap.intern_str(name.as_bytes()));
+        let _ = t.set(Value::number(0.0), v);
     }
     let handles: Vec<Value> = script_args
         .iter()
-        .map(|a| Value::GcRef(state.global.heap.intern_str(a.as_bytes())))
+        .map(|a| Value::gc(state.global.heap.intern_str(a.as_bytes())))
         .collect();
     for (i, v) in handles.into_iter().enumerate() {
-        let _ = t.set(Value::Number((i + 1) as f64), v);
+        let _ = t.set(Value::number((i + 1) as f64), v);
     }
     let arg_handle = state.global.heap.alloc_table(t);
     if let GcHandle::Table(g) = state.global.globals {
         let key = state.global.heap.intern_str(b"arg");
         if let Some(globals) = state.global.heap.get_table_mut(g) {
-            let _ = globals.set(Value::GcRef(key), Value::GcRef(arg_handle));
+            let _ = globals.set(Value::gc(key), Value::gc(arg_handle));
         }
     }
 }
 
+/// 未捕捉エラーを、エラー発生時点のスタックトレースバック付きで整形する。
+///
+/// トレースバックは [`LuaState::traceback_on_error`] を立てた state で記録されたもの。
+/// 本家 `lua.c` の `traceback` ハンドラ同様、エラーオブジェクトが文字列・数値でなければ付けない。
+/// 末尾の `[C]: ?` は本家でスクリプトを呼ぶ C 関数（ここでは CLI）のフレーム。
+pub fn render_uncaught(state: &mut LuaState, e: &LuaError) -> String {
+    let msg = render_error(state, e);
+    let traceback = state.error_traceback.take();
+    match (e, traceback) {
+        (LuaError::Runtime(v), Some(tb)) if v.is_number() || v.as_string().is_some() => {
+            format!("{msg}\n{tb}\n\t[C]: ?")
+        }
+        (LuaError::Runtime(_), _) | (_, None) => msg,
+        (_, Some(tb)) => format!("{msg}\n{tb}\n\t[C]: ?"),
+    }
+}
+
 /// 未捕捉エラーを本家 `lua.c` に寄せて整形する。
 ///
 /// Lua のエラーオブジェクトは任意の値を取りうる。文字列ならそのまま、数値なら数値表現、
 /// それ以外で `__tostring` も無い場合は本家同様 `(error object is a <type> value)` とする。
 pub fn render_error(state: &LuaState, e: &LuaError) -> String {
-    match e {
-        LuaError::Runtime(Value::GcRef(GcHandle::Str(key))) => {
-            match state.global.heap.get_str(*key) {
-                Some(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
-                None => "(error object is a dangling string)".to_string(),
-            }
-        }
-        LuaError::Runtime(Value::Number(n)) => format!("{n}"),
-        LuaError::Runtime(Value::Nil) => "nil".to_string(),
-        LuaError::Runtime(Value::Boolean(b)) => b.to_string(),
-        LuaError::Runtime(v) => {
-            format!("(error object is a {} value)", v.type_of().name())
+    let LuaError::Runtime(v) = e else {
+        return e.to_string();
+    };
+    if let Some(key) = v.as_string() {
+        match state.global.heap.get_str(key) {
+            Some(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+            None => "(error object is a dangling string)".to_string(),
         }
-        other => other.to_string(),
+    } else if let Some(n) = v.as_number() {
+        format!("{n}")
+    } else if v.is_nil() {
+        "nil".to_string()
+    } else if let Some(b) = v.as_boolean() {
+        b.to_string()
+    } else {
+        format!("(error object is a {} value)", v.type_of().name())
     }
 }
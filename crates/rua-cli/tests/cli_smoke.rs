@@ -70,6 +70,41 @@ fn runtime_error_exits_one() {
     assert!(stderr.contains("boom"), "stderr: {stderr}");
 }
 
+#[test]
+fn uncaught_error_prints_traceback_from_error_point() {
+    let src = b"local function f()\n  error('boom')\nend\nlocal function g() f() end\nprint(pcall(g))\ng()\n";
+    let (stdout, stderr, code) = run_with_stdin(&["-"], src);
+    assert_eq!(code, 1);
+    // pcall で捕捉したエラーのトレースバックは残らない。
+    assert_eq!(stdout, "false\tstdin:2: boom\n");
+    assert_eq!(
+        stderr,
+        "rua: stdin:2: boom\n\
+         stack traceback:\n\
+         \t[C]: in function 'error'\n\
+         \tstdin:2: in function 'f'\n\
+         \tstdin:4: in function 'g'\n\
+         \tstdin:6: in main chunk\n\
+         \t[C]: ?\n"
+    );
+}
+
+#[test]
+fn deep_recursion_traceback_is_elided() {
+    let src = b"local function f() return 1 + f() end\nprint(pcall(f))\nf()\n";
+    let (stdout, stderr, code) = run_with_stdin(&["-"], src);
+    assert_eq!(code, 1);
+    assert_eq!(stdout, "false\tstdin:1: stack overflow\n");
+    // 先頭 12 段と末尾の段だけを表示し、間を `...` で省略する。
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines.len(), 2 + 12 + 1 + 11, "stderr: {stderr}");
+    assert_eq!(lines[0], "rua: stdin:1: stack overflow");
+    assert_eq!(lines[2], "\tstdin:1: in function 'f'");
+    assert_eq!(lines[14], "\t...");
+    assert_eq!(lines[24], "\tstdin:3: in main chunk");
+    assert_eq!(lines[25], "\t[C]: ?");
+}
+
 #[test]
 fn script_args_exposed_as_arg_and_vararg() {
     let src = b"print(arg[0], arg[1], arg[2])\nprint(...)\n";
@@ -101,3 +136,14 @@ fn syntax_error_exits_one() {
     assert_eq!(code, 1);
     assert!(stderr.contains("rua:"), "stderr: {stderr}");
 }
+
+#[test]
+fn lang_flag_enables_goto() {
+    let script = b"for i = 1, 3 do if i == 2 then goto continue end print(i) ::continue:: end\n";
+    let (stdout, stderr, code) = run_with_stdin(&["--lang", "5.2", "-"], script);
+    assert_eq!(code, 0, "stderr: {stderr}");
+    assert_eq!(stdout, "1\n3\n");
+    // 既定は 5.1 なので同じスクリプトは構文エラー。
+    let (_stdout, _stderr, code) = run_with_stdin(&["-"], script);
+    assert_eq!(code, 1);
+}
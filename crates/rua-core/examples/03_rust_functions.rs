@@ -27,7 +27,7 @@ fn vec_len(state: &mut LuaState) -> LuaResult<i32> {
     let y = aux::check_number(state, &args, 1, "vec_len")?;
     let z = aux::check_number(state, &args, 2, "vec_len")?;
     let len = (x * x + y * y + z * z).sqrt();
-    aux::ret(state, vec![Value::Number(len)])
+    aux::ret(state, vec![Value::number(len)])
 }
 
 // ── Native function 2: repeat a string ───────────────────────────────────────
@@ -55,7 +55,7 @@ fn dump_table(state: &mut LuaState) -> LuaResult<i32> {
         .get_table(tk)
         .map(|t| {
             let mut result = Vec::new();
-            let mut cur = Value::Nil;
+            let mut cur = Value::NIL;
             while let Ok(Some((k, v))) = t.next(&cur) {
                 cur = k;
                 result.push((cur, v));
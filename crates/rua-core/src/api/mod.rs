@@ -19,20 +19,29 @@
 //! # 設計と安全性
 //! - [`Lua`] は [`LuaState`](crate::state::LuaState) を**所有**する（第二マイルストーン時点）。
 //! - [`Table`]/[`Function`] は GC ハンドルの薄いラッパで、対象 [`Lua`] が生きている間有効
-//!   （GC は明示起動のみ。詳細は [`value`] のモジュールコメント参照）。
+//!   （Rust 側へ渡したハンドルはレジストリ配下のアンカー表に登録し、自動 GC から守る。
+//!   最後の複製が drop されると登録を外す。詳細は [`value`] のモジュールコメント参照）。
 //! - すべての実行は [`pcall`](crate::state::call::pcall) 境界で保護し、エラー時はスタックを巻き戻す。
 
 pub mod convert;
 pub mod value;
 
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 pub use convert::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti};
 pub use value::{Function, Table, Value};
 
-use crate::error::{LuaError, LuaResult};
-use crate::gc::GcHandle;
-use crate::state::{LuaState, NativeFn};
+use value::{Anchor, AnchorRefs};
+
+pub use crate::compiler::Lang;
+
+use crate::error::{InterruptReason, LuaError, LuaResult};
+use crate::gc::stats::{GcEvent, GcStats};
+use crate::gc::{GcHandle, TableKey};
+use crate::state::{Deadline, LuaState, NativeFn};
 use crate::value::Value as CoreValue;
 use crate::value::closure::{Closure, LuaClosure, NativeClosure};
 use crate::value::table::Table as CoreTable;
@@ -40,6 +49,40 @@ use crate::value::table::Table as CoreTable;
 /// 高レベル API のエントリポイント。1 つの Lua 実行環境（状態 + 標準ライブラリ）を持つ。
 pub struct Lua {
     state: LuaState,
+    /// Rust 側へ渡した [`Table`]/[`Function`] のアンカー表（レジストリから参照され GC ルートになる）。
+    anchors: TableKey,
+    /// Rust 側が保持しているハンドルの参照数（[`Table`]/[`Function`] と共有）。
+    anchor_refs: Rc<AnchorRefs>,
+    /// 期限切れをスクリプトの `pcall` で捕捉させるか（[`Lua::set_catchable_timeouts`]）。
+    catchable_timeouts: bool,
+}
+
+/// 実行の期限の指定。[`Instant`] はその時刻、[`Duration`] は指定した時点からの経過時間。
+pub trait IntoDeadline {
+    /// 期限の時刻へ変換する。
+    fn into_deadline(self) -> Instant;
+}
+
+impl IntoDeadline for Instant {
+    fn into_deadline(self) -> Instant {
+        self
+    }
+}
+
+impl IntoDeadline for Duration {
+    fn into_deadline(self) -> Instant {
+        Instant::now() + self
+    }
+}
+
+/// アンカー表を保持するレジストリのキー。
+const ANCHORS_KEY: &[u8] = b"rua.api.anchors";
+
+impl Drop for Lua {
+    /// 破棄時に state を閉じ、残っている userdata の `__gc` を呼ぶ（本家 `lua_close`）。
+    fn drop(&mut self) {
+        self.state.close();
+    }
 }
 
 impl Default for Lua {
@@ -53,13 +96,59 @@ impl Lua {
     pub fn new() -> Self {
         let mut state = LuaState::new();
         crate::stdlib::open_libs(&mut state);
-        Lua { state }
+        Lua::from_state(state)
     }
 
     /// 標準ライブラリを開かない素の Lua 環境を作る。
     pub fn new_bare() -> Self {
+        Lua::from_state(LuaState::new())
+    }
+
+    /// 状態にアンカー表を用意して包む。
+    fn from_state(mut state: LuaState) -> Self {
+        let anchors = state.global.heap.alloc_table(CoreTable::new());
+        let key = state.new_string(ANCHORS_KEY);
+        if let GcHandle::Table(rk) = state.global.registry
+            && let Some(reg) = state.global.heap.get_table_mut(rk)
+        {
+            let _ = reg.set(key, CoreValue::gc(anchors));
+        }
+        let GcHandle::Table(anchors) = anchors else {
+            unreachable!("alloc_table returns a table handle")
+        };
         Lua {
-            state: LuaState::new(),
+            state,
+            anchors,
+            anchor_refs: Rc::new(AnchorRefs::default()),
+            catchable_timeouts: false,
+        }
+    }
+
+    /// Rust 側へ渡すハンドルをアンカー表に登録し、自動 GC で回収されないようにする。
+    ///
+    /// 返る [`Anchor`] の最後の複製が drop されると登録は解除待ちになる。
+    fn anchor(&mut self, h: GcHandle) -> Anchor {
+        self.release_anchors();
+        if self.anchor_refs.retain(h)
+            && let Some(t) = self.state.global.heap.get_table_mut(self.anchors)
+        {
+            let _ = t.set(CoreValue::gc(h), CoreValue::TRUE);
+        }
+        Anchor::new(h, self.anchor_refs.clone())
+    }
+
+    /// 解除待ちのハンドルをアンカー表から外す（その間に再び保持されたものは残す）。
+    fn release_anchors(&mut self) {
+        let released = self.anchor_refs.take_released();
+        if released.is_empty() {
+            return;
+        }
+        if let Some(t) = self.state.global.heap.get_table_mut(self.anchors) {
+            for h in released {
+                if !self.anchor_refs.is_held(h) {
+                    let _ = t.set(CoreValue::gc(h), CoreValue::NIL);
+                }
+            }
         }
     }
 
@@ -73,6 +162,115 @@ impl Lua {
         &mut self.state
     }
 
+    /// 使用メモリの上限（バイト）を設定する。`None` で無制限。
+    ///
+    /// 上限を超えるとまず全回収を行い、それでも収まらなければスクリプトへ
+    /// `"not enough memory"`（[`LuaError::Memory`]）を送出する。`pcall` で捕捉できる。
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.state.global.heap.set_memory_limit(limit);
+    }
+
+    /// 現在の使用メモリの上限。
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.state.global.heap.memory_limit()
+    }
+
+    /// VM スタックの上限（スロット数）を設定する。既定は
+    /// [`DEFAULT_STACK_LIMIT`](crate::state::DEFAULT_STACK_LIMIT)。
+    ///
+    /// Lua 関数同士の再帰はこの大きさまで深くでき、超えると `"stack overflow"` を送出する
+    /// （`pcall` で捕捉できる）。
+    pub fn set_stack_limit(&mut self, slots: usize) {
+        self.state.global.stack_limit = slots;
+    }
+
+    /// 現在の VM スタックの上限（スロット数）。
+    pub fn stack_limit(&self) -> usize {
+        self.state.global.stack_limit
+    }
+
+    /// 実行できる Lua 命令数の上限を設定する。`None` で無制限。
+    ///
+    /// 残り数は以降の全実行で共有して減っていき、使い切るとスクリプトを
+    /// [`LuaError::Interrupted`]（[`InterruptReason::InstructionLimit`](crate::error::InterruptReason::InstructionLimit)）
+    /// で打ち切る。`pcall` では捕捉されない。再び実行するには上限を設定し直す。
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.state.instruction_budget = limit;
+    }
+
+    /// 残りの実行可能命令数（`None` は無制限）。
+    pub fn instructions_remaining(&self) -> Option<u64> {
+        self.state.instruction_budget
+    }
+
+    /// 実行中のスクリプトを外部から止めるためのフラグを返す（無ければ作る）。
+    ///
+    /// 別スレッドから `true` を立てると、次の命令の実行前にスクリプトを
+    /// [`LuaError::Interrupted`]（[`InterruptReason::Requested`](crate::error::InterruptReason::Requested)）
+    /// で打ち切る。`pcall` や `__gc` の中でも捕捉されず、フラグはエラーがこの API の呼び出し元へ
+    /// 戻った時点で下ろす。
+    ///
+    /// ```
+    /// use std::sync::atomic::Ordering;
+    /// use rua_core::api::Lua;
+    /// let mut lua = Lua::new();
+    /// let flag = lua.interrupt_handle();
+    /// let stopper = std::thread::spawn(move || flag.store(true, Ordering::Relaxed));
+    /// stopper.join().unwrap();
+    /// assert!(lua.load("while true do end").exec().is_err());
+    /// ```
+    pub fn interrupt_handle(&mut self) -> Arc<AtomicBool> {
+        self.state
+            .interrupt
+            .get_or_insert_with(|| Arc::new(AtomicBool::new(false)))
+            .clone()
+    }
+
+    /// 期限切れ（[`InterruptReason::Deadline`](crate::error::InterruptReason::Deadline)）を
+    /// スクリプトの `pcall` で捕捉できるようにする。既定は false で、期限切れは埋め込み側まで
+    /// 伝播する。捕捉させた場合、期限は一度発火した時点で外れる。
+    pub fn set_catchable_timeouts(&mut self, catchable: bool) {
+        self.catchable_timeouts = catchable;
+    }
+
+    /// 現在の使用メモリ（バイト, [`Heap::bytes_in_use`](crate::gc::Heap::bytes_in_use)）。
+    pub fn used_memory(&self) -> usize {
+        self.state.global.heap.bytes_in_use()
+    }
+
+    /// 累計の GC 統計（サイクル数・解放数・停止時間）。
+    pub fn gc_stats(&self) -> &GcStats {
+        self.state.global.heap.stats()
+    }
+
+    /// GC サイクルの開始・終了で呼ばれるフックを設定する（既存のフックは置き換える）。
+    ///
+    /// ```
+    /// use rua_core::api::Lua;
+    /// use rua_core::gc::stats::GcEvent;
+    /// let mut lua = Lua::new();
+    /// lua.set_gc_callback(|event, stats| {
+    ///     if event == GcEvent::CycleEnd {
+    ///         eprintln!("gc #{} paused {:?}", stats.cycles, stats.last_pause);
+    ///     }
+    /// });
+    /// lua.load("collectgarbage()").exec().unwrap();
+    /// ```
+    pub fn set_gc_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(GcEvent, &GcStats) + 'static,
+    {
+        self.state
+            .global
+            .heap
+            .set_callback(Some(Box::new(callback)));
+    }
+
+    /// [`Lua::set_gc_callback`] で設定したフックを外す。
+    pub fn remove_gc_callback(&mut self) {
+        self.state.global.heap.set_callback(None);
+    }
+
     /// 文字列メッセージから実行時エラー（Lua 文字列値を保持）を作る。
     pub fn runtime_error(&mut self, msg: impl Into<String>) -> LuaError {
         let v = self.state.new_string(msg.into().as_bytes());
@@ -88,47 +286,59 @@ impl Lua {
     #[allow(clippy::wrong_self_convention)]
     pub(crate) fn to_core(&mut self, v: Value) -> CoreValue {
         match v {
-            Value::Nil => CoreValue::Nil,
-            Value::Boolean(b) => CoreValue::Boolean(b),
-            Value::Number(n) => CoreValue::Number(n),
+            Value::Nil => CoreValue::NIL,
+            Value::Boolean(b) => CoreValue::boolean(b),
+            Value::Number(n) => CoreValue::number(n),
             Value::String(bytes) => self.state.new_string(&bytes),
-            Value::Table(t) => CoreValue::GcRef(t.handle()),
-            Value::Function(f) => CoreValue::GcRef(f.handle()),
-            Value::LightUserData(p) => CoreValue::LightUserData(p),
+            Value::Table(t) => CoreValue::gc(t.handle()),
+            Value::Function(f) => CoreValue::gc(f.handle()),
+            Value::LightUserData(p) => CoreValue::light_userdata(p),
         }
     }
 
-    /// コア [`CoreValue`] を高レベル [`Value`] へ（文字列内容をコピー）。
+    /// コア [`CoreValue`] を高レベル [`Value`] へ（文字列内容をコピー、テーブル/関数はアンカー）。
     ///
-    /// `from_*` は通常 `self` を取らないが、ヒープアクセスに `&self` が必要なため
-    /// clippy の `wrong_self_convention` を抑制する。
+    /// `from_*` は通常 `self` を取らないが、ヒープアクセスとアンカー登録に `&mut self` が
+    /// 必要なため clippy の `wrong_self_convention` を抑制する。
     #[allow(clippy::wrong_self_convention)]
-    pub(crate) fn from_core(&self, v: CoreValue) -> Value {
-        match v {
-            CoreValue::Nil => Value::Nil,
-            CoreValue::Boolean(b) => Value::Boolean(b),
-            CoreValue::Number(n) => Value::Number(n),
-            CoreValue::LightUserData(p) => Value::LightUserData(p),
-            CoreValue::GcRef(h) => match h {
-                GcHandle::Str(k) => {
-                    let bytes = self
-                        .state
-                        .global
-                        .heap
-                        .get_str(k)
-                        .map(|s| s.as_bytes().to_vec())
-                        .unwrap_or_default();
-                    Value::String(bytes)
-                }
-                GcHandle::Table(_) => Value::Table(Table(h)),
-                GcHandle::Closure(_) => Value::Function(Function(h)),
-                // full userdata は高レベル API v1 では未サポート。
-                // 失わないよう lightuserdata 風プレースホルダにフォールバックする。
-                // TODO(lua-capi): 高レベル Userdata 型を追加する。
-                GcHandle::Userdata(_) => Value::LightUserData(std::ptr::null_mut()),
-                // スレッド（コルーチン）は高レベル API v1 では未サポート。
-                GcHandle::Thread(_) => Value::LightUserData(std::ptr::null_mut()),
-            },
+    pub(crate) fn from_core(&mut self, v: CoreValue) -> Value {
+        if let Some(b) = v.as_boolean() {
+            return Value::Boolean(b);
+        }
+        if let Some(n) = v.as_number() {
+            return Value::Number(n);
+        }
+        if let Some(p) = v.as_light_userdata() {
+            return Value::LightUserData(p);
+        }
+        let Some(h) = v.as_gc() else {
+            return Value::Nil;
+        };
+        match h {
+            GcHandle::Str(k) => {
+                let bytes = self
+                    .state
+                    .global
+                    .heap
+                    .get_str(k)
+                    .map(|s| s.as_bytes().to_vec())
+                    .unwrap_or_default();
+                Value::String(bytes)
+            }
+            GcHandle::Table(_) => {
+                let anchor = self.anchor(h);
+                Value::Table(Table::new(h, Some(anchor)))
+            }
+            GcHandle::Closure(_) => {
+                let anchor = self.anchor(h);
+                Value::Function(Function::new(h, anchor))
+            }
+            // full userdata は高レベル API v1 では未サポート。
+            // 失わないよう lightuserdata 風プレースホルダにフォールバックする。
+            // TODO(lua-capi): 高レベル Userdata 型を追加する。
+            GcHandle::Userdata(_) => Value::LightUserData(std::ptr::null_mut()),
+            // スレッド（コルーチン）は高レベル API v1 では未サポート。
+            GcHandle::Thread(_) => Value::LightUserData(std::ptr::null_mut()),
         }
     }
 
@@ -137,23 +347,35 @@ impl Lua {
     /// 新しい空テーブルを作る（本家 `lua_newtable`）。
     pub fn create_table(&mut self) -> Table {
         let h = self.state.global.heap.alloc_table(CoreTable::new());
-        Table(h)
+        let anchor = self.anchor(h);
+        Table::new(h, Some(anchor))
     }
 
-    /// グローバル環境テーブル `_G`（本家 `LUA_GLOBALSINDEX`）。
+    /// グローバル環境テーブル `_G`（本家 `LUA_GLOBALSINDEX`）。常にルートなのでアンカーしない。
     pub fn globals(&self) -> Table {
-        Table(self.state.global.globals)
+        Table::new(self.state.global.globals, None)
     }
 
     /// テーブルへ `key = value` を代入する（raw 代入, `__newindex` 非経由）。
-    pub fn set<K: IntoLua, V: IntoLua>(&mut self, table: Table, key: K, value: V) -> LuaResult<()> {
+    pub fn set<K: IntoLua, V: IntoLua>(
+        &mut self,
+        table: &Table,
+        key: K,
+        value: V,
+    ) -> LuaResult<()> {
         let k = key.into_lua(self)?;
         let v = value.into_lua(self)?;
         let ck = self.to_core(k);
+        let ck = self.state.global.heap.intern_key(ck);
         let cv = self.to_core(v);
         let GcHandle::Table(tk) = table.handle() else {
             return Err(self.runtime_error("not a table"));
         };
+        // キーと値はどこからも根付いていないので、回収せずに上限だけ検査する。
+        if !cv.is_nil() {
+            let growth = self.state.global.heap.table_insert_growth(tk, ck);
+            self.state.global.heap.reserve(growth)?;
+        }
         match self.state.global.heap.get_table_mut(tk) {
             Some(t) => t.set(ck, cv).map_err(|_| {
                 // nil/NaN キーは本家でエラー。
@@ -164,9 +386,10 @@ impl Lua {
     }
 
     /// テーブルから `key` を取得する（raw 取得, `__index` 非経由）。
-    pub fn get<K: IntoLua, R: FromLua>(&mut self, table: Table, key: K) -> LuaResult<R> {
+    pub fn get<K: IntoLua, R: FromLua>(&mut self, table: &Table, key: K) -> LuaResult<R> {
         let k = key.into_lua(self)?;
         let ck = self.to_core(k);
+        let ck = self.state.global.heap.table_key(ck);
         let GcHandle::Table(tk) = table.handle() else {
             return Err(self.runtime_error("not a table"));
         };
@@ -176,7 +399,7 @@ impl Lua {
             .heap
             .get_table(tk)
             .map(|t| t.get(&ck))
-            .unwrap_or(CoreValue::Nil);
+            .unwrap_or(CoreValue::NIL);
         let v = self.from_core(cv);
         R::from_lua(v, self)
     }
@@ -184,13 +407,13 @@ impl Lua {
     /// グローバル変数 `name` を設定する。
     pub fn set_global<V: IntoLua>(&mut self, name: &str, value: V) -> LuaResult<()> {
         let g = self.globals();
-        self.set(g, name, value)
+        self.set(&g, name, value)
     }
 
     /// グローバル変数 `name` を取得する。
     pub fn get_global<R: FromLua>(&mut self, name: &str) -> LuaResult<R> {
         let g = self.globals();
-        self.get(g, name)
+        self.get(&g, name)
     }
 
     // ---- 関数 ----------------------------------------------------------
@@ -207,7 +430,8 @@ impl Lua {
             .global
             .heap
             .alloc_closure(Closure::Native(NativeClosure::new(f)));
-        Function(h)
+        let anchor = self.anchor(h);
+        Function::new(h, anchor)
     }
 
     /// グローバルにネイティブ関数を登録する簡易ヘルパ。
@@ -222,15 +446,53 @@ impl Lua {
         func: Function,
         args: A,
     ) -> LuaResult<R> {
+        self.release_anchors();
         let arg_vals = args.into_lua_multi(self)?;
         let core_args: Vec<CoreValue> = arg_vals.into_iter().map(|v| self.to_core(v)).collect();
-        let fval = CoreValue::GcRef(func.handle());
+        let fval = CoreValue::gc(func.handle());
         let results =
-            crate::state::call::pcall(&mut self.state, |s| crate::vm::call(s, fval, &core_args))?;
-        let high: Vec<Value> = results.into_iter().map(|v| self.from_core(v)).collect();
+            crate::state::call::pcall(&mut self.state, |s| crate::vm::call(s, fval, &core_args));
+        // 中断要求はここで埋め込み側へ届いたので、フラグを下ろす。
+        if let (Err(LuaError::Interrupted(InterruptReason::Requested)), Some(flag)) =
+            (&results, &self.state.interrupt)
+        {
+            flag.store(false, Ordering::Relaxed);
+        }
+        let high: Vec<Value> = results?.into_iter().map(|v| self.from_core(v)).collect();
         R::from_lua_multi(high, self)
     }
 
+    /// 期限付きで関数を呼び出す。期限を過ぎると
+    /// [`LuaError::Interrupted`]（[`InterruptReason::Deadline`](crate::error::InterruptReason::Deadline)）
+    /// で打ち切り、スタックを巻き戻して返す。
+    ///
+    /// 入れ子の期限付き呼び出しでは早い方の期限が効き、戻るときに外側の期限へ戻す。
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use rua_core::api::{Function, Lua};
+    /// let mut lua = Lua::new();
+    /// let spin: Function = lua.load("while true do end").into_function().unwrap();
+    /// let r: Result<(), _> = lua.call_with_deadline(spin, (), Duration::from_millis(10));
+    /// assert!(r.is_err());
+    /// ```
+    pub fn call_with_deadline<A: IntoLuaMulti, R: FromLuaMulti>(
+        &mut self,
+        func: Function,
+        args: A,
+        deadline: impl IntoDeadline,
+    ) -> LuaResult<R> {
+        let mut at = deadline.into_deadline();
+        let outer = self.state.deadline;
+        if let Some(outer) = outer {
+            at = at.min(outer.at);
+        }
+        self.state.deadline = Some(Deadline::new(at, self.catchable_timeouts));
+        let r = self.call(func, args);
+        self.state.deadline = outer;
+        r
+    }
+
     // ---- ロード/評価 ---------------------------------------------------
 
     /// ソースをチャンクとして読み込む（本家 `luaL_loadstring`/`load` 相当）。
@@ -241,16 +503,28 @@ impl Lua {
         // 既定のチャンク名はソース文字列自身（本家 `luaL_loadstring` と同様、
         // `[string "..."]` 形式に整形される）。
         let name = String::from_utf8_lossy(&src).into_owned();
+        let lang = self.state.global.lang;
         Chunk {
             lua: self,
             source: src,
             name,
+            lang,
         }
     }
 
     /// 内部: ソースをコンパイルして Lua 関数値（クロージャ）を確保する。
-    fn compile_closure(&mut self, source: &[u8], chunkname: &str) -> LuaResult<Function> {
-        let proto = crate::compiler::compile(&mut self.state.global.heap, source, chunkname)?;
+    fn compile_closure(
+        &mut self,
+        source: &[u8],
+        chunkname: &str,
+        lang: Lang,
+    ) -> LuaResult<Function> {
+        let proto = crate::compiler::compile_with_lang(
+            &mut self.state.global.heap,
+            source,
+            chunkname,
+            lang,
+        )?;
         let h = self
             .state
             .global
@@ -259,15 +533,17 @@ impl Lua {
                 Rc::new(proto),
                 self.state.global.globals,
             )));
-        Ok(Function(h))
+        let anchor = self.anchor(h);
+        Ok(Function::new(h, anchor))
     }
 }
 
-/// [`Lua::load`] が返すチャンクビルダ。チャンク名の設定後に実行/評価する。
+/// [`Lua::load`] が返すチャンクビルダ。チャンク名・言語水準の設定後に実行/評価する。
 pub struct Chunk<'lua> {
     lua: &'lua mut Lua,
     source: Vec<u8>,
     name: String,
+    lang: Lang,
 }
 
 impl<'lua> Chunk<'lua> {
@@ -280,9 +556,20 @@ impl<'lua> Chunk<'lua> {
         self
     }
 
+    /// 受理する言語水準を設定する（既定は [`GlobalState::lang`](crate::state::GlobalState::lang)、
+    /// 通常 [`Lang::Lua51`]）。
+    ///
+    /// [`Lang::Lua52`] で `goto`/ラベルと `\x`・`\z`・`\u{...}` エスケープを使える。
+    /// このチャンクのコンパイルにだけ効き、チャンク内の `load`/`require` には影響しない。
+    pub fn set_lang(mut self, lang: Lang) -> Self {
+        self.lang = lang;
+        self
+    }
+
     /// コンパイルのみ行い、実行可能な関数値を返す（実行はしない）。
     pub fn into_function(self) -> LuaResult<Function> {
-        self.lua.compile_closure(&self.source, &self.name)
+        self.lua
+            .compile_closure(&self.source, &self.name, self.lang)
     }
 
     /// チャンクを実行し、戻り値を捨てる（本家 `dofile`/`dostring` の値無視版）。
@@ -293,17 +580,65 @@ impl<'lua> Chunk<'lua> {
 
     /// チャンクを実行し、最初の戻り値を Rust 値へ変換して返す。
     pub fn eval<R: FromLua>(self) -> LuaResult<R> {
-        let Chunk { lua, source, name } = self;
-        let func = lua.compile_closure(&source, &name)?;
+        let Chunk {
+            lua,
+            source,
+            name,
+            lang,
+        } = self;
+        let func = lua.compile_closure(&source, &name, lang)?;
         let results: Vec<Value> = lua.call(func, ())?;
         let first = results.into_iter().next().unwrap_or(Value::Nil);
         R::from_lua(first, lua)
     }
 
+    /// 期限付きでチャンクを実行し、戻り値を捨てる（[`Lua::call_with_deadline`]）。
+    pub fn exec_with_deadline(self, deadline: impl IntoDeadline) -> LuaResult<()> {
+        let _: Vec<Value> = self.call_with_deadline((), deadline)?;
+        Ok(())
+    }
+
+    /// 期限付きでチャンクを実行し、最初の戻り値を Rust 値へ変換して返す。
+    pub fn eval_with_deadline<R: FromLua>(self, deadline: impl IntoDeadline) -> LuaResult<R> {
+        let at = deadline.into_deadline();
+        let Chunk {
+            lua,
+            source,
+            name,
+            lang,
+        } = self;
+        let func = lua.compile_closure(&source, &name, lang)?;
+        let results: Vec<Value> = lua.call_with_deadline(func, (), at)?;
+        let first = results.into_iter().next().unwrap_or(Value::Nil);
+        R::from_lua(first, lua)
+    }
+
+    /// 期限付きでチャンクを引数付きで実行し、多値の戻り値を返す。
+    pub fn call_with_deadline<A: IntoLuaMulti, R: FromLuaMulti>(
+        self,
+        args: A,
+        deadline: impl IntoDeadline,
+    ) -> LuaResult<R> {
+        let at = deadline.into_deadline();
+        let Chunk {
+            lua,
+            source,
+            name,
+            lang,
+        } = self;
+        let func = lua.compile_closure(&source, &name, lang)?;
+        lua.call_with_deadline(func, args, at)
+    }
+
     /// チャンクを引数付きで実行し、多値の戻り値を返す。
     pub fn call<A: IntoLuaMulti, R: FromLuaMulti>(self, args: A) -> LuaResult<R> {
-        let Chunk { lua, source, name } = self;
-        let func = lua.compile_closure(&source, &name)?;
+        let Chunk {
+            lua,
+            source,
+            name,
+            lang,
+        } = self;
+        let func = lua.compile_closure(&source, &name, lang)?;
         lua.call(func, args)
     }
 }
@@ -5,39 +5,160 @@
 //! として持ち、テーブル/関数は GC ハンドルの薄いラッパ（[`Table`]/[`Function`]）で表す。
 //!
 //! # GC 安全性（重要）
-//! 第二マイルストーン時点では GC は **明示起動のみ**（[`crate::state::LuaState::collect_garbage`]）で、
-//! 高レベル API 経路では自動回収を行わない。よって [`Table`]/[`Function`] が保持する
-//! [`GcHandle`] は対象 [`Lua`](super::Lua) が生きている限り有効である。
-//! 自動 GC（インクリメンタル化）を導入する際は、これらの参照をレジストリへアンカーして
-//! 寿命を守る必要がある（TODO: 性能フェーズ, ARCHITECTURE.md §5）。
+//! GC はスクリプト実行中に自動起動する（[`crate::state::LuaState::check_gc`]）。
+//! [`Lua`](super::Lua) は Rust 側へ渡した [`Table`]/[`Function`] をレジストリ配下のアンカー表に
+//! 登録するため、これらが保持する [`GcHandle`] は対象 [`Lua`](super::Lua) が生きている限り有効である。
+//! アンカーはハンドルごとに参照数を数え、最後の複製が drop されると解除を予約する
+//! （実際の解除は次に [`Lua`](super::Lua) がアンカー表へ触れるとき）。
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 use std::os::raw::c_void;
+use std::rc::Rc;
 
 use crate::gc::GcHandle;
 
+/// Rust 側が保持しているハンドルの参照数と、解除待ちのハンドル。
+#[derive(Default)]
+pub(crate) struct AnchorRefs {
+    counts: RefCell<HashMap<GcHandle, usize>>,
+    released: RefCell<Vec<GcHandle>>,
+}
+
+impl AnchorRefs {
+    /// 参照数を 1 増やす。新たに保持され始めたら true。
+    pub(crate) fn retain(&self, h: GcHandle) -> bool {
+        let mut counts = self.counts.borrow_mut();
+        let n = counts.entry(h).or_insert(0);
+        *n += 1;
+        *n == 1
+    }
+
+    /// 参照数を 1 減らし、0 になったら解除待ちへ積む。
+    fn release(&self, h: GcHandle) {
+        let mut counts = self.counts.borrow_mut();
+        if let Some(n) = counts.get_mut(&h) {
+            *n -= 1;
+            if *n == 0 {
+                counts.remove(&h);
+                self.released.borrow_mut().push(h);
+            }
+        }
+    }
+
+    /// まだ Rust 側が保持しているか。
+    pub(crate) fn is_held(&self, h: GcHandle) -> bool {
+        self.counts.borrow().contains_key(&h)
+    }
+
+    /// 解除待ちのハンドルを取り出す。
+    pub(crate) fn take_released(&self) -> Vec<GcHandle> {
+        std::mem::take(&mut self.released.borrow_mut())
+    }
+}
+
+/// アンカー表への 1 参照。複製で参照数が増え、drop で減る。
+pub(crate) struct Anchor {
+    handle: GcHandle,
+    refs: Rc<AnchorRefs>,
+}
+
+impl Anchor {
+    /// [`AnchorRefs::retain`] 済みのハンドルから作る。
+    pub(crate) fn new(handle: GcHandle, refs: Rc<AnchorRefs>) -> Self {
+        Anchor { handle, refs }
+    }
+}
+
+impl Clone for Anchor {
+    fn clone(&self) -> Self {
+        self.refs.retain(self.handle);
+        Anchor {
+            handle: self.handle,
+            refs: self.refs.clone(),
+        }
+    }
+}
+
+impl Drop for Anchor {
+    fn drop(&mut self) {
+        self.refs.release(self.handle);
+    }
+}
+
 /// 高レベル API のテーブル参照（GC 上のテーブルへの薄いハンドル）。
 ///
 /// 不変条件: 内部 [`GcHandle`] は必ず [`GcHandle::Table`]。
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Table(pub(crate) GcHandle);
+/// アンカーが無いのは常にルートから届くテーブル（`_G`）だけ。
+#[derive(Clone)]
+pub struct Table {
+    handle: GcHandle,
+    _anchor: Option<Anchor>,
+}
 
 impl Table {
+    pub(crate) fn new(handle: GcHandle, anchor: Option<Anchor>) -> Self {
+        Table {
+            handle,
+            _anchor: anchor,
+        }
+    }
+
     /// 内部 GC ハンドル。
-    pub fn handle(self) -> GcHandle {
-        self.0
+    pub fn handle(&self) -> GcHandle {
+        self.handle
+    }
+}
+
+impl PartialEq for Table {
+    fn eq(&self, other: &Self) -> bool {
+        self.handle == other.handle
+    }
+}
+
+impl Eq for Table {}
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Table").field(&self.handle).finish()
     }
 }
 
 /// 高レベル API の関数参照（GC 上のクロージャ／ネイティブ関数への薄いハンドル）。
 ///
 /// 不変条件: 内部 [`GcHandle`] は必ず [`GcHandle::Closure`]。
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Function(pub(crate) GcHandle);
+#[derive(Clone)]
+pub struct Function {
+    handle: GcHandle,
+    _anchor: Anchor,
+}
 
 impl Function {
+    pub(crate) fn new(handle: GcHandle, anchor: Anchor) -> Self {
+        Function {
+            handle,
+            _anchor: anchor,
+        }
+    }
+
     /// 内部 GC ハンドル。
-    pub fn handle(self) -> GcHandle {
-        self.0
+    pub fn handle(&self) -> GcHandle {
+        self.handle
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.handle == other.handle
+    }
+}
+
+impl Eq for Function {}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Function").field(&self.handle).finish()
     }
 }
 
@@ -65,6 +65,10 @@ pub enum StmtKind {
     Return(Vec<Expr>),
     /// `break`。
     Break,
+    /// `goto name`（Lua 5.2 拡張）。
+    Goto(String),
+    /// `::name::`（Lua 5.2 拡張）。
+    Label(String),
 }
 
 /// 関数文の名前 `a.b.c:m`。
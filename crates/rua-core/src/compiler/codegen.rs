@@ -7,6 +7,11 @@
 //! および `lparser.c` のレジスタ割付（`freereg`/`nactvar`）・スコープ（block/upvalue）・
 //! 制御構造（if/while/repeat/for）の生成規則を忠実に再現する。最終的な byte-exact 検証は
 //! lua-conformance のゴールデン比較（本家 `luac -l`）で行う。
+//!
+//! `goto`/ラベル（Lua 5.2 拡張）は本家 5.2 `lparser.c` の方式で解決する。未解決の `goto` は
+//! ブロックを出るたびに外側のブロックへ移し、ラベルの定義時・ブロック脱出時に照合する。
+//! 捕捉されたローカルのスコープを抜ける `goto` は、5.2 と同じく `JMP` の A（0 以外なら
+//! `R(A-1)` 以上の upvalue を閉じる）で upvalue を閉じる。
 
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -176,6 +181,8 @@ struct UpvalDesc {
     from_local: bool,
     /// 親レジスタ番号 または 親 upvalue インデックス。
     index: u32,
+    /// 変数名（`Proto::upvalue_names`, デバッグ用）。
+    name: String,
 }
 
 /// ブロック制御（本家 `BlockCnt`）。
@@ -188,6 +195,20 @@ struct BlockCnt {
     has_upval: bool,
     /// break のジャンプリスト。
     break_list: i32,
+    /// このブロックで最初のラベルの `FuncState::labels` 内の位置。
+    first_label: usize,
+    /// このブロックで最初の未解決 goto の `FuncState::gotos` 内の位置。
+    first_goto: usize,
+}
+
+/// ラベル、または未解決の goto（本家 5.2 `Labeldesc`）。
+struct LabelDesc {
+    name: String,
+    /// ラベルの位置、または goto の `JMP` の位置。
+    pc: i32,
+    line: u32,
+    /// その位置で有効なローカル数。
+    nactvar: usize,
 }
 
 /// 有効なローカル変数（index = レジスタ番号）。
@@ -214,6 +235,10 @@ struct FuncState {
     jpc: i32,
     /// 直近のジャンプ先 pc（本家 `lasttarget`）。
     lasttarget: i32,
+    /// 可視なラベル（外側のブロックのものが先）。
+    labels: Vec<LabelDesc>,
+    /// 飛び先がまだ見つかっていない goto。
+    gotos: Vec<LabelDesc>,
 }
 
 impl FuncState {
@@ -230,6 +255,8 @@ impl FuncState {
             upvalues: Vec::new(),
             jpc: NO_JUMP,
             lasttarget: -1,
+            labels: Vec::new(),
+            gotos: Vec::new(),
         }
     }
 
@@ -328,15 +355,15 @@ impl FuncState {
         } else {
             n.to_bits()
         };
-        self.add_constant(ConstKey::Num(bits), Value::Number(n))
+        self.add_constant(ConstKey::Num(bits), Value::number(n))
     }
 
     fn bool_k(&mut self, b: bool) -> u32 {
-        self.add_constant(ConstKey::Bool(b), Value::Boolean(b))
+        self.add_constant(ConstKey::Bool(b), Value::boolean(b))
     }
 
     fn nil_k(&mut self) -> u32 {
-        self.add_constant(ConstKey::Nil, Value::Nil)
+        self.add_constant(ConstKey::Nil, Value::NIL)
     }
 
     // ---- ジャンプ（本家 lcode.c のジャンプリスト操作）----------------------
@@ -462,6 +489,15 @@ impl FuncState {
         self.fix_jump(list, l2)
     }
 
+    /// 本家 5.2 `luaK_patchclose`: ジャンプリストの各 `JMP` に、`level` 以上の upvalue を閉じさせる。
+    fn patch_close(&mut self, mut list: i32, level: usize) {
+        while list != NO_JUMP {
+            let next = self.get_jump(list);
+            self.with_code(list, |i| set_arg_a(i, level as u32 + 1));
+            list = next;
+        }
+    }
+
     /// 本家 `luaK_jump`: 保留 jpc を取り込んで JMP を出す。
     fn emit_jump(&mut self, line: u32) -> LuaResult<i32> {
         let saved = self.jpc;
@@ -1024,9 +1060,19 @@ impl<'h> CodeGen<'h> {
         self.remove_vars(0);
         let nactvar = self.cur().nactvar();
         self.cur().code_ret(nactvar, 0, line);
+        if let Some(gt) = self.cur().gotos.first() {
+            let (msg, line) = (
+                format!(
+                    "no visible label '{}' for <goto> at line {}",
+                    gt.name, gt.line
+                ),
+                gt.line,
+            );
+            return Err(self.err_at(line, msg));
+        }
         let mut fs = self.states.pop().expect("a function to close");
         fs.proto.num_upvalues = fs.upvalues.len() as u8;
-        fs.proto.upvalue_names = vec![String::new(); fs.upvalues.len()];
+        fs.proto.upvalue_names = fs.upvalues.iter().map(|uv| uv.name.clone()).collect();
         Ok((fs.proto, fs.upvalues))
     }
 
@@ -1034,12 +1080,18 @@ impl<'h> CodeGen<'h> {
         LuaError::Syntax(msg.into())
     }
 
+    /// `<chunk>:<line>: <msg>` 形式の構文エラー（本家 5.2 `semerror`）。
+    fn err_at(&self, line: u32, msg: impl AsRef<str>) -> LuaError {
+        let id = crate::compiler::chunk_id(&self.chunk);
+        LuaError::Syntax(format!("{id}:{line}: {}", msg.as_ref()))
+    }
+
     // ---- 文字列定数（heap でインターン）-----------------------------------
 
     fn string_k(&mut self, bytes: &[u8]) -> u32 {
         let h = self.heap.intern_str(bytes);
         let fs = self.states.last_mut().unwrap();
-        fs.add_constant(ConstKey::Str(bytes.to_vec()), Value::GcRef(h))
+        fs.add_constant(ConstKey::Str(bytes.to_vec()), Value::gc(h))
     }
 
     // ---- スコープ・ローカル変数（本家 lparser.c）--------------------------
@@ -1082,17 +1134,22 @@ impl<'h> CodeGen<'h> {
     }
 
     fn enter_block(&mut self, is_loop: bool) {
-        let nactvar = self.cur().actives.len();
-        self.cur().blocks.push(BlockCnt {
+        let fs = self.cur();
+        let bl = BlockCnt {
             is_loop,
-            nactvar,
+            nactvar: fs.actives.len(),
             has_upval: false,
             break_list: NO_JUMP,
-        });
+            first_label: fs.labels.len(),
+            first_goto: fs.gotos.len(),
+        };
+        fs.blocks.push(bl);
     }
 
     fn leave_block(&mut self, line: u32) -> LuaResult<()> {
         let bl = self.cur().blocks.pop().expect("a block to leave");
+        self.cur().labels.truncate(bl.first_label);
+        self.move_gotos_out(&bl)?;
         self.remove_vars(bl.nactvar);
         if bl.has_upval {
             self.cur()
@@ -1145,7 +1202,7 @@ impl<'h> CodeGen<'h> {
     }
 
     /// `fs_level` 番目の関数に upvalue を登録（重複排除）。本家 `indexupvalue`。
-    fn index_upvalue(&mut self, fs_level: usize, _name: &str, from_local: bool, index: u32) -> u32 {
+    fn index_upvalue(&mut self, fs_level: usize, name: &str, from_local: bool, index: u32) -> u32 {
         let fs = &mut self.states[fs_level];
         for (i, uv) in fs.upvalues.iter().enumerate() {
             if uv.from_local == from_local && uv.index == index {
@@ -1155,7 +1212,11 @@ impl<'h> CodeGen<'h> {
         let idx = fs.upvalues.len() as u32;
         // 上限チェックは呼び出し側方針に委ねる（MAX_UPVALUES）。
         debug_assert!(fs.upvalues.len() < MAX_UPVALUES);
-        fs.upvalues.push(UpvalDesc { from_local, index });
+        fs.upvalues.push(UpvalDesc {
+            from_local,
+            index,
+            name: name.to_string(),
+        });
         idx
     }
 
@@ -1174,8 +1235,24 @@ impl<'h> CodeGen<'h> {
     // ---- 文の生成 ----------------------------------------------------------
 
     fn statements(&mut self, block: &Block) -> LuaResult<()> {
-        for stmt in &block.stmts {
-            self.statement(stmt)?;
+        self.stat_list(block, false)
+    }
+
+    /// 文の並びを生成する。`until` は repeat 本体（`until` の条件式がローカルを参照しうるので、
+    /// 末尾のラベルもローカルのスコープ内として扱う）。
+    fn stat_list(&mut self, block: &Block, until: bool) -> LuaResult<()> {
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            if let StmtKind::Label(name) = &stmt.kind {
+                // 本家 5.2 `labelstat`: ブロック末尾（後ろは他のラベルのみ）のラベルは、
+                // ブロック内のローカルのスコープ外とみなす。
+                let at_end = !until
+                    && block.stmts[i + 1..]
+                        .iter()
+                        .all(|s| matches!(s.kind, StmtKind::Label(_)));
+                self.label_stat(name, stmt.line, at_end)?;
+            } else {
+                self.statement(stmt)?;
+            }
             // 本家 statement() 末尾と同様、文ごとに一時レジスタを解放する。
             let fs = self.cur();
             debug_assert!(fs.freereg >= fs.nactvar());
@@ -1216,6 +1293,8 @@ impl<'h> CodeGen<'h> {
             StmtKind::Function { name, body } => self.func_stat(name, body, line),
             StmtKind::Return(exprs) => self.return_stat(exprs, line),
             StmtKind::Break => self.break_stat(line),
+            StmtKind::Goto(name) => self.goto_stat(name, line),
+            StmtKind::Label(name) => self.label_stat(name, line, false),
         }
     }
 
@@ -1406,6 +1485,125 @@ impl<'h> CodeGen<'h> {
         Ok(())
     }
 
+    // ---- goto / ラベル（本家 5.2 lparser.c）---------------------------------
+
+    /// 現在のブロックの最初のラベル・goto の位置。関数直下は 0。
+    fn block_firsts(&mut self) -> (usize, usize) {
+        self.cur()
+            .blocks
+            .last()
+            .map_or((0, 0), |bl| (bl.first_label, bl.first_goto))
+    }
+
+    /// `goto name`。既に見えているラベル（後方ジャンプ）ならその場で解決する。
+    fn goto_stat(&mut self, name: &str, line: u32) -> LuaResult<()> {
+        let pc = self.cur().emit_jump(line)?;
+        let fs = self.cur();
+        let nactvar = fs.actives.len();
+        fs.gotos.push(LabelDesc {
+            name: name.to_string(),
+            pc,
+            line,
+            nactvar,
+        });
+        let g = fs.gotos.len() - 1;
+        self.find_label(g)?;
+        Ok(())
+    }
+
+    /// `::name::`。`at_end` はブロック末尾のラベル（[`Self::stat_list`]）。
+    fn label_stat(&mut self, name: &str, line: u32, at_end: bool) -> LuaResult<()> {
+        let (first_label, first_goto) = self.block_firsts();
+        if let Some(prev) = self.cur().labels[first_label..]
+            .iter()
+            .find(|l| l.name == name)
+        {
+            let msg = format!("label '{name}' already defined on line {}", prev.line);
+            return Err(self.err_at(line, msg));
+        }
+        let pc = self.cur().get_label();
+        let fs = self.cur();
+        let nactvar = if at_end {
+            fs.blocks.last().map_or(0, |bl| bl.nactvar)
+        } else {
+            fs.actives.len()
+        };
+        fs.labels.push(LabelDesc {
+            name: name.to_string(),
+            pc,
+            line,
+            nactvar,
+        });
+        // 本家 `findgotos`: このブロックの未解決 goto のうち同名のものを解決する。
+        let l = fs.labels.len() - 1;
+        let mut i = first_goto;
+        while i < self.cur().gotos.len() {
+            if self.cur().gotos[i].name == name {
+                self.close_goto(i, l)?;
+            } else {
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// 本家 `findlabel`: goto `g` を現在のブロックのラベルと照合し、見つかれば解決する。
+    fn find_label(&mut self, g: usize) -> LuaResult<bool> {
+        let (first_label, _) = self.block_firsts();
+        let fs = self.cur();
+        let Some(l) =
+            (first_label..fs.labels.len()).find(|&l| fs.labels[l].name == fs.gotos[g].name)
+        else {
+            return Ok(false);
+        };
+        // 捕捉されうるローカルのスコープから出る後方ジャンプは upvalue を閉じる。
+        let (gt, lb) = (&fs.gotos[g], &fs.labels[l]);
+        if gt.nactvar > lb.nactvar {
+            let (pc, level) = (gt.pc, lb.nactvar);
+            fs.patch_close(pc, level);
+        }
+        self.close_goto(g, l)?;
+        Ok(true)
+    }
+
+    /// 本家 `closegoto`: goto `g` をラベル `l` へ繋ぎ、未解決リストから外す。
+    fn close_goto(&mut self, g: usize, l: usize) -> LuaResult<()> {
+        let fs = self.cur();
+        let gt = fs.gotos.remove(g);
+        let lb = &fs.labels[l];
+        if gt.nactvar < lb.nactvar {
+            let var = &fs.actives[gt.nactvar].name;
+            let msg = format!(
+                "<goto {}> at line {} jumps into the scope of local '{var}'",
+                gt.name, gt.line
+            );
+            let line = lb.line;
+            return Err(self.err_at(line, msg));
+        }
+        let target = lb.pc;
+        fs.patch_list(gt.pc, target)
+    }
+
+    /// 本家 `movegotosout`: 抜けるブロック `bl` の未解決 goto を外側のブロックへ移す。
+    fn move_gotos_out(&mut self, bl: &BlockCnt) -> LuaResult<()> {
+        let mut i = bl.first_goto;
+        while i < self.cur().gotos.len() {
+            let fs = self.cur();
+            let gt = &mut fs.gotos[i];
+            if gt.nactvar > bl.nactvar {
+                let pc = gt.pc;
+                gt.nactvar = bl.nactvar;
+                if bl.has_upval {
+                    fs.patch_close(pc, bl.nactvar);
+                }
+            }
+            if !self.find_label(i)? {
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+
     // ---- 制御構造 ----------------------------------------------------------
 
     /// 条件式をコンパイルし、偽脱出リストを返す（本家 `cond`）。
@@ -1470,7 +1668,7 @@ impl<'h> CodeGen<'h> {
         let repeat_init = self.cur().get_label();
         self.enter_block(true); // ループブロック
         self.enter_block(false); // スコープブロック
-        self.statements(body)?;
+        self.stat_list(body, true)?;
         // 条件はスコープブロック内で評価。
         let cond_exit = self.cond(cond, line)?;
         let inner_upval = self.cur().blocks.last().unwrap().has_upval;
@@ -1647,7 +1845,8 @@ impl<'h> CodeGen<'h> {
         self.cur().reserve_regs(nparams)?;
         self.cur().proto.is_vararg = body.is_vararg;
         self.statements(&body.body)?;
-        let (proto, upvals) = self.close_func(body.last_line)?;
+        let (mut proto, upvals) = self.close_func(body.last_line)?;
+        proto.last_line_defined = body.last_line;
         // 親へ proto を登録し CLOSURE を出す。
         self.push_closure(proto, upvals, body.line)
     }
@@ -2004,13 +2203,18 @@ impl FuncState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::Lang;
     use crate::compiler::parser::Parser;
 
     /// ソースをコンパイルして main `Proto` を得る。
     fn compile(src: &str) -> Proto {
+        try_compile(src, Lang::Lua51).expect("codegen")
+    }
+
+    fn try_compile(src: &str, lang: Lang) -> LuaResult<Proto> {
         let mut heap = Heap::new();
-        let block = Parser::parse(src.as_bytes(), "test").expect("parse");
-        CodeGen::compile(&mut heap, &block, "test").expect("codegen")
+        let block = Parser::parse(src.as_bytes(), "=test", lang).expect("parse");
+        CodeGen::compile(&mut heap, &block, "=test")
     }
 
     /// 命令列をオペコード列に変換（検査用）。
@@ -2032,9 +2236,11 @@ mod tests {
         let p = compile("return 1 + 2");
         assert_eq!(ops(&p), vec![OpCode::LoadK, OpCode::Return, OpCode::Return]);
         assert_eq!(p.constants.len(), 1);
-        match p.constants[0] {
-            Value::Number(n) => assert_eq!(n, 3.0),
-            _ => panic!("expected number"),
+        let v = p.constants[0];
+        if let Some(n) = v.as_number() {
+            assert_eq!(n, 3.0)
+        } else {
+            panic!("expected number")
         }
     }
 
@@ -2200,11 +2406,12 @@ mod tests {
     #[test]
     fn vararg_outside_errors() {
         let mut heap = Heap::new();
-        let block = Parser::parse(b"return ...", "test").unwrap();
+        let block = Parser::parse(b"return ...", "test", Lang::Lua51).unwrap();
         // main は vararg なので OK、関数内の非可変長で `...` はエラー。
         assert!(CodeGen::compile(&mut heap, &block, "test").is_ok());
 
-        let block2 = Parser::parse(b"local function f() return ... end", "test").unwrap();
+        let block2 =
+            Parser::parse(b"local function f() return ... end", "test", Lang::Lua51).unwrap();
         assert!(CodeGen::compile(&mut Heap::new(), &block2, "test").is_err());
     }
 
@@ -2235,9 +2442,11 @@ mod tests {
     fn unary_neg_folds_constant() {
         let p = compile("return -5");
         assert_eq!(ops(&p), vec![OpCode::LoadK, OpCode::Return, OpCode::Return]);
-        match p.constants[0] {
-            Value::Number(n) => assert_eq!(n, -5.0),
-            _ => panic!(),
+        let v = p.constants[0];
+        if let Some(n) = v.as_number() {
+            assert_eq!(n, -5.0)
+        } else {
+            panic!()
         }
     }
 
@@ -2256,4 +2465,46 @@ mod tests {
         assert_eq!(p.line_info.len(), p.code.len());
         assert!(p.line_info.contains(&2));
     }
+
+    #[test]
+    fn goto_compiles_to_jumps() {
+        // 前方 goto は `x = 1`（LOADK + SETGLOBAL）を飛び越える JMP。
+        let p = try_compile("goto skip x = 1 ::skip::", Lang::Lua52).unwrap();
+        let jmp = p.code[0];
+        assert_eq!(jmp.opcode(), Some(OpCode::Jmp));
+        assert_eq!((jmp.a(), jmp.sbx()), (0, 2));
+    }
+
+    #[test]
+    fn goto_out_of_captured_scope_closes_upvalues() {
+        // ループ本体の x は捕捉されるので、先頭へ戻る JMP は A = 1（R(0) 以上を閉じる）。
+        let p = try_compile(
+            "::top:: local x = 1 f = function() return x end if g() then goto top end",
+            Lang::Lua52,
+        )
+        .unwrap();
+        let back = p
+            .code
+            .iter()
+            .find(|i| i.opcode() == Some(OpCode::Jmp) && i.sbx() < 0)
+            .expect("backward jump");
+        assert_eq!(back.a(), 1);
+    }
+
+    #[test]
+    fn goto_errors() {
+        let err = |src: &str| try_compile(src, Lang::Lua52).unwrap_err().to_string();
+        assert!(
+            err("goto f\nlocal x\n::f:: print(x)")
+                .ends_with("test:3: <goto f> at line 1 jumps into the scope of local 'x'")
+        );
+        assert!(
+            err("do ::l:: end goto l")
+                .ends_with("test:1: no visible label 'l' for <goto> at line 1")
+        );
+        assert!(err("::a::\n::a::").ends_with("test:2: label 'a' already defined on line 1"));
+        // ブロック末尾のラベルはローカルのスコープ外、repeat 本体の末尾は内側。
+        assert!(try_compile("do goto e local x ::e:: end", Lang::Lua52).is_ok());
+        assert!(try_compile("repeat goto e local x ::e:: until x", Lang::Lua52).is_err());
+    }
 }
@@ -5,7 +5,11 @@
 //! 行番号は本家 `inclinenumber` と同じく `\n` / `\r` / `\r\n` / `\n\r` を 1 行として数える。
 //!
 //! エラー文言は本家 `luaX_lexerror` に合わせ `<chunk>:<line>: <msg>` 形式（必要なら ` near '<token>'`）。
+//!
+//! [`Lang::Lua52`] では 5.2 以降の字句（予約語 `goto`、`::`、`\x` / `\z` / `\u{...}` エスケープ）も
+//! 受理する。
 
+use crate::compiler::Lang;
 use crate::error::{LuaError, LuaResult};
 
 /// Lua 5.1 のトークン種別（本家 `RESERVED` + 記号 + リテラル）。
@@ -41,6 +45,8 @@ pub enum Token {
     True,
     Until,
     While,
+    /// `goto`（[`Lang::Lua52`] のみ予約語）。
+    Goto,
 
     // --- 記号 ---
     Plus,      // +
@@ -65,6 +71,7 @@ pub enum Token {
     RBracket,  // ]
     Semicolon, // ;
     Colon,     // :
+    DbColon,   // ::（Lang::Lua52 のみ）
     Comma,     // ,
     Dot,       // .
     Concat,    // ..
@@ -130,6 +137,7 @@ impl Token {
             Token::True => "true".into(),
             Token::Until => "until".into(),
             Token::While => "while".into(),
+            Token::Goto => "goto".into(),
             Token::Plus => "+".into(),
             Token::Minus => "-".into(),
             Token::Star => "*".into(),
@@ -152,6 +160,7 @@ impl Token {
             Token::RBracket => "]".into(),
             Token::Semicolon => ";".into(),
             Token::Colon => ":".into(),
+            Token::DbColon => "::".into(),
             Token::Comma => ",".into(),
             Token::Dot => ".".into(),
             Token::Concat => "..".into(),
@@ -178,17 +187,20 @@ pub struct Lexer<'a> {
     chunk: String,
     /// 文字列/数値リテラル組み立て用バッファ。
     buff: Vec<u8>,
+    /// 受理する言語の水準。
+    lang: Lang,
 }
 
 impl<'a> Lexer<'a> {
-    /// 入力と（短縮済みの）チャンク名から字句解析器を作る。
-    pub fn new(src: &'a [u8], chunk: impl Into<String>) -> Self {
+    /// 入力と（短縮済みの）チャンク名・言語水準から字句解析器を作る。
+    pub fn new(src: &'a [u8], chunk: impl Into<String>, lang: Lang) -> Self {
         let mut lx = Lexer {
             src,
             pos: 0,
             line: 1,
             chunk: chunk.into(),
             buff: Vec::new(),
+            lang,
         };
         // 本家 luaL_loadfile 相当のシバン行スキップ（先頭が `#`）。
         if lx.src.first() == Some(&b'#') {
@@ -430,6 +442,10 @@ impl<'a> Lexer<'a> {
                 }
                 b':' => {
                     self.advance();
+                    if self.lang >= Lang::Lua52 && self.cur() == Some(b':') {
+                        self.advance();
+                        return self.spanned(Token::DbColon);
+                    }
                     return self.spanned(Token::Colon);
                 }
                 b',' => {
@@ -452,7 +468,11 @@ impl<'a> Lexer<'a> {
                     // 識別子は ASCII のみ（Lua 5.1 の名前は ASCII 英数字 + '_'）。
                     let word = std::str::from_utf8(&self.src[start..self.pos])
                         .expect("identifier bytes are ASCII");
-                    let tok = Token::keyword(word).unwrap_or_else(|| Token::Name(word.to_string()));
+                    let tok = match Token::keyword(word) {
+                        Some(kw) => kw,
+                        None if word == "goto" && self.lang >= Lang::Lua52 => Token::Goto,
+                        None => Token::Name(word.to_string()),
+                    };
                     return self.spanned(tok);
                 }
                 other => {
@@ -623,6 +643,29 @@ impl<'a> Lexer<'a> {
                             }
                             self.buff.push(val as u8);
                         }
+                        b'x' if self.lang >= Lang::Lua52 => {
+                            self.advance();
+                            let v = self.read_hex_escape(delim)?;
+                            self.buff.push(v);
+                        }
+                        b'z' if self.lang >= Lang::Lua52 => {
+                            // `\z`: 続く空白（改行を含む）を読み飛ばす。
+                            self.advance();
+                            while let Some(ws) = self.cur() {
+                                if Self::is_newline(ws) {
+                                    self.inc_line()?;
+                                } else if matches!(ws, b' ' | b'\t' | b'\x0b' | b'\x0c') {
+                                    self.advance();
+                                } else {
+                                    break;
+                                }
+                            }
+                        }
+                        b'u' if self.lang >= Lang::Lua52 => {
+                            self.advance();
+                            let cp = self.read_utf8_escape(delim)?;
+                            utf8_encode(cp, &mut self.buff);
+                        }
                         other => {
                             // 本家: それ以外は文字そのものを保存（`\\`, `\"`, `\'`, `\?` …）。
                             self.buff.push(other);
@@ -639,6 +682,68 @@ impl<'a> Lexer<'a> {
         Ok(std::mem::take(&mut self.buff))
     }
 
+    /// 本家 5.2 `escerror`: エスケープの誤り。`near` には読みかけの文字列（開きの引用符から
+    /// 不正なエスケープまで）を示す。
+    fn escape_error(&self, delim: u8, seq: &[u8], msg: &str) -> LuaError {
+        let mut near = vec![delim];
+        near.extend_from_slice(&self.buff);
+        near.push(b'\\');
+        near.extend_from_slice(seq);
+        self.error_near(msg, &String::from_utf8_lossy(&near))
+    }
+
+    /// `\xXX`（16進 2 桁）。呼び出し時 `x` は消費済み。
+    fn read_hex_escape(&mut self, delim: u8) -> LuaResult<u8> {
+        let mut seq = vec![b'x'];
+        let mut v = 0u8;
+        for _ in 0..2 {
+            let c = self.cur();
+            if let Some(c) = c {
+                seq.push(c);
+            }
+            match c.and_then(|c| (c as char).to_digit(16)) {
+                Some(d) => {
+                    v = v * 16 + d as u8;
+                    self.advance();
+                }
+                None => return Err(self.escape_error(delim, &seq, "hexadecimal digit expected")),
+            }
+        }
+        Ok(v)
+    }
+
+    /// 本家 5.3 `readutf8esc`: `\u{XXX}`（コードポイント 2^31 未満）。呼び出し時 `u` は消費済み。
+    fn read_utf8_escape(&mut self, delim: u8) -> LuaResult<u32> {
+        let mut seq = vec![b'u'];
+        if self.cur() != Some(b'{') {
+            seq.extend(self.cur());
+            return Err(self.escape_error(delim, &seq, "missing '{'"));
+        }
+        seq.push(b'{');
+        self.advance();
+        let mut cp: u32 = 0;
+        let mut digits = 0;
+        while let Some(d) = self.cur().and_then(|c| (c as char).to_digit(16)) {
+            seq.push(self.cur().unwrap());
+            self.advance();
+            digits += 1;
+            cp = match cp.checked_mul(16).map(|v| v + d) {
+                Some(v) if v <= 0x7FFF_FFFF => v,
+                _ => return Err(self.escape_error(delim, &seq, "UTF-8 value too large")),
+            };
+        }
+        if digits == 0 {
+            seq.extend(self.cur());
+            return Err(self.escape_error(delim, &seq, "hexadecimal digit expected"));
+        }
+        if self.cur() != Some(b'}') {
+            seq.extend(self.cur());
+            return Err(self.escape_error(delim, &seq, "missing '}'"));
+        }
+        self.advance();
+        Ok(cp)
+    }
+
     /// 本家 `read_numeral`: 数値リテラルを読み f64 に変換する。
     /// 16進整数 `0x..` は strtoul 相当、10進/小数/指数は strtod 相当。
     fn read_numeral(&mut self) -> LuaResult<f64> {
@@ -662,6 +767,28 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// 本家 5.3 `luaO_utf8esc`: コードポイントを（5.3 と同じく 6 バイトまでの拡張）UTF-8 で追記する。
+fn utf8_encode(cp: u32, out: &mut Vec<u8>) {
+    if cp < 0x80 {
+        out.push(cp as u8);
+        return;
+    }
+    let mut tail = Vec::with_capacity(5);
+    let mut x = cp;
+    // 先頭バイトに収まる最大値（継続バイトを 1 つ足すごとに 1 ビット減る）。
+    let mut mfb: u32 = 0x3f;
+    loop {
+        tail.push(0x80 | (x & 0x3f) as u8);
+        x >>= 6;
+        mfb >>= 1;
+        if x <= mfb {
+            break;
+        }
+    }
+    out.push(((!mfb << 1) | x) as u8);
+    out.extend(tail.iter().rev());
+}
+
 /// 本家 `luaO_str2d` 相当の数値変換。16進整数 (`0x..`) と 10進実数を扱う。
 pub fn parse_number(text: &str) -> Option<f64> {
     let t = text.trim();
@@ -707,7 +834,11 @@ mod tests {
     use super::*;
 
     fn lex_all(src: &str) -> Vec<Token> {
-        let mut lx = Lexer::new(src.as_bytes(), "test");
+        lex_all_in(src, Lang::Lua51)
+    }
+
+    fn lex_all_in(src: &str, lang: Lang) -> Vec<Token> {
+        let mut lx = Lexer::new(src.as_bytes(), "test", lang);
         let mut out = Vec::new();
         loop {
             let s = lx.next_token().expect("lex error");
@@ -785,7 +916,7 @@ mod tests {
 
     #[test]
     fn malformed_number() {
-        let mut lx = Lexer::new(b"3.3.3", "test");
+        let mut lx = Lexer::new(b"3.3.3", "test", Lang::Lua51);
         assert!(lx.next_token().is_err());
     }
 
@@ -814,9 +945,9 @@ mod tests {
 
     #[test]
     fn unfinished_string() {
-        let mut lx = Lexer::new(b"\"abc", "test");
+        let mut lx = Lexer::new(b"\"abc", "test", Lang::Lua51);
         assert!(lx.next_token().is_err());
-        let mut lx2 = Lexer::new(b"\"ab\nc\"", "test");
+        let mut lx2 = Lexer::new(b"\"ab\nc\"", "test", Lang::Lua51);
         assert!(lx2.next_token().is_err());
     }
 
@@ -861,7 +992,7 @@ mod tests {
 
     #[test]
     fn line_tracking() {
-        let mut lx = Lexer::new(b"a\nb\r\nc", "test");
+        let mut lx = Lexer::new(b"a\nb\r\nc", "test", Lang::Lua51);
         let a = lx.next_token().unwrap();
         assert_eq!(a.line, 1);
         let b = lx.next_token().unwrap();
@@ -878,7 +1009,55 @@ mod tests {
     #[test]
     fn invalid_long_delimiter() {
         // "[=" の後に '[' が来ない → invalid long string delimiter
-        let mut lx = Lexer::new(b"[=x", "test");
+        let mut lx = Lexer::new(b"[=x", "test", Lang::Lua51);
         assert!(lx.next_token().is_err());
     }
+
+    #[test]
+    fn lua52_goto_and_labels() {
+        assert_eq!(
+            lex_all_in("goto top ::top::", Lang::Lua52),
+            vec![
+                Token::Goto,
+                Token::Name("top".into()),
+                Token::DbColon,
+                Token::Name("top".into()),
+                Token::DbColon,
+            ]
+        );
+        // 5.1 では goto は名前、`::` は `:` 2 つ。
+        assert_eq!(
+            lex_all("goto ::"),
+            vec![Token::Name("goto".into()), Token::Colon, Token::Colon]
+        );
+    }
+
+    #[test]
+    fn lua52_escapes() {
+        let lex52 = |src: &str| lex_all_in(src, Lang::Lua52);
+        assert_eq!(lex52(r#" "\x41\x7a" "#), vec![Token::Str(b"Az".to_vec())]);
+        assert_eq!(lex52("\"a\\z  \n\t b\""), vec![Token::Str(b"ab".to_vec())]);
+        assert_eq!(
+            lex52(r#" "\u{48}\u{e9}\u{20AC}\u{1F600}" "#),
+            vec![Token::Str("Hé€😀".as_bytes().to_vec())]
+        );
+        assert_eq!(
+            lex52(r#" "\u{7FFFFFFF}" "#),
+            vec![Token::Str(vec![0xFD, 0xBF, 0xBF, 0xBF, 0xBF, 0xBF])]
+        );
+        // 5.1 では未知のエスケープとして文字そのもの。
+        assert_eq!(lex_all(r#" "\x41" "#), vec![Token::Str(b"x41".to_vec())]);
+    }
+
+    #[test]
+    fn lua52_escape_errors() {
+        let err = |src: &str| {
+            let mut lx = Lexer::new(src.as_bytes(), "test", Lang::Lua52);
+            lx.next_token().unwrap_err().to_string()
+        };
+        assert!(err(r#" "ab\xg1" "#).ends_with(r#"hexadecimal digit expected near '"ab\xg'"#));
+        assert!(err(r#" "\u41" "#).ends_with(r#"missing '{' near '"\u4'"#));
+        assert!(err(r#" "\u{41" "#).ends_with(r#"missing '}' near '"\u{41"'"#));
+        assert!(err(r#" "\u{80000000}" "#).contains("UTF-8 value too large"));
+    }
 }
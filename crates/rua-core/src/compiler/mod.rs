@@ -17,6 +17,39 @@ use crate::vm::proto::Proto;
 use codegen::CodeGen;
 use parser::Parser;
 
+/// 受理する言語の水準。既定は Lua 5.1 そのもの。
+///
+/// [`Lang::Lua52`] は 5.1 の文法に、5.2 以降の構文拡張（`goto` 文と `::label::`、文字列の
+/// `\x` / `\z` / `\u{...}` エスケープ、空文 `;`、ブロック途中の `break`）を加える。`goto` は
+/// 予約語になるため、`goto` を名前として使う 5.1 のコードはこのモードでは読めない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
+pub enum Lang {
+    /// Lua 5.1（本家 5.1 と同じ文法）。
+    #[default]
+    Lua51,
+    /// Lua 5.1 + 5.2 以降の構文拡張。
+    Lua52,
+}
+
+impl Lang {
+    /// `"5.1"` / `"5.2"` から変換する（CLI の `--lang` 等）。
+    pub fn from_name(name: &str) -> Option<Lang> {
+        match name {
+            "5.1" => Some(Lang::Lua51),
+            "5.2" => Some(Lang::Lua52),
+            _ => None,
+        }
+    }
+
+    /// [`Lang::from_name`] が受け付ける表記。
+    pub fn name(self) -> &'static str {
+        match self {
+            Lang::Lua51 => "5.1",
+            Lang::Lua52 => "5.2",
+        }
+    }
+}
+
 /// ソース文字列とチャンク名から Lua チャンクをコンパイルし、main 関数の [`Proto`] を返す。
 ///
 /// これがフロントエンドの公開エントリ（CLI/VM から呼ぶ）。文字列定数のインターンに
@@ -25,10 +58,20 @@ use parser::Parser;
 ///
 /// `chunkname` は本家 `luaL_loadbuffer`/`lua_load` 同様、`@file`（ファイル）・`=name`
 /// （表示名そのまま）・その他（`[string "..."]` 形式）の規約に従う。エラー/デバッグ表示には
-/// [`chunk_id`] で短縮した名前を用いる。
+/// [`chunk_id`] で短縮した名前を用いる。文法は既定の [`Lang`]（Lua 5.1）。
 pub fn compile(heap: &mut Heap, src: &[u8], chunkname: &str) -> LuaResult<Proto> {
+    compile_with_lang(heap, src, chunkname, Lang::default())
+}
+
+/// [`compile`] の文法指定版。`lang` は受理する文法（[`Lang`]）。
+pub fn compile_with_lang(
+    heap: &mut Heap,
+    src: &[u8],
+    chunkname: &str,
+    lang: Lang,
+) -> LuaResult<Proto> {
     let id = chunk_id(chunkname);
-    let block = Parser::parse(src, id.clone())?;
+    let block = Parser::parse(src, id.clone(), lang)?;
     // NOTE(lua-stdlib→lua-frontend): `Proto::source` には **生のチャンク名**（`@file` 等）を
     // 渡す。VM 側（`interp::short_src` / `CallInfo.source`）が表示時に短縮するため、ここで
     // 短縮済み `id` を渡すとネストした関数の source が二重短縮され `[string "..."]` になる
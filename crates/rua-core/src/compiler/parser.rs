@@ -3,7 +3,11 @@
 //! Lua 5.1 文法の再帰下降パーサ。[`Lexer`] からトークンを 1 つ先読み付きで取得し、
 //! [`ast::Block`] を構築する。演算子優先順位・右結合（`..`/`^`）・単項演算子は
 //! 本家 `subexpr` の優先度表を忠実に再現する。エラー文言は本家 `lparser.c` に合わせる。
+//!
+//! [`Lang::Lua52`] では 5.2 の文（`goto`・`::label::`・空文 `;`・ブロック途中の `break`）も受理する。
+//! ラベルの可視性と `goto` の検査は codegen が行う。
 
+use crate::compiler::Lang;
 use crate::compiler::ast::*;
 use crate::compiler::lexer::{Lexer, Spanned, Token};
 use crate::error::{LuaError, LuaResult};
@@ -20,18 +24,21 @@ pub struct Parser<'a> {
     ahead: Option<Spanned>,
     /// 再帰の深さ（`enterlevel`/`leavelevel` 相当）。
     level: u32,
+    /// 受理する言語の水準。
+    lang: Lang,
 }
 
 impl<'a> Parser<'a> {
-    /// ソースと（短縮済み）チャンク名からパーサを構築し、チャンクを解析する。
-    pub fn parse(src: &'a [u8], chunk: impl Into<String>) -> LuaResult<Block> {
-        let mut lexer = Lexer::new(src, chunk);
+    /// ソースと（短縮済み）チャンク名・言語水準からパーサを構築し、チャンクを解析する。
+    pub fn parse(src: &'a [u8], chunk: impl Into<String>, lang: Lang) -> LuaResult<Block> {
+        let mut lexer = Lexer::new(src, chunk, lang);
         let first = lexer.next_token()?;
         let mut p = Parser {
             lexer,
             tok: first,
             ahead: None,
             level: 0,
+            lang,
         };
         let block = p.chunk()?;
         p.expect_eof()?;
@@ -165,12 +172,16 @@ impl<'a> Parser<'a> {
         )
     }
 
-    /// `chunk -> { stat [';'] }`。`return`/`break` は最後の文。
+    /// `chunk -> { stat [';'] }`。`return`/`break` は最後の文（5.2 では `return` のみ）。
     fn chunk(&mut self) -> LuaResult<Block> {
         self.enter_level()?;
         let mut stmts = Vec::new();
         let mut is_last = false;
         while !is_last && !self.block_follow() {
+            // 5.2: 空文 `;`。
+            if self.lang >= Lang::Lua52 && self.test_next(&Token::Semicolon)? {
+                continue;
+            }
             let (stmt, last) = self.statement()?;
             is_last = last;
             stmts.push(stmt);
@@ -212,7 +223,17 @@ impl<'a> Parser<'a> {
             Token::Return => (self.return_stat()?, true),
             Token::Break => {
                 self.advance()?;
-                (StmtKind::Break, true)
+                (StmtKind::Break, self.lang == Lang::Lua51)
+            }
+            Token::Goto => {
+                self.advance()?;
+                (StmtKind::Goto(self.expect_name()?), false)
+            }
+            Token::DbColon => {
+                self.advance()?;
+                let name = self.expect_name()?;
+                self.expect(&Token::DbColon)?;
+                (StmtKind::Label(name), false)
             }
             _ => (self.expr_stat()?, false),
         };
@@ -739,7 +760,11 @@ mod tests {
     use super::*;
 
     fn parse(src: &str) -> LuaResult<Block> {
-        Parser::parse(src.as_bytes(), "test")
+        Parser::parse(src.as_bytes(), "test", Lang::Lua51)
+    }
+
+    fn parse52(src: &str) -> LuaResult<Block> {
+        Parser::parse(src.as_bytes(), "test", Lang::Lua52)
     }
 
     fn parse_ok(src: &str) -> Block {
@@ -1016,4 +1041,19 @@ mod tests {
         let src = "return ".to_string() + &"(".repeat(1000) + "1" + &")".repeat(1000);
         assert!(parse(&src).is_err());
     }
+
+    #[test]
+    fn lua52_goto_label_and_empty_statements() {
+        let b = parse52(";; ::top:: ; goto top; break ; x = 1").unwrap();
+        let kinds: Vec<_> = b.stmts.iter().map(|s| &s.kind).collect();
+        assert_eq!(kinds[0], &StmtKind::Label("top".into()));
+        assert_eq!(kinds[1], &StmtKind::Goto("top".into()));
+        assert_eq!(kinds[2], &StmtKind::Break);
+        assert!(matches!(kinds[3], StmtKind::Assign { .. }));
+        // 5.1 では goto は名前で、`break` の後に文は続けられない。
+        assert!(parse("goto = 1").is_ok());
+        assert!(parse("::top::").is_err());
+        assert!(parse("while x do break x = 1 end").is_err());
+        assert!(parse52("goto = 1").is_err());
+    }
 }
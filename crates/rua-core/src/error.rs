@@ -22,16 +22,51 @@ pub enum LuaError {
     ErrorInError,
     /// 内部実装エラー（rua の bug。本来到達しない経路）。
     Internal(String),
+    /// 実行の打ち切り（命令数の上限・外部からの中断要求）。サンドボックス用。
+    /// スクリプトが自分で止められないよう、`pcall`/`xpcall`/`coroutine.resume` では捕捉されず
+    /// 埋め込み側まで伝播する（[`InterruptReason::catchable`] なものを除く）。
+    Interrupted(InterruptReason),
     /// コルーチン yield（制御フロー専用、通常のエラーではない）。
     /// `pcall` を透過して `coroutine.resume` まで伝播する。
     Yield(Vec<Value>),
 }
 
+/// [`LuaError::Interrupted`] の理由。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptReason {
+    /// 命令数の上限（[`LuaState::instruction_budget`](crate::state::LuaState::instruction_budget)）を使い切った。
+    InstructionLimit,
+    /// 中断フラグ（[`LuaState::interrupt`](crate::state::LuaState::interrupt)）が立てられた。
+    Requested,
+    /// 実行の期限（[`LuaState::deadline`](crate::state::LuaState::deadline)）を過ぎた。
+    /// `catchable` なら通常のエラーと同じくスクリプトの `pcall` で捕捉できる。
+    Deadline {
+        /// スクリプトの `pcall` で捕捉できるか（[`Deadline::catchable`](crate::state::Deadline::catchable)）。
+        catchable: bool,
+    },
+}
+
+impl InterruptReason {
+    /// エラーメッセージ。
+    pub fn message(self) -> &'static str {
+        match self {
+            InterruptReason::InstructionLimit => "instruction limit exceeded",
+            InterruptReason::Requested => "interrupted",
+            InterruptReason::Deadline { .. } => "execution timed out",
+        }
+    }
+
+    /// スクリプトの `pcall`/`xpcall`/`coroutine.resume` で捕捉できるか。
+    pub fn catchable(self) -> bool {
+        matches!(self, InterruptReason::Deadline { catchable: true })
+    }
+}
+
 impl LuaError {
     /// 文字列メッセージから実行時エラーを作る簡易コンストラクタ。
     ///
     /// 注意: Lua セマンティクス上、本来エラーオブジェクトはインターン済み Lua 文字列
-    /// （[`Value::GcRef`]）であるべき。ヒープへアクセスできない箇所での暫定用途に限り、
+    /// （[`Value::gc`]）であるべき。ヒープへアクセスできない箇所での暫定用途に限り、
     /// ここでは Rust 文字列を `Syntax`/`Internal` 系で包む。Lua 文字列値へ昇格する責務は
     /// 呼び出し側（ヒープを持つ `state`）にある。TODO(lua-vm): 実行時メッセージの Lua 文字列化。
     pub fn runtime_msg(msg: impl Into<String>) -> Self {
@@ -47,6 +82,7 @@ impl core::fmt::Display for LuaError {
             LuaError::Memory => write!(f, "not enough memory"),
             LuaError::ErrorInError => write!(f, "error in error handling"),
             LuaError::Internal(s) => write!(f, "internal error: {s}"),
+            LuaError::Interrupted(r) => f.write_str(r.message()),
             LuaError::Yield(_) => write!(f, "attempt to yield"),
         }
     }
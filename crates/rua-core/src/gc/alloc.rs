@@ -1,28 +1,83 @@
 //! アロケータ抽象（本家 `lmem.c` / `lua_Alloc` フック相当）。
 //!
 //! 本家 Lua は全メモリ確保を 1 つの `lua_Alloc` コールバックに集約し、組み込み側が
-//! 差し替えられる。第一マイルストーンでは Rust の標準アロケータ（`SlotMap` 経由）に委ね、
-//! 本モジュールは将来 C API（rua-capi）で `lua_Alloc` を受ける際の差し込み口を予約する。
+//! 差し替えられる。rua の実体は Rust の標準アロケータ（`SlotMap` 経由）に委ね、
+//! 使用量（[`Heap::bytes_in_use`](super::Heap::bytes_in_use)）の変化だけを [`AllocHook`] で
+//! 組み込み側へ通知する。C API（rua-capi）は `lua_newstate` の `lua_Alloc` をこのフックへ橋渡しする。
 //!
-//! TODO(lua-runtime/lua-capi, 第二マイルストーン):
-//!   - `lua_Alloc` 互換シグネチャ `fn(ud, ptr, osize, nsize) -> ptr` の橋渡し。
-//!   - GC 起動閾値（本家 `g->GCthreshold`）に基づく自動コレクションのトリガ。
+//! 自動コレクションの起動判定（本家 `g->GCthreshold`）は [`GcConfig`] を元に
+//! [`Heap::needs_step`](super::Heap::needs_step) が行う。
+
+/// インクリメンタル GC の 1 ステップの基本単位（バイト、本家 `GCSTEPSIZE`）。
+///
+/// サイクル中は使用量がこれだけ増えるたびに 1 ステップ進め、1 ステップでは
+/// `GC_STEP_SIZE × stepmul / 100` バイト相当のオブジェクトを走査/sweep する。
+pub const GC_STEP_SIZE: usize = 1024;
+
+/// sweep で 1 オブジェクトを調べる作業量（本家 `GCSWEEPCOST`）。
+pub const GC_SWEEP_COST: usize = 10;
+
+/// 使用量の変化を組み込み側のアロケータへ問い合わせるフック。
+///
+/// 引数は新しい使用バイト数。確保を拒否する（本家 `lua_Alloc` が `NULL` を返す）なら false。
+/// [`Heap::reserve`](crate::gc::Heap::reserve) が大きな確保の前と安全点で呼ぶ。
+pub type AllocHook = Box<dyn FnMut(usize) -> bool>;
+
+/// 自動 GC の進め方。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GcMode {
+    /// 確保に比例して少しずつ mark/sweep を進める（本家 5.1 の既定）。
+    #[default]
+    Incremental,
+    /// 閾値に達したら 1 サイクルを一括で行う。
+    StopTheWorld,
+    /// 世代別（本家 5.4 の generational 相当）。通常は若いオブジェクトだけを対象にする
+    /// minor コレクションを行い、前回の major 以降に使用量が `major_multiplier` % 増えたら
+    /// 全体を対象にする major コレクションを行う。どちらも一括で行う。
+    Generational,
+}
 
 /// GC 起動方針の設定（本家 `global_State` の GC パラメータに相当）。
 #[derive(Debug, Clone)]
 pub struct GcConfig {
-    /// 自動 GC を行うか。false の間は明示 collect のみ。
+    /// 自動 GC を行うか。false の間は明示 collect のみ（`collectgarbage("stop")` で false）。
     pub enabled: bool,
-    /// 前回 collect 後、この確保回数を超えたら collect を検討する（暫定の単純閾値）。
+    /// 自動 GC の進め方。
+    pub mode: GcMode,
+    /// サイクルを始める使用バイト数の下限。小さなヒープで GC が頻発するのを防ぐ。
     pub step_threshold: usize,
+    /// 前回サイクル後の使用量の何 % に達したら次のサイクルを始めるか（本家 `gcpause`）。
+    pub pause: u32,
+    /// 確保速度に対する GC 作業速度の比率 %（本家 `gcstepmul`）。0 なら 1 ステップで 1 サイクル。
+    pub stepmul: u32,
+    /// 世代別モードで、前回コレクション後の使用量から何 % 増えたら minor を行うか
+    /// （本家 5.4 `genminormul`）。
+    pub minor_multiplier: u32,
+    /// 世代別モードで、前回 major 後の使用量から何 % 増えたら major を行うか
+    /// （本家 5.4 `genmajormul`）。
+    pub major_multiplier: u32,
+}
+
+impl GcConfig {
+    /// 1 ステップの作業量（本家 `luaC_step` の `lim`）。
+    pub fn step_budget(&self) -> usize {
+        match GC_STEP_SIZE * self.stepmul as usize / 100 {
+            0 => usize::MAX,
+            n => n,
+        }
+    }
 }
 
 impl Default for GcConfig {
     fn default() -> Self {
         GcConfig {
             enabled: true,
-            // 本家の GCpause/GCstepmul 相当は性能フェーズで再設計する。暫定値。
-            step_threshold: 1024,
+            mode: GcMode::Incremental,
+            step_threshold: 64 * 1024,
+            pause: 200,
+            stepmul: 200,
+            minor_multiplier: 20,
+            major_multiplier: 100,
         }
     }
 }
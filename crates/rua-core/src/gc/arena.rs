@@ -0,0 +1,158 @@
+//! 型別アリーナ（世代付きキーのスロット配列）。
+//!
+//! キーは「スロット番号 32 ビット + 世代 16 ビット」の 48 ビットに収まり、
+//! [`Value`](crate::value::Value) の NaN-boxing のペイロードへそのまま詰められる。
+//! スロットを解放するたびに世代を進めるので、解放済みキーでの `get` は `None` を返す。
+//! 世代が尽きたスロットは再利用せずに退役させ、同じキーが別のオブジェクトを指すことは無い。
+
+use std::marker::PhantomData;
+
+/// アリーナのキー。[`arena_key!`] で型ごとに定義する。
+pub trait ArenaKey: Copy {
+    fn new(index: u32, generation: u16) -> Self;
+    fn index(self) -> u32;
+    fn generation(self) -> u16;
+
+    /// 48 ビット表現（上位 16 ビットが世代, 下位 32 ビットがスロット番号）。
+    fn to_bits(self) -> u64 {
+        (u64::from(self.generation()) << 32) | u64::from(self.index())
+    }
+
+    /// [`ArenaKey::to_bits`] の逆。
+    fn from_bits(bits: u64) -> Self {
+        Self::new(bits as u32, (bits >> 32) as u16)
+    }
+}
+
+/// [`ArenaKey`] を実装するキー型を定義する。
+macro_rules! arena_key {
+    ($($(#[$meta:meta])* pub struct $name:ident;)*) => {$(
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name {
+            index: u32,
+            generation: u16,
+        }
+
+        impl $crate::gc::arena::ArenaKey for $name {
+            fn new(index: u32, generation: u16) -> Self {
+                $name { index, generation }
+            }
+            fn index(self) -> u32 {
+                self.index
+            }
+            fn generation(self) -> u16 {
+                self.generation
+            }
+        }
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}({}v{})", stringify!($name), self.index, self.generation)
+            }
+        }
+    )*};
+}
+pub(crate) use arena_key;
+
+struct Slot<T> {
+    generation: u16,
+    value: Option<T>,
+}
+
+/// 世代付きキーで引くスロット配列（GC が使う操作だけを持つ）。
+pub struct Arena<K, T> {
+    slots: Vec<Slot<T>>,
+    /// 再利用できる空きスロット。
+    free: Vec<u32>,
+    len: usize,
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<K, T> Default for Arena<K, T> {
+    fn default() -> Self {
+        Arena {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<K: ArenaKey, T> Arena<K, T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, value: T) -> K {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.value = Some(value);
+            return K::new(index, slot.generation);
+        }
+        let index = u32::try_from(self.slots.len()).expect("arena slot index overflow");
+        self.slots.push(Slot {
+            generation: 0,
+            value: Some(value),
+        });
+        K::new(index, 0)
+    }
+
+    pub fn get(&self, key: K) -> Option<&T> {
+        self.slots
+            .get(key.index() as usize)
+            .filter(|s| s.generation == key.generation())
+            .and_then(|s| s.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, key: K) -> Option<&mut T> {
+        self.slots
+            .get_mut(key.index() as usize)
+            .filter(|s| s.generation == key.generation())
+            .and_then(|s| s.value.as_mut())
+    }
+
+    pub fn remove(&mut self, key: K) -> Option<T> {
+        let slot = self
+            .slots
+            .get_mut(key.index() as usize)
+            .filter(|s| s.generation == key.generation())?;
+        let value = slot.value.take()?;
+        self.len -= 1;
+        // 世代が尽きたスロットは退役させる（空きリストへ戻さない）。
+        if let Some(next) = slot.generation.checked_add(1) {
+            slot.generation = next;
+            self.free.push(key.index());
+        }
+        Some(value)
+    }
+
+    /// 生きているキーを列挙する。
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.value.as_ref().map(|_| K::new(i as u32, s.generation)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(i, s)| {
+            s.value
+                .as_ref()
+                .map(|v| (K::new(i as u32, s.generation), v))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (K, &mut T)> + '_ {
+        self.slots.iter_mut().enumerate().filter_map(|(i, s)| {
+            let generation = s.generation;
+            s.value.as_mut().map(|v| (K::new(i as u32, generation), v))
+        })
+    }
+}
@@ -2,34 +2,98 @@
 //!
 //! # 設計
 //! GC オブジェクト（string/table/closure/userdata、将来 thread）を **型別アリーナ**
-//! （[`slotmap::SlotMap`]）に格納する。[`Value`](crate::value::Value) はオブジェクト本体ではなく
+//! （[`arena::Arena`]）に格納する。[`Value`](crate::value::Value) はオブジェクト本体ではなく
 //! 世代付きハンドル [`GcHandle`] を保持する。ハンドルは [`Copy`] かつ型タグ（enum 判別子）を内包するため、
 //! ハンドルだけで Lua の型判定ができ、本体へのデリファレンスを伴わない。
 //!
 //! # 安全性
-//! 生ポインタを用いず、世代付きキーで dangling を排除する。`SlotMap` はスロット解放時に世代を進めるため、
+//! 生ポインタを用いず、世代付きキーで dangling を排除する。`Arena` はスロット解放時に世代を進めるため、
 //! 解放済みハンドルでの `get` は安全に `None` を返す。本モジュールに `unsafe` は無い。
 //!
-//! # 回収アルゴリズム（当面 stop-the-world mark-and-sweep）
-//! 1. ルート集合（VM スタック + レジストリ + グローバル環境, [`crate::state`] が提供）から到達可能性を辿る。
-//! 2. 各オブジェクトの [`Trace`] 実装で子ハンドルを灰色集合へ積む（tri-color の簡略版: 白/灰+黒）。
-//! 3. 未マークのスロットを sweep（解放）。インターン文字列はインターナからも除去。
+//! # 回収アルゴリズム（インクリメンタル tri-color mark-and-sweep、本家 5.1 `lgc.c` 相当）
+//! 各オブジェクトは白（未到達）/灰（到達済み・子未走査）/黒（走査済み）のいずれかの色を持つ。
+//! 1 サイクルは [`GcPhase`] の順に進み、[`Heap::step`] が 1 回あたり有限の作業量だけ進める。
+//! 1. `Pause` → `Propagate`: ルート集合（[`crate::state`] が提供）を灰にする。
+//! 2. `Propagate`: 灰色集合から取り出したオブジェクトの [`Trace`] で子を灰にし、自身を黒にする。
+//!    灰色集合が空になったら atomic 段（ルート再走査 + `gray_again` の再走査）を一括で行う。
+//! 3. `Sweep`: atomic 時点の全オブジェクトを順に調べ、白は解放、それ以外は白へ戻す。
+//!    インターン文字列はインターナからも除去する。
 //!
-//! インクリメンタル化・weak table・`__gc` finalizer は性能/互換フェーズで対応（TODO）。
+//! [`Heap::collect`] は進行中のサイクルを打ち切って全段を一度に行う（stop-the-world、本家 `luaC_fullgc`）。
+//!
+//! # ライトバリア（本家 `luaC_barrier` / `luaC_barriert`）
+//! `Propagate` 中に黒オブジェクトへ白への参照が書き込まれると不変条件（黒 → 白の参照なし）が崩れる。
+//! - テーブル/クロージャ/ユーザーデータ/スレッド: 可変参照の取得（`get_*_mut`）時に、黒なら灰へ戻して
+//!   `gray_again` に積む（後退バリア）。[`Table::set`] を含むすべての書き換えはこの経路を通る。
+//! - closed upvalue: セルはヒープ外（`Rc<RefCell<_>>`）にあるため、VM が書き込む値を
+//!   [`Heap::barrier`] で灰にする（前進バリア）。
+//!
+//! スタック・レジストリ・グローバル環境など（ルート）への書き込みは atomic 段の再走査で拾うため
+//! バリア不要。サイクル途中で確保したオブジェクトは白で生まれ、同じ理由で取りこぼさない。
+//!
+//! # 自動起動（本家 `luaC_checkGC` / `luaC_step`）
+//! 確保系（`alloc_str`/`alloc_table`/`alloc_closure` …）は自身では回収しない
+//! （ヒープはルート集合を知らないため）。VM が安全点（`NEWTABLE`/`CONCAT`/`CLOSURE` 直後、
+//! ネイティブ関数からの復帰時）で [`Heap::needs_step`] を問い合わせ、閾値を超えていれば
+//! [`crate::state::LuaState::check_gc`] が [`Heap::step`] を 1 回進める。
+//! 閾値と 1 ステップの作業量は [`GcConfig`] の `pause`/`stepmul` で調整する（本家と同じ意味）。
+//!
+//! # weak table（本家 `__mode`、ephemeron は 5.2 `lgc.c` 相当）
+//! メタテーブルの `__mode` に `k`/`v` を含むテーブルは、該当する側を強参照として辿らない。
+//! weak table は灰のまま `weak` リストへ登録し、atomic 段で再走査する（バリア不要にするため）。
+//! - `"v"`: キーのみ辿る。
+//! - `"k"`: ephemeron。キーが（他経路で）生存しているエントリの値だけを辿る。atomic 段で
+//!   新たに生存が判明したキーがなくなるまで繰り返す（キーを値だけが参照する循環も回収できる）。
+//! - `"kv"`: どちらも辿らない。
+//!
+//! マーク確定後、白いキー/値を持つエントリを取り除く（本家 `cleartable`）。
+//! 文字列は「値」として扱い weak 参照の対象にしない（本家同様、常に生存扱いで消さない）。
+//!
+//! # finalizer（本家 5.1 `luaC_separateudata` / `GCTM`）
+//! atomic 段でマーク確定後、メタテーブルに `__gc` を持つ白い userdata を `to_finalize` へ分離し、
+//! 1 サイクルだけ生き返らせる（再マークして子も辿る）。`__gc` の呼び出しはヒープではなく
+//! [`crate::state::LuaState`] が安全点で行い、呼び出し順は finalizer 登録（メタテーブル設定）の逆順。
+//! 分離済みの userdata は `finalized` となり、次のサイクルで到達不能なら通常どおり解放される。
+//! weak table の値としての分離済み userdata は掃除対象になる（キーとしては残る、本家同様）。
+//!
+//! # 世代別モード（本家 5.4 `lgc.c` の generational 相当、[`GcMode::Generational`]）
+//! 各オブジェクトは [`Age`] を持つ。確保直後は若く（`Young`）、コレクションを 1 回生き延びると
+//! 古く（`Old`）なる。古いオブジェクトはコレクション間も黒のまま保つ。
+//! - minor: ルートから若いオブジェクトだけを辿り（黒い古いオブジェクトで止まる）、
+//!   確保以降に溜めた若いオブジェクトの一覧だけを sweep する。
+//! - 古いオブジェクトから若いオブジェクトへの参照は後退バリアが拾う: 古い（黒い）オブジェクトを
+//!   書き換えると灰へ戻して `gray_again` に積み（remembered set）、次の minor で再走査する。
+//!   closed upvalue への書き込みは前進バリアで値を灰にする。weak table は常に灰のまま
+//!   `gray_again` に置き、毎回再走査・掃除する。
+//! - major: 全オブジェクトを白へ戻して全体を mark-and-sweep し、生存物をすべて古くする。
+//!   前回の major 以降に使用量が [`GcConfig::major_multiplier`] % 増えたら行う。
+//!
+//! どちらも一括で行う（インクリメンタルには進めない）。到達不能になった古いオブジェクトは
+//! 次の major まで残る。
 
 pub mod alloc;
+pub mod arena;
+pub mod snapshot;
+pub mod stats;
 
-use slotmap::{SlotMap, new_key_type};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::Instant;
 
+use crate::error::{LuaError, LuaResult};
 use crate::value::Value;
 use crate::value::closure::Closure;
-use crate::value::string::LuaString;
+use crate::vm::proto::Proto;
+
+use self::alloc::{AllocHook, GC_STEP_SIZE, GC_SWEEP_COST, GcConfig, GcMode};
+use self::arena::{Arena, ArenaKey, arena_key};
+use self::stats::{GcCallback, GcEvent, GcStats};
+use crate::value::string::{LuaString, MAX_SHORT_LEN};
 use crate::value::table::Table;
 use crate::value::thread::LuaThread;
 use crate::value::userdata::Userdata;
 
-new_key_type! {
+arena_key! {
     /// 文字列アリーナ用キー（世代付き）。
     pub struct StringKey;
     /// テーブルアリーナ用キー（世代付き）。
@@ -42,7 +106,7 @@ new_key_type! {
     pub struct ThreadKey;
 }
 
-/// GC 管理オブジェクトへの参照。`Value::GcRef` が保持する。
+/// GC 管理オブジェクトへの参照。`Value::gc` が保持する。
 ///
 /// enum 判別子が Lua の型タグを兼ねるため、本体をデリファレンスせずに型判定できる。
 /// `Copy` なので VM スタック上を値として安価に移動できる。
@@ -55,23 +119,75 @@ pub enum GcHandle {
     Thread(ThreadKey),
 }
 
-/// 各 GC オブジェクトに付与するヘッダ（mark ビット）。本家 `GCObject` の `marked` に相当。
+impl GcHandle {
+    /// キー部分の 48 ビット表現（種別は含まない, [`ArenaKey::to_bits`]）。
+    /// `tostring` の疑似アドレスや [`Value`] の NaN-boxing に使う。
+    pub fn key_bits(self) -> u64 {
+        match self {
+            GcHandle::Str(k) => k.to_bits(),
+            GcHandle::Table(k) => k.to_bits(),
+            GcHandle::Closure(k) => k.to_bits(),
+            GcHandle::Userdata(k) => k.to_bits(),
+            GcHandle::Thread(k) => k.to_bits(),
+        }
+    }
+}
+
+/// tri-color マーキングの色（本家 `marked` の WHITE/GRAY/BLACK ビット）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    /// 未到達。sweep 時点で白なら回収される。
+    White,
+    /// 到達済みだが子を未走査（灰色集合に積まれている）。
+    Gray,
+    /// 自身と子の走査が済んでいる。
+    Black,
+}
+
+/// 世代別モードでのオブジェクトの年齢（本家 5.4 `marked` の age ビット）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Age {
+    /// 直近のコレクション以降に確保された。minor の対象。
+    Young,
+    /// コレクションを生き延びた。次の major まで回収されない。
+    Old,
+}
+
+/// 各 GC オブジェクトに付与するヘッダ（色・年齢・計上サイズ）。本家 `GCObject` の `marked` に相当。
 #[derive(Debug)]
 struct GcBox<T> {
-    /// mark フェーズで到達済みなら true。sweep 後に false へ戻す。
-    marked: bool,
+    /// 現在の色。sweep で生存したものは白へ戻す（世代別モードでは黒のまま古くする）。
+    color: Color,
+    /// 世代別モードでの年齢。それ以外のモードでは常に `Young`。
+    age: Age,
+    /// [`Heap::bytes_in_use`] に計上済みのバイト数。
+    size: usize,
     value: T,
 }
 
 impl<T> GcBox<T> {
-    fn new(value: T) -> Self {
+    fn new(value: T, size: usize) -> Self {
         GcBox {
-            marked: false,
+            color: Color::White,
+            age: Age::Young,
+            size,
             value,
         }
     }
 }
 
+/// GC サイクルの段階（本家 `gcstate` の `GCSpause`/`GCSpropagate`/`GCSsweep`）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GcPhase {
+    /// サイクル間。全オブジェクトが白。
+    #[default]
+    Pause,
+    /// 灰色集合を少しずつ走査している。ライトバリアが有効。
+    Propagate,
+    /// atomic 段で確定した白を少しずつ解放している。
+    Sweep,
+}
+
 /// オブジェクトが保持する子 GC ハンドルを列挙するためのトレイト（本家の伝搬マークに相当）。
 ///
 /// 実装側は、自身が参照する [`Value`]／[`GcHandle`] を [`Tracer`] へ渡す。
@@ -79,12 +195,32 @@ pub trait Trace {
     fn trace(&self, tracer: &mut Tracer);
 }
 
+/// オブジェクトが占めるおおよそのバイト数を返すトレイト（本家は `lua_Alloc` で正確に数える）。
+///
+/// 構造体本体と、所有する可変長バッファ（容量ベース）を合算する。[`Heap`] はこの値で
+/// 使用量（[`Heap::bytes_in_use`]）と GC の起動・作業量を決める。
+pub trait HeapSize {
+    fn heap_size(&self) -> usize;
+}
+
 /// mark フェーズの灰色集合（worklist）。到達したハンドルを積む。
+///
+/// ルート集合の収集（[`crate::state::LuaState::roots`]）にも使う。
+#[derive(Default)]
 pub struct Tracer {
     gray: Vec<GcHandle>,
 }
 
 impl Tracer {
+    pub fn new() -> Self {
+        Tracer::default()
+    }
+
+    /// 積まれたハンドル列を取り出す（ルート集合の構築用）。
+    pub fn into_handles(self) -> Vec<GcHandle> {
+        self.gray
+    }
+
     /// ハンドルを灰色集合へ積む。
     pub fn mark(&mut self, handle: GcHandle) {
         self.gray.push(handle);
@@ -92,8 +228,8 @@ impl Tracer {
 
     /// 値が GC 参照なら灰色集合へ積む。プリミティブ値は無視。
     pub fn mark_value(&mut self, value: &Value) {
-        if let Value::GcRef(h) = value {
-            self.gray.push(*h);
+        if let Some(h) = value.as_gc() {
+            self.gray.push(h);
         }
     }
 }
@@ -103,15 +239,60 @@ impl Tracer {
 /// `global_State`（[`crate::state::GlobalState`]）が 1 つ保持し、すべての GC オブジェクトを所有する。
 #[derive(Default)]
 pub struct Heap {
-    strings: SlotMap<StringKey, GcBox<LuaString>>,
-    tables: SlotMap<TableKey, GcBox<Table>>,
-    closures: SlotMap<ClosureKey, GcBox<Closure>>,
-    userdata: SlotMap<UserdataKey, GcBox<Userdata>>,
-    threads: SlotMap<ThreadKey, GcBox<LuaThread>>,
-    /// 文字列インターナ: バイト列 → 既存キー。Lua 文字列は同値なら同一オブジェクト。
+    strings: Arena<StringKey, GcBox<LuaString>>,
+    tables: Arena<TableKey, GcBox<Table>>,
+    closures: Arena<ClosureKey, GcBox<Closure>>,
+    userdata: Arena<UserdataKey, GcBox<Userdata>>,
+    threads: Arena<ThreadKey, GcBox<LuaThread>>,
+    /// 文字列インターナ: バイト列 → 既存キー。短い文字列と、テーブルのキーに使われた長い文字列を
+    /// 登録する。登録済みの文字列は同値なら同一オブジェクト。
     interner: HashMap<Box<[u8]>, StringKey>,
-    /// 直近の collect 以降に確保したオブジェクト数の目安（GC 起動閾値判定のたたき台）。
+    /// 直近のサイクル完了以降に確保したオブジェクト数。
     alloc_count: usize,
+    /// 計上済みの使用バイト数（本家 `g->totalbytes`）。
+    total_bytes: usize,
+    /// 直近に可変参照を渡したオブジェクト。書き換え後のサイズを次の操作時に計上し直す。
+    resized: Option<GcHandle>,
+    /// Lua クロージャが参照する proto（アドレス → (計上バイト数, 参照クロージャ数)）。
+    /// proto はヒープ外の `Rc` で共有されるため、最初の参照で計上し最後の参照解放で差し引く。
+    protos: HashMap<*const Proto, (usize, usize)>,
+    /// 直近のサイクル完了直後の使用バイト数（本家 `g->estimate` 相当）。
+    estimate: usize,
+    /// 現在の GC 段階。
+    phase: GcPhase,
+    /// 灰色集合（本家 `g->gray`）。
+    gray: Vec<GcHandle>,
+    /// バリアで灰へ戻したオブジェクト。atomic 段でまとめて再走査する（本家 `g->grayagain`）。
+    gray_again: Vec<GcHandle>,
+    /// 走査済みの weak table（本家 `g->weak`）。atomic 段で再走査・掃除する。
+    weak: Vec<TableKey>,
+    /// `__gc` 呼び出し待ちの userdata（本家 `g->tmudata`）。末尾から順に呼ぶ。呼ぶまではルート扱い。
+    to_finalize: Vec<UserdataKey>,
+    /// userdata の生成通し番号の次の値。
+    next_serial: u64,
+    /// atomic 段時点の全オブジェクトのうち未 sweep のもの（本家 `g->sweepgc` 相当）。
+    sweep_list: Vec<GcHandle>,
+    /// `sweep_list` に残っている白いインターン済み文字列（atomic 段で死んでいたもの）。
+    /// インターナから再利用されたらここから外して生き返らせる。
+    sweep_dead_strs: HashSet<StringKey>,
+    /// サイクル途中で次のステップを行う使用バイト数（本家 `g->GCthreshold`）。
+    step_threshold: usize,
+    /// 世代別モードで動いているか（[`Heap::set_generational`]）。
+    generational: bool,
+    /// 世代別モードへ切り替えてから major を済ませたか。済むまでは古いオブジェクトがない。
+    gen_ready: bool,
+    /// 世代別モードで、直近のコレクション以降に確保したオブジェクト（minor の sweep 対象）。
+    young: Vec<GcHandle>,
+    /// 直近の major 完了直後の使用バイト数。
+    major_base: usize,
+    /// 使用メモリの上限（バイト）。`None` は無制限（[`Heap::reserve`]）。
+    memory_limit: Option<usize>,
+    /// 組み込み側アロケータへの問い合わせ（C API の `lua_Alloc`）。
+    alloc_hook: Option<AllocHook>,
+    /// 累計統計。
+    stats: GcStats,
+    /// サイクルの開始・終了で呼ぶフック。
+    callback: Option<GcCallback>,
 }
 
 impl Heap {
@@ -121,47 +302,169 @@ impl Heap {
 
     // ---- 確保（allocate）-------------------------------------------------
 
+    /// Lua 文字列を確保する。短い文字列はインターンし（[`Heap::intern_str`]）、
+    /// 長い文字列（[`MAX_SHORT_LEN`] バイト超）はハッシュせずに新しいオブジェクトとして確保する。
+    pub fn alloc_str(&mut self, bytes: &[u8]) -> GcHandle {
+        if bytes.len() <= MAX_SHORT_LEN {
+            self.intern_str(bytes)
+        } else {
+            self.alloc_long_str(LuaString::new(bytes))
+        }
+    }
+
+    /// 組み立て済みのバッファから Lua 文字列を確保する（[`Heap::alloc_str`] と同じ規則）。
+    /// 長い文字列はバッファをそのまま本体にし、複製しない。
+    pub fn alloc_str_from(&mut self, buf: Vec<u8>) -> GcHandle {
+        if buf.len() <= MAX_SHORT_LEN {
+            self.intern_str(&buf)
+        } else {
+            self.alloc_long_str(LuaString::from_vec(buf))
+        }
+    }
+
+    fn alloc_long_str(&mut self, s: LuaString) -> GcHandle {
+        let size = s.heap_size();
+        self.total_bytes += size;
+        let key = self.strings.insert(GcBox::new(s, size));
+        self.track_young(GcHandle::Str(key))
+    }
+
     /// 文字列をインターンして確保する。既存の同値文字列があればそのハンドルを返す。
     ///
-    /// インターンによりバイトバッファの安定性が保証されるため、将来の C API で
-    /// `lua_tolstring` が返すポインタ安定性要件（ARCHITECTURE.md §5）を満たす土台になる。
+    /// 長さによらずインターンするので、戻り値はテーブルのキーにそのまま使える。通常の文字列の
+    /// 生成には [`Heap::alloc_str`] を使う。
     pub fn intern_str(&mut self, bytes: &[u8]) -> GcHandle {
         if let Some(&key) = self.interner.get(bytes) {
+            self.revive_str(key);
             return GcHandle::Str(key);
         }
-        let s = LuaString::new(bytes);
-        let key = self.strings.insert(GcBox::new(s));
+        let mut s = LuaString::new(bytes);
+        s.set_interned();
+        // インターナのキー（バイト列の複製 + エントリ）も文字列の大きさに含める。
+        let size = s.heap_size() + interner_entry_size(bytes);
+        self.total_bytes += size;
+        let key = self.strings.insert(GcBox::new(s, size));
         self.interner.insert(bytes.into(), key);
-        self.alloc_count += 1;
-        GcHandle::Str(key)
+        self.track_young(GcHandle::Str(key))
+    }
+
+    /// sweep 待ちの白い文字列は「死んでいるが未解放」。インターナから再利用するなら生き返らせる
+    /// （本家 `luaS_newlstr` の `changewhite`）。
+    ///
+    /// sweep 済みの生存者や sweep 中に確保した文字列も白いが、これらは解放されないので触らない
+    /// （黒くすると次のサイクルを浮遊ゴミとして生き延びてしまう）。
+    fn revive_str(&mut self, key: StringKey) {
+        if self.phase == GcPhase::Sweep
+            && self.sweep_dead_strs.remove(&key)
+            && let Some(b) = self.strings.get_mut(key)
+        {
+            b.color = Color::Black;
+        }
+    }
+
+    /// テーブルへ代入するキーを正規化する。インターンしていない長い文字列は、同値の
+    /// インターン済み文字列があればそれに置き換え、無ければその文字列自身をインターンする。
+    ///
+    /// テーブルは文字列キーをハンドルで照合するため、キーに入る文字列は常にインターン済みにしておく。
+    pub fn intern_key(&mut self, key: Value) -> Value {
+        let Some(k) = key.as_string() else {
+            return key;
+        };
+        let Some(b) = self.strings.get(k) else {
+            return key;
+        };
+        if b.value.is_interned() {
+            return key;
+        }
+        if let Some(&canon) = self.interner.get(b.value.as_bytes()) {
+            self.revive_str(canon);
+            return Value::string(canon);
+        }
+        let bytes: Box<[u8]> = b.value.as_bytes().into();
+        let extra = interner_entry_size(&bytes);
+        if let Some(b) = self.strings.get_mut(k) {
+            b.value.set_interned();
+            b.size += extra;
+        }
+        self.total_bytes += extra;
+        self.interner.insert(bytes, k);
+        key
+    }
+
+    /// テーブルを引くキーを正規化する。インターンしていない長い文字列は、同値のインターン済み
+    /// 文字列があればそれに置き換える（無ければどのテーブルのキーでもないので、そのまま返す）。
+    pub fn table_key(&self, key: Value) -> Value {
+        let Some(s) = key.as_string().and_then(|k| self.get_str(k)) else {
+            return key;
+        };
+        if s.is_interned() {
+            return key;
+        }
+        self.interner
+            .get(s.as_bytes())
+            .map_or(key, |&canon| Value::string(canon))
+    }
+
+    /// 生（raw）等価（本家 `luaV_rawequalobj`）。インターンしていない長い文字列は内容で比べる。
+    pub fn raw_equal(&self, a: Value, b: Value) -> bool {
+        if a == b {
+            return true;
+        }
+        match (
+            a.as_string().and_then(|k| self.get_str(k)),
+            b.as_string().and_then(|k| self.get_str(k)),
+        ) {
+            (Some(x), Some(y)) => {
+                !(x.is_interned() && y.is_interned()) && x.as_bytes() == y.as_bytes()
+            }
+            _ => false,
+        }
     }
 
     /// テーブルを確保する。
     pub fn alloc_table(&mut self, table: Table) -> GcHandle {
-        let key = self.tables.insert(GcBox::new(table));
-        self.alloc_count += 1;
-        GcHandle::Table(key)
+        let size = table.heap_size();
+        self.total_bytes += size;
+        let key = self.tables.insert(GcBox::new(table, size));
+        self.track_young(GcHandle::Table(key))
     }
 
     /// クロージャ（Lua/ネイティブ）を確保する。
     pub fn alloc_closure(&mut self, closure: Closure) -> GcHandle {
-        let key = self.closures.insert(GcBox::new(closure));
-        self.alloc_count += 1;
-        GcHandle::Closure(key)
+        if let Closure::Lua(c) = &closure {
+            self.retain_proto(c.proto());
+        }
+        let size = closure.heap_size();
+        self.total_bytes += size;
+        let key = self.closures.insert(GcBox::new(closure, size));
+        self.track_young(GcHandle::Closure(key))
     }
 
-    /// ユーザーデータを確保する。
-    pub fn alloc_userdata(&mut self, ud: Userdata) -> GcHandle {
-        let key = self.userdata.insert(GcBox::new(ud));
-        self.alloc_count += 1;
-        GcHandle::Userdata(key)
+    /// ユーザーデータを確保する。メタテーブル付きで確保したものはこの時点で finalizer 登録とみなす。
+    pub fn alloc_userdata(&mut self, mut ud: Userdata) -> GcHandle {
+        self.next_serial += 1;
+        ud.set_serial(self.next_serial);
+        let size = ud.heap_size();
+        self.total_bytes += size;
+        let key = self.userdata.insert(GcBox::new(ud, size));
+        self.track_young(GcHandle::Userdata(key))
     }
 
     /// コルーチン（スレッド）を確保する。
     pub fn alloc_thread(&mut self, thread: LuaThread) -> GcHandle {
-        let key = self.threads.insert(GcBox::new(thread));
+        let size = thread.heap_size();
+        self.total_bytes += size;
+        let key = self.threads.insert(GcBox::new(thread, size));
+        self.track_young(GcHandle::Thread(key))
+    }
+
+    /// 確保の共通後処理。世代別モードなら minor の sweep 対象に加える。
+    fn track_young(&mut self, handle: GcHandle) -> GcHandle {
         self.alloc_count += 1;
-        GcHandle::Thread(key)
+        if self.generational {
+            self.young.push(handle);
+        }
+        handle
     }
 
     // ---- 参照（access）---------------------------------------------------
@@ -176,8 +479,12 @@ impl Heap {
         self.tables.get(key).map(|b| &b.value)
     }
 
+    /// テーブルの可変参照。書き換えに備えて後退バリアを掛ける（[`Table::set`] 等の前提）。
     pub fn get_table_mut(&mut self, key: TableKey) -> Option<&mut Table> {
-        self.tables.get_mut(key).map(|b| &mut b.value)
+        self.settle_resized(Some(GcHandle::Table(key)));
+        let b = self.tables.get_mut(key)?;
+        barrier_back(self.phase, &mut self.gray_again, b, GcHandle::Table(key));
+        Some(&mut b.value)
     }
 
     pub fn get_closure(&self, key: ClosureKey) -> Option<&Closure> {
@@ -185,7 +492,10 @@ impl Heap {
     }
 
     pub fn get_closure_mut(&mut self, key: ClosureKey) -> Option<&mut Closure> {
-        self.closures.get_mut(key).map(|b| &mut b.value)
+        self.settle_resized(Some(GcHandle::Closure(key)));
+        let b = self.closures.get_mut(key)?;
+        barrier_back(self.phase, &mut self.gray_again, b, GcHandle::Closure(key));
+        Some(&mut b.value)
     }
 
     pub fn get_userdata(&self, key: UserdataKey) -> Option<&Userdata> {
@@ -193,7 +503,10 @@ impl Heap {
     }
 
     pub fn get_userdata_mut(&mut self, key: UserdataKey) -> Option<&mut Userdata> {
-        self.userdata.get_mut(key).map(|b| &mut b.value)
+        self.settle_resized(Some(GcHandle::Userdata(key)));
+        let b = self.userdata.get_mut(key)?;
+        barrier_back(self.phase, &mut self.gray_again, b, GcHandle::Userdata(key));
+        Some(&mut b.value)
     }
 
     pub fn get_thread(&self, key: ThreadKey) -> Option<&LuaThread> {
@@ -201,7 +514,10 @@ impl Heap {
     }
 
     pub fn get_thread_mut(&mut self, key: ThreadKey) -> Option<&mut LuaThread> {
-        self.threads.get_mut(key).map(|b| &mut b.value)
+        self.settle_resized(Some(GcHandle::Thread(key)));
+        let b = self.threads.get_mut(key)?;
+        barrier_back(self.phase, &mut self.gray_again, b, GcHandle::Thread(key));
+        Some(&mut b.value)
     }
 
     /// 現在の生存オブジェクト総数（テスト/デバッグ用）。
@@ -213,88 +529,772 @@ impl Heap {
             + self.threads.len()
     }
 
-    // ---- 回収（mark-and-sweep）------------------------------------------
+    /// 直近のサイクル完了以降に確保したオブジェクト数。
+    pub fn alloc_count(&self) -> usize {
+        self.alloc_count
+    }
+
+    /// 現在の使用バイト数の概算（本家 `g->totalbytes`、`collectgarbage("count")` の元）。
+    ///
+    /// 各オブジェクトの [`HeapSize`] と proto の大きさの合計。直近に書き換えたオブジェクトの
+    /// 伸縮もその場で反映する。
+    pub fn bytes_in_use(&self) -> usize {
+        match self.resized.and_then(|h| self.current_size(h)) {
+            Some((now, recorded)) => self.total_bytes + now - recorded,
+            None => self.total_bytes,
+        }
+    }
+
+    /// 使用メモリの上限（バイト）を設定する。`None` で無制限。
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    /// 使用メモリの上限。
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    /// 組み込み側アロケータへの問い合わせフックを設定する。
+    pub fn set_alloc_hook(&mut self, hook: Option<AllocHook>) {
+        self.alloc_hook = hook;
+    }
+
+    /// 使用量があと `extra` バイト増えても上限とアロケータの許す範囲に収まるか検査する
+    /// （本家 `luaM_realloc_` の失敗経路）。収まらなければ [`LuaError::Memory`]。
+    ///
+    /// 大きな確保（長い文字列の組み立て・テーブルの伸長）の前に呼び、上限を越えて確保しない
+    /// ようにする。回収はしない（[`LuaState::reserve_memory`](crate::state::LuaState::reserve_memory)
+    /// は失敗時に全回収してから再検査する）。
+    pub fn reserve(&mut self, extra: usize) -> LuaResult<()> {
+        if self.memory_limit.is_none() && self.alloc_hook.is_none() {
+            return Ok(());
+        }
+        let used = self.bytes_in_use().saturating_add(extra);
+        if self.memory_limit.is_some_and(|limit| used > limit) {
+            return Err(LuaError::Memory);
+        }
+        if let Some(hook) = &mut self.alloc_hook
+            && !hook(used)
+        {
+            return Err(LuaError::Memory);
+        }
+        Ok(())
+    }
+
+    /// テーブル `key` へ `k` を代入したときに伸長で増えるバイト数（[`Table::insert_growth`]）。
+    ///
+    /// 上限もフックも無ければ見積もらずに 0 を返す。
+    pub fn table_insert_growth(&self, key: TableKey, k: Value) -> usize {
+        if self.memory_limit.is_none() && self.alloc_hook.is_none() {
+            return 0;
+        }
+        let k = self.table_key(k);
+        self.tables
+            .get(key)
+            .map_or(0, |b| b.value.insert_growth(&k))
+    }
+
+    /// オブジェクトの現在のサイズと計上済みサイズ。
+    fn current_size(&self, handle: GcHandle) -> Option<(usize, usize)> {
+        match handle {
+            GcHandle::Str(k) => self.strings.get(k).map(|b| (b.size, b.size)),
+            GcHandle::Table(k) => self.tables.get(k).map(|b| (b.value.heap_size(), b.size)),
+            GcHandle::Closure(k) => self.closures.get(k).map(|b| (b.value.heap_size(), b.size)),
+            GcHandle::Userdata(k) => self.userdata.get(k).map(|b| (b.value.heap_size(), b.size)),
+            GcHandle::Thread(k) => self.threads.get(k).map(|b| (b.value.heap_size(), b.size)),
+        }
+    }
+
+    /// 直近に可変参照を渡したオブジェクトのサイズを計上し直し、`next` を次の対象にする。
+    ///
+    /// 可変参照の借用が終わった後（= 次にヒープを触る時点）で呼ばれるため、書き換え結果が確定している。
+    fn settle_resized(&mut self, next: Option<GcHandle>) {
+        if let Some(h) = std::mem::replace(&mut self.resized, next)
+            && let Some((now, recorded)) = self.current_size(h)
+        {
+            self.total_bytes = self.total_bytes + now - recorded;
+            match h {
+                GcHandle::Str(_) => {}
+                GcHandle::Table(k) => set_size(self.tables.get_mut(k), now),
+                GcHandle::Closure(k) => set_size(self.closures.get_mut(k), now),
+                GcHandle::Userdata(k) => set_size(self.userdata.get_mut(k), now),
+                GcHandle::Thread(k) => set_size(self.threads.get_mut(k), now),
+            }
+        }
+    }
+
+    /// Lua クロージャが参照する proto を計上する（最初の参照時のみ加算）。
+    fn retain_proto(&mut self, proto: &Rc<Proto>) {
+        let entry = self.protos.entry(Rc::as_ptr(proto)).or_insert_with(|| {
+            let size = proto.heap_size();
+            (size, 0)
+        });
+        if entry.1 == 0 {
+            self.total_bytes += entry.0;
+        }
+        entry.1 += 1;
+    }
+
+    /// Lua クロージャの解放に伴い proto の参照を外す（最後の参照なら差し引く）。
+    fn release_proto(&mut self, proto: &Rc<Proto>) {
+        let ptr = Rc::as_ptr(proto);
+        if let Some(entry) = self.protos.get_mut(&ptr) {
+            entry.1 -= 1;
+            if entry.1 == 0 {
+                self.total_bytes -= entry.0;
+                self.stats.bytes_freed += entry.0 as u64;
+                self.protos.remove(&ptr);
+            }
+        }
+    }
+
+    /// 現在の GC 段階。
+    pub fn phase(&self) -> GcPhase {
+        self.phase
+    }
+
+    /// 自動 GC を 1 ステップ進めるべきか（本家 `luaC_checkGC` の `totalbytes >= GCthreshold`）。
+    ///
+    /// サイクル間は使用バイト数が `max(step_threshold, 前回サイクル後の使用量 × pause / 100)` に
+    /// 達したらサイクルを始め、サイクル中は前回ステップ後の使用量 + [`GC_STEP_SIZE`] に達するたびに
+    /// 1 ステップ進める。世代別モードでは `pause` の代わりに `100 + minor_multiplier` を使う。
+    pub fn needs_step(&self, config: &GcConfig) -> bool {
+        if !config.enabled {
+            return false;
+        }
+        let threshold = match (self.phase, config.mode) {
+            (GcPhase::Pause, GcMode::Generational) => config.step_threshold.max(
+                self.estimate
+                    .saturating_mul(100 + config.minor_multiplier as usize)
+                    / 100,
+            ),
+            (GcPhase::Pause, _) => config
+                .step_threshold
+                .max(self.estimate.saturating_mul(config.pause as usize) / 100),
+            _ => self.step_threshold,
+        };
+        self.bytes_in_use() >= threshold
+    }
+
+    /// 次のステップにルート集合が必要か（サイクル開始・atomic 段が起こりうる段階か）。
+    pub fn step_needs_roots(&self) -> bool {
+        self.phase != GcPhase::Sweep
+    }
+
+    /// 前進バリア（本家 `luaC_barrier`）。
+    ///
+    /// ヒープ外のセル（closed upvalue）へ値を書き込む際に呼ぶ。`Propagate` 中と世代別モードでは
+    /// 書き込む値を灰にする（世代別モードでは次の minor で走査される）。
+    pub fn barrier(&mut self, value: Value) {
+        if (self.phase == GcPhase::Propagate || self.generational)
+            && let Some(h) = value.as_gc()
+        {
+            self.shade(h);
+        }
+    }
 
-    /// ルート集合から到達不能なオブジェクトを回収する（stop-the-world）。
+    // ---- 回収（インクリメンタル mark-and-sweep）------------------------------
+
+    /// GC を 1 ステップ進める（本家 `luaC_step`）。サイクルが完了したら true を返す。
+    ///
+    /// 作業量は `GC_STEP_SIZE × stepmul / 100` バイト相当（走査したオブジェクトの大きさ、
+    /// sweep は 1 個あたり [`GC_SWEEP_COST`]、本家と同じ勘定）。
+    /// `roots` は [`Heap::step_needs_roots`] が true のときに現在のルート集合を渡す
+    /// （false なら空でよい）。
+    ///
+    /// `config.mode` が [`GcMode::Generational`] なら minor か major を 1 回行い、常に true を返す。
+    pub fn step(&mut self, config: &GcConfig, roots: Vec<GcHandle>) -> bool {
+        let start = Instant::now();
+        let done = self.run_step(config, roots);
+        self.stats.record_pause(start.elapsed());
+        done
+    }
+
+    fn run_step(&mut self, config: &GcConfig, roots: Vec<GcHandle>) -> bool {
+        self.set_generational(config.mode == GcMode::Generational);
+        if self.generational {
+            let limit = self
+                .major_base
+                .saturating_mul(100 + config.major_multiplier as usize)
+                / 100;
+            if !self.gen_ready || self.bytes_in_use() > limit {
+                self.full_cycle(roots);
+            } else {
+                self.minor_cycle(roots);
+            }
+            return true;
+        }
+        let mut budget = config.step_budget();
+        loop {
+            match self.phase {
+                GcPhase::Pause => {
+                    self.start_cycle(roots.iter().copied());
+                    budget = budget.saturating_sub(1);
+                }
+                GcPhase::Propagate => {
+                    budget = self.propagate(budget);
+                    if !self.gray.is_empty() {
+                        break;
+                    }
+                    self.atomic(roots.iter().copied());
+                    self.begin_sweep(false);
+                }
+                GcPhase::Sweep => {
+                    self.sweep(budget);
+                    if self.sweep_list.is_empty() {
+                        self.finish_cycle();
+                        return true;
+                    }
+                    break;
+                }
+            }
+            if budget == 0 {
+                break;
+            }
+        }
+        self.step_threshold = self.bytes_in_use() + GC_STEP_SIZE;
+        false
+    }
+
+    /// ルート集合から到達不能なオブジェクトを回収する（stop-the-world、本家 `luaC_fullgc`）。
     ///
     /// `roots` は VM スタック・レジストリ・グローバル環境など、生存が保証された
     /// 全ハンドルの列。重複や無効ハンドルが混じっても安全（get が None を返す）。
+    /// 進行中のインクリメンタルサイクルがあれば、マークを捨てて最初からやり直す。
+    /// 世代別モードでは major コレクションになる。
     pub fn collect<I>(&mut self, roots: I)
     where
         I: IntoIterator<Item = GcHandle>,
     {
-        // --- mark ---
-        let mut tracer = Tracer {
-            gray: roots.into_iter().collect(),
+        let start = Instant::now();
+        self.full_cycle(roots.into_iter().collect());
+        self.stats.record_pause(start.elapsed());
+    }
+
+    /// 世代別モードの入切（本家 5.4 `luaC_changemode`）。
+    ///
+    /// 進行中のサイクルは片付けてから切り替える。入れた直後の最初のステップは major になり、
+    /// 生存物をすべて古くする。切ると全オブジェクトを白・若い状態へ戻す。
+    pub fn set_generational(&mut self, on: bool) {
+        if self.generational == on {
+            return;
+        }
+        self.abort_cycle();
+        if !on {
+            self.reset_marks();
+        }
+        self.generational = on;
+        self.gen_ready = false;
+    }
+
+    /// 世代別モードで動いているか。
+    pub fn is_generational(&self) -> bool {
+        self.generational
+    }
+
+    /// オブジェクトが古い（世代別モードで前回のコレクションを生き延びた）か。
+    pub fn is_old(&self, handle: GcHandle) -> bool {
+        let age = match handle {
+            GcHandle::Str(k) => self.strings.get(k).map(|b| b.age),
+            GcHandle::Table(k) => self.tables.get(k).map(|b| b.age),
+            GcHandle::Closure(k) => self.closures.get(k).map(|b| b.age),
+            GcHandle::Userdata(k) => self.userdata.get(k).map(|b| b.age),
+            GcHandle::Thread(k) => self.threads.get(k).map(|b| b.age),
         };
-        while let Some(handle) = tracer.gray.pop() {
-            // 1) 当該スロットを黒化（marked=true）。既に黒なら無視（循環で停止）。
-            // 2) 黒化後に改めて不変参照で trace し、子を灰色集合へ積む。
-            //    set と trace を分離するのは、可変借用と不変借用の競合を避けるため。
+        age == Some(Age::Old)
+    }
+
+    /// 進行中のインクリメンタルサイクルを片付けて `Pause` へ戻す。
+    fn abort_cycle(&mut self) {
+        match self.phase {
+            GcPhase::Pause => {}
+            GcPhase::Propagate => {
+                // 途中までのマークは破棄する（白へ戻すだけで解放はしない）。
+                self.reset_marks();
+                self.phase = GcPhase::Pause;
+            }
+            GcPhase::Sweep => {
+                // atomic 段で確定済みの死骸は解放しきる。
+                self.sweep(usize::MAX);
+                self.finish_cycle();
+            }
+        }
+    }
+
+    /// 全オブジェクトを白・若い状態へ戻し、灰色集合と remembered set を捨てる。
+    fn reset_marks(&mut self) {
+        self.gray.clear();
+        self.gray_again.clear();
+        self.weak.clear();
+        self.young.clear();
+        whiten_all(&mut self.strings);
+        whiten_all(&mut self.tables);
+        whiten_all(&mut self.closures);
+        whiten_all(&mut self.userdata);
+        whiten_all(&mut self.threads);
+    }
+
+    /// 全体を一括で回収する（世代別モードでは major）。
+    fn full_cycle(&mut self, roots: Vec<GcHandle>) {
+        self.abort_cycle();
+        if self.generational {
+            self.reset_marks();
+        }
+        self.start_cycle(roots.iter().copied());
+        self.propagate(usize::MAX);
+        self.atomic(roots.iter().copied());
+        self.begin_sweep(false);
+        self.sweep(usize::MAX);
+        if self.generational {
+            self.major_base = self.bytes_in_use();
+            self.gen_ready = true;
+        }
+        self.finish_cycle();
+    }
+
+    /// 若いオブジェクトだけを一括で回収する（世代別モードの minor）。
+    ///
+    /// 古いオブジェクトは黒なので辿らず、`gray_again`（書き換えられた古いオブジェクトと weak table）
+    /// だけを atomic 段で再走査する。
+    fn minor_cycle(&mut self, roots: Vec<GcHandle>) {
+        self.start_cycle(roots.iter().copied());
+        self.propagate(usize::MAX);
+        self.atomic(roots.iter().copied());
+        self.begin_sweep(true);
+        self.sweep(usize::MAX);
+        self.stats.minor_cycles += 1;
+        self.finish_cycle();
+    }
+
+    /// サイクルを開始する: ルートを灰にする（本家 `markroot`）。
+    fn start_cycle<I: IntoIterator<Item = GcHandle>>(&mut self, roots: I) {
+        for h in roots {
+            self.shade(h);
+        }
+        self.mark_to_finalize();
+        self.phase = GcPhase::Propagate;
+        self.notify(GcEvent::CycleStart);
+    }
+
+    /// 白いオブジェクトを灰にして灰色集合へ積む（本家 `markobject`）。子を持たない文字列は直接黒にする。
+    fn shade(&mut self, handle: GcHandle) {
+        match handle {
+            GcHandle::Str(k) => {
+                if let Some(b) = self.strings.get_mut(k) {
+                    b.color = Color::Black;
+                }
+            }
+            GcHandle::Table(k) => shade_box(self.tables.get_mut(k), &mut self.gray, handle),
+            GcHandle::Closure(k) => shade_box(self.closures.get_mut(k), &mut self.gray, handle),
+            GcHandle::Userdata(k) => shade_box(self.userdata.get_mut(k), &mut self.gray, handle),
+            GcHandle::Thread(k) => shade_box(self.threads.get_mut(k), &mut self.gray, handle),
+        }
+    }
+
+    /// 灰色集合を作業量 `budget` の分だけ走査する（本家 `propagatemark`）。残り作業量を返す。
+    fn propagate(&mut self, mut budget: usize) -> usize {
+        let mut tracer = Tracer::new();
+        while budget > 0 {
+            let Some(handle) = self.gray.pop() else {
+                break;
+            };
+            let cost = self.current_size(handle).map_or(1, |(_, size)| size.max(1));
+            // 黒化してから不変参照で trace し、子を灰にする。
+            // 黒化と trace を分離するのは、可変借用と不変借用の競合を避けるため。
             match handle {
-                GcHandle::Str(k) => {
-                    // 文字列は子を持たないので黒化のみ。
-                    if let Some(b) = self.strings.get_mut(k) {
-                        b.marked = true;
-                    }
+                GcHandle::Str(_) => {}
+                GcHandle::Table(k) => self.traverse_table(k, &mut tracer),
+                GcHandle::Closure(k) => blacken(&mut self.closures, k, &mut tracer),
+                GcHandle::Userdata(k) => blacken(&mut self.userdata, k, &mut tracer),
+                GcHandle::Thread(k) => blacken(&mut self.threads, k, &mut tracer),
+            }
+            for child in tracer.gray.drain(..) {
+                self.shade(child);
+            }
+            budget = budget.saturating_sub(cost);
+        }
+        budget
+    }
+
+    /// atomic 段（本家 `atomic`）: ルートとバリア対象を再走査してマークを確定し、sweep へ移る。
+    ///
+    /// ミューテータが割り込まないよう一括で行う。sweep 対象は呼び出し側が [`Heap::begin_sweep`] で決める。
+    fn atomic<I: IntoIterator<Item = GcHandle>>(&mut self, roots: I) {
+        for h in roots {
+            self.shade(h);
+        }
+        let again = std::mem::take(&mut self.gray_again);
+        self.gray.extend(again);
+        // weak table は灰のまま残してあるので、ここで最新の内容を辿り直す。
+        let weak = std::mem::take(&mut self.weak);
+        self.gray.extend(weak.into_iter().map(GcHandle::Table));
+        self.propagate(usize::MAX);
+        self.converge_ephemerons();
+        // 到達不能になった finalizer 付き userdata を分離し、呼び出しまで生き返らせる。
+        self.separate_finalizable(false);
+        self.mark_to_finalize();
+        self.propagate(usize::MAX);
+        self.converge_ephemerons();
+        self.clear_weak_tables();
+    }
+
+    /// sweep 対象を確定して `Sweep` へ移る。`minor` なら若いオブジェクトだけ、そうでなければ全部。
+    fn begin_sweep(&mut self, minor: bool) {
+        self.sweep_list.clear();
+        if minor {
+            self.sweep_list.append(&mut self.young);
+        } else {
+            self.young.clear();
+            self.sweep_list
+                .extend(self.strings.keys().map(GcHandle::Str));
+            self.sweep_list
+                .extend(self.tables.keys().map(GcHandle::Table));
+            self.sweep_list
+                .extend(self.closures.keys().map(GcHandle::Closure));
+            self.sweep_list
+                .extend(self.userdata.keys().map(GcHandle::Userdata));
+            self.sweep_list
+                .extend(self.threads.keys().map(GcHandle::Thread));
+        }
+        let strings = &self.strings;
+        self.sweep_dead_strs = self
+            .sweep_list
+            .iter()
+            .filter_map(|h| match *h {
+                GcHandle::Str(k) => strings
+                    .get(k)
+                    .filter(|b| b.color == Color::White && b.value.is_interned())
+                    .map(|_| k),
+                _ => None,
+            })
+            .collect();
+        self.phase = GcPhase::Sweep;
+    }
+
+    // ---- finalizer ---------------------------------------------------------
+
+    /// メタテーブル `mt` のフィールド `name` を raw に読む（未インターンなら必ず `nil`）。
+    ///
+    /// 回収中に文字列を確保しないよう、インターナを引くだけにする。
+    fn metafield(&self, mt: Option<GcHandle>, name: &[u8]) -> Value {
+        let Some(GcHandle::Table(mt)) = mt else {
+            return Value::NIL;
+        };
+        let Some(&key) = self.interner.get(name) else {
+            return Value::NIL;
+        };
+        match self.get_table(mt) {
+            Some(t) => t.get(&Value::string(key)),
+            None => Value::NIL,
+        }
+    }
+
+    /// userdata の `__gc` メタメソッド（無ければ `nil`）。
+    pub fn userdata_finalizer(&self, key: UserdataKey) -> Value {
+        match self.get_userdata(key) {
+            Some(ud) => self.metafield(ud.metatable(), b"__gc"),
+            None => Value::NIL,
+        }
+    }
+
+    /// userdata のメタテーブルを設定する（`debug.setmetatable` / `lua_setmetatable`）。
+    ///
+    /// `__gc` を持つメタテーブルなら、その時点を finalizer の登録（本家 5.2 の
+    /// `luaC_checkfinalizer`）とみなして通し番号を振り直す。
+    pub fn set_userdata_metatable(&mut self, key: UserdataKey, mt: Option<GcHandle>) {
+        let serial = if self.metafield(mt, b"__gc").is_nil() {
+            None
+        } else {
+            self.next_serial += 1;
+            Some(self.next_serial)
+        };
+        if let Some(u) = self.get_userdata_mut(key) {
+            u.set_metatable(mt);
+            if let Some(serial) = serial {
+                u.set_serial(serial);
+            }
+        }
+    }
+
+    /// `__gc` を持つ未分離の userdata を `to_finalize` へ移す（本家 `luaC_separateudata`）。
+    ///
+    /// `all` が false なら白（到達不能）のものだけ、true（state の close 時）なら全部を対象にする。
+    /// 後から登録したものから呼ばれるよう、finalizer 登録順（確保時またはメタテーブル設定時に
+    /// 振る通し番号）に並べて末尾に積む。
+    pub fn separate_finalizable(&mut self, all: bool) {
+        let mut found: Vec<(u64, UserdataKey)> = self
+            .userdata
+            .iter()
+            .filter(|(_, b)| {
+                (all || b.color == Color::White)
+                    && !b.value.is_finalized()
+                    && !self.metafield(b.value.metatable(), b"__gc").is_nil()
+            })
+            .map(|(k, b)| (b.value.serial(), k))
+            .collect();
+        found.sort_unstable();
+        for &(_, k) in &found {
+            if let Some(b) = self.userdata.get_mut(k) {
+                b.value.set_finalized();
+            }
+        }
+        // 既存の待ち行列より後（= 先に呼ばれる）に積む。
+        self.to_finalize.extend(found.into_iter().map(|(_, k)| k));
+    }
+
+    /// 呼び出し待ちの userdata を灰にする（本家 `marktmu`）。
+    fn mark_to_finalize(&mut self) {
+        for i in 0..self.to_finalize.len() {
+            self.shade(GcHandle::Userdata(self.to_finalize[i]));
+        }
+    }
+
+    /// 次に `__gc` を呼ぶ userdata を取り出す（無ければ `None`）。
+    ///
+    /// 取り出した値は呼び出し側がスタックに置いてルート保持すること。
+    pub fn take_finalizable(&mut self) -> Option<GcHandle> {
+        self.to_finalize.pop().map(GcHandle::Userdata)
+    }
+
+    // ---- weak table ------------------------------------------------------
+
+    /// テーブルの weak 指定 `(weak_keys, weak_values)` を `__mode` から読む。
+    fn weak_mode(&self, table: &Table) -> (bool, bool) {
+        match self
+            .metafield(table.metatable(), b"__mode")
+            .as_string()
+            .and_then(|k| self.get_str(k))
+        {
+            Some(s) => (s.as_bytes().contains(&b'k'), s.as_bytes().contains(&b'v')),
+            None => (false, false),
+        }
+    }
+
+    /// テーブルを走査する（本家 `traversetable`）。weak table は強参照側だけを辿り、灰のまま登録する。
+    fn traverse_table(&mut self, key: TableKey, tracer: &mut Tracer) {
+        let Some(b) = self.tables.get(key) else {
+            return;
+        };
+        if b.color == Color::Black {
+            return;
+        }
+        let (weak_keys, weak_values) = self.weak_mode(&b.value);
+        if !weak_keys && !weak_values {
+            blacken(&mut self.tables, key, tracer);
+            return;
+        }
+        if let Some(mt) = b.value.metatable() {
+            tracer.mark(mt);
+        }
+        for (k, v) in b.value.entries() {
+            if !weak_keys {
+                tracer.mark_value(&k);
+            }
+            // ephemeron: 値はキーが生存している場合のみ辿る。
+            if !weak_values && (!weak_keys || !self.is_white(&k)) {
+                tracer.mark_value(&v);
+            }
+        }
+        self.weak.push(key);
+    }
+
+    /// weak table の値として見たとき、エントリを消すべきか（本家 `iscleared` の値側）。
+    ///
+    /// finalizer 待ちの userdata も値としては回収対象とみなす（キーとしては残す）。
+    fn is_cleared(&self, value: &Value) -> bool {
+        if let Some(k) = value.as_userdata()
+            && self.userdata.get(k).is_some_and(|b| b.value.is_finalized())
+        {
+            return true;
+        }
+        self.is_white(value)
+    }
+
+    /// 値が白い（未到達の）非文字列オブジェクトか。文字列は値扱いで常に false。
+    fn is_white(&self, value: &Value) -> bool {
+        let Some(h) = value.as_gc() else {
+            return false;
+        };
+        let color = match h {
+            GcHandle::Str(_) => return false,
+            GcHandle::Table(k) => self.tables.get(k).map(|b| b.color),
+            GcHandle::Closure(k) => self.closures.get(k).map(|b| b.color),
+            GcHandle::Userdata(k) => self.userdata.get(k).map(|b| b.color),
+            GcHandle::Thread(k) => self.threads.get(k).map(|b| b.color),
+        };
+        color == Some(Color::White)
+    }
+
+    /// ephemeron の収束: 生存が判明したキーに対応する値を、変化がなくなるまで辿る。
+    fn converge_ephemerons(&mut self) {
+        loop {
+            let mut tracer = Tracer::new();
+            for &key in &self.weak {
+                let Some(b) = self.tables.get(key) else {
+                    continue;
+                };
+                if self.weak_mode(&b.value) != (true, false) {
+                    continue;
                 }
-                GcHandle::Table(k) => {
-                    if mark_box(self.tables.get_mut(k))
-                        && let Some(b) = self.tables.get(k)
-                    {
-                        b.value.trace(&mut tracer);
+                for (k, v) in b.value.entries() {
+                    if !self.is_white(&k) && self.is_white(&v) {
+                        tracer.mark_value(&v);
                     }
                 }
-                GcHandle::Closure(k) => {
-                    if mark_box(self.closures.get_mut(k))
-                        && let Some(b) = self.closures.get(k)
-                    {
-                        b.value.trace(&mut tracer);
-                    }
+            }
+            let found = tracer.into_handles();
+            if found.is_empty() {
+                return;
+            }
+            for h in found {
+                self.shade(h);
+            }
+            self.propagate(usize::MAX);
+        }
+    }
+
+    /// weak table から死んだキー/値を持つエントリを取り除く（本家 `cleartable`）。
+    ///
+    /// `weak` の一覧はサイクル完了時（[`Heap::finish_cycle`]）まで残す。
+    fn clear_weak_tables(&mut self) {
+        for i in 0..self.weak.len() {
+            let key = self.weak[i];
+            let Some(b) = self.tables.get(key) else {
+                continue;
+            };
+            let (weak_keys, weak_values) = self.weak_mode(&b.value);
+            let mut dead = Vec::new();
+            let mut strings = Vec::new();
+            for (k, v) in b.value.entries() {
+                if (weak_keys && self.is_white(&k)) || (weak_values && self.is_cleared(&v)) {
+                    dead.push(k);
+                    continue;
                 }
-                GcHandle::Userdata(k) => {
-                    if mark_box(self.userdata.get_mut(k))
-                        && let Some(b) = self.userdata.get(k)
-                    {
-                        b.value.trace(&mut tracer);
+                // 残るエントリの文字列は辿っていないので、ここで生存させる。
+                for x in [k, v] {
+                    if let Some(k) = x.as_string() {
+                        strings.push(GcHandle::Str(k));
                     }
                 }
-                GcHandle::Thread(k) => {
-                    // スレッドはスタック内の値を子として持つ。
-                    if mark_box(self.threads.get_mut(k))
-                        && let Some(b) = self.threads.get(k)
-                    {
-                        b.value.trace(&mut tracer);
-                    }
+            }
+            for h in strings {
+                self.shade(h);
+            }
+            if let Some(b) = self.tables.get_mut(key) {
+                for k in dead {
+                    let _ = b.value.set(k, Value::NIL);
                 }
             }
         }
+    }
 
-        // --- sweep ---
-        let interner = &mut self.interner;
-        self.strings.retain(|_k, b| {
-            let keep = b.marked;
-            b.marked = false;
-            if !keep {
-                interner.remove(b.value.as_bytes());
+    /// sweep 対象を作業量 `budget` の分だけ処理する（本家 `sweeplist`）。残り作業量を返す。
+    ///
+    /// 白は解放して使用量から差し引き、それ以外は次のサイクルに備えて白へ戻す
+    /// （世代別モードでは黒のまま古くする）。
+    fn sweep(&mut self, mut budget: usize) -> usize {
+        let promote = self.generational;
+        while budget > 0 {
+            let Some(handle) = self.sweep_list.pop() else {
+                break;
+            };
+            let freed = match handle {
+                GcHandle::Str(k) => sweep_slot(&mut self.strings, k, promote).map(|b| {
+                    self.sweep_dead_strs.remove(&k);
+                    if b.value.is_interned() {
+                        self.interner.remove(b.value.as_bytes());
+                    }
+                    self.stats.freed.strings += 1;
+                    b.size
+                }),
+                GcHandle::Table(k) => sweep_slot(&mut self.tables, k, promote).map(|b| {
+                    self.stats.freed.tables += 1;
+                    b.size
+                }),
+                GcHandle::Closure(k) => sweep_slot(&mut self.closures, k, promote).map(|b| {
+                    if let Closure::Lua(c) = &b.value {
+                        self.release_proto(c.proto());
+                    }
+                    self.stats.freed.closures += 1;
+                    b.size
+                }),
+                GcHandle::Userdata(k) => sweep_slot(&mut self.userdata, k, promote).map(|b| {
+                    self.stats.freed.userdata += 1;
+                    b.size
+                }),
+                GcHandle::Thread(k) => sweep_slot(&mut self.threads, k, promote).map(|mut b| {
+                    b.value.stack.close_upvalues();
+                    self.stats.freed.threads += 1;
+                    b.size
+                }),
+            };
+            if let Some(size) = freed {
+                self.total_bytes -= size;
+                self.stats.bytes_freed += size as u64;
             }
-            keep
-        });
-        self.tables.retain(|_k, b| sweep_box(b));
-        self.closures.retain(|_k, b| sweep_box(b));
-        self.userdata.retain(|_k, b| sweep_box(b));
-        self.threads.retain(|_k, b| sweep_box(b));
+            budget = budget.saturating_sub(GC_SWEEP_COST);
+        }
+        budget
+    }
 
+    /// サイクル完了: 次サイクルの起動閾値の基準を更新する。
+    ///
+    /// 世代別モードでは生き残った weak table を灰へ戻して `gray_again` に置き、次の minor でも
+    /// 再走査・掃除させる（古い weak table から若いオブジェクトへの参照はバリアを通らないため）。
+    fn finish_cycle(&mut self) {
+        let mut weak = std::mem::take(&mut self.weak);
+        if self.generational {
+            weak.sort_unstable();
+            weak.dedup();
+            for key in weak {
+                if let Some(b) = self.tables.get_mut(key) {
+                    b.color = Color::Gray;
+                    self.gray_again.push(GcHandle::Table(key));
+                }
+            }
+        }
+        self.phase = GcPhase::Pause;
+        self.sweep_dead_strs.clear();
         self.alloc_count = 0;
+        self.estimate = self.bytes_in_use();
+        self.stats.cycles += 1;
+        self.notify(GcEvent::CycleEnd);
+    }
+
+    /// サイクル通知フックを呼ぶ。
+    fn notify(&mut self, event: GcEvent) {
+        if let Some(cb) = &mut self.callback {
+            cb(event, &self.stats);
+        }
+    }
+
+    // ---- 統計 ------------------------------------------------------------
+
+    /// 累計の GC 統計。
+    pub fn stats(&self) -> &GcStats {
+        &self.stats
+    }
+
+    /// サイクルの開始・終了で呼ぶフックを設定する（`None` で解除）。
+    pub fn set_callback(&mut self, callback: Option<GcCallback>) {
+        self.callback = callback;
+    }
+}
+
+impl HeapSize for LuaThread {
+    fn heap_size(&self) -> usize {
+        std::mem::size_of::<LuaThread>()
+            + self.stack.stack.capacity() * std::mem::size_of::<Value>()
+            + self.stack.call_info.capacity() * std::mem::size_of::<crate::state::CallInfo>()
     }
 }
 
 impl Trace for LuaThread {
     fn trace(&self, tracer: &mut Tracer) {
-        // 保存済みスタック内の GC 値を mark する。
-        for v in &self.saved_stack {
-            tracer.mark_value(v);
-        }
+        // 持っている実行スタック（スタック内の GC 値・フレームのクロージャ・可変長引数・
+        // 退避した実行状態・持ち主のスレッド）を mark する。
+        self.stack.trace(tracer);
         // body 関数も mark する。
         if let Some(v) = &self.body {
             tracer.mark_value(v);
@@ -302,20 +1302,77 @@ impl Trace for LuaThread {
     }
 }
 
-/// `get_mut` の結果を黒化し、「今回新たに黒化した（= trace すべき）」なら true を返す。
-fn mark_box<T>(slot: Option<&mut GcBox<T>>) -> bool {
-    match slot {
-        Some(b) if !b.marked => {
-            b.marked = true;
-            true
-        }
-        _ => false,
+/// 白いオブジェクトを灰にして `gray` へ積む。
+fn shade_box<T>(slot: Option<&mut GcBox<T>>, gray: &mut Vec<GcHandle>, handle: GcHandle) {
+    if let Some(b) = slot
+        && b.color == Color::White
+    {
+        b.color = Color::Gray;
+        gray.push(handle);
+    }
+}
+
+/// 灰色オブジェクトを黒にし、子を `tracer` へ積む。既に黒（重複して積まれていた）なら何もしない。
+fn blacken<K: ArenaKey, T: Trace>(arena: &mut Arena<K, GcBox<T>>, key: K, tracer: &mut Tracer) {
+    let Some(b) = arena.get_mut(key) else {
+        return;
+    };
+    if b.color == Color::Black {
+        return;
     }
+    b.color = Color::Black;
+    b.value.trace(tracer);
 }
 
-/// sweep 用 retain 述語: 生存なら mark を白へ戻して保持、白なら破棄。
-fn sweep_box<T>(b: &mut GcBox<T>) -> bool {
-    let keep = b.marked;
-    b.marked = false;
-    keep
+/// 後退バリア（本家 `luaC_barrierback`）: `Propagate` 中の黒オブジェクトと世代別モードの古いオブジェクトを
+/// 灰へ戻し、atomic 段（世代別モードでは次の minor）で再走査させる。
+fn barrier_back<T>(
+    phase: GcPhase,
+    gray_again: &mut Vec<GcHandle>,
+    b: &mut GcBox<T>,
+    handle: GcHandle,
+) {
+    if (phase == GcPhase::Propagate || b.age == Age::Old) && b.color == Color::Black {
+        b.color = Color::Gray;
+        gray_again.push(handle);
+    }
+}
+
+/// sweep 1 件: 白なら取り除いて返し、それ以外は白へ戻す（`promote` なら黒のまま古くする）。
+fn sweep_slot<K: ArenaKey, T>(
+    arena: &mut Arena<K, GcBox<T>>,
+    key: K,
+    promote: bool,
+) -> Option<GcBox<T>> {
+    let b = arena.get_mut(key)?;
+    if b.color == Color::White {
+        arena.remove(key)
+    } else if promote {
+        b.color = Color::Black;
+        b.age = Age::Old;
+        None
+    } else {
+        b.color = Color::White;
+        None
+    }
+}
+
+/// インターナのエントリ（キーのバイト列の複製 + エントリ）の大きさ。
+fn interner_entry_size(bytes: &[u8]) -> usize {
+    bytes.len() + std::mem::size_of::<(Box<[u8]>, StringKey)>()
+}
+
+/// 計上済みサイズを書き換える。
+fn set_size<T>(slot: Option<&mut GcBox<T>>, size: usize) {
+    if let Some(b) = slot {
+        b.size = size;
+    }
+}
+
+/// 進行中のマークを破棄する（全オブジェクトを白・若い状態へ戻す）。
+fn whiten_all<K: ArenaKey, T>(arena: &mut Arena<K, GcBox<T>>) {
+    for (_, b) in arena.iter_mut() {
+        b.color = Color::White;
+        b.age = Age::Young;
+    }
 }
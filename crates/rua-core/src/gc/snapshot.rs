@@ -0,0 +1,560 @@
+//! ヒープスナップショット（長寿命 state のリーク調査用オブジェクトグラフ）。
+//!
+//! [`LuaState::heap_snapshot`](crate::state::LuaState::heap_snapshot) がルート集合
+//! （[`LuaState::roots`](crate::state::LuaState::roots) に名前を付けたもの）から GC グラフを
+//! 幅優先で辿り、到達可能な各オブジェクトの型・大きさ・名前付きの辺・ルートからの保持経路を記録する。
+//! スナップショットはヒープを読むだけで、GC の色や会計には触れない。
+//!
+//! - 辺の名前: テーブルは値側がキー（`name` / `[1]`）、キー側が `(key)`、関数は upvalue 名・
+//!   `(env)`・`(constant)`、共通で `(metatable)`。weak table の弱い側の辺は `weak` 印付き。
+//! - 保持経路: 強い辺を優先して辿った最短経路。weak 参照でしか届かないオブジェクトだけが
+//!   weak 辺を経由する（次の GC で消えうることが経路から分かる）。
+//! - 出力: [`HeapSnapshot::to_json`] / [`HeapSnapshot::to_dot`]（Graphviz）。
+//! - 差分: [`HeapSnapshot::diff`] が型ごとの増減と、新規・肥大したオブジェクトを保持経路付きで返す。
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fmt::Write as _;
+
+use super::{GcHandle, Heap, Trace, Tracer};
+use crate::value::Value;
+use crate::value::closure::{Closure, UpvalueState};
+use crate::value::convert::number_to_string;
+
+/// 名前の表示に使う文字列の最大バイト数。
+const NAME_LIMIT: usize = 40;
+
+/// スナップショット中の 1 オブジェクト。
+#[derive(Debug, Clone)]
+pub struct SnapshotObject {
+    /// ヒープ上のハンドル。同じ state から取った別のスナップショットとの照合に使う。
+    pub handle: GcHandle,
+    /// Lua の型名（`"table"`, `"function"` 等）。
+    pub kind: &'static str,
+    /// 概算バイト数（[`Heap::bytes_in_use`] と同じ勘定）。
+    pub size: usize,
+    /// 短い説明（文字列の先頭部分、関数の定義位置、weak table の `__mode` 等）。
+    pub name: String,
+    /// このオブジェクトから出る辺。
+    pub edges: Vec<SnapshotEdge>,
+    /// ルートであればその名前（`"_G"`, `"registry"`, `"(stack)"` 等）。
+    pub root: Option<String>,
+    /// 保持経路上の親と、そこからの辺の名前。ルートは `None`。
+    pub retainer: Option<(GcHandle, String)>,
+}
+
+/// 名前付きの辺。
+#[derive(Debug, Clone)]
+pub struct SnapshotEdge {
+    /// 辺の名前（テーブルのキー、upvalue 名など）。
+    pub name: String,
+    /// 参照先。
+    pub to: GcHandle,
+    /// weak table の弱い側の参照か。
+    pub weak: bool,
+}
+
+/// ある時点のヒープのオブジェクトグラフ。
+#[derive(Debug, Clone, Default)]
+pub struct HeapSnapshot {
+    objects: Vec<SnapshotObject>,
+    index: HashMap<GcHandle, usize>,
+}
+
+impl HeapSnapshot {
+    /// 名前付きルート集合から到達可能なオブジェクトを辿ってスナップショットを作る。
+    ///
+    /// 同じハンドルが複数回現れた場合は最初の名前を採る。
+    pub fn take(heap: &Heap, roots: &[(String, GcHandle)]) -> Self {
+        let mut snap = HeapSnapshot::default();
+        let mut queue = VecDeque::new();
+        let mut deferred = Vec::new();
+        for (name, h) in roots {
+            if snap.visit(heap, *h, None) {
+                snap.objects.last_mut().unwrap().root = Some(name.clone());
+                queue.push_back(*h);
+            }
+        }
+        loop {
+            while let Some(h) = queue.pop_front() {
+                let edges = snap.objects[snap.index[&h]].edges.clone();
+                for e in edges {
+                    if e.weak {
+                        deferred.push((h, e));
+                    } else if snap.visit(heap, e.to, Some((h, e.name))) {
+                        queue.push_back(e.to);
+                    }
+                }
+            }
+            // 強い辺で届かなかったものだけ weak 辺から辿る。
+            for (from, e) in std::mem::take(&mut deferred) {
+                if snap.visit(heap, e.to, Some((from, e.name))) {
+                    queue.push_back(e.to);
+                }
+            }
+            if queue.is_empty() {
+                return snap;
+            }
+        }
+    }
+
+    /// 未訪問なら記録して true を返す。
+    fn visit(&mut self, heap: &Heap, h: GcHandle, retainer: Option<(GcHandle, String)>) -> bool {
+        if self.index.contains_key(&h) {
+            return false;
+        }
+        let Some((size, _)) = heap.current_size(h) else {
+            return false;
+        };
+        let (kind, name, edges) = describe(heap, h);
+        self.index.insert(h, self.objects.len());
+        self.objects.push(SnapshotObject {
+            handle: h,
+            kind,
+            size,
+            name,
+            edges,
+            root: None,
+            retainer,
+        });
+        true
+    }
+
+    /// 到達可能な全オブジェクト（幅優先の訪問順）。
+    pub fn objects(&self) -> &[SnapshotObject] {
+        &self.objects
+    }
+
+    /// ハンドルに対応するオブジェクト。
+    pub fn get(&self, handle: GcHandle) -> Option<&SnapshotObject> {
+        self.index.get(&handle).map(|&i| &self.objects[i])
+    }
+
+    /// 到達可能なオブジェクトの合計バイト数。
+    pub fn total_bytes(&self) -> usize {
+        self.objects.iter().map(|o| o.size).sum()
+    }
+
+    /// ルートからオブジェクトまでの保持経路（ルート名に続けて辺の名前を並べたもの）。
+    ///
+    /// 例: `["_G", "cache", "[3]"]`。スナップショットに含まれなければ空。
+    pub fn retaining_path(&self, handle: GcHandle) -> Vec<String> {
+        let mut path = Vec::new();
+        let mut cur = self.get(handle);
+        while let Some(obj) = cur {
+            match &obj.retainer {
+                Some((from, edge)) => {
+                    path.push(edge.clone());
+                    cur = self.get(*from);
+                }
+                None => {
+                    path.extend(obj.root.clone());
+                    break;
+                }
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    /// JSON へ書き出す。
+    ///
+    /// `{"objects":[{"id","type","size","name","root","retainer":{"from","edge"},"edges":[...]}]}`。
+    /// `id` は `"table@3v1"` の形で、同じ state の別スナップショットと照合できる。
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"objects\":[");
+        for (i, o) in self.objects.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "{{\"id\":{},\"type\":\"{}\",\"size\":{},\"name\":{},\"root\":",
+                json_str(&object_id(o.handle)),
+                o.kind,
+                o.size,
+                json_str(&o.name)
+            );
+            match &o.root {
+                Some(r) => out.push_str(&json_str(r)),
+                None => out.push_str("null"),
+            }
+            out.push_str(",\"retainer\":");
+            match &o.retainer {
+                Some((from, edge)) => {
+                    let _ = write!(
+                        out,
+                        "{{\"from\":{},\"edge\":{}}}",
+                        json_str(&object_id(*from)),
+                        json_str(edge)
+                    );
+                }
+                None => out.push_str("null"),
+            }
+            out.push_str(",\"edges\":[");
+            for (j, e) in o.edges.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                let _ = write!(
+                    out,
+                    "{{\"name\":{},\"to\":{},\"weak\":{}}}",
+                    json_str(&e.name),
+                    json_str(&object_id(e.to)),
+                    e.weak
+                );
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// Graphviz dot へ書き出す。ルートは二重枠、weak 辺は破線で描く。
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph heap {\n  node [shape=box];\n");
+        for o in &self.objects {
+            let mut label = format!("{} {} B", o.kind, o.size);
+            if !o.name.is_empty() {
+                label.push('\n');
+                label.push_str(&o.name);
+            }
+            if let Some(r) = &o.root {
+                label = format!("{r}\n{label}");
+            }
+            let _ = writeln!(
+                out,
+                "  {} [label={}{}];",
+                dot_str(&object_id(o.handle)),
+                dot_str(&label),
+                if o.root.is_some() {
+                    ", peripheries=2"
+                } else {
+                    ""
+                }
+            );
+        }
+        for o in &self.objects {
+            for e in &o.edges {
+                if !self.index.contains_key(&e.to) {
+                    continue;
+                }
+                let _ = writeln!(
+                    out,
+                    "  {} -> {} [label={}{}];",
+                    dot_str(&object_id(o.handle)),
+                    dot_str(&object_id(e.to)),
+                    dot_str(&e.name),
+                    if e.weak { ", style=dashed" } else { "" }
+                );
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// `before`（以前のスナップショット）からの増減を求める。`self` が新しい側。
+    pub fn diff(&self, before: &HeapSnapshot) -> SnapshotDiff {
+        let mut kinds: Vec<KindDelta> = Vec::new();
+        let mut tally = |kind: &'static str, size: usize, after: bool| {
+            let pos = match kinds.iter().position(|k| k.kind == kind) {
+                Some(p) => p,
+                None => {
+                    kinds.push(KindDelta {
+                        kind,
+                        ..KindDelta::default()
+                    });
+                    kinds.len() - 1
+                }
+            };
+            let k = &mut kinds[pos];
+            if after {
+                k.count_after += 1;
+                k.bytes_after += size;
+            } else {
+                k.count_before += 1;
+                k.bytes_before += size;
+            }
+        };
+        for o in &before.objects {
+            tally(o.kind, o.size, false);
+        }
+        let mut added = Vec::new();
+        let mut grown = Vec::new();
+        for o in &self.objects {
+            tally(o.kind, o.size, true);
+            let entry = |size_before| DiffEntry {
+                handle: o.handle,
+                kind: o.kind,
+                name: o.name.clone(),
+                size_before,
+                size_after: o.size,
+                path: self.retaining_path(o.handle),
+            };
+            match before.get(o.handle) {
+                None => added.push(entry(0)),
+                Some(old) if old.size < o.size => grown.push(entry(old.size)),
+                Some(_) => {}
+            }
+        }
+        let removed = before
+            .objects
+            .iter()
+            .filter(|o| !self.index.contains_key(&o.handle))
+            .count();
+        kinds.sort_by_key(|k| std::cmp::Reverse(k.bytes_after as isize - k.bytes_before as isize));
+        added.sort_by_key(|e| std::cmp::Reverse(e.size_after));
+        grown.sort_by_key(|e| std::cmp::Reverse(e.size_after - e.size_before));
+        SnapshotDiff {
+            kinds,
+            added,
+            grown,
+            removed,
+        }
+    }
+}
+
+/// 2 つのスナップショットの差分（[`HeapSnapshot::diff`]）。
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDiff {
+    /// 型ごとの件数・バイト数の変化（バイト増加の大きい順）。
+    pub kinds: Vec<KindDelta>,
+    /// 新しく現れたオブジェクト（大きい順）。
+    pub added: Vec<DiffEntry>,
+    /// 大きくなったオブジェクト（増分の大きい順）。
+    pub grown: Vec<DiffEntry>,
+    /// 消えたオブジェクトの数。
+    pub removed: usize,
+}
+
+/// 型ごとの増減。
+#[derive(Debug, Clone, Default)]
+pub struct KindDelta {
+    pub kind: &'static str,
+    pub count_before: usize,
+    pub count_after: usize,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+}
+
+/// 新規または肥大したオブジェクト。
+#[derive(Debug, Clone)]
+pub struct DiffEntry {
+    pub handle: GcHandle,
+    pub kind: &'static str,
+    pub name: String,
+    /// 以前の大きさ（新規なら 0）。
+    pub size_before: usize,
+    pub size_after: usize,
+    /// 新しい側のスナップショットでの保持経路。
+    pub path: Vec<String>,
+}
+
+impl fmt::Display for SnapshotDiff {
+    /// 人が読む要約。オブジェクトの列挙は上位 20 件まで。
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<10} {:>16} {:>20}", "type", "count", "bytes")?;
+        for k in &self.kinds {
+            writeln!(
+                f,
+                "{:<10} {:>16} {:>20}",
+                k.kind,
+                format!(
+                    "{:+} ({})",
+                    k.count_after as isize - k.count_before as isize,
+                    k.count_after
+                ),
+                format!(
+                    "{:+} ({})",
+                    k.bytes_after as isize - k.bytes_before as isize,
+                    k.bytes_after
+                ),
+            )?;
+        }
+        writeln!(f, "removed: {}", self.removed)?;
+        for (title, entries) in [("added", &self.added), ("grown", &self.grown)] {
+            writeln!(f, "{title}: {}", entries.len())?;
+            for e in entries.iter().take(20) {
+                writeln!(
+                    f,
+                    "  {:+} {} {} {}",
+                    e.size_after - e.size_before,
+                    object_id(e.handle),
+                    e.path.join(" -> "),
+                    e.name
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// オブジェクトの型名・説明・辺を求める。
+fn describe(heap: &Heap, h: GcHandle) -> (&'static str, String, Vec<SnapshotEdge>) {
+    let mut edges = Vec::new();
+    let mut edge = |name: String, v: &Value, weak: bool| {
+        if let Some(to) = v.as_gc() {
+            edges.push(SnapshotEdge { name, to, weak });
+        }
+    };
+    let (kind, name) = match h {
+        GcHandle::Str(k) => {
+            let name = heap.get_str(k).map(|s| preview(s.as_bytes()));
+            ("string", name.unwrap_or_default())
+        }
+        GcHandle::Table(k) => {
+            let Some(t) = heap.get_table(k) else {
+                return ("table", String::new(), edges);
+            };
+            let (weak_keys, weak_values) = heap.weak_mode(t);
+            for (key, value) in t.entries() {
+                // 文字列キーは強参照（本家同様に weak の対象外）。
+                edge("(key)".to_string(), &key, weak_keys && !is_str(&key));
+                edge(key_name(heap, &key), &value, weak_values && !is_str(&value));
+            }
+            if let Some(mt) = t.metatable() {
+                edge("(metatable)".to_string(), &Value::gc(mt), false);
+            }
+            let mode = match (weak_keys, weak_values) {
+                (true, true) => "__mode=kv",
+                (true, false) => "__mode=k",
+                (false, true) => "__mode=v",
+                (false, false) => "",
+            };
+            ("table", mode.to_string())
+        }
+        GcHandle::Closure(k) => match heap.get_closure(k) {
+            Some(Closure::Lua(c)) => {
+                let proto = c.proto();
+                edge("(env)".to_string(), &Value::gc(c.env()), false);
+                for (i, uv) in c.upvalues().iter().enumerate() {
+                    if let UpvalueState::Closed(v) = &*uv.borrow() {
+                        let name = proto
+                            .upvalue_names
+                            .get(i)
+                            .cloned()
+                            .unwrap_or_else(|| format!("upvalue {}", i + 1));
+                        edge(name, v, false);
+                    }
+                }
+                let mut tracer = Tracer::new();
+                proto.trace_constants(&mut tracer);
+                for c in tracer.into_handles() {
+                    edge("(constant)".to_string(), &Value::gc(c), false);
+                }
+                let source = proto.source.as_deref().unwrap_or("?");
+                ("function", format!("{}:{}", source, proto.line_defined))
+            }
+            Some(Closure::Native(c)) => {
+                for (i, v) in c.upvalues().iter().enumerate() {
+                    edge(format!("upvalue {}", i + 1), v, false);
+                }
+                ("function", "[native]".to_string())
+            }
+            None => ("function", String::new()),
+        },
+        GcHandle::Userdata(k) => {
+            if let Some(u) = heap.get_userdata(k) {
+                if let Some(mt) = u.metatable() {
+                    edge("(metatable)".to_string(), &Value::gc(mt), false);
+                }
+                if let Some(env) = u.env() {
+                    edge("(env)".to_string(), &Value::gc(env), false);
+                }
+            }
+            ("userdata", String::new())
+        }
+        GcHandle::Thread(k) => {
+            if let Some(t) = heap.get_thread(k) {
+                let mut tracer = Tracer::new();
+                t.trace(&mut tracer);
+                for c in tracer.into_handles() {
+                    edge("(stack)".to_string(), &Value::gc(c), false);
+                }
+            }
+            ("thread", String::new())
+        }
+    };
+    (kind, name, edges)
+}
+
+fn is_str(v: &Value) -> bool {
+    v.as_string().is_some()
+}
+
+/// テーブルのキーを辺の名前にする（文字列はそのまま、それ以外は `[...]`）。
+fn key_name(heap: &Heap, key: &Value) -> String {
+    if let Some(k) = key.as_string() {
+        heap.get_str(k)
+            .map(|s| preview(s.as_bytes()))
+            .unwrap_or_default()
+    } else if let Some(n) = key.as_number() {
+        format!("[{}]", number_to_string(n))
+    } else if let Some(b) = key.as_boolean() {
+        format!("[{b}]")
+    } else if let Some(h) = key.as_gc() {
+        format!("[{}]", object_id(h))
+    } else {
+        "[?]".to_string()
+    }
+}
+
+/// 先頭 [`NAME_LIMIT`] バイトを表示用に切り出す。
+fn preview(bytes: &[u8]) -> String {
+    let mut s = String::from_utf8_lossy(&bytes[..bytes.len().min(NAME_LIMIT)]).into_owned();
+    if bytes.len() > NAME_LIMIT {
+        s.push_str("...");
+    }
+    s
+}
+
+/// 出力用のオブジェクト ID（`"table@3v1"`）。
+fn object_id(h: GcHandle) -> String {
+    let kind = match h {
+        GcHandle::Str(_) => "string",
+        GcHandle::Table(_) => "table",
+        GcHandle::Closure(_) => "function",
+        GcHandle::Userdata(_) => "userdata",
+        GcHandle::Thread(_) => "thread",
+    };
+    let bits = h.key_bits();
+    format!("{kind}@{}v{}", bits as u32, bits >> 32)
+}
+
+/// JSON 文字列リテラルにする。
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// dot の引用文字列にする（改行は `\n` のまま渡す）。
+fn dot_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,67 @@
+//! GC の統計とサイクル通知（組み込み側の監視用、本家にはない rua 拡張）。
+//!
+//! [`Heap`](super::Heap) が回収のたびに [`GcStats`] を更新する。停止時間は
+//! [`Heap::collect`](super::Heap::collect) / [`Heap::step`](super::Heap::step) 1 回の所要時間
+//! （＝ミューテータが止まっていた時間）を測る。サイクルの開始と終了で [`GcCallback`] を呼ぶ。
+
+use std::time::Duration;
+
+/// アリーナ別の件数。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArenaCounts {
+    pub strings: u64,
+    pub tables: u64,
+    pub closures: u64,
+    pub userdata: u64,
+    pub threads: u64,
+}
+
+impl ArenaCounts {
+    /// 全アリーナの合計。
+    pub fn total(&self) -> u64 {
+        self.strings + self.tables + self.closures + self.userdata + self.threads
+    }
+}
+
+/// 状態の生成以降の累計統計。
+#[derive(Debug, Clone, Default)]
+pub struct GcStats {
+    /// 完了したサイクル数（minor を含む）。
+    pub cycles: u64,
+    /// うち世代別モードの minor コレクションの数。
+    pub minor_cycles: u64,
+    /// 解放したオブジェクト数（アリーナ別）。
+    pub freed: ArenaCounts,
+    /// 解放したバイト数（[`Heap::bytes_in_use`](super::Heap::bytes_in_use) と同じ勘定）。
+    pub bytes_freed: u64,
+    /// GC による停止（`collect` / `step` の呼び出し）の回数。
+    pub pauses: u64,
+    /// 停止時間の合計。
+    pub total_pause: Duration,
+    /// 最長の停止時間。
+    pub max_pause: Duration,
+    /// 直近の停止時間。
+    pub last_pause: Duration,
+}
+
+impl GcStats {
+    /// 停止 1 回分を記録する。
+    pub(crate) fn record_pause(&mut self, pause: Duration) {
+        self.pauses += 1;
+        self.total_pause += pause;
+        self.max_pause = self.max_pause.max(pause);
+        self.last_pause = pause;
+    }
+}
+
+/// [`GcCallback`] へ渡すサイクルの節目。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcEvent {
+    /// ルートのマークを始めた（本家 `GCSpause` を抜けた時点）。
+    CycleStart,
+    /// sweep を終えてサイクルが完了した。統計は更新済み。
+    CycleEnd,
+}
+
+/// サイクルの開始・終了で呼ばれるフック。ヒープには触れられない（統計だけを受け取る）。
+pub type GcCallback = Box<dyn FnMut(GcEvent, &GcStats)>;
@@ -11,7 +11,6 @@
 //! 実際のフレーム積み下ろしは lua-vm（`vm::interp`）が実装し、ここから委譲する。
 //!
 //! TODO(lua-runtime/lua-vm):
-//!   - `precall`/`postcall`（フレーム構築・戻り値整列, 本家 `luaD_precall`/`luaD_poscall`）。
 //!   - コルーチン `resume`/`yield`（本家 `lua_resume`/`lua_yield`）。Rust では VM ループを
 //!     再開可能な状態機械、もしくは別スタックで表現する（設計は VM 立ち上げ後に確定）。
 //!   - スタックのオーバーフロー検査と伸長（本家 `luaD_growstack`）。
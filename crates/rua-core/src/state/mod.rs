@@ -6,24 +6,38 @@
 //!   （本家 `lua_State`）。コルーチンは複数の [`LuaState`] が 1 つの [`GlobalState`] を共有する。
 //!
 //! # GC ルート（ARCHITECTURE.md §5）
-//! ルート集合 = 各スレッドの VM スタック上の生存値 + レジストリ + グローバル環境。
+//! ルート集合 = VM スタック上の生存値 + 各コールフレーム（実行中クロージャ・可変長引数・
+//! yield で退避した実行状態）+ レジストリ + グローバル環境 + 型共有メタテーブル。
+//! 中断中コルーチンのスタック/フレームはスレッドオブジェクトの [`Trace`] 経由で辿る。
 //! [`LuaState::collect_garbage`] がこれらを集めて [`Heap::collect`](crate::gc::Heap::collect) を呼ぶ。
 //!
-//! # 所有モデル（第一マイルストーン時点の決定）
-//! 本マイルストーンでは C へ `lua_State*` を渡さないため、`lua_State` のポインタ安定性制約は無い。
-//! よって `LuaState` が `GlobalState` を**直接所有**する単純構成を採る（Rust の借用検査で安全）。
-//! 第二マイルストーン（C API）で `lua_State*` を不透明安定ポインタとして公開する際、
-//! `Box`/ピン留め + 共有 `global_State`（`Rc`/`Arc`）へ再構成する。TODO(lua-runtime/lua-capi)。
+//! ネイティブ関数は、Lua を呼び戻す間も保持し続ける値を**スタック上に置く**こと
+//! （本家 C 関数と同じ規約）。Rust のローカル変数だけが持つ値は自動 GC のルートにならない。
+//!
+//! # 所有モデル
+//! `LuaState` は `GlobalState` を**直接所有**する（Rust の借用検査で安全）。各スレッドの実行スタック
+//! （VM スタック + コールスタック, [`ThreadStack`]）は独立しており、実行中のスレッドのものだけが
+//! `LuaState` の `stack`/`call_info` にある。コルーチンの resume/yield は
+//! [`LuaState::switch_thread`] でスレッドオブジェクトが持つ実行スタックと入れ替える
+//! （`global_State` を共有したまま `lua_State` を切り替えるのと同じ）。
+//! スタックのインデックスはスレッドごとに 0 から始まるので、どこから resume しても変わらない。
 
 pub mod call;
 
+use std::any::Any;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
 
+use crate::compiler::Lang;
+use crate::error::{LuaError, LuaResult};
 use crate::gc::Heap;
-use crate::gc::alloc::GcConfig;
-use crate::gc::{ClosureKey, GcHandle};
+use crate::gc::alloc::{GC_STEP_SIZE, GcConfig, GcMode};
+use crate::gc::snapshot::HeapSnapshot;
+use crate::gc::{ClosureKey, GcHandle, ThreadKey, Trace, Tracer};
 use crate::value::Value;
-use crate::value::closure::{Closure, Upvalue};
+use crate::value::closure::{Closure, Upvalue, UpvalueState};
 use crate::value::table::Table;
 use crate::vm::proto::Proto;
 
@@ -36,55 +50,171 @@ use crate::vm::proto::Proto;
 /// TODO(lua-vm/lua-stdlib): 引数アクセス用のヘルパ API（`L.arg(n)` 等）を整備する。
 pub type NativeFn = fn(&mut LuaState) -> crate::error::LuaResult<i32>;
 
+/// ネイティブ関数の継続（本家 5.2 `lua_KFunction` 相当）。
+///
+/// 第 2 引数は継続の原因になった呼び出しの結果。[`vm::call_k`](crate::vm::call_k) なら常に `Ok`、
+/// [`vm::pcall_k`](crate::vm::pcall_k) なら捕捉したエラーも `Err` で渡る。第 3 引数は yield 時に
+/// 保存したコンテキスト。戻り値は [`NativeFn`] と同じくスタックに積んだ戻り値の個数。
+pub type ContinuationFn =
+    fn(&mut LuaState, LuaResult<Vec<Value>>, Box<dyn Any>) -> crate::error::LuaResult<i32>;
+
+/// yield で中断したネイティブ関数フレームの再開点。
+///
+/// [`vm::call_k`](crate::vm::call_k)/[`vm::pcall_k`](crate::vm::pcall_k) で呼んだ関数が yield すると
+/// 呼び出し元ネイティブ関数の [`CallInfo`] に置かれ、resume 時に
+/// [`vm::resume_execute`](crate::vm::resume_execute) が `func` を呼んで処理の続きを行う。
+///
+/// `ctx` は GC のルートにならない。再開後も使う Lua 値はネイティブ関数のスタックに置くこと。
+pub struct Continuation {
+    /// 再開時に呼ぶ継続関数。
+    pub func: ContinuationFn,
+    /// yield 時点の処理状態（継続関数が downcast して使う）。
+    pub ctx: Box<dyn Any>,
+    /// 保護呼び出し（[`vm::pcall_k`](crate::vm::pcall_k)）なら、呼び出し時点のスタックの深さ。
+    /// 再開後に起きたエラーはこの深さまで巻き戻してから継続関数へ渡す。
+    pub protected_depth: Option<usize>,
+}
+
+impl std::fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Continuation")
+            .field("protected_depth", &self.protected_depth)
+            .finish_non_exhaustive()
+    }
+}
+
 /// コルーチン yield/resume 時に Lua フレームの実行状態を保存するための構造体。
 ///
 /// `execute` ループがコルーチン yield を検出した際、次回 resume で再開するために
-/// その時点のローカル変数（pc・proto・upvalue・vararg・open upvalue・スタックトップ）を
+/// その時点のローカル変数（pc・proto・upvalue・open upvalue・スタックトップ）を
 /// この構造体へ退避する。resume 時に `vm::interp::resume_execute` が読み取って復元する。
 #[derive(Debug, Clone)]
 pub struct LuaFrameState {
-    /// yield を発生させた CALL 命令の pc（proto.code のインデックス）。
-    /// resume 時にこの命令を再読みして結果レジスタを特定し、resume 引数を配置する。
+    /// 呼び出し中の命令の pc（proto.code のインデックス）。`CALL` のほか、yield した
+    /// メタメソッドを呼んだ命令（`GETTABLE`・算術・比較・`CONCAT`・`TFORLOOP` など）もある。
+    /// resume 時にこの命令を再読みし、結果を置いて命令の残りを終える（本家 5.2 `luaV_finishOp`）。
     pub resume_call_pc: usize,
     /// 現在実行中のプロトタイプ。
     pub proto: Rc<Proto>,
-    /// このフレームの upvalue 群。
-    pub upvals: Vec<Upvalue>,
-    /// このフレームの可変長引数。
-    pub varargs: Vec<Value>,
+    /// このフレームの upvalue 群（実行中クロージャの配列を共有する）。
+    pub upvals: Rc<[Upvalue]>,
     /// open upvalue リスト（絶対スタックインデックス → Upvalue セル）。
     pub open: Vec<(usize, Upvalue)>,
     /// 多値操作で動くスタックトップ（絶対インデックス）。
     pub top: usize,
     /// このフレームの関数環境テーブル（Lua 5.1 の fenv）。
     pub env: crate::gc::GcHandle,
+    /// `LE` を `__lt` の `not (b < a)` で評価している途中か（再開時に結果を反転する,
+    /// 本家 5.2 `CIST_LEQ`）。
+    pub le_by_lt: bool,
 }
 
 /// コールスタックのフレーム（本家 `CallInfo` 相当）。
 ///
 /// 関数呼び出しごとに 1 つ積まれ、スタック上の関数位置・ベース・戻り先などを記録する。
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct CallInfo {
     /// このフレームのスタックベース（最初のローカル/引数のインデックス）。
     pub base: usize,
-    /// 呼び出された関数値のスタック位置。
+    /// 呼び出された関数値のスタック位置。引数はその直後に積まれ、戻り値もここから置かれる。
     pub func: usize,
     /// 期待される戻り値の数（`LUA_MULTRET` 相当は将来表現）。
     pub expected_results: usize,
-    /// 実行中プロトタイプの整形済みソース名（本家 `short_src`）。ネイティブ関数フレームは `None`。
-    pub source: Option<String>,
     /// このフレームで現在（または直近のサブ呼び出し時点で）実行中の命令のソース行。
     /// Lua フレームでは VM が逐次更新する。ネイティブ関数フレームは 0。
     pub current_line: u32,
+    /// このフレームで現在（または直近のサブ呼び出し時点で）実行中の命令の pc（本家 `currentpc`）。
+    /// 有効なローカル変数の判定（`debug.getlocal`）に使う。ネイティブ関数フレームは 0。
+    pub current_pc: usize,
     /// ネイティブクロージャフレームの場合、実行中のクロージャのヒープキーを保持する。
     pub native_closure: Option<crate::gc::ClosureKey>,
-    /// コルーチン yield 時に保存する Lua フレームの実行状態。
-    /// Lua クロージャフレームが yield で中断されたときのみ `Some`。
-    pub lua_frame: Option<Box<LuaFrameState>>,
+    /// Lua クロージャフレームの場合、実行中のクロージャのヒープキー（TCO で差し替わる）。
+    /// 呼び出し元レジスタから消えた関数でも、実行中は GC ルートとして生存させる。
+    pub lua_closure: Option<crate::gc::ClosureKey>,
+    /// このフレームの可変長引数（`...`）の個数。固定引数を超えた実引数で、値は
+    /// `stack[base - nvarargs..base]` に置かれている（本家 5.1 `adjust_varargs` と同じ配置）。
+    pub nvarargs: usize,
+    /// 退避した Lua フレームの実行状態。このフレームから Lua 関数を呼んでいる間と、
+    /// yield で中断されている間だけ `Some`。
+    pub lua_frame: Option<LuaFrameState>,
     /// このフレームが Lua クロージャである場合の関数環境テーブルハンドル。
     /// `setfenv`/`getfenv` がレベル指定でフレームを辿るために使う。
     /// ネイティブ関数フレームは `None`。
     pub env: Option<crate::gc::GcHandle>,
+    /// このフレームで行った末尾呼び出しの回数（`TailReturn` フックの発生回数, 本家 `tailcalls`）。
+    pub tail_calls: u32,
+    /// yield で中断したネイティブ関数フレームの再開点（[`Continuation`]）。
+    pub continuation: Option<Continuation>,
+}
+
+impl Trace for CallInfo {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(k) = self.native_closure {
+            tracer.mark(GcHandle::Closure(k));
+        }
+        if let Some(k) = self.lua_closure {
+            tracer.mark(GcHandle::Closure(k));
+        }
+        if let Some(env) = self.env {
+            tracer.mark(env);
+        }
+        if let Some(frame) = &self.lua_frame {
+            frame.proto.trace_constants(tracer);
+            tracer.mark(frame.env);
+            for uv in frame.upvals.iter() {
+                if let UpvalueState::Closed(v) = &*uv.borrow() {
+                    tracer.mark_value(v);
+                }
+            }
+        }
+    }
+}
+
+/// 1 スレッドの実行スタック（本家 `lua_State` の `stack`/`base_ci`）とデバッグフック。
+///
+/// 実行中のスレッドのものは [`LuaState`] の `stack`/`call_info`/`thread`/`hook` にあり、
+/// それ以外はスレッドオブジェクト（[`LuaThread`](crate::value::thread::LuaThread)）が持つ。
+#[derive(Debug, Default)]
+pub struct ThreadStack {
+    /// VM 値スタック。
+    pub stack: Vec<Value>,
+    /// コールスタック。
+    pub call_info: Vec<CallInfo>,
+    /// このスタックを持つスレッド。メインスレッドは `None`。
+    pub thread: Option<ThreadKey>,
+    /// このスレッドのデバッグフック。
+    pub hook: HookState,
+}
+
+impl ThreadStack {
+    /// フレームが退避している open upvalue をすべて閉じる（スレッドの解放時,
+    /// 本家 `luaE_freethread` の `luaF_close`）。
+    pub fn close_upvalues(&mut self) {
+        for ci in &mut self.call_info {
+            let Some(frame) = ci.lua_frame.as_mut() else {
+                continue;
+            };
+            for (idx, uv) in frame.open.drain(..) {
+                let v = self.stack.get(idx).copied().unwrap_or(Value::NIL);
+                *uv.borrow_mut() = UpvalueState::Closed(v);
+            }
+        }
+    }
+}
+
+impl Trace for ThreadStack {
+    fn trace(&self, tracer: &mut Tracer) {
+        for v in &self.stack {
+            tracer.mark_value(v);
+        }
+        for ci in &self.call_info {
+            ci.trace(tracer);
+        }
+        if let Some(k) = self.thread {
+            tracer.mark(GcHandle::Thread(k));
+        }
+        tracer.mark_value(&self.hook.data);
+    }
 }
 
 /// 全スレッド共有の状態（本家 `global_State`）。
@@ -112,8 +242,16 @@ pub struct GlobalState {
     pub nil_metatable: Option<GcHandle>,
     /// GC 起動設定。
     pub gc_config: GcConfig,
+    /// VM スタックの上限（スロット数、本家 5.2 以降の `LUAI_MAXSTACK`）。Lua 関数のフレームが
+    /// これを超えると `"stack overflow"` を送出する。Lua 同士の再帰の深さはこれで決まる。
+    pub stack_limit: usize,
+    /// `load`/`loadstring`/`dofile`/`require` がソースをコンパイルするときの言語水準。
+    pub lang: Lang,
 }
 
+/// [`GlobalState::stack_limit`] の既定値（本家 `LUAI_MAXSTACK`）。
+pub const DEFAULT_STACK_LIMIT: usize = 1_000_000;
+
 impl GlobalState {
     /// レジストリとグローバル環境を確保した初期 `global_State` を作る。
     pub fn new() -> Self {
@@ -129,6 +267,8 @@ impl GlobalState {
             boolean_metatable: None,
             nil_metatable: None,
             gc_config: GcConfig::default(),
+            stack_limit: DEFAULT_STACK_LIMIT,
+            lang: Lang::default(),
         }
     }
 }
@@ -139,14 +279,125 @@ impl Default for GlobalState {
     }
 }
 
+/// デバッグフックのイベント（本家 `LUA_HOOKCALL` 等）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// 関数の呼び出し（フレームを積んだ直後）。末尾呼び出しでフレームを差し替えた直後も
+    /// これになる（本家 5.1 `luaD_precall`。5.2 の `"tail call"` イベントは無い）。
+    Call,
+    /// 関数からの復帰（フレームを降ろす直前）。
+    Return,
+    /// 末尾呼び出しで消えたフレームの復帰。`Return` の後に末尾呼び出しの回数だけ続く。
+    TailReturn,
+    /// 新しい行の実行開始（値は行番号）。後方ジャンプでも同じ行で再度発生する。
+    Line(u32),
+    /// [`HookState::base_count`] 命令ごとの発生。
+    Count,
+}
+
+impl HookEvent {
+    /// `debug.sethook` のフック関数へ渡すイベント名。
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::Call => "call",
+            HookEvent::Return => "return",
+            HookEvent::TailReturn => "tail return",
+            HookEvent::Line(_) => "line",
+            HookEvent::Count => "count",
+        }
+    }
+}
+
+/// フック関数（本家 `lua_Hook`）。発生元のフレームは `call_info` の末尾にある。
+pub type HookFn = fn(&mut LuaState, HookEvent) -> LuaResult<()>;
+
+/// [`HookState::mask`] のビット（本家 `LUA_MASKCALL` 等）。
+pub const HOOK_MASK_CALL: u8 = 1 << 0;
+/// `Return` / `TailReturn` イベント。
+pub const HOOK_MASK_RET: u8 = 1 << 1;
+/// `Line` イベント。
+pub const HOOK_MASK_LINE: u8 = 1 << 2;
+/// `Count` イベント。
+pub const HOOK_MASK_COUNT: u8 = 1 << 3;
+
+/// スレッドのデバッグフック設定（本家 `lua_State` の `hook`/`hookmask`/`hookcount`）。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HookState {
+    /// フック関数。`None` ならフック無し。
+    pub func: Option<HookFn>,
+    /// 有効なイベントのビット集合（`HOOK_MASK_*`）。
+    pub mask: u8,
+    /// `Count` イベントの間隔（命令数）。
+    pub base_count: u32,
+    /// 次の `Count` イベントまでの残り命令数。
+    pub count: u32,
+    /// フック実行中（本家 `allowhook` の否定）。フックの中ではフックを呼ばない。
+    pub running: bool,
+    /// フックが使う値（`debug.sethook` に渡した Lua 関数）。GC のルートになる。
+    pub data: Value,
+}
+
+/// VM が時刻を確認する間隔（命令数）。`Instant::now` を毎命令呼ばないための間引き。
+pub const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+/// 実行の期限（[`LuaState::deadline`]）。
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    /// 期限の時刻。
+    pub at: Instant,
+    /// スクリプトの `pcall` で捕捉できるか。既定は false（埋め込み側まで伝播する）。
+    pub catchable: bool,
+    /// 次に時刻を確認するまでの残り命令数。
+    pub countdown: u32,
+}
+
+impl Deadline {
+    /// `at` を期限とする。最初の命令の実行前に一度時刻を確認する。
+    pub fn new(at: Instant, catchable: bool) -> Self {
+        Deadline {
+            at,
+            catchable,
+            countdown: 1,
+        }
+    }
+}
+
 /// 1 実行スレッド（本家 `lua_State`）。VM スタックとコールスタックを持つ。
 pub struct LuaState {
-    /// 共有グローバル状態。第一マイルストーンでは直接所有（上記「所有モデル」参照）。
+    /// 共有グローバル状態（上記「所有モデル」参照）。
     pub global: GlobalState,
-    /// VM 値スタック（レジスタ機械のレジスタ領域）。
+    /// 実行中スレッドの VM 値スタック（レジスタ機械のレジスタ領域）。
     pub stack: Vec<Value>,
-    /// コールスタック（本家の CallInfo 配列）。
+    /// 実行中スレッドのコールスタック（本家の CallInfo 配列）。
     pub call_info: Vec<CallInfo>,
+    /// 実行中のスレッド（コルーチン）。メインスレッドなら `None`。
+    pub thread: Option<ThreadKey>,
+    /// [`vm::call`](crate::vm::call) のネスト深度（本家 `nCcalls`）。
+    /// [`MAX_C_CALLS`](crate::vm::interp::MAX_C_CALLS) で Rust 再帰を制限する。
+    pub n_ccalls: usize,
+    /// 実行中スレッドのデバッグフック（[`LuaState::set_hook`]）。
+    pub hook: HookState,
+    /// 残りの実行可能命令数（サンドボックス用, 本家には無い）。`None` は無制限。
+    /// Lua の命令を 1 つ実行するごとに減り、使い切ると
+    /// [`InterruptReason::InstructionLimit`](crate::error::InterruptReason::InstructionLimit) を送出する。
+    pub instruction_budget: Option<u64>,
+    /// 外部（別スレッド）から立てる中断フラグ。立っていれば次の命令の実行前に
+    /// [`InterruptReason::Requested`](crate::error::InterruptReason::Requested) を送出する。
+    /// フラグはエラーを受け取った埋め込み側が下ろす（[`api::Lua`](crate::api::Lua) は呼び出しから戻るときに下ろす）。
+    pub interrupt: Option<Arc<AtomicBool>>,
+    /// 実行の期限（壁時計）。過ぎると
+    /// [`InterruptReason::Deadline`](crate::error::InterruptReason::Deadline) を送出する。
+    /// 捕捉できる期限は送出時に `None` に戻り、捕捉できない期限は設定した側が外すまで残る。
+    pub deadline: Option<Deadline>,
+    /// エラーが Lua フレームを巻き戻し始める時点でスタックトレースバックを記録するか
+    /// （本家 `lua.c` が `docall` に渡す `traceback` メッセージハンドラ相当）。
+    pub traceback_on_error: bool,
+    /// 記録したトレースバック（未捕捉エラーの表示用）。スクリプト側の `pcall`/`xpcall`/
+    /// `coroutine.resume` がエラーを捕捉すると破棄される。
+    pub error_traceback: Option<String>,
+    /// 実行中のスクリプト側の保護呼び出し（`pcall`/`xpcall`/`coroutine.resume`/`__gc`）の数。
+    /// 0 より大きい間のエラーは捕捉されるので、トレースバックを記録しない。
+    pub protected_calls: usize,
 }
 
 impl LuaState {
@@ -156,51 +407,336 @@ impl LuaState {
             global: GlobalState::new(),
             stack: Vec::new(),
             call_info: Vec::new(),
+            thread: None,
+            n_ccalls: 0,
+            hook: HookState::default(),
+            instruction_budget: None,
+            interrupt: None,
+            deadline: None,
+            traceback_on_error: false,
+            error_traceback: None,
+            protected_calls: 0,
+        }
+    }
+
+    /// 実行中スレッドのデバッグフックを設定する（本家 `lua_sethook`）。`func` が `None` か `mask` が 0 なら解除。
+    ///
+    /// `count` は `HOOK_MASK_COUNT` の間隔（命令数）で、0 なら `Count` イベントを無効にする。
+    pub fn set_hook(&mut self, func: Option<HookFn>, mut mask: u8, count: u32) {
+        if count == 0 {
+            mask &= !HOOK_MASK_COUNT;
+        }
+        if func.is_none() || mask == 0 {
+            self.hook = HookState {
+                running: self.hook.running,
+                ..HookState::default()
+            };
+            return;
+        }
+        self.hook.func = func;
+        self.hook.mask = mask;
+        self.hook.base_count = count;
+        self.hook.count = count;
+    }
+
+    /// 実行中のスレッドの実行スタックを `other` と入れ替える（resume/yield のスレッド切り替え）。
+    ///
+    /// 呼び出し後は `other` が切り替え前のスレッドの実行スタックを持つ。
+    pub fn switch_thread(&mut self, other: &mut ThreadStack) {
+        std::mem::swap(&mut self.stack, &mut other.stack);
+        std::mem::swap(&mut self.call_info, &mut other.call_info);
+        std::mem::swap(&mut self.thread, &mut other.thread);
+        std::mem::swap(&mut self.hook, &mut other.hook);
+    }
+
+    /// スレッド `thread`（`None` はメインスレッド）の VM スタックを返す。
+    ///
+    /// 実行中のスレッドなら `self.stack`、中断中のコルーチンならそのスレッドオブジェクトが持つもの。
+    /// 実行中のコルーチンを（間接的に）resume した側のスレッドのものは、resume したコルーチンの
+    /// スレッドオブジェクトを辿って探す。open upvalue が別スレッドのローカルを指すときに使う。
+    pub fn stack_of(&self, thread: Option<ThreadKey>) -> Option<&[Value]> {
+        self.locate_stack(thread).map(|owner| match owner {
+            Some(k) => self
+                .global
+                .heap
+                .get_thread(k)
+                .map_or(&[] as &[Value], |th| &th.stack.stack),
+            None => &self.stack,
+        })
+    }
+
+    /// [`LuaState::stack_of`] の書き込み版。スロットが無ければ何もしない。
+    pub fn set_stack_slot(&mut self, thread: Option<ThreadKey>, idx: usize, v: Value) {
+        let slot = match self.locate_stack(thread) {
+            Some(None) => self.stack.get_mut(idx),
+            Some(Some(k)) => {
+                // スレッドオブジェクトへの書き込み（get_thread_mut が後退バリアを掛ける）。
+                self.global
+                    .heap
+                    .get_thread_mut(k)
+                    .and_then(|th| th.stack.stack.get_mut(idx))
+            }
+            None => None,
+        };
+        if let Some(slot) = slot {
+            *slot = v;
+        }
+    }
+
+    /// スレッド `thread`（`None` はメインスレッド）の実行スタックへ一時的に切り替えて `f` を呼ぶ
+    /// （`debug.traceback(co)` などの他スレッドの検査用）。
+    ///
+    /// 実行スタックが見つからない（正常に終了したコルーチンなど）なら `None`。`f` の間に GC を
+    /// 起こしたりスタックの深さを変えたまま戻ったりしないこと。
+    pub fn with_thread<R>(
+        &mut self,
+        thread: Option<ThreadKey>,
+        f: impl FnOnce(&mut LuaState) -> R,
+    ) -> Option<R> {
+        let holder = match self.locate_stack(thread)? {
+            None => return Some(f(self)),
+            Some(k) => k,
+        };
+        let mut stack = std::mem::take(&mut self.global.heap.get_thread_mut(holder)?.stack);
+        self.switch_thread(&mut stack);
+        self.global.heap.get_thread_mut(holder)?.stack = stack;
+        let r = f(self);
+        let th = self.global.heap.get_thread_mut(holder)?;
+        let mut stack = std::mem::take(&mut th.stack);
+        self.switch_thread(&mut stack);
+        if let Some(th) = self.global.heap.get_thread_mut(holder) {
+            th.stack = stack;
+        }
+        Some(r)
+    }
+
+    /// スレッド `thread` の VM スタックの置き場所。`Some(None)` は `self.stack`、
+    /// `Some(Some(k))` はスレッドオブジェクト `k` が持つ実行スタック。
+    fn locate_stack(&self, thread: Option<ThreadKey>) -> Option<Option<ThreadKey>> {
+        if thread == self.thread {
+            return Some(None);
+        }
+        let holds = |k: ThreadKey| {
+            self.global
+                .heap
+                .get_thread(k)
+                .is_some_and(|th| th.stack.thread == thread)
+        };
+        if let Some(k) = thread
+            && holds(k)
+        {
+            return Some(Some(k));
+        }
+        let mut cur = self.thread;
+        while let Some(k) = cur {
+            if holds(k) {
+                return Some(Some(k));
+            }
+            cur = self.global.heap.get_thread(k)?.stack.thread;
         }
+        None
     }
 
-    /// バイト列をインターンして Lua 文字列値を得る（よく使うため state 経由のショートカット）。
+    /// バイト列から Lua 文字列値を得る（よく使うため state 経由のショートカット）。
+    /// 短い文字列はインターンし、長い文字列はインターンしない（[`Heap::alloc_str`](crate::gc::Heap::alloc_str)）。
     pub fn new_string(&mut self, bytes: &[u8]) -> Value {
-        Value::GcRef(self.global.heap.intern_str(bytes))
+        Value::gc(self.global.heap.alloc_str(bytes))
+    }
+
+    /// 組み立て済みのバッファから Lua 文字列値を得る。長い文字列はバッファを複製しない。
+    pub fn new_string_from(&mut self, buf: Vec<u8>) -> Value {
+        Value::gc(self.global.heap.alloc_str_from(buf))
     }
 
     /// 新しいテーブルを確保して値を返す。
     pub fn new_table(&mut self) -> Value {
-        Value::GcRef(self.global.heap.alloc_table(Table::new()))
+        Value::gc(self.global.heap.alloc_table(Table::new()))
     }
 
     /// このスレッドの現在の GC ルート集合を列挙する。
     ///
-    /// ルート = レジストリ + グローバル環境 + VM スタック上の全 GC 値。
-    /// TODO(lua-runtime): コルーチン対応時は全生存スレッドのスタックを合算する。
+    /// ルート = レジストリ + グローバル環境 + 型共有メタテーブル + VM スタック上の全 GC 値、
+    /// および各コールフレームの実行中クロージャ・可変長引数・退避済み実行状態と、実行中のスレッド。
+    /// open upvalue はスタックスロットを指すためスタック経由で到達する。中断中コルーチンと、
+    /// 実行中コルーチンを resume した側のスレッドの実行スタックはスレッドオブジェクトの
+    /// [`Trace`] 経由で到達する。
     pub fn roots(&self) -> Vec<GcHandle> {
-        let mut roots = Vec::with_capacity(self.stack.len() + 3);
-        roots.push(self.global.registry);
-        roots.push(self.global.globals);
-        if let Some(mt) = self.global.string_metatable {
-            roots.push(mt);
+        let mut tracer = Tracer::new();
+        tracer.mark(self.global.registry);
+        tracer.mark(self.global.globals);
+        for mt in [
+            self.global.string_metatable,
+            self.global.number_metatable,
+            self.global.boolean_metatable,
+            self.global.nil_metatable,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            tracer.mark(mt);
         }
-        if let Some(mt) = self.global.number_metatable {
-            roots.push(mt);
+        for v in &self.stack {
+            tracer.mark_value(v);
         }
-        if let Some(mt) = self.global.boolean_metatable {
-            roots.push(mt);
+        for ci in &self.call_info {
+            ci.trace(&mut tracer);
         }
-        if let Some(mt) = self.global.nil_metatable {
-            roots.push(mt);
+        if let Some(k) = self.thread {
+            tracer.mark(GcHandle::Thread(k));
         }
-        for v in &self.stack {
-            if let Value::GcRef(h) = v {
-                roots.push(*h);
+        tracer.mark_value(&self.hook.data);
+        tracer.into_handles()
+    }
+
+    /// ルート集合から辿ったヒープのスナップショットを取る（リーク調査用）。
+    ///
+    /// ルートには `_G`・`registry`・型共有メタテーブル名を付け、スタックとコールフレーム
+    /// から届くものは `(stack)` とする。保持経路はこの順で優先される。
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        let mut roots = vec![
+            ("_G".to_string(), self.global.globals),
+            ("registry".to_string(), self.global.registry),
+        ];
+        for (name, mt) in [
+            ("string metatable", self.global.string_metatable),
+            ("number metatable", self.global.number_metatable),
+            ("boolean metatable", self.global.boolean_metatable),
+            ("nil metatable", self.global.nil_metatable),
+        ] {
+            if let Some(h) = mt {
+                roots.push((name.to_string(), h));
+            }
+        }
+        for h in self.roots() {
+            if !roots.iter().any(|(_, r)| *r == h) {
+                roots.push(("(stack)".to_string(), h));
             }
         }
-        roots
+        HeapSnapshot::take(&self.global.heap, &roots)
     }
 
-    /// ルート集合から到達不能なオブジェクトを回収する（stop-the-world）。
-    pub fn collect_garbage(&mut self) {
+    /// ルート集合から到達不能なオブジェクトを回収する（stop-the-world、本家 `luaC_fullgc`）。
+    ///
+    /// [`GcMode::Generational`] では major コレクションになる。
+    /// 回収で分離された userdata の `__gc` もこの中で呼ぶ（[`LuaState::call_finalizers`]）。
+    pub fn collect_garbage(&mut self) -> LuaResult<()> {
+        let generational = self.global.gc_config.mode == GcMode::Generational;
+        self.global.heap.set_generational(generational);
         let roots = self.roots();
         self.global.heap.collect(roots);
+        self.call_finalizers()
+    }
+
+    /// GC を 1 ステップ進める（本家 `luaC_step`）。サイクルが完了したら true を返す。
+    pub fn gc_step(&mut self) -> LuaResult<bool> {
+        let roots = if self.global.heap.step_needs_roots() {
+            self.roots()
+        } else {
+            Vec::new()
+        };
+        let done = self.global.heap.step(&self.global.gc_config, roots);
+        self.call_finalizers()?;
+        Ok(done)
+    }
+
+    /// 分離済み userdata の `__gc` を新しいものから順に呼ぶ（本家 `luaC_callGCTM`）。
+    ///
+    /// 各 finalizer は保護呼び出しで実行し、エラーは GC を起こした箇所へ伝播させずに捨てる。
+    /// ただし打ち切り（[`LuaError::Interrupted`]）は捨てずにそのまま返す（捕捉できる期限切れも、
+    /// スクリプトの `pcall` が見られるよう GC を起こした箇所へ伝播させる）。
+    /// 残りの finalizer は待ち行列に残り、次の呼び出しで実行される。
+    /// 呼び出し中に起きた GC が分離した分もこのループで続けて呼ぶ。
+    pub fn call_finalizers(&mut self) -> LuaResult<()> {
+        while let Some(h) = self.global.heap.take_finalizable() {
+            let GcHandle::Userdata(k) = h else {
+                continue;
+            };
+            let gc = self.global.heap.userdata_finalizer(k);
+            if gc.is_nil() {
+                continue;
+            }
+            // 待ち行列から外れた userdata は、呼び出し中スタック上に置いてルート保持する。
+            let ud = Value::gc(h);
+            self.stack.push(ud);
+            let depth = self.stack.len();
+            self.protected_calls += 1;
+            let res = call::pcall(self, |s| crate::vm::call(s, gc, &[ud]));
+            self.protected_calls -= 1;
+            self.stack.truncate(depth - 1);
+            match res {
+                Err(e @ LuaError::Interrupted(_)) => return Err(e),
+                Err(_) => self.error_traceback = None,
+                Ok(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// state を閉じる（本家 `lua_close`）: 残っている全 userdata の `__gc` を呼ぶ。
+    ///
+    /// スタックとコールフレームを空にしてから、到達可能かどうかに関わらず `__gc` を持つ
+    /// 未 finalize の userdata をすべて finalize する。2 回目以降の呼び出しは何もしない。
+    /// 打ち切られた finalizer があっても、残りの finalizer は続けて呼ぶ。
+    pub fn close(&mut self) {
+        self.stack.clear();
+        self.call_info.clear();
+        self.global.heap.separate_finalizable(true);
+        while self.call_finalizers().is_err() {}
+    }
+
+    /// `collectgarbage("step", n)` / `lua_gc(LUA_GCSTEP)` の本体。
+    ///
+    /// 少なくとも 1 ステップ、`n` KB の確保に相当するだけ進める。
+    /// 途中でサイクルが完了したら true を返す。
+    pub fn gc_step_by(&mut self, n: usize) -> LuaResult<bool> {
+        let mut debt = n.saturating_mul(1024);
+        loop {
+            if self.gc_step()? {
+                return Ok(true);
+            }
+            if debt < GC_STEP_SIZE {
+                return Ok(false);
+            }
+            debt -= GC_STEP_SIZE;
+        }
+    }
+
+    /// 確保量が閾値を超えていれば GC を進める（本家 `luaC_checkGC`）。
+    ///
+    /// VM の安全点（全生存値がスタック・コールフレーム・レジストリから到達可能な時点）
+    /// でのみ呼ぶこと。[`GcConfig::enabled`] が false の間は GC を進めず、
+    /// メモリ上限の検査（[`LuaState::check_memory`]）だけを行う。
+    pub fn check_gc(&mut self) -> LuaResult<()> {
+        if self.global.heap.needs_step(&self.global.gc_config) {
+            match self.global.gc_config.mode {
+                GcMode::Incremental | GcMode::Generational => {
+                    self.gc_step()?;
+                }
+                GcMode::StopTheWorld => self.collect_garbage()?,
+            }
+        }
+        self.check_memory()
+    }
+
+    /// 使用量が上限内か検査する（本家 `luaM_realloc_` の失敗経路）。
+    ///
+    /// [`Heap::memory_limit`](crate::gc::Heap::memory_limit) を超えるかアロケータのフックが
+    /// 拒否したら、まず全回収を行い、それでも収まらなければ [`LuaError::Memory`](crate::error::LuaError::Memory) を返す。
+    /// `GcConfig::enabled` に関わらず回収する（緊急 GC）。安全点でのみ呼ぶこと。
+    pub fn check_memory(&mut self) -> LuaResult<()> {
+        self.reserve_memory(0)
+    }
+
+    /// これから `extra` バイト確保できるか検査する（[`Heap::reserve`](crate::gc::Heap::reserve)）。
+    ///
+    /// 収まらなければ全回収してから再検査し、それでも駄目なら [`LuaError::Memory`](crate::error::LuaError::Memory)。
+    /// 回収するので、生存値がすべてスタック・レジストリから到達できる時点でのみ呼ぶこと。
+    pub fn reserve_memory(&mut self, extra: usize) -> LuaResult<()> {
+        if self.global.heap.reserve(extra).is_ok() {
+            return Ok(());
+        }
+        self.collect_garbage()?;
+        self.global.heap.reserve(extra)
     }
 
     // -------------------------------------------------------------------------
@@ -230,6 +766,14 @@ impl LuaState {
         self.call_info.last()?.native_closure
     }
 
+    /// コールフレーム `ci` の整形済みソース名（本家 `short_src`）。ネイティブ関数フレームは `None`。
+    pub fn frame_source(&self, ci: &CallInfo) -> Option<String> {
+        match self.global.heap.get_closure(ci.lua_closure?)? {
+            Closure::Lua(lc) => Some(crate::vm::interp::short_src(lc.proto().source.as_deref())),
+            Closure::Native(_) => None,
+        }
+    }
+
     /// 現在実行中のネイティブクロージャの `i` 番目の upvalue を返す（0-origin）。
     ///
     /// 本家 `lua_upvalueindex(i)` の内部実装補助。
@@ -240,7 +784,7 @@ impl LuaState {
     /// ```ignore
     /// // stdlib 関数内での使用例
     /// fn my_native(state: &mut LuaState) -> LuaResult<i32> {
-    ///     let upv0 = state.current_upvalue(0).unwrap_or(Value::Nil);
+    ///     let upv0 = state.current_upvalue(0).unwrap_or(Value::NIL);
     ///     // upv0 を使って処理...
     ///     Ok(0)
     /// }
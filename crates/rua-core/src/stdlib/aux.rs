@@ -9,8 +9,6 @@
 //! 本モジュールはこの規約を扱いやすくするヘルパ（引数取得・戻り値設定・型検査・
 //! エラー生成・メタフィールド取得・`tostring`/比較）をまとめる。
 
-use slotmap::Key;
-
 use crate::error::{LuaError, LuaResult};
 use crate::gc::{GcHandle, TableKey, ThreadKey};
 use crate::state::LuaState;
@@ -54,7 +52,7 @@ pub fn ret0(state: &mut LuaState) -> LuaResult<i32> {
 
 /// `args` の `i` 番目（0 始まり）。範囲外は `nil`。
 pub fn opt_value(args: &[Value], i: usize) -> Value {
-    args.get(i).copied().unwrap_or(Value::Nil)
+    args.get(i).copied().unwrap_or(Value::NIL)
 }
 
 // ============================================================================
@@ -80,7 +78,7 @@ fn type_arg_error(
     expected: &str,
     got: Value,
 ) -> LuaError {
-    let got_name = if matches!(got, Value::Nil) {
+    let got_name = if got.is_nil() {
         "no value"
     } else {
         got.type_of().name()
@@ -104,33 +102,29 @@ pub fn check_str_bytes(
     i: usize,
     fname: &str,
 ) -> LuaResult<Vec<u8>> {
-    match opt_value(args, i) {
-        Value::GcRef(GcHandle::Str(k)) => {
-            Ok(state.global.heap.get_str(k).unwrap().as_bytes().to_vec())
-        }
-        Value::Number(n) => Ok(number_to_string(n).into_bytes()),
-        other => Err(type_arg_error(state, i + 1, fname, "string", other)),
+    let v = opt_value(args, i);
+    if let Some(k) = v.as_string() {
+        Ok(state.global.heap.get_str(k).unwrap().as_bytes().to_vec())
+    } else if let Some(n) = v.as_number() {
+        Ok(number_to_string(n).into_bytes())
+    } else {
+        Err(type_arg_error(state, i + 1, fname, "string", v))
     }
 }
 
 /// 数値を取得する（数値そのもの、または数値に見える文字列を変換）。
 pub fn check_number(state: &mut LuaState, args: &[Value], i: usize, fname: &str) -> LuaResult<f64> {
-    match opt_value(args, i) {
-        Value::Number(n) => Ok(n),
-        Value::GcRef(GcHandle::Str(k)) => {
-            let bytes = state.global.heap.get_str(k).unwrap().as_bytes().to_vec();
-            match str_to_number(&bytes) {
-                Some(n) => Ok(n),
-                None => Err(type_arg_error(
-                    state,
-                    i + 1,
-                    fname,
-                    "number",
-                    opt_value(args, i),
-                )),
-            }
-        }
-        other => Err(type_arg_error(state, i + 1, fname, "number", other)),
+    let v = opt_value(args, i);
+    if let Some(n) = v.as_number() {
+        return Ok(n);
+    }
+    let converted = v.as_string().and_then(|k| {
+        let bytes = state.global.heap.get_str(k).unwrap().as_bytes().to_vec();
+        str_to_number(&bytes)
+    });
+    match converted {
+        Some(n) => Ok(n),
+        None => Err(type_arg_error(state, i + 1, fname, "number", v)),
     }
 }
 
@@ -142,7 +136,7 @@ pub fn opt_number(
     fname: &str,
     default: f64,
 ) -> LuaResult<f64> {
-    if matches!(opt_value(args, i), Value::Nil) {
+    if opt_value(args, i).is_nil() {
         Ok(default)
     } else {
         check_number(state, args, i, fname)
@@ -162,7 +156,7 @@ pub fn opt_int(
     fname: &str,
     default: i64,
 ) -> LuaResult<i64> {
-    if matches!(opt_value(args, i), Value::Nil) {
+    if opt_value(args, i).is_nil() {
         Ok(default)
     } else {
         check_int(state, args, i, fname)
@@ -176,9 +170,10 @@ pub fn check_table(
     i: usize,
     fname: &str,
 ) -> LuaResult<TableKey> {
-    match opt_value(args, i) {
-        Value::GcRef(GcHandle::Table(k)) => Ok(k),
-        other => Err(type_arg_error(state, i + 1, fname, "table", other)),
+    let v = opt_value(args, i);
+    match v.as_table() {
+        Some(k) => Ok(k),
+        None => Err(type_arg_error(state, i + 1, fname, "table", v)),
     }
 }
 
@@ -189,9 +184,11 @@ pub fn check_function(
     i: usize,
     fname: &str,
 ) -> LuaResult<Value> {
-    match opt_value(args, i) {
-        v @ Value::GcRef(GcHandle::Closure(_)) => Ok(v),
-        other => Err(type_arg_error(state, i + 1, fname, "function", other)),
+    let v = opt_value(args, i);
+    if v.as_closure().is_some() {
+        Ok(v)
+    } else {
+        Err(type_arg_error(state, i + 1, fname, "function", v))
     }
 }
 
@@ -202,9 +199,10 @@ pub fn check_thread(
     i: usize,
     fname: &str,
 ) -> LuaResult<ThreadKey> {
-    match opt_value(args, i) {
-        Value::GcRef(GcHandle::Thread(k)) => Ok(k),
-        other => Err(type_arg_error(state, i + 1, fname, "coroutine", other)),
+    let v = opt_value(args, i);
+    match v.as_thread() {
+        Some(k) => Ok(k),
+        None => Err(type_arg_error(state, i + 1, fname, "coroutine", v)),
     }
 }
 
@@ -214,12 +212,8 @@ pub fn check_thread(
 
 /// 文字列値ならバイト列を返す。
 pub fn str_bytes(state: &LuaState, v: Value) -> Option<Vec<u8>> {
-    match v {
-        Value::GcRef(GcHandle::Str(k)) => {
-            state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
-        }
-        _ => None,
-    }
+    let k = v.as_string()?;
+    state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
 }
 
 // ============================================================================
@@ -232,7 +226,7 @@ pub fn make_native(state: &mut LuaState, f: NativeFn) -> Value {
         .global
         .heap
         .alloc_closure(Closure::Native(NativeClosure::new(f)));
-    Value::GcRef(h)
+    Value::gc(h)
 }
 
 /// `NativeFn` を upvalue 付きクロージャとして確保する（`coroutine.wrap` 等で使用）。
@@ -244,13 +238,13 @@ pub fn make_native_with_upvalue(state: &mut LuaState, f: NativeFn, upval: Value)
             f,
             vec![upval],
         )));
-    Value::GcRef(h)
+    Value::gc(h)
 }
 
 /// グローバル/ライブラリテーブル `tk` に `name = f`（ネイティブ関数）を登録する。
 pub fn register(state: &mut LuaState, tk: TableKey, name: &str, f: NativeFn) {
     let fval = make_native(state, f);
-    let key = state.new_string(name.as_bytes());
+    let key = Value::gc(state.global.heap.intern_str(name.as_bytes()));
     if let Some(t) = state.global.heap.get_table_mut(tk) {
         let _ = t.set(key, fval);
     }
@@ -258,7 +252,7 @@ pub fn register(state: &mut LuaState, tk: TableKey, name: &str, f: NativeFn) {
 
 /// テーブル `tk` に `name = v` を登録する。
 pub fn set_field(state: &mut LuaState, tk: TableKey, name: &str, v: Value) {
-    let key = state.new_string(name.as_bytes());
+    let key = Value::gc(state.global.heap.intern_str(name.as_bytes()));
     if let Some(t) = state.global.heap.get_table_mut(tk) {
         let _ = t.set(key, v);
     }
@@ -270,21 +264,21 @@ pub fn set_field(state: &mut LuaState, tk: TableKey, name: &str, v: Value) {
 
 /// 値のメタテーブル（テーブルキー）を返す。
 pub fn metatable_handle(state: &LuaState, v: Value) -> Option<TableKey> {
-    let mt = match v {
-        Value::GcRef(GcHandle::Table(k)) => {
-            state.global.heap.get_table(k).and_then(|t| t.metatable())
-        }
-        Value::GcRef(GcHandle::Userdata(k)) => state
-            .global
-            .heap
-            .get_userdata(k)
+    let mt = match v.type_of() {
+        LuaType::Table => v
+            .as_table()
+            .and_then(|k| state.global.heap.get_table(k))
+            .and_then(|t| t.metatable()),
+        LuaType::Userdata => v
+            .as_userdata()
+            .and_then(|k| state.global.heap.get_userdata(k))
             .and_then(|u| u.metatable()),
         // 文字列は型共有メタテーブルを参照（VM の `metatable_of` と整合）。
-        Value::GcRef(GcHandle::Str(_)) => state.global.string_metatable,
+        LuaType::String => state.global.string_metatable,
         // 数値・boolean・nil の型共有メタテーブル（debug.setmetatable で設定）。
-        Value::Number(_) => state.global.number_metatable,
-        Value::Boolean(_) => state.global.boolean_metatable,
-        Value::Nil => state.global.nil_metatable,
+        LuaType::Number => state.global.number_metatable,
+        LuaType::Boolean => state.global.boolean_metatable,
+        LuaType::Nil => state.global.nil_metatable,
         _ => None,
     };
     match mt {
@@ -296,43 +290,30 @@ pub fn metatable_handle(state: &LuaState, v: Value) -> Option<TableKey> {
 /// 値のメタフィールド（イベント名のハンドラ）を取得（無ければ `nil`）。
 pub fn get_metafield(state: &mut LuaState, v: Value, event: &str) -> Value {
     let Some(mtk) = metatable_handle(state, v) else {
-        return Value::Nil;
+        return Value::NIL;
     };
-    let key = state.new_string(event.as_bytes());
+    let key = Value::gc(state.global.heap.intern_str(event.as_bytes()));
     state
         .global
         .heap
         .get_table(mtk)
         .map(|t| t.get(&key))
-        .unwrap_or(Value::Nil)
+        .unwrap_or(Value::NIL)
 }
 
 // ============================================================================
 // tostring（__tostring 込み）
 // ============================================================================
 
-/// GC オブジェクトの疑似アドレス（`tostring` 表示用）。
-fn gc_addr(h: GcHandle) -> u64 {
-    match h {
-        GcHandle::Str(k) => k.data().as_ffi(),
-        GcHandle::Table(k) => k.data().as_ffi(),
-        GcHandle::Closure(k) => k.data().as_ffi(),
-        GcHandle::Userdata(k) => k.data().as_ffi(),
-        GcHandle::Thread(k) => k.data().as_ffi(),
-    }
-}
-
 /// 値を `tostring` のバイト列へ変換する（`__tostring` メタメソッドを尊重）。
 pub fn tostring_value(state: &mut LuaState, v: Value) -> LuaResult<Vec<u8>> {
     let mm = get_metafield(state, v, "__tostring");
-    if !matches!(mm, Value::Nil) {
+    if !mm.is_nil() {
         let res = crate::vm::call(state, mm, &[v])?;
-        let first = res.into_iter().next().unwrap_or(Value::Nil);
-        return match first {
-            Value::GcRef(GcHandle::Str(k)) => {
-                Ok(state.global.heap.get_str(k).unwrap().as_bytes().to_vec())
-            }
-            _ => Err(rt_error(state, "'__tostring' must return a string")),
+        let first = res.into_iter().next().unwrap_or(Value::NIL);
+        return match first.as_string() {
+            Some(k) => Ok(state.global.heap.get_str(k).unwrap().as_bytes().to_vec()),
+            None => Err(rt_error(state, "'__tostring' must return a string")),
         };
     }
     Ok(raw_tostring(state, v))
@@ -340,27 +321,30 @@ pub fn tostring_value(state: &mut LuaState, v: Value) -> LuaResult<Vec<u8>> {
 
 /// メタメソッド非経由の既定 `tostring`。
 pub fn raw_tostring(state: &LuaState, v: Value) -> Vec<u8> {
-    match v {
-        Value::Nil => b"nil".to_vec(),
-        Value::Boolean(true) => b"true".to_vec(),
-        Value::Boolean(false) => b"false".to_vec(),
-        Value::Number(n) => number_to_string(n).into_bytes(),
-        Value::LightUserData(p) => format!("userdata: {p:p}").into_bytes(),
-        Value::GcRef(GcHandle::Str(k)) => state
+    if let Some(n) = v.as_number() {
+        return number_to_string(n).into_bytes();
+    }
+    if let Some(b) = v.as_boolean() {
+        return if b {
+            b"true".to_vec()
+        } else {
+            b"false".to_vec()
+        };
+    }
+    if let Some(p) = v.as_light_userdata() {
+        return format!("userdata: {p:p}").into_bytes();
+    }
+    match v.as_gc() {
+        None => b"nil".to_vec(),
+        Some(GcHandle::Str(k)) => state
             .global
             .heap
             .get_str(k)
             .map(|s| s.as_bytes().to_vec())
             .unwrap_or_default(),
-        Value::GcRef(h) => {
-            let kind = match h {
-                GcHandle::Table(_) => "table",
-                GcHandle::Closure(_) => "function",
-                GcHandle::Userdata(_) => "userdata",
-                GcHandle::Thread(_) => "thread",
-                GcHandle::Str(_) => unreachable!(),
-            };
-            format!("{kind}: 0x{:012x}", gc_addr(h)).into_bytes()
+        Some(h) => {
+            let kind = v.type_of().name();
+            format!("{kind}: 0x{:012x}", h.key_bits()).into_bytes()
         }
     }
 }
@@ -371,31 +355,29 @@ pub fn raw_tostring(state: &LuaState, v: Value) -> Vec<u8> {
 
 /// Lua の `a < b`（数値・文字列・`__lt` メタメソッド）。
 pub fn lua_lt(state: &mut LuaState, a: Value, b: Value) -> LuaResult<bool> {
-    match (a, b) {
-        (Value::Number(x), Value::Number(y)) => Ok(x < y),
-        (Value::GcRef(GcHandle::Str(ka)), Value::GcRef(GcHandle::Str(kb))) => {
-            let sa = state.global.heap.get_str(ka).unwrap().as_bytes().to_vec();
-            let sb = state.global.heap.get_str(kb).unwrap().as_bytes().to_vec();
-            Ok(sa < sb)
-        }
-        _ => {
-            let mut mm = get_metafield(state, a, "__lt");
-            if matches!(mm, Value::Nil) {
-                mm = get_metafield(state, b, "__lt");
-            }
-            if matches!(mm, Value::Nil) {
-                let (ta, tb) = (a.type_of().name(), b.type_of().name());
-                let msg = if ta == tb {
-                    format!("attempt to compare two {ta} values")
-                } else {
-                    format!("attempt to compare {ta} with {tb}")
-                };
-                return Err(rt_error(state, msg));
-            }
-            let res = crate::vm::call(state, mm, &[a, b])?;
-            Ok(res.into_iter().next().unwrap_or(Value::Nil).is_truthy())
-        }
+    if let (Some(x), Some(y)) = (a.as_number(), b.as_number()) {
+        return Ok(x < y);
+    }
+    if let (Some(ka), Some(kb)) = (a.as_string(), b.as_string()) {
+        let sa = state.global.heap.get_str(ka).unwrap().as_bytes().to_vec();
+        let sb = state.global.heap.get_str(kb).unwrap().as_bytes().to_vec();
+        return Ok(sa < sb);
+    }
+    let mut mm = get_metafield(state, a, "__lt");
+    if mm.is_nil() {
+        mm = get_metafield(state, b, "__lt");
+    }
+    if mm.is_nil() {
+        let (ta, tb) = (a.type_of().name(), b.type_of().name());
+        let msg = if ta == tb {
+            format!("attempt to compare two {ta} values")
+        } else {
+            format!("attempt to compare {ta} with {tb}")
+        };
+        return Err(rt_error(state, msg));
     }
+    let res = crate::vm::call(state, mm, &[a, b])?;
+    Ok(res.into_iter().next().unwrap_or(Value::NIL).is_truthy())
 }
 
 /// `luaL_where(level)` 相当。`level` 段上の呼び出し元の `"source:line: "` を返す。
@@ -412,7 +394,7 @@ pub fn lua_where(state: &LuaState, level: u32) -> String {
         return String::new();
     };
     let ci = &state.call_info[idx];
-    match &ci.source {
+    match state.frame_source(ci) {
         Some(src) if ci.current_line > 0 => format!("{}:{}: ", src, ci.current_line),
         _ => String::new(),
     }
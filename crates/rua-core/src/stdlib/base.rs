@@ -4,11 +4,14 @@
 //! `pcall`/`xpcall`/`rawget`/`rawset`/`rawequal`/`setmetatable`/`getmetatable`/`unpack` と
 //! グローバル `_G`/`_VERSION` を登録する。
 
+use std::any::Any;
 use std::io::Write;
 use std::rc::Rc;
 
-use crate::compiler::compile;
+use crate::compiler::compile_with_lang;
 use crate::error::{LuaError, LuaResult};
+use crate::gc::alloc::GcMode;
+use crate::gc::stats::GcStats;
 use crate::gc::{GcHandle, TableKey};
 use crate::state::LuaState;
 use crate::value::Value;
@@ -54,7 +57,7 @@ pub fn open(state: &mut LuaState) {
     aux::register(state, g, "gcinfo", l_gcinfo);
 
     // _G はグローバル環境テーブル自身。
-    aux::set_field(state, g, "_G", Value::GcRef(state.global.globals));
+    aux::set_field(state, g, "_G", Value::gc(state.global.globals));
     let ver = state.new_string(b"Lua 5.1");
     aux::set_field(state, g, "_VERSION", ver);
 }
@@ -98,13 +101,13 @@ fn l_tostring(state: &mut LuaState) -> LuaResult<i32> {
     let v = aux::opt_value(&args, 0);
     // Lua 5.1: __tostring メタメソッドの戻り値はそのまま返す（文字列チェックなし）。
     let mm = aux::get_metafield(state, v, "__tostring");
-    if !matches!(mm, Value::Nil) {
+    if !mm.is_nil() {
         let res = crate::vm::call(state, mm, &[v])?;
-        let first = res.into_iter().next().unwrap_or(Value::Nil);
+        let first = res.into_iter().next().unwrap_or(Value::NIL);
         return aux::ret(state, vec![first]);
     }
     let bytes = aux::raw_tostring(state, v);
-    let s = state.new_string(&bytes);
+    let s = state.new_string_from(bytes);
     aux::ret(state, vec![s])
 }
 
@@ -115,18 +118,18 @@ fn l_tonumber(state: &mut LuaState) -> LuaResult<i32> {
         return Err(aux::arg_error(state, 1, "tonumber", "value expected"));
     }
     let v = aux::opt_value(&args, 0);
-    if matches!(aux::opt_value(&args, 1), Value::Nil) {
+    if aux::opt_value(&args, 1).is_nil() {
         // 基数なし: number はそのまま、数値文字列は変換、その他 nil。
-        let result = match v {
-            Value::Number(_) => v,
-            Value::GcRef(GcHandle::Str(k)) => {
-                let bytes = state.global.heap.get_str(k).unwrap().as_bytes().to_vec();
-                match str_to_number(&bytes) {
-                    Some(n) => Value::Number(n),
-                    None => Value::Nil,
-                }
+        let result = if v.is_number() {
+            v
+        } else if let Some(k) = v.as_string() {
+            let bytes = state.global.heap.get_str(k).unwrap().as_bytes().to_vec();
+            match str_to_number(&bytes) {
+                Some(n) => Value::number(n),
+                None => Value::NIL,
             }
-            _ => Value::Nil,
+        } else {
+            Value::NIL
         };
         aux::ret(state, vec![result])
     } else {
@@ -137,8 +140,8 @@ fn l_tonumber(state: &mut LuaState) -> LuaResult<i32> {
         }
         let bytes = aux::check_str_bytes(state, &args, 0, "tonumber")?;
         let result = parse_in_base(&bytes, base as u32)
-            .map(Value::Number)
-            .unwrap_or(Value::Nil);
+            .map(Value::number)
+            .unwrap_or(Value::NIL);
         aux::ret(state, vec![result])
     }
 }
@@ -179,14 +182,14 @@ fn ipairs_aux(state: &mut LuaState) -> LuaResult<i32> {
             .heap
             .get_table(tk)
             .map(|t| t.get_int(i as usize))
-            .unwrap_or(Value::Nil)
+            .unwrap_or(Value::NIL)
     } else {
-        Value::Nil
+        Value::NIL
     };
-    if matches!(v, Value::Nil) {
+    if v.is_nil() {
         aux::ret0(state)
     } else {
-        aux::ret(state, vec![Value::Number(i as f64), v])
+        aux::ret(state, vec![Value::number(i as f64), v])
     }
 }
 
@@ -195,7 +198,7 @@ fn l_ipairs(state: &mut LuaState) -> LuaResult<i32> {
     let _ = aux::check_table(state, &args, 0, "ipairs")?;
     let t = args[0];
     let iter = aux::make_native(state, ipairs_aux);
-    aux::ret(state, vec![iter, t, Value::Number(0.0)])
+    aux::ret(state, vec![iter, t, Value::number(0.0)])
 }
 
 fn l_pairs(state: &mut LuaState) -> LuaResult<i32> {
@@ -203,19 +206,19 @@ fn l_pairs(state: &mut LuaState) -> LuaResult<i32> {
     let _ = aux::check_table(state, &args, 0, "pairs")?;
     let t = args[0];
     let iter = aux::make_native(state, l_next);
-    aux::ret(state, vec![iter, t, Value::Nil])
+    aux::ret(state, vec![iter, t, Value::NIL])
 }
 
 fn l_next(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let tk = aux::check_table(state, &args, 0, "next")?;
-    let key = aux::opt_value(&args, 1);
+    let key = state.global.heap.table_key(aux::opt_value(&args, 1));
     let result = state.global.heap.get_table(tk).map(|t| t.next(&key));
     match result {
         Some(Ok(Some((k, v)))) => aux::ret(state, vec![k, v]),
-        Some(Ok(None)) => aux::ret(state, vec![Value::Nil]),
+        Some(Ok(None)) => aux::ret(state, vec![Value::NIL]),
         Some(Err(())) => Err(aux::rt_error(state, "invalid key to 'next'")),
-        None => aux::ret(state, vec![Value::Nil]),
+        None => aux::ret(state, vec![Value::NIL]),
     }
 }
 
@@ -227,7 +230,7 @@ fn l_select(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let first = aux::opt_value(&args, 0);
     // select('#', ...) は引数個数。
-    if let Value::GcRef(GcHandle::Str(k)) = first {
+    if let Some(k) = first.as_string() {
         let is_hash = state
             .global
             .heap
@@ -236,7 +239,7 @@ fn l_select(state: &mut LuaState) -> LuaResult<i32> {
             .unwrap_or(false);
         if is_hash {
             let n = (args.len() - 1) as f64;
-            return aux::ret(state, vec![Value::Number(n)]);
+            return aux::ret(state, vec![Value::number(n)]);
         }
     }
     let n = aux::check_int(state, &args, 0, "select")?;
@@ -271,15 +274,16 @@ fn l_error(state: &mut LuaState) -> LuaResult<i32> {
     let level = aux::opt_int(state, &args, 1, "error", 1)?;
     // 本家 `luaB_error`: 文字列メッセージかつ level>0 なら "source:line: " を前置する
     // （`luaL_where(level)` 相当）。非文字列や level==0 はそのまま送出。
-    let errval = match msg {
-        Value::GcRef(GcHandle::Str(k)) if level > 0 => {
-            let body = state.global.heap.get_str(k).unwrap().as_bytes().to_vec();
-            let prefix = aux::lua_where(state, level as u32);
-            let mut buf = prefix.into_bytes();
-            buf.extend_from_slice(&body);
-            state.new_string(&buf)
-        }
-        other => other,
+    let errval = if let Some(k) = msg.as_string()
+        && level > 0
+    {
+        let body = state.global.heap.get_str(k).unwrap().as_bytes().to_vec();
+        let prefix = aux::lua_where(state, level as u32);
+        let mut buf = prefix.into_bytes();
+        buf.extend_from_slice(&body);
+        state.new_string_from(buf)
+    } else {
+        msg
     };
     Err(LuaError::Runtime(errval))
 }
@@ -296,9 +300,13 @@ fn l_assert(state: &mut LuaState) -> LuaResult<i32> {
     } else {
         // 本家 `luaB_assert`: `luaL_error(L, "%s", msg)`。msg は文字列化され、
         // level 1（assert の呼び出し元）の位置が前置される。
-        let msg_bytes = match aux::opt_value(&args, 1) {
-            Value::Nil => b"assertion failed!".to_vec(),
-            _ => aux::check_str_bytes(state, &args, 1, "assert")?,
+        let msg_bytes = {
+            let v = aux::opt_value(&args, 1);
+            if v.is_nil() {
+                b"assertion failed!".to_vec()
+            } else {
+                aux::check_str_bytes(state, &args, 1, "assert")?
+            }
         };
         let prefix = aux::lua_where(state, 1);
         let mut buf = prefix.into_bytes();
@@ -319,6 +327,7 @@ fn error_to_value(state: &mut LuaState, e: LuaError) -> Value {
         LuaError::Syntax(s) | LuaError::Internal(s) => state.new_string(s.as_bytes()),
         LuaError::Memory => state.new_string(b"not enough memory"),
         LuaError::ErrorInError => state.new_string(b"error in error handling"),
+        LuaError::Interrupted(r) => state.new_string(r.message().as_bytes()),
         LuaError::Yield(_) => state.new_string(b"attempt to yield across a C-call boundary"),
     }
 }
@@ -330,17 +339,31 @@ fn l_pcall(state: &mut LuaState) -> LuaResult<i32> {
     }
     let func = args[0];
     let call_args = args[1..].to_vec();
-    let result = crate::state::call::pcall(state, |s| crate::vm::call(s, func, &call_args));
+    // 呼んだ関数が yield したら、resume 後の結果で pcall_cont が続きを行う。
+    let result = crate::vm::pcall_k(state, func, &call_args, pcall_cont, || Box::new(()))?;
+    pcall_cont(state, result, Box::new(()))
+}
+
+/// `pcall` の結果を `true, ...` / `false, errobj` にする（yield 後の再開でも使う継続）。
+fn pcall_cont(
+    state: &mut LuaState,
+    result: LuaResult<Vec<Value>>,
+    _ctx: Box<dyn Any>,
+) -> LuaResult<i32> {
     match result {
         Ok(rets) => {
             let mut out = Vec::with_capacity(rets.len() + 1);
-            out.push(Value::Boolean(true));
+            out.push(Value::TRUE);
             out.extend(rets);
             aux::ret(state, out)
         }
+        // 実行の打ち切りはスクリプトに捕捉させない。
+        Err(e @ LuaError::Interrupted(r)) if !r.catchable() => Err(e),
         Err(e) => {
+            // 捕捉したエラーのトレースバックは表示しない。
+            state.error_traceback = None;
             let ev = error_to_value(state, e);
-            aux::ret(state, vec![Value::Boolean(false), ev])
+            aux::ret(state, vec![Value::FALSE, ev])
         }
     }
 }
@@ -349,23 +372,36 @@ fn l_xpcall(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let func = aux::opt_value(&args, 0);
     let handler = aux::opt_value(&args, 1);
-    if matches!(func, Value::Nil) {
+    if func.is_nil() {
         return Err(aux::arg_error(state, 1, "xpcall", "value expected"));
     }
-    let result = crate::state::call::pcall(state, |s| crate::vm::call(s, func, &[]));
+    let result = crate::vm::pcall_k(state, func, &[], xpcall_cont, || Box::new(handler))?;
+    xpcall_cont(state, result, Box::new(handler))
+}
+
+/// `xpcall` の継続。ctx はエラーハンドラ。
+fn xpcall_cont(
+    state: &mut LuaState,
+    result: LuaResult<Vec<Value>>,
+    ctx: Box<dyn Any>,
+) -> LuaResult<i32> {
     match result {
         Ok(rets) => {
             let mut out = Vec::with_capacity(rets.len() + 1);
-            out.push(Value::Boolean(true));
+            out.push(Value::TRUE);
             out.extend(rets);
             aux::ret(state, out)
         }
+        Err(e @ LuaError::Interrupted(r)) if !r.catchable() => Err(e),
         Err(e) => {
+            // 捕捉したエラーのトレースバックは表示しない。
+            state.error_traceback = None;
             let ev = error_to_value(state, e);
             // ハンドラを errobj で呼ぶ。
+            let handler = ctx.downcast::<Value>().map_or(Value::NIL, |h| *h);
             let hres = crate::vm::call(state, handler, &[ev])?;
-            let hval = hres.into_iter().next().unwrap_or(Value::Nil);
-            aux::ret(state, vec![Value::Boolean(false), hval])
+            let hval = hres.into_iter().next().unwrap_or(Value::NIL);
+            aux::ret(state, vec![Value::FALSE, hval])
         }
     }
 }
@@ -377,21 +413,22 @@ fn l_xpcall(state: &mut LuaState) -> LuaResult<i32> {
 fn l_rawget(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let tk = aux::check_table(state, &args, 0, "rawget")?;
-    let key = aux::opt_value(&args, 1);
+    let key = state.global.heap.table_key(aux::opt_value(&args, 1));
     let v = state
         .global
         .heap
         .get_table(tk)
         .map(|t| t.get(&key))
-        .unwrap_or(Value::Nil);
+        .unwrap_or(Value::NIL);
     aux::ret(state, vec![v])
 }
 
 fn l_rawset(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let tk = aux::check_table(state, &args, 0, "rawset")?;
-    let key = aux::opt_value(&args, 1);
     let val = aux::opt_value(&args, 2);
+    crate::vm::interp::reserve_insert(state, tk, aux::opt_value(&args, 1), val)?;
+    let key = state.global.heap.intern_key(aux::opt_value(&args, 1));
     let res = state.global.heap.get_table_mut(tk).map(|t| t.set(key, val));
     match res {
         Some(Err(crate::value::table::TableKeyError::NilKey)) => {
@@ -408,23 +445,25 @@ fn l_rawequal(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let a = aux::opt_value(&args, 0);
     let b = aux::opt_value(&args, 1);
-    aux::ret(state, vec![Value::Boolean(a == b)])
+    let eq = state.global.heap.raw_equal(a, b);
+    aux::ret(state, vec![Value::boolean(eq)])
 }
 
 fn l_rawlen(state: &mut LuaState) -> LuaResult<i32> {
     // Lua 5.1 には rawlen は無いが、table/string の生の長さ取得として提供（簡便）。
     let args = aux::args_vec(state);
-    let len = match aux::opt_value(&args, 0) {
-        Value::GcRef(GcHandle::Table(k)) => state
-            .global
-            .heap
-            .get_table(k)
-            .map(|t| t.length())
-            .unwrap_or(0),
-        Value::GcRef(GcHandle::Str(k)) => {
+    let len = {
+        let v = aux::opt_value(&args, 0);
+        if let Some(k) = v.as_table() {
+            state
+                .global
+                .heap
+                .get_table(k)
+                .map(|t| t.length())
+                .unwrap_or(0)
+        } else if let Some(k) = v.as_string() {
             state.global.heap.get_str(k).map(|s| s.len()).unwrap_or(0)
-        }
-        _ => {
+        } else {
             return Err(aux::arg_error(
                 state,
                 1,
@@ -433,7 +472,7 @@ fn l_rawlen(state: &mut LuaState) -> LuaResult<i32> {
             ));
         }
     };
-    aux::ret(state, vec![Value::Number(len as f64)])
+    aux::ret(state, vec![Value::number(len as f64)])
 }
 
 // ============================================================================
@@ -444,10 +483,10 @@ fn l_setmetatable(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let tk = aux::check_table(state, &args, 0, "setmetatable")?;
     let mt = aux::opt_value(&args, 1);
-    let mt_handle = match mt {
-        Value::Nil => None,
-        Value::GcRef(h @ GcHandle::Table(_)) => Some(h),
-        _ => {
+    let mt_handle = match mt.as_table() {
+        Some(k) => Some(GcHandle::Table(k)),
+        None if mt.is_nil() => None,
+        None => {
             return Err(aux::arg_error(
                 state,
                 2,
@@ -467,24 +506,21 @@ fn l_setmetatable(state: &mut LuaState) -> LuaResult<i32> {
 }
 
 fn has_protected_metatable(state: &mut LuaState, tk: TableKey) -> bool {
-    !matches!(
-        aux::get_metafield(state, Value::GcRef(GcHandle::Table(tk)), "__metatable"),
-        Value::Nil
-    )
+    !aux::get_metafield(state, Value::table(tk), "__metatable").is_nil()
 }
 
 fn l_getmetatable(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let v = aux::opt_value(&args, 0);
     let Some(mtk) = aux::metatable_handle(state, v) else {
-        return aux::ret(state, vec![Value::Nil]);
+        return aux::ret(state, vec![Value::NIL]);
     };
     // __metatable があればそれを返す（保護）。
     let protected = aux::get_metafield(state, v, "__metatable");
-    if !matches!(protected, Value::Nil) {
+    if !protected.is_nil() {
         return aux::ret(state, vec![protected]);
     }
-    aux::ret(state, vec![Value::GcRef(GcHandle::Table(mtk))])
+    aux::ret(state, vec![Value::table(mtk)])
 }
 
 // ============================================================================
@@ -495,7 +531,7 @@ fn l_unpack(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let tk = aux::check_table(state, &args, 0, "unpack")?;
     let i = aux::opt_int(state, &args, 1, "unpack", 1)?;
-    let j = if matches!(aux::opt_value(&args, 2), Value::Nil) {
+    let j = if aux::opt_value(&args, 2).is_nil() {
         state
             .global
             .heap
@@ -514,14 +550,14 @@ fn l_unpack(state: &mut LuaState) -> LuaResult<i32> {
                 .heap
                 .get_table(tk)
                 .map(|t| t.get_int(idx as usize))
-                .unwrap_or(Value::Nil)
+                .unwrap_or(Value::NIL)
         } else {
             state
                 .global
                 .heap
                 .get_table(tk)
-                .map(|t| t.get(&Value::Number(idx as f64)))
-                .unwrap_or(Value::Nil)
+                .map(|t| t.get(&Value::number(idx as f64)))
+                .unwrap_or(Value::NIL)
         };
         out.push(v);
         idx += 1;
@@ -538,12 +574,13 @@ fn l_unpack(state: &mut LuaState) -> LuaResult<i32> {
 ///
 /// エラーメッセージは本家に倣い `[chunkname]:line: message` 形式で返す（"syntax error: " プレフィックスなし）。
 fn compile_to_function(state: &mut LuaState, src: &[u8], chunkname: &str) -> Result<Value, String> {
-    match compile(&mut state.global.heap, src, chunkname) {
+    let lang = state.global.lang;
+    match compile_with_lang(&mut state.global.heap, src, chunkname, lang) {
         Ok(proto) => {
             let env = state.global.globals;
             let closure = LuaClosure::new_with_env(Rc::new(proto), env);
             let h = state.global.heap.alloc_closure(Closure::Lua(closure));
-            Ok(Value::GcRef(h))
+            Ok(Value::gc(h))
         }
         Err(crate::error::LuaError::Syntax(s)) => Err(s),
         Err(e) => Err(format!("{e}")),
@@ -556,7 +593,7 @@ fn ret_loaded(state: &mut LuaState, compiled: Result<Value, String>) -> LuaResul
         Ok(f) => aux::ret(state, vec![f]),
         Err(e) => {
             let msg = state.new_string(e.as_bytes());
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
@@ -566,10 +603,14 @@ fn l_loadstring(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let src = aux::check_str_bytes(state, &args, 0, "loadstring")?;
     // chunkname 省略時は本家同様ソース文字列自体（compile 側で `[string "..."]` に短縮）。
-    let chunkname = match aux::opt_value(&args, 1) {
-        Value::Nil => String::from_utf8_lossy(&src).into_owned(),
-        _ => String::from_utf8_lossy(&aux::check_str_bytes(state, &args, 1, "loadstring")?)
-            .into_owned(),
+    let chunkname = {
+        let v = aux::opt_value(&args, 1);
+        if v.is_nil() {
+            String::from_utf8_lossy(&src).into_owned()
+        } else {
+            String::from_utf8_lossy(&aux::check_str_bytes(state, &args, 1, "loadstring")?)
+                .into_owned()
+        }
     };
     let compiled = compile_to_function(state, &src, &chunkname);
     ret_loaded(state, compiled)
@@ -579,15 +620,19 @@ fn l_loadstring(state: &mut LuaState) -> LuaResult<i32> {
 fn l_load(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let func = aux::opt_value(&args, 0);
-    let chunkname = match aux::opt_value(&args, 1) {
-        Value::Nil => "=(load)".to_string(),
-        _ => String::from_utf8_lossy(&aux::check_str_bytes(state, &args, 1, "load")?).into_owned(),
+    let chunkname = {
+        let v = aux::opt_value(&args, 1);
+        if v.is_nil() {
+            "=(load)".to_string()
+        } else {
+            String::from_utf8_lossy(&aux::check_str_bytes(state, &args, 1, "load")?).into_owned()
+        }
     };
     let mut src: Vec<u8> = Vec::new();
     loop {
         let piece = crate::vm::call(state, func, &[])?;
-        match piece.into_iter().next() {
-            Some(Value::GcRef(GcHandle::Str(k))) => {
+        match piece.into_iter().next().and_then(|v| v.as_string()) {
+            Some(k) => {
                 let bytes = state
                     .global
                     .heap
@@ -609,16 +654,16 @@ fn l_load(state: &mut LuaState) -> LuaResult<i32> {
 
 /// ファイル（省略時は stdin）を読み、先頭の `#` 行（shebang）は読み飛ばす。
 fn read_file_source(state: &mut LuaState, args: &[Value]) -> Result<(Vec<u8>, String), String> {
-    let (mut src, name) = match aux::opt_value(args, 0) {
-        Value::Nil => {
+    let (mut src, name) = {
+        let v = aux::opt_value(args, 0);
+        if v.is_nil() {
             use std::io::Read;
             let mut buf = Vec::new();
             std::io::stdin()
                 .read_to_end(&mut buf)
                 .map_err(|e| format!("cannot read stdin: {e}"))?;
             (buf, "=stdin".to_string())
-        }
-        Value::GcRef(GcHandle::Str(k)) => {
+        } else if let Some(k) = v.as_string() {
             let path = state
                 .global
                 .heap
@@ -627,8 +672,9 @@ fn read_file_source(state: &mut LuaState, args: &[Value]) -> Result<(Vec<u8>, St
                 .unwrap_or_default();
             let bytes = std::fs::read(&path).map_err(|e| format!("cannot open {path}: {e}"))?;
             (bytes, format!("@{path}"))
+        } else {
+            return Err("bad argument (string expected)".to_string());
         }
-        _ => return Err("bad argument (string expected)".to_string()),
     };
     // shebang（先頭が '#'）の行を読み飛ばす。行番号維持のため改行は残す。
     if src.first() == Some(&b'#') {
@@ -651,7 +697,7 @@ fn l_loadfile(state: &mut LuaState) -> LuaResult<i32> {
         }
         Err(e) => {
             let msg = state.new_string(e.as_bytes());
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
@@ -678,53 +724,53 @@ fn l_dofile(state: &mut LuaState) -> LuaResult<i32> {
 fn l_setfenv(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     // 第2引数: table
-    let new_env_handle = match aux::opt_value(&args, 1) {
-        Value::GcRef(h @ GcHandle::Table(_)) => h,
-        other => {
+    let env = aux::opt_value(&args, 1);
+    let new_env_handle = match env.as_table() {
+        Some(k) => GcHandle::Table(k),
+        None => {
             return Err(aux::arg_error(
                 state,
                 2,
                 "setfenv",
-                &format!("table expected, got {}", aux::type_name(other)),
+                &format!("table expected, got {}", aux::type_name(env)),
             ));
         }
     };
 
-    match aux::opt_value(&args, 0) {
+    let v = aux::opt_value(&args, 0);
+    if let Some(n) = v.as_number() {
         // 数値レベル指定
-        Value::Number(n) => {
-            let level = n.trunc() as usize;
-            // level 0 はスレッドのグローバル環境を差し替える
-            // level 1+ は呼び出しスタックを辿る
-            let ok = state.set_fenv_at_level(level, new_env_handle);
-            if !ok {
-                return Err(aux::arg_error(state, 1, "setfenv", "invalid level"));
-            }
-            // レベル指定時は返り値なし（本家準拠）。
-            aux::ret0(state)
+        let level = n.trunc() as usize;
+        // level 0 はスレッドのグローバル環境を差し替える
+        // level 1+ は呼び出しスタックを辿る
+        let ok = state.set_fenv_at_level(level, new_env_handle);
+        if !ok {
+            return Err(aux::arg_error(state, 1, "setfenv", "invalid level"));
         }
+        // レベル指定時は返り値なし（本家準拠）。
+        aux::ret0(state)
+    } else if let Some(ck) = v.as_closure() {
         // 関数値指定
-        Value::GcRef(GcHandle::Closure(ck)) => {
-            // Lua クロージャの env を差し替える。
-            // ネイティブ関数は env を持たないが、寛容に扱う（エラーにしない）。
-            match state.global.heap.get_closure_mut(ck) {
-                Some(Closure::Lua(lc)) => {
-                    lc.set_env(new_env_handle);
-                }
-                Some(Closure::Native(_)) => {
-                    // ネイティブ関数は env 差し替え不可だが、寛容に無視。
-                }
-                None => {}
+        // Lua クロージャの env を差し替える。
+        // ネイティブ関数は env を持たないが、寛容に扱う（エラーにしない）。
+        match state.global.heap.get_closure_mut(ck) {
+            Some(Closure::Lua(lc)) => {
+                lc.set_env(new_env_handle);
+            }
+            Some(Closure::Native(_)) => {
+                // ネイティブ関数は env 差し替え不可だが、寛容に無視。
             }
-            let f = aux::opt_value(&args, 0);
-            aux::ret(state, vec![f])
+            None => {}
         }
-        other => Err(aux::arg_error(
+        let f = aux::opt_value(&args, 0);
+        aux::ret(state, vec![f])
+    } else {
+        Err(aux::arg_error(
             state,
             1,
             "setfenv",
-            &format!("number or function expected, got {}", aux::type_name(other)),
-        )),
+            &format!("number or function expected, got {}", aux::type_name(v)),
+        ))
     }
 }
 
@@ -735,31 +781,29 @@ fn l_setfenv(state: &mut LuaState) -> LuaResult<i32> {
 ///   n == 0 はスレッドのグローバル環境。省略時は n == 1（呼び出し元）。
 fn l_getfenv(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
-    let fenv = match aux::opt_value(&args, 0) {
-        Value::Nil => {
+    let fenv = {
+        let v = aux::opt_value(&args, 0);
+        if v.is_nil() {
             // 省略時は level 1（呼び出し元）。
             state.fenv_at_level(1)
-        }
-        Value::Number(n) => {
+        } else if let Some(n) = v.as_number() {
             let level = n.trunc() as usize;
             state.fenv_at_level(level)
-        }
-        Value::GcRef(GcHandle::Closure(ck)) => {
+        } else if let Some(ck) = v.as_closure() {
             match state.global.heap.get_closure(ck) {
                 Some(Closure::Lua(lc)) => Some(lc.env()),
                 // ネイティブ関数は env を持たない → グローバル環境を返す（寛容）。
                 Some(Closure::Native(_)) => Some(state.global.globals),
                 None => None,
             }
-        }
-        _ => {
+        } else {
             // 省略時 level 1 と同じ扱い。
             state.fenv_at_level(1)
         }
     };
     match fenv {
-        Some(h) => aux::ret(state, vec![Value::GcRef(h)]),
-        None => aux::ret(state, vec![Value::Nil]),
+        Some(h) => aux::ret(state, vec![Value::gc(h)]),
+        None => aux::ret(state, vec![Value::NIL]),
     }
 }
 
@@ -776,29 +820,29 @@ fn l_newproxy(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let v = aux::opt_value(&args, 0);
 
-    let mt = match v {
+    let mt = if v == Value::TRUE {
         // true: 新しいメタテーブルを作成。
-        Value::Boolean(true) => {
-            let tbl = state
-                .global
-                .heap
-                .alloc_table(crate::value::table::Table::new());
-            Some(tbl)
-        }
+        let tbl = state
+            .global
+            .heap
+            .alloc_table(crate::value::table::Table::new());
+        Some(tbl)
+    } else if let Some(k) = v.as_userdata() {
         // userdata: そのメタテーブルを共有する。
-        Value::GcRef(GcHandle::Userdata(k)) => state
+        state
             .global
             .heap
             .get_userdata(k)
-            .and_then(|u| u.metatable()),
+            .and_then(|u| u.metatable())
+    } else {
         // false / nil / その他: メタテーブルなし。
-        _ => None,
+        None
     };
 
     let mut ud = Userdata::new(Box::new(()));
     ud.set_metatable(mt);
     let h = state.global.heap.alloc_userdata(ud);
-    aux::ret(state, vec![Value::GcRef(h)])
+    aux::ret(state, vec![Value::gc(h)])
 }
 
 // ============================================================================
@@ -807,22 +851,28 @@ fn l_newproxy(state: &mut LuaState) -> LuaResult<i32> {
 
 /// `collectgarbage([opt [, arg]])` — GC 制御。
 ///
-/// 現状の rua は実行中に GC を起動していない（ルート集合の収集が未配線, #17）。
-/// 不完全なルートで `Heap::collect` を呼ぶと生存オブジェクトを誤回収して壊れるため、
-/// `collect`/`step` は **安全な no-op**（実回収しない）とし、各オプションは本家準拠の
-/// 形（型・既定値）で値を返す。`count` はメモリ会計未実装のため生存オブジェクト数を
-/// KB の近似として返す（Lua 5.1 は単一の数値を返す）。
+/// `collect` は全ルートから一括回収し、`stop`/`restart` は自動 GC（[`LuaState::check_gc`]）を
+/// 停止/再開する。`step` はインクリメンタル GC を `arg` 単位分進め、サイクルが完了したら `true`。
+/// `setpause`/`setstepmul` は本家同様に新しい値を設定して旧値を返す。
+/// `count` は使用中メモリを小数部付きの KB で返し、2 番目の戻り値として
+/// KB 未満の端数バイトを返す（Lua 5.2 と同じ形）。
+/// `stats` は rua 拡張で、累計の GC 統計（[`GcStats`]）をテーブルで返す。
+/// `generational [, minormul [, majormul]]` と `incremental [, pause [, stepmul]]` は Lua 5.4 と
+/// 同じく自動 GC のモードを切り替え（0 の引数は現在値のまま）、直前のモード名を返す。
 fn l_collectgarbage(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
-    let opt = match aux::opt_value(&args, 0) {
-        Value::Nil => b"collect".to_vec(),
-        Value::GcRef(GcHandle::Str(k)) => state
-            .global
-            .heap
-            .get_str(k)
-            .map(|s| s.as_bytes().to_vec())
-            .unwrap_or_default(),
-        _ => {
+    let opt = {
+        let v = aux::opt_value(&args, 0);
+        if v.is_nil() {
+            b"collect".to_vec()
+        } else if let Some(k) = v.as_string() {
+            state
+                .global
+                .heap
+                .get_str(k)
+                .map(|s| s.as_bytes().to_vec())
+                .unwrap_or_default()
+        } else {
             return Err(aux::arg_error(
                 state,
                 1,
@@ -831,23 +881,111 @@ fn l_collectgarbage(state: &mut LuaState) -> LuaResult<i32> {
             ));
         }
     };
+    let ex = aux::opt_int(state, &args, 1, "collectgarbage", 0)?;
     let result = match opt.as_slice() {
-        // 実回収は #17（ルート収集）待ち。ここでは安全に何もしない。
-        b"collect" | b"" => Value::Number(0.0),
-        // KB 近似（生存オブジェクト数）。
-        b"count" => Value::Number(state.global.heap.live_object_count() as f64),
-        b"step" => Value::Boolean(false),
-        b"setpause" => Value::Number(200.0),
-        b"setstepmul" => Value::Number(100.0),
-        b"stop" | b"restart" => Value::Number(0.0),
-        _ => Value::Number(0.0),
+        // 引数・ネイティブフレームはスタック上にあるためここで回収して安全。
+        b"collect" | b"" => {
+            state.collect_garbage()?;
+            Value::number(0.0)
+        }
+        b"count" => {
+            let bytes = state.global.heap.bytes_in_use();
+            let kb = Value::number(bytes as f64 / 1024.0);
+            return aux::ret(state, vec![kb, Value::number((bytes % 1024) as f64)]);
+        }
+        b"stats" => {
+            let stats = state.global.heap.stats().clone();
+            let t = gc_stats_table(state, &stats);
+            return aux::ret(state, vec![t]);
+        }
+        b"step" => Value::boolean(state.gc_step_by(ex.max(0) as usize)?),
+        b"setpause" => {
+            let old = state.global.gc_config.pause;
+            state.global.gc_config.pause = ex.max(0) as u32;
+            Value::number(old as f64)
+        }
+        b"setstepmul" => {
+            let old = state.global.gc_config.stepmul;
+            state.global.gc_config.stepmul = ex.max(0) as u32;
+            Value::number(old as f64)
+        }
+        b"stop" => {
+            state.global.gc_config.enabled = false;
+            Value::number(0.0)
+        }
+        b"restart" => {
+            state.global.gc_config.enabled = true;
+            Value::number(0.0)
+        }
+        b"generational" | b"incremental" => {
+            let ex2 = aux::opt_int(state, &args, 2, "collectgarbage", 0)?;
+            let config = &mut state.global.gc_config;
+            let old = match config.mode {
+                GcMode::Generational => "generational",
+                GcMode::Incremental | GcMode::StopTheWorld => "incremental",
+            };
+            let (mode, first, second) = if opt == b"generational" {
+                (
+                    GcMode::Generational,
+                    &mut config.minor_multiplier,
+                    &mut config.major_multiplier,
+                )
+            } else {
+                (GcMode::Incremental, &mut config.pause, &mut config.stepmul)
+            };
+            if ex > 0 {
+                *first = ex as u32;
+            }
+            if ex2 > 0 {
+                *second = ex2 as u32;
+            }
+            config.mode = mode;
+            state.new_string(old.as_bytes())
+        }
+        _ => Value::number(0.0),
     };
     aux::ret(state, vec![result])
 }
 
-/// `gcinfo()` — 非推奨（Lua 5.1）。使用中メモリ(KB)を返す。
-/// メモリ会計が未実装のため生存オブジェクト数を近似値として返す（collectgarbage("count") と同じ）。
+/// `collectgarbage("stats")` の戻り値。停止時間は秒。
+///
+/// `{ cycles, minor_cycles, bytes_freed, pauses, total_pause, max_pause, last_pause,
+///    freed = { strings, tables, closures, userdata, threads } }`
+fn gc_stats_table(state: &mut LuaState, stats: &GcStats) -> Value {
+    let freed = state.new_table();
+    let Some(freed_key) = freed.as_table() else {
+        unreachable!("new_table returns a table")
+    };
+    for (name, n) in [
+        ("strings", stats.freed.strings),
+        ("tables", stats.freed.tables),
+        ("closures", stats.freed.closures),
+        ("userdata", stats.freed.userdata),
+        ("threads", stats.freed.threads),
+    ] {
+        aux::set_field(state, freed_key, name, Value::number(n as f64));
+    }
+    let t = state.new_table();
+    let Some(tk) = t.as_table() else {
+        unreachable!("new_table returns a table")
+    };
+    for (name, v) in [
+        ("cycles", stats.cycles as f64),
+        ("minor_cycles", stats.minor_cycles as f64),
+        ("bytes_freed", stats.bytes_freed as f64),
+        ("pauses", stats.pauses as f64),
+        ("total_pause", stats.total_pause.as_secs_f64()),
+        ("max_pause", stats.max_pause.as_secs_f64()),
+        ("last_pause", stats.last_pause.as_secs_f64()),
+    ] {
+        aux::set_field(state, tk, name, Value::number(v));
+    }
+    aux::set_field(state, tk, "freed", freed);
+    t
+}
+
+/// `gcinfo()` — 非推奨（Lua 5.1）。使用中メモリを整数の KB で返す。
 fn l_gcinfo(state: &mut LuaState) -> LuaResult<i32> {
-    let kb = state.global.heap.live_object_count() as f64;
-    aux::ret(state, vec![Value::Number(kb)])
+    let kb = (state.global.heap.bytes_in_use() >> 10) as f64;
+    aux::ret(state, vec![Value::number(kb)])
 }
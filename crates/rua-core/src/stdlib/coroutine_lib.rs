@@ -3,25 +3,30 @@
 //! `coroutine.create` / `coroutine.resume` / `coroutine.yield` /
 //! `coroutine.status` / `coroutine.wrap` / `coroutine.isyieldable` を実装する。
 //!
-//! # 方式: スタック・スライシング（シングルスレッド）
-//! コルーチンはメインスレッドの `LuaState` のスタックとコールフレームを
-//! スライスとして `LuaThread` に保存/復元することで、OS スレッドや unsafe を
-//! 一切使わずに実現する。
+//! # 方式: スレッドごとの実行スタックを切り替える（シングルスレッド）
+//! 各コルーチンは自分の VM スタックとコールスタック（[`ThreadStack`](crate::state::ThreadStack)）
+//! を持ち、全スレッドで 1 つの `GlobalState` を共有する。`LuaState` の `stack`/`call_info` は
+//! 実行中スレッドのもので、resume/yield で [`LuaState::switch_thread`] により入れ替える。
+//! OS スレッドや unsafe は使わない。
 //!
 //! ## resume 時
-//! 1. コルーチンの保存済みスタック/call_info を `state` へ append する。
-//! 2. 初回は `body` 関数を通常呼び出し、2 回目以降は `vm::resume_execute` で再開。
-//! 3. 正常終了: Dead にして結果を `true, ...` で返す。
-//! 4. `LuaError::Yield`: フレームを `LuaThread` へ保存し `true, ...` で返す。
-//! 5. エラー: Dead にして `false, errmsg` で返す。
+//! 1. コルーチンの実行スタックを `state` と入れ替え、resume した側の実行スタックは
+//!    コルーチンのスレッドオブジェクトに置く（GC がそこから辿る）。
+//! 2. 初回は `body` 関数を呼び出し、2 回目以降は `vm::resume_execute` で再開。
+//! 3. 実行スタックを入れ替え直して resume した側へ戻る。
+//! 4. 正常終了: Dead にして結果を `true, ...` で返す。
+//! 5. `LuaError::Yield`: コルーチンの実行スタックを `LuaThread` へ保存し `true, ...` で返す。
+//! 6. エラー: Dead にして `false, errmsg` で返す。
 //!
 //! ## yield 時
 //! `LuaError::Yield(vals)` を発生させる。`execute_inner` が各フレームで
 //! `CallInfo.lua_frame` に実行状態を保存しながら伝播させ、`l_resume` まで届ける。
+//! 途中のネイティブ関数は `vm::call_k`/`vm::pcall_k` で置いた継続（`CallInfo.continuation`）
+//! を残し、再開時に `vm::resume_execute` がそれを呼ぶ。
 
 use crate::error::{LuaError, LuaResult};
 use crate::gc::{GcHandle, ThreadKey};
-use crate::state::LuaState;
+use crate::state::{HookState, LuaState};
 use crate::value::Value;
 use crate::value::thread::{LuaThread, ThreadStatus};
 
@@ -30,9 +35,8 @@ use super::aux;
 /// coroutine ライブラリをグローバル環境の `coroutine` テーブルとして登録する。
 pub fn open(state: &mut LuaState) {
     let lib = state.new_table();
-    let lib_tk = match lib {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(lib_tk) = lib.as_table() else {
+        return;
     };
 
     aux::register(state, lib_tk, "create", l_create);
@@ -58,9 +62,27 @@ pub fn open(state: &mut LuaState) {
 fn l_create(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let f = aux::check_function(state, &args, 0, "coroutine.create")?;
-    let thread = LuaThread::new(f);
-    let h = state.global.heap.alloc_thread(thread);
-    aux::ret(state, vec![Value::GcRef(h)])
+    let th = new_thread(state, f);
+    aux::ret(state, vec![th])
+}
+
+/// `body` を本体とする新しいコルーチンを確保する。
+///
+/// デバッグフックは生成したスレッドのものを引き継ぐ（本家 `lua_newthread`）。
+fn new_thread(state: &mut LuaState, body: Value) -> Value {
+    let h = state.global.heap.alloc_thread(LuaThread::new(body));
+    let hook = HookState {
+        count: state.hook.base_count,
+        running: false,
+        ..state.hook
+    };
+    if let GcHandle::Thread(k) = h
+        && let Some(th) = state.global.heap.get_thread_mut(k)
+    {
+        th.stack.thread = Some(k);
+        th.stack.hook = hook;
+    }
+    Value::gc(h)
 }
 
 // ============================================================================
@@ -70,95 +92,11 @@ fn l_create(state: &mut LuaState) -> LuaResult<i32> {
 fn l_resume(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let tk = aux::check_thread(state, &args, 0, "coroutine.resume")?;
-    let resume_args: Vec<Value> = args[1..].to_vec();
-
-    // ステータス確認
-    let status = state.global.heap.get_thread(tk).map(|t| t.status);
-    match status {
-        None => {
-            let ev = state.new_string(b"coroutine handle invalid");
-            return aux::ret(state, vec![Value::Boolean(false), ev]);
-        }
-        Some(ThreadStatus::Dead) => {
-            let ev = state.new_string(b"cannot resume dead coroutine");
-            return aux::ret(state, vec![Value::Boolean(false), ev]);
-        }
-        Some(ThreadStatus::Running) => {
-            let ev = state.new_string(b"cannot resume non-suspended coroutine");
-            return aux::ret(state, vec![Value::Boolean(false), ev]);
-        }
-        _ => {}
-    }
-
-    // Running にマーク
-    state.global.heap.get_thread_mut(tk).unwrap().status = ThreadStatus::Running;
-
-    let stack_marker = state.stack.len();
-    let ci_marker = state.call_info.len();
-
-    // コルーチンのスタック/call_info を積み戻す
-    let (saved_stack, saved_ci, is_first) = {
-        let th = state.global.heap.get_thread_mut(tk).unwrap();
-        let is_first = th.body.is_some();
-        (
-            std::mem::take(&mut th.saved_stack),
-            std::mem::take(&mut th.saved_call_info),
-            is_first,
-        )
-    };
-    state.stack.extend(saved_stack);
-    state.call_info.extend(saved_ci);
-
-    let result = if is_first {
-        // 初回 resume: body 関数を呼び出す
-        let body = state
-            .global
-            .heap
-            .get_thread_mut(tk)
-            .unwrap()
-            .body
-            .take()
-            .unwrap();
-        crate::vm::call(state, body, &resume_args)
-    } else {
-        // 再開: 保存済みフレームから続きを実行
-        crate::vm::resume_execute(state, stack_marker, ci_marker, resume_args)
-    };
-
-    match result {
-        Ok(vals) => {
-            // 正常終了
-            let th = state.global.heap.get_thread_mut(tk).unwrap();
-            th.status = ThreadStatus::Dead;
-            // コルーチンフレームをクリア（通常呼び出しが truncate している）
-            let mut out = vec![Value::Boolean(true)];
-            out.extend(vals);
-            state.stack.truncate(stack_marker);
-            state.call_info.truncate(ci_marker);
-            aux::ret(state, out)
-        }
-        Err(LuaError::Yield(vals)) => {
-            // yield — コルーチンのフレームを保存
-            let th = state.global.heap.get_thread_mut(tk).unwrap();
-            th.status = ThreadStatus::Suspended;
-            th.saved_stack = state.stack.drain(stack_marker..).collect();
-            th.saved_call_info = state.call_info.drain(ci_marker..).collect();
-            let mut out = vec![Value::Boolean(true)];
-            out.extend(vals);
-            aux::ret(state, out)
-        }
-        Err(e) => {
-            // エラー
-            let th = state.global.heap.get_thread_mut(tk).unwrap();
-            th.status = ThreadStatus::Dead;
-            th.saved_stack.clear();
-            th.saved_call_info.clear();
-            state.stack.truncate(stack_marker);
-            state.call_info.truncate(ci_marker);
-            let ev = error_to_value(state, e);
-            aux::ret(state, vec![Value::Boolean(false), ev])
-        }
-    }
+    // コルーチン内のエラーはここで捕捉される（`coroutine.wrap` は呼び出し元へ再送出するので数えない）。
+    state.protected_calls += 1;
+    let out = resume_thread(state, tk, args[1..].to_vec());
+    state.protected_calls -= 1;
+    aux::ret(state, out?)
 }
 
 // ============================================================================
@@ -166,6 +104,12 @@ fn l_resume(state: &mut LuaState) -> LuaResult<i32> {
 // ============================================================================
 
 fn l_yield(state: &mut LuaState) -> LuaResult<i32> {
+    if state.thread.is_none() {
+        return Err(aux::rt_error(
+            state,
+            "attempt to yield from outside a coroutine",
+        ));
+    }
     let args = aux::args_vec(state);
     // スタックをクリーンにしてから Yield エラーを発生させる。
     // Yield は execute_inner が各フレームで LuaFrameState を保存しながら伝播する。
@@ -202,8 +146,7 @@ fn l_status(state: &mut LuaState) -> LuaResult<i32> {
 fn l_wrap(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let f = aux::check_function(state, &args, 0, "coroutine.wrap")?;
-    let thread = LuaThread::new(f);
-    let th_val = Value::GcRef(state.global.heap.alloc_thread(thread));
+    let th_val = new_thread(state, f);
 
     // コルーチンハンドルを upvalue として持つクロージャを返す。
     let wrapped = aux::make_native_with_upvalue(state, wrap_iter, th_val);
@@ -213,125 +156,123 @@ fn l_wrap(state: &mut LuaState) -> LuaResult<i32> {
 fn wrap_iter(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     // upvalue[0] がコルーチンハンドル
-    let th_val = state.current_upvalue(0).unwrap_or(Value::Nil);
-    let tk = match th_val {
-        Value::GcRef(GcHandle::Thread(k)) => k,
-        _ => return Err(aux::rt_error(state, "wrap: invalid coroutine")),
+    let th_val = state.current_upvalue(0).unwrap_or(Value::NIL);
+    let Some(tk) = th_val.as_thread() else {
+        return Err(aux::rt_error(state, "wrap: invalid coroutine"));
     };
 
     let resume_result = resume_thread(state, tk, args)?;
     // resume_result[0] は boolean (ok/fail)
-    match resume_result.first().copied().unwrap_or(Value::Nil) {
-        Value::Boolean(true) => {
-            let vals: Vec<Value> = resume_result[1..].to_vec();
-            aux::ret(state, vals)
-        }
-        _ => {
-            let errmsg = resume_result.get(1).copied().unwrap_or(Value::Nil);
-            Err(LuaError::Runtime(errmsg))
-        }
+    if resume_result.first() == Some(&Value::TRUE) {
+        let vals: Vec<Value> = resume_result[1..].to_vec();
+        aux::ret(state, vals)
+    } else {
+        let errmsg = resume_result.get(1).copied().unwrap_or(Value::NIL);
+        Err(LuaError::Runtime(errmsg))
     }
 }
 
-/// `l_resume` と同じ処理を関数として切り出した版。
+/// コルーチン `tk` を `resume_args` で再開し、`true, ...` / `false, errmsg` を返す
+/// （`coroutine.resume` と `coroutine.wrap` の関数の本体）。
 fn resume_thread(
     state: &mut LuaState,
     tk: ThreadKey,
     resume_args: Vec<Value>,
 ) -> LuaResult<Vec<Value>> {
-    {
-        let th = match state.global.heap.get_thread(tk) {
-            Some(t) => t,
-            None => {
-                let ev = state.new_string(b"coroutine handle invalid");
-                return Ok(vec![Value::Boolean(false), ev]);
-            }
-        };
-        match th.status {
-            ThreadStatus::Dead => {
-                let ev = state.new_string(b"cannot resume dead coroutine");
-                return Ok(vec![Value::Boolean(false), ev]);
-            }
-            ThreadStatus::Running => {
-                let ev = state.new_string(b"cannot resume non-suspended coroutine");
-                return Ok(vec![Value::Boolean(false), ev]);
-            }
-            _ => {}
+    let status = state.global.heap.get_thread(tk).map(|t| t.status);
+    let refusal: Option<&[u8]> = match status {
+        None => Some(b"coroutine handle invalid"),
+        Some(ThreadStatus::Dead) => Some(b"cannot resume dead coroutine"),
+        Some(ThreadStatus::Running | ThreadStatus::Normal) => {
+            Some(b"cannot resume non-suspended coroutine")
         }
+        Some(ThreadStatus::Suspended) => None,
+    };
+    if let Some(msg) = refusal {
+        let ev = state.new_string(msg);
+        return Ok(vec![Value::FALSE, ev]);
     }
 
-    state.global.heap.get_thread_mut(tk).unwrap().status = ThreadStatus::Running;
-
-    let stack_marker = state.stack.len();
-    let ci_marker = state.call_info.len();
-
-    let (saved_stack, saved_ci, is_first) = {
+    // resume する側のコルーチンは、相手が戻るまで Normal。
+    let resumer = state.thread;
+    set_status(state, resumer, ThreadStatus::Normal);
+    let (mut stack, body) = {
         let th = state.global.heap.get_thread_mut(tk).unwrap();
-        let is_first = th.body.is_some();
-        (
-            std::mem::take(&mut th.saved_stack),
-            std::mem::take(&mut th.saved_call_info),
-            is_first,
-        )
+        th.status = ThreadStatus::Running;
+        (std::mem::take(&mut th.stack), th.body.take())
     };
-    state.stack.extend(saved_stack);
-    state.call_info.extend(saved_ci);
+    // コルーチンの実行スタックへ切り替える。resume した側の実行スタックは、
+    // GC が辿れるようコルーチンのスレッドオブジェクトに置いておく。
+    state.switch_thread(&mut stack);
+    state.global.heap.get_thread_mut(tk).unwrap().stack = stack;
 
-    let result = if is_first {
-        let body = state
-            .global
-            .heap
-            .get_thread_mut(tk)
-            .unwrap()
-            .body
-            .take()
-            .unwrap();
-        crate::vm::call(state, body, &resume_args)
-    } else {
-        crate::vm::resume_execute(state, stack_marker, ci_marker, resume_args)
+    let result = match body {
+        // 初回 resume: body 関数を呼び出す
+        Some(body) => crate::vm::interp::call_yieldable(state, body, &resume_args),
+        // 再開: 保存済みフレームから続きを実行
+        None => crate::vm::resume_execute(state, resume_args),
     };
+    if let Err(e) = &result
+        && !matches!(e, LuaError::Yield(_))
+    {
+        // エラーで終了したコルーチンのフレームは残す（`debug.traceback(co)` 用）が、
+        // その open upvalue は閉じる。
+        crate::vm::interp::close_frame_upvals(state, 0);
+    }
+
+    let mut stack = std::mem::take(&mut state.global.heap.get_thread_mut(tk).unwrap().stack);
+    state.switch_thread(&mut stack);
+    set_status(state, resumer, ThreadStatus::Running);
 
+    let th = state.global.heap.get_thread_mut(tk).unwrap();
     match result {
         Ok(vals) => {
-            state.global.heap.get_thread_mut(tk).unwrap().status = ThreadStatus::Dead;
-            state.stack.truncate(stack_marker);
-            state.call_info.truncate(ci_marker);
-            let mut out = vec![Value::Boolean(true)];
+            // 正常終了
+            th.status = ThreadStatus::Dead;
+            let mut out = vec![Value::TRUE];
             out.extend(vals);
             Ok(out)
         }
         Err(LuaError::Yield(vals)) => {
-            let th = state.global.heap.get_thread_mut(tk).unwrap();
+            // yield — コルーチンの実行スタックを保存
             th.status = ThreadStatus::Suspended;
-            th.saved_stack = state.stack.drain(stack_marker..).collect();
-            th.saved_call_info = state.call_info.drain(ci_marker..).collect();
-            let mut out = vec![Value::Boolean(true)];
+            th.stack = stack;
+            let mut out = vec![Value::TRUE];
             out.extend(vals);
             Ok(out)
         }
         Err(e) => {
-            let th = state.global.heap.get_thread_mut(tk).unwrap();
+            // エラー — 終了時点のコルーチンの実行スタックを保存
             th.status = ThreadStatus::Dead;
-            th.saved_stack.clear();
-            th.saved_call_info.clear();
-            state.stack.truncate(stack_marker);
-            state.call_info.truncate(ci_marker);
+            th.stack = stack;
+            // 実行の打ち切りは resume の呼び出し元へそのまま伝播する。
+            if let LuaError::Interrupted(r) = e
+                && !r.catchable()
+            {
+                return Err(e);
+            }
+            // エラーはここで捕捉されるので、コルーチン内で記録したトレースバックは不要。
+            state.error_traceback = None;
             let ev = error_to_value(state, e);
-            Ok(vec![Value::Boolean(false), ev])
+            Ok(vec![Value::FALSE, ev])
         }
     }
 }
 
+fn set_status(state: &mut LuaState, thread: Option<ThreadKey>, status: ThreadStatus) {
+    if let Some(th) = thread.and_then(|k| state.global.heap.get_thread_mut(k)) {
+        th.status = status;
+    }
+}
+
 // ============================================================================
 // coroutine.isyieldable()
 // ============================================================================
 
 fn l_isyieldable(state: &mut LuaState) -> LuaResult<i32> {
-    // メインスレッドから直接呼ばれた場合は false。
-    // コルーチン内なら true（簡易実装: call_info の深さで判定は難しいので常に true）。
-    // TODO: メインスレッド判定を実装する。
-    let _ = state;
-    aux::ret(state, vec![Value::Boolean(true)])
+    // メインスレッドでは false。
+    let yieldable = state.thread.is_some();
+    aux::ret(state, vec![Value::boolean(yieldable)])
 }
 
 // ============================================================================
@@ -339,9 +280,11 @@ fn l_isyieldable(state: &mut LuaState) -> LuaResult<i32> {
 // ============================================================================
 
 fn l_running(state: &mut LuaState) -> LuaResult<i32> {
-    // 現在実行中のコルーチンを返す。メインスレッドでは nil, true を返す（Lua 5.2+ 互換）。
-    let _ = state;
-    aux::ret(state, vec![Value::Nil, Value::Boolean(true)])
+    // 現在実行中のコルーチンと false を返す。メインスレッドでは nil, true を返す。
+    match state.thread {
+        Some(k) => aux::ret(state, vec![Value::thread(k), Value::FALSE]),
+        None => aux::ret(state, vec![Value::NIL, Value::TRUE]),
+    }
 }
 
 // ============================================================================
@@ -354,6 +297,7 @@ fn error_to_value(state: &mut LuaState, e: LuaError) -> Value {
         LuaError::Syntax(s) | LuaError::Internal(s) => state.new_string(s.as_bytes()),
         LuaError::Memory => state.new_string(b"not enough memory"),
         LuaError::ErrorInError => state.new_string(b"error in error handling"),
+        LuaError::Interrupted(r) => state.new_string(r.message().as_bytes()),
         LuaError::Yield(_) => state.new_string(b"unexpected yield"),
     }
 }
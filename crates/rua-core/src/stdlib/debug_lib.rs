@@ -2,26 +2,37 @@
 //!
 //! `traceback`/`getinfo`/`getmetatable`/`setmetatable`/`getregistry`/
 //! `getupvalue`/`setupvalue`/`getlocal`/`setlocal`/`sethook`/`gethook` を提供する。
+//! rua 拡張として `heapsnapshot` を持つ。
 //!
 //! # 設計方針
-//! - `debug.traceback` と `debug.getinfo` はテスト互換上最重要。必ず文字列/テーブルを返す。
-//! - `sethook`/`gethook` はフック機構未実装のため no-op スタブ。
-//! - `getlocal`/`setlocal` は最小実装（範囲外相当の nil を返すスタブ）。
+//! - `debug.traceback` と `debug.getinfo` はテスト互換上最重要。`getinfo` は本家 5.1 の全オプション
+//!   （`n`/`S`/`l`/`u`/`f`/`L`）に対応し、範囲外のレベルには nil を返す。レベルは本家
+//!   `lua_getstack` と同じく末尾呼び出しで消えたフレーム（`what == "tail"`）も数える。
+//! - `sethook` は Lua 関数をスレッドのフック（[`HookState::data`](crate::state::HookState::data)）に置き、
+//!   VM には Rust 側のフック（[`LuaState::set_hook`]）として中継関数を設定する。フックはスレッドごとで、
+//!   新しいコルーチンは生成したスレッドのものを引き継ぐ。
+//! - `thread` 引数を取る関数（`traceback`/`getinfo`/`getlocal`/`setlocal`/`sethook`/`gethook`）は、
+//!   そのスレッドの実行スタックへ一時的に切り替えて（[`LuaState::with_thread`]）処理する。
+//! - `getlocal`/`setlocal` はフレームの現在 pc で有効なローカル変数を `Proto::local_vars` から
+//!   引き、レジスタ（`state.stack`）を読み書きする。
 
 use crate::error::LuaResult;
-use crate::gc::{GcHandle, TableKey};
-use crate::state::LuaState;
+use crate::gc::{GcHandle, TableKey, ThreadKey};
+use crate::state::{
+    HOOK_MASK_CALL, HOOK_MASK_COUNT, HOOK_MASK_LINE, HOOK_MASK_RET, HookEvent, LuaState,
+};
 use crate::value::Value;
 use crate::value::closure::Closure;
 
+use crate::vm::debug::{StackLevel, stack_level};
+
 use super::aux;
 
 /// debug ライブラリをグローバル環境へ開く。
 pub fn open(state: &mut LuaState) {
     let t = state.new_table();
-    let tk = match t {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(tk) = t.as_table() else {
+        return;
     };
 
     aux::register(state, tk, "traceback", l_traceback);
@@ -35,6 +46,7 @@ pub fn open(state: &mut LuaState) {
     aux::register(state, tk, "setlocal", l_setlocal);
     aux::register(state, tk, "sethook", l_sethook);
     aux::register(state, tk, "gethook", l_gethook);
+    aux::register(state, tk, "heapsnapshot", l_heapsnapshot);
 
     if let GcHandle::Table(g) = state.global.globals {
         aux::set_field(state, g, "debug", t);
@@ -45,65 +57,36 @@ pub fn open(state: &mut LuaState) {
 // debug.traceback([message [, level]])
 // ============================================================================
 
-/// コールスタックを文字列として組み立てる。
-///
-/// call_info を末尾から辿り、source:line の情報を列挙する。
-fn build_traceback(state: &LuaState) -> String {
-    let mut lines: Vec<String> = Vec::new();
-    // call_info は [0]=最古フレーム、末尾=現在フレーム の順。
-    // 末尾フレームは traceback 自身（ネイティブ）なのでスキップする。
-    let frames = state.call_info.len();
-    let skip = if frames > 0 { 1 } else { 0 };
-
-    for ci in state.call_info[..frames.saturating_sub(skip)].iter().rev() {
-        match &ci.source {
-            Some(src) if ci.current_line > 0 => {
-                lines.push(format!("\t{}:{}", src, ci.current_line));
-            }
-            Some(src) => {
-                lines.push(format!("\t{}: ?", src));
-            }
-            None => {
-                lines.push("\t[C]: ?".to_string());
-            }
-        }
-    }
-
-    if lines.is_empty() {
-        String::from("stack traceback:")
-    } else {
-        format!("stack traceback:\n{}", lines.join("\n"))
-    }
-}
-
 fn l_traceback(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
-    let msg = aux::opt_value(&args, 0);
-    // _level = args[1] (省略可, 未使用だが受け取る)
-
-    let result = match msg {
+    let (thread, arg) = thread_arg(state, &args);
+    let msg = aux::opt_value(&args, arg);
+    // level 0 は traceback 自身なので、既定では呼び出し元（level 1）から列挙する。
+    // 別のスレッドを指定したらその最上位（level 0）から。
+    let default_level = if thread == state.thread { 1 } else { 0 };
+    let level = aux::opt_int(state, &args, arg + 1, "traceback", default_level)?.max(0) as usize;
+
+    let prefix = if msg.is_nil() {
         // nil: スタックトレース文字列のみ。
-        Value::Nil => {
-            let tb = build_traceback(state);
-            state.new_string(tb.as_bytes())
-        }
+        String::new()
+    } else if let Some(k) = msg.as_string() {
         // 文字列: メッセージ + "\n" + スタックトレース。
-        Value::GcRef(GcHandle::Str(k)) => {
-            let prefix = state
-                .global
-                .heap
-                .get_str(k)
-                .map(|s| s.as_bytes().to_vec())
-                .unwrap_or_default();
-            let prefix_str = String::from_utf8_lossy(&prefix).into_owned();
-            let tb = build_traceback(state);
-            let full = format!("{}\n{}", prefix_str, tb);
-            state.new_string(full.as_bytes())
-        }
+        let bytes = state
+            .global
+            .heap
+            .get_str(k)
+            .map(|s| s.as_bytes().to_vec())
+            .unwrap_or_default();
+        format!("{}\n", String::from_utf8_lossy(&bytes))
+    } else {
         // 非文字列かつ非 nil: そのまま返す（本家の動作）。
-        other => other,
+        return aux::ret(state, vec![msg]);
     };
-
+    // 正常に終了したコルーチンのフレームは無い（エラーで終了したものは残っている）。
+    let tb = state
+        .with_thread(thread, |s| crate::vm::debug::traceback(s, level))
+        .unwrap_or_else(|| "stack traceback:".to_string());
+    let result = state.new_string(format!("{prefix}{tb}").as_bytes());
     aux::ret(state, vec![result])
 }
 
@@ -111,171 +94,193 @@ fn l_traceback(state: &mut LuaState) -> LuaResult<i32> {
 // debug.getinfo([f] [, what])
 // ============================================================================
 
-/// Lua クロージャの情報をテーブルへ埋める。
-fn fill_lua_closure_info(state: &mut LuaState, tk: TableKey, closure_key: crate::gc::ClosureKey) {
-    let (source, short_src, line_defined, last_line_defined, nups) =
-        match state.global.heap.get_closure(closure_key) {
-            Some(Closure::Lua(lc)) => {
-                let proto = lc.proto().clone();
-                let src = proto.source.clone().unwrap_or_else(|| "=?".to_string());
-                let short_src =
-                    if let Some(rest) = src.strip_prefix('@').or_else(|| src.strip_prefix('=')) {
-                        rest.to_string()
-                    } else {
-                        // チャンク文字列の短縮表示（先頭60文字）。
-                        let truncated: String = src.chars().take(60).collect();
-                        format!("[string \"{}\"]", truncated.replace('\n', " "))
-                    };
-                (
-                    src,
-                    short_src,
-                    proto.line_defined,
-                    proto.last_line_defined,
-                    lc.upvalues().len(),
-                )
-            }
-            Some(Closure::Native(_)) => ("=[C]".to_string(), "[C]".to_string(), 0u32, 0u32, 0usize),
-            None => return,
-        };
+/// `getinfo` が対象から読む値。他スレッドのフレームは、その実行スタックへ切り替えている間に集める。
+struct FrameInfo {
+    /// 対象の関数。末尾呼び出しで消えたフレームなら `None`。
+    closure: Option<crate::gc::ClosureKey>,
+    /// 実行中の行。Lua 関数のフレーム以外は -1。
+    current_line: f64,
+    /// 呼び出し側の命令から推定した名前と種別（`'n'`）。
+    name: Option<(&'static str, String)>,
+}
 
-    let src_val = state.new_string(source.as_bytes());
-    aux::set_field(state, tk, "source", src_val);
+/// 実行中のスレッドの `level` 番目のフレームの情報。範囲外なら `None`。
+fn frame_info(state: &LuaState, level: usize) -> Option<FrameInfo> {
+    match stack_level(state, level)? {
+        StackLevel::Frame(idx) => {
+            let ci = &state.call_info[idx];
+            Some(FrameInfo {
+                closure: ci.lua_closure.or(ci.native_closure),
+                current_line: if ci.lua_closure.is_some() {
+                    ci.current_line as f64
+                } else {
+                    -1.0
+                },
+                name: crate::vm::debug::func_name(state, idx),
+            })
+        }
+        // 本家 5.1 の `info_tailcall` と同じく、名前は nil でなく空文字列。
+        StackLevel::TailCall => Some(FrameInfo {
+            closure: None,
+            current_line: -1.0,
+            name: Some(("", String::new())),
+        }),
+    }
+}
 
-    let short_val = state.new_string(short_src.as_bytes());
-    aux::set_field(state, tk, "short_src", short_val);
+fn set_str(state: &mut LuaState, tk: TableKey, name: &str, s: &str) {
+    let v = state.new_string(s.as_bytes());
+    aux::set_field(state, tk, name, v);
+}
 
-    aux::set_field(state, tk, "linedefined", Value::Number(line_defined as f64));
+/// `'S'`: source / short_src / linedefined / lastlinedefined / what。
+fn info_source(state: &mut LuaState, tk: TableKey, closure: Option<crate::gc::ClosureKey>) {
+    let lua_proto = closure.and_then(|ck| match state.global.heap.get_closure(ck) {
+        Some(Closure::Lua(lc)) => Some(lc.proto().clone()),
+        _ => None,
+    });
+    let (source, short_src, line_defined, last_line_defined, what) = match lua_proto {
+        Some(proto) => {
+            let source = proto.source.clone().unwrap_or_else(|| "=?".to_string());
+            let short_src = crate::vm::interp::short_src(proto.source.as_deref());
+            let what = if proto.line_defined == 0 {
+                "main"
+            } else {
+                "Lua"
+            };
+            (
+                source,
+                short_src,
+                proto.line_defined as f64,
+                proto.last_line_defined as f64,
+                what,
+            )
+        }
+        None if closure.is_some() => ("=[C]".into(), "[C]".into(), -1.0, -1.0, "C"),
+        // 末尾呼び出しで消えたフレーム。
+        None => (
+            "=(tail call)".into(),
+            "(tail call)".into(),
+            -1.0,
+            -1.0,
+            "tail",
+        ),
+    };
+    set_str(state, tk, "source", &source);
+    set_str(state, tk, "short_src", &short_src);
+    aux::set_field(state, tk, "linedefined", Value::number(line_defined));
     aux::set_field(
         state,
         tk,
         "lastlinedefined",
-        Value::Number(last_line_defined as f64),
+        Value::number(last_line_defined),
     );
-    aux::set_field(state, tk, "nups", Value::Number(nups as f64));
+    set_str(state, tk, "what", what);
+}
 
-    // what: Lua / C / main
-    let what = match state.global.heap.get_closure(closure_key) {
-        Some(Closure::Lua(lc)) => {
-            if lc.proto().line_defined == 0 {
-                "main"
-            } else {
-                "Lua"
-            }
+/// `'L'`: 命令のある行を集めた `{ [line] = true }`（Lua 関数以外は nil）。
+fn info_active_lines(state: &mut LuaState, tk: TableKey, closure: Option<crate::gc::ClosureKey>) {
+    let lines = match closure.and_then(|ck| state.global.heap.get_closure(ck)) {
+        Some(Closure::Lua(lc)) => lc.proto().line_info.clone(),
+        _ => {
+            aux::set_field(state, tk, "activelines", Value::NIL);
+            return;
         }
-        _ => "C",
     };
-    let what_val = state.new_string(what.as_bytes());
-    aux::set_field(state, tk, "what", what_val);
+    let t = state.new_table();
+    // 先にフィールドへ置いて GC から守る。
+    aux::set_field(state, tk, "activelines", t);
+    if let Some(lk) = t.as_table()
+        && let Some(table) = state.global.heap.get_table_mut(lk)
+    {
+        for line in lines {
+            let _ = table.set(Value::number(line as f64), Value::TRUE);
+        }
+    }
 }
 
 fn l_getinfo(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
+    let (thread, arg) = thread_arg(state, &args);
+
+    let info = {
+        let v = aux::opt_value(&args, arg);
+        if let Some(ck) = v.as_closure() {
+            FrameInfo {
+                closure: Some(ck),
+                current_line: -1.0,
+                name: None,
+            }
+        } else if let Some(level) = v.as_number() {
+            let info = (level >= 0.0)
+                .then(|| state.with_thread(thread, |s| frame_info(s, level as usize)))
+                .flatten()
+                .flatten();
+            match info {
+                Some(info) => info,
+                // 範囲外のレベル（終了したコルーチンを含む）は nil。
+                None => return aux::ret(state, vec![Value::NIL]),
+            }
+        } else {
+            return Err(aux::arg_error(
+                state,
+                arg + 1,
+                "getinfo",
+                "function or level expected",
+            ));
+        }
+    };
+    let options = {
+        let v = aux::opt_value(&args, arg + 1);
+        if v.is_nil() {
+            b"flnSu".to_vec()
+        } else {
+            aux::check_str_bytes(state, &args, arg + 1, "getinfo")?
+        }
+    };
+    let closure = info.closure;
 
-    // 戻り値テーブルを確保。
     let tbl = state.new_table();
-    let tk = match tbl {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return aux::ret(state, vec![Value::Nil]),
+    let Some(tk) = tbl.as_table() else {
+        return aux::ret(state, vec![Value::NIL]);
     };
-
-    // デフォルト値を設定。
-    aux::set_field(state, tk, "currentline", Value::Number(-1.0));
-    aux::set_field(state, tk, "linedefined", Value::Number(-1.0));
-    aux::set_field(state, tk, "lastlinedefined", Value::Number(-1.0));
-    aux::set_field(state, tk, "nups", Value::Number(0.0));
-    {
-        let nil_v = Value::Nil;
-        aux::set_field(state, tk, "name", nil_v);
-    }
-    {
-        let empty = state.new_string(b"");
-        aux::set_field(state, tk, "namewhat", empty);
-    }
-
-    match aux::opt_value(&args, 0) {
-        // 引数なし、または nil: 呼び出し元（スタックレベル1）の情報。
-        Value::Nil => {
-            // 呼び出し元のフレームを探す。
-            // call_info の末尾は getinfo 自身（ネイティブ）、その1つ前が呼び出し元。
-            let frames = state.call_info.len();
-            if frames >= 2 {
-                let ci = &state.call_info[frames - 2];
-                let current_line = ci.current_line;
-                let source = ci.source.clone();
-                let closure_key = if let Some(Value::GcRef(GcHandle::Closure(k))) = state
-                    .stack
-                    .get(state.call_info[frames - 2].func)
-                    .copied()
-                    .map(Some)
-                    .unwrap_or(None)
-                {
-                    Some(k)
-                } else {
-                    None
+    // 結果テーブルを組み立てる間、スタックに置いて GC から守る。
+    state.stack.push(tbl);
+
+    for &opt in &options {
+        match opt {
+            b'S' => info_source(state, tk, closure),
+            b'l' => {
+                aux::set_field(state, tk, "currentline", Value::number(info.current_line));
+            }
+            b'u' => {
+                let nups = match closure.and_then(|ck| state.global.heap.get_closure(ck)) {
+                    Some(Closure::Lua(lc)) => lc.upvalues().len(),
+                    Some(Closure::Native(nc)) => nc.upvalues().len(),
+                    None => 0,
                 };
-
-                aux::set_field(state, tk, "currentline", Value::Number(current_line as f64));
-                if let Some(src) = source {
-                    let src_v = state.new_string(src.as_bytes());
-                    aux::set_field(state, tk, "source", src_v);
-                    let short = src.strip_prefix('@').unwrap_or(src.as_str()).to_string();
-                    let short_v = state.new_string(short.as_bytes());
-                    aux::set_field(state, tk, "short_src", short_v);
-                }
-                if let Some(ck) = closure_key {
-                    let func_v = Value::GcRef(GcHandle::Closure(ck));
-                    aux::set_field(state, tk, "func", func_v);
-                    fill_lua_closure_info(state, tk, ck);
-                }
+                aux::set_field(state, tk, "nups", Value::number(nups as f64));
             }
-        }
-
-        // 数値: スタックレベル。
-        Value::Number(level) => {
-            let level = level as usize;
-            let frames = state.call_info.len();
-            // level 0 = 現在の関数（getinfo 自身）、level 1 = 呼び出し元...
-            if level < frames {
-                let ci_idx = frames - 1 - level;
-                let ci = &state.call_info[ci_idx];
-                let current_line = ci.current_line;
-                let source = ci.source.clone();
-                let func_stack_pos = ci.func;
-
-                aux::set_field(state, tk, "currentline", Value::Number(current_line as f64));
-                if let Some(src) = source {
-                    let src_v = state.new_string(src.as_bytes());
-                    aux::set_field(state, tk, "source", src_v);
-                    let short = src.strip_prefix('@').unwrap_or(src.as_str()).to_string();
-                    let short_v = state.new_string(short.as_bytes());
-                    aux::set_field(state, tk, "short_src", short_v);
-                }
-
-                // func フィールド: スタック上の関数値。
-                if let Some(&func_val) = state.stack.get(func_stack_pos) {
-                    aux::set_field(state, tk, "func", func_val);
-                    if let Value::GcRef(GcHandle::Closure(ck)) = func_val {
-                        fill_lua_closure_info(state, tk, ck);
-                    }
-                }
+            b'n' => {
+                let (namewhat, name) = match &info.name {
+                    Some((what, name)) => (*what, state.new_string(name.as_bytes())),
+                    None => ("", Value::NIL),
+                };
+                aux::set_field(state, tk, "name", name);
+                set_str(state, tk, "namewhat", namewhat);
+            }
+            b'f' => {
+                let func = closure.map_or(Value::NIL, Value::closure);
+                aux::set_field(state, tk, "func", func);
+            }
+            b'L' => info_active_lines(state, tk, closure),
+            _ => {
+                state.stack.pop();
+                return Err(aux::arg_error(state, arg + 2, "getinfo", "invalid option"));
             }
-        }
-
-        // 関数値: クロージャ情報を直接取得。
-        Value::GcRef(GcHandle::Closure(ck)) => {
-            let func_v = Value::GcRef(GcHandle::Closure(ck));
-            aux::set_field(state, tk, "func", func_v);
-            fill_lua_closure_info(state, tk, ck);
-            // スタックから currentline を探す（呼び出し中なら）。
-            // 関数値のみ指定時は currentline = -1 のまま（呼び出し元なし）。
-        }
-
-        // その他（テーブル等）は nil を返す（無効引数）。
-        _ => {
-            return aux::ret(state, vec![Value::Nil]);
         }
     }
-
+    state.stack.pop();
     aux::ret(state, vec![tbl])
 }
 
@@ -287,21 +292,22 @@ fn l_getmetatable(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let v = aux::opt_value(&args, 0);
     // aux::metatable_handle は __metatable を考慮しないので直接取得する。
-    let mt = match v {
-        Value::GcRef(GcHandle::Table(k)) => {
-            state.global.heap.get_table(k).and_then(|t| t.metatable())
-        }
-        Value::GcRef(GcHandle::Userdata(k)) => state
+    let mt = if let Some(k) = v.as_table() {
+        state.global.heap.get_table(k).and_then(|t| t.metatable())
+    } else if let Some(k) = v.as_userdata() {
+        state
             .global
             .heap
             .get_userdata(k)
-            .and_then(|u| u.metatable()),
-        Value::GcRef(GcHandle::Str(_)) => state.global.string_metatable,
-        _ => None,
+            .and_then(|u| u.metatable())
+    } else if v.as_string().is_some() {
+        state.global.string_metatable
+    } else {
+        None
     };
     let result = match mt {
-        Some(h) => Value::GcRef(h),
-        None => Value::Nil,
+        Some(h) => Value::gc(h),
+        None => Value::NIL,
     };
     aux::ret(state, vec![result])
 }
@@ -314,10 +320,10 @@ fn l_setmetatable(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let v = aux::opt_value(&args, 0);
     let mt = aux::opt_value(&args, 1);
-    let mt_handle = match mt {
-        Value::Nil => None,
-        Value::GcRef(h @ GcHandle::Table(_)) => Some(h),
-        _ => {
+    let mt_handle = match mt.as_table() {
+        Some(k) => Some(GcHandle::Table(k)),
+        None if mt.is_nil() => None,
+        None => {
             return Err(aux::arg_error(
                 state,
                 2,
@@ -326,34 +332,24 @@ fn l_setmetatable(state: &mut LuaState) -> LuaResult<i32> {
             ));
         }
     };
-    match v {
-        Value::GcRef(GcHandle::Table(tk)) => {
-            if let Some(t) = state.global.heap.get_table_mut(tk) {
-                t.set_metatable(mt_handle);
-            }
-        }
-        Value::GcRef(GcHandle::Userdata(uk)) => {
-            if let Some(u) = state.global.heap.get_userdata_mut(uk) {
-                u.set_metatable(mt_handle);
-            }
+    if let Some(tk) = v.as_table() {
+        if let Some(t) = state.global.heap.get_table_mut(tk) {
+            t.set_metatable(mt_handle);
         }
+    } else if let Some(uk) = v.as_userdata() {
+        state.global.heap.set_userdata_metatable(uk, mt_handle);
+    } else if v.as_string().is_some() {
         // 文字列型には string_metatable をセット。
-        Value::GcRef(GcHandle::Str(_)) => {
-            state.global.string_metatable = mt_handle;
-        }
+        state.global.string_metatable = mt_handle;
+    } else if v.is_number() {
         // 数値型の型共有メタテーブル。
-        Value::Number(_) => {
-            state.global.number_metatable = mt_handle;
-        }
+        state.global.number_metatable = mt_handle;
+    } else if v.as_boolean().is_some() {
         // boolean型の型共有メタテーブル。
-        Value::Boolean(_) => {
-            state.global.boolean_metatable = mt_handle;
-        }
+        state.global.boolean_metatable = mt_handle;
+    } else if v.is_nil() {
         // nil型の型共有メタテーブル。
-        Value::Nil => {
-            state.global.nil_metatable = mt_handle;
-        }
-        _ => {}
+        state.global.nil_metatable = mt_handle;
     }
     aux::ret(state, vec![v])
 }
@@ -363,7 +359,7 @@ fn l_setmetatable(state: &mut LuaState) -> LuaResult<i32> {
 // ============================================================================
 
 fn l_getregistry(state: &mut LuaState) -> LuaResult<i32> {
-    let reg = Value::GcRef(state.global.registry);
+    let reg = Value::gc(state.global.registry);
     aux::ret(state, vec![reg])
 }
 
@@ -374,45 +370,50 @@ fn l_getregistry(state: &mut LuaState) -> LuaResult<i32> {
 fn l_getupvalue(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let f = aux::opt_value(&args, 0);
-    let n = match aux::opt_value(&args, 1) {
-        Value::Number(v) => v as usize,
-        _ => return aux::ret(state, vec![Value::Nil]),
+    let n = {
+        let v = aux::opt_value(&args, 1);
+        if let Some(v) = v.as_number() {
+            v as usize
+        } else {
+            return aux::ret(state, vec![Value::NIL]);
+        }
     };
     if n == 0 {
-        return aux::ret(state, vec![Value::Nil]);
+        return aux::ret(state, vec![Value::NIL]);
     }
     let idx = n - 1; // 0-origin へ変換。
 
-    match f {
-        Value::GcRef(GcHandle::Closure(ck)) => {
-            match state.global.heap.get_closure(ck) {
-                Some(Closure::Lua(lc)) => {
-                    let name = lc
-                        .proto()
-                        .upvalue_names
-                        .get(idx)
-                        .cloned()
-                        .unwrap_or_else(|| format!("(upvalue {})", n));
-                    let val = match lc.upvalue(idx) {
-                        Some(uv) => match &*uv.borrow() {
-                            crate::value::closure::UpvalueState::Closed(v) => *v,
-                            crate::value::closure::UpvalueState::Open(stack_idx) => {
-                                state.stack.get(*stack_idx).copied().unwrap_or(Value::Nil)
-                            }
-                        },
-                        None => return aux::ret(state, vec![Value::Nil]),
-                    };
-                    let name_v = state.new_string(name.as_bytes());
-                    aux::ret(state, vec![name_v, val])
-                }
-                Some(Closure::Native(_)) => {
-                    // ネイティブクロージャの upvalue は名前情報なし。
-                    aux::ret(state, vec![Value::Nil])
-                }
-                None => aux::ret(state, vec![Value::Nil]),
+    if let Some(ck) = f.as_closure() {
+        match state.global.heap.get_closure(ck) {
+            Some(Closure::Lua(lc)) => {
+                let name = lc
+                    .proto()
+                    .upvalue_names
+                    .get(idx)
+                    .cloned()
+                    .unwrap_or_else(|| format!("(upvalue {})", n));
+                let val = match lc.upvalue(idx) {
+                    Some(uv) => match &*uv.borrow() {
+                        crate::value::closure::UpvalueState::Closed(v) => *v,
+                        crate::value::closure::UpvalueState::Open(thread, stack_idx) => state
+                            .stack_of(*thread)
+                            .and_then(|s| s.get(*stack_idx))
+                            .copied()
+                            .unwrap_or(Value::NIL),
+                    },
+                    None => return aux::ret(state, vec![Value::NIL]),
+                };
+                let name_v = state.new_string(name.as_bytes());
+                aux::ret(state, vec![name_v, val])
             }
+            Some(Closure::Native(_)) => {
+                // ネイティブクロージャの upvalue は名前情報なし。
+                aux::ret(state, vec![Value::NIL])
+            }
+            None => aux::ret(state, vec![Value::NIL]),
         }
-        _ => aux::ret(state, vec![Value::Nil]),
+    } else {
+        aux::ret(state, vec![Value::NIL])
     }
 }
 
@@ -423,91 +424,311 @@ fn l_getupvalue(state: &mut LuaState) -> LuaResult<i32> {
 fn l_setupvalue(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let f = aux::opt_value(&args, 0);
-    let n = match aux::opt_value(&args, 1) {
-        Value::Number(v) => v as usize,
-        _ => return aux::ret(state, vec![Value::Nil]),
+    let n = {
+        let v = aux::opt_value(&args, 1);
+        if let Some(v) = v.as_number() {
+            v as usize
+        } else {
+            return aux::ret(state, vec![Value::NIL]);
+        }
     };
     let new_val = aux::opt_value(&args, 2);
     if n == 0 {
-        return aux::ret(state, vec![Value::Nil]);
+        return aux::ret(state, vec![Value::NIL]);
     }
     let idx = n - 1;
 
-    match f {
-        Value::GcRef(GcHandle::Closure(ck)) => {
-            // upvalue名を先に取得（不変参照が必要なため）。
-            let name = match state.global.heap.get_closure(ck) {
-                Some(Closure::Lua(lc)) => {
-                    if lc.upvalue(idx).is_none() {
-                        return aux::ret(state, vec![Value::Nil]);
-                    }
-                    lc.proto()
-                        .upvalue_names
-                        .get(idx)
-                        .cloned()
-                        .unwrap_or_else(|| format!("(upvalue {})", n))
+    if let Some(ck) = f.as_closure() {
+        // upvalue名を先に取得（不変参照が必要なため）。
+        let name = match state.global.heap.get_closure(ck) {
+            Some(Closure::Lua(lc)) => {
+                if lc.upvalue(idx).is_none() {
+                    return aux::ret(state, vec![Value::NIL]);
                 }
-                _ => return aux::ret(state, vec![Value::Nil]),
-            };
+                lc.proto()
+                    .upvalue_names
+                    .get(idx)
+                    .cloned()
+                    .unwrap_or_else(|| format!("(upvalue {})", n))
+            }
+            _ => return aux::ret(state, vec![Value::NIL]),
+        };
 
-            // upvalue を書き換える。
-            let uv_ref = match state.global.heap.get_closure(ck) {
-                Some(Closure::Lua(lc)) => lc.upvalue(idx).cloned(),
-                _ => None,
-            };
+        // upvalue を書き換える。
+        let uv_ref = match state.global.heap.get_closure(ck) {
+            Some(Closure::Lua(lc)) => lc.upvalue(idx).cloned(),
+            _ => None,
+        };
 
-            if let Some(uv) = uv_ref {
-                let mut borrow = uv.borrow_mut();
-                match &*borrow {
-                    crate::value::closure::UpvalueState::Closed(_) => {
-                        *borrow = crate::value::closure::UpvalueState::Closed(new_val);
-                    }
-                    crate::value::closure::UpvalueState::Open(stack_idx) => {
-                        let si = *stack_idx;
-                        drop(borrow);
-                        if let Some(slot) = state.stack.get_mut(si) {
-                            *slot = new_val;
-                        }
-                        let name_v = state.new_string(name.as_bytes());
-                        return aux::ret(state, vec![name_v]);
-                    }
+        if let Some(uv) = uv_ref {
+            let mut borrow = uv.borrow_mut();
+            match &*borrow {
+                crate::value::closure::UpvalueState::Closed(_) => {
+                    state.global.heap.barrier(new_val);
+                    *borrow = crate::value::closure::UpvalueState::Closed(new_val);
+                }
+                crate::value::closure::UpvalueState::Open(thread, stack_idx) => {
+                    let (thread, si) = (*thread, *stack_idx);
+                    drop(borrow);
+                    state.set_stack_slot(thread, si, new_val);
+                    let name_v = state.new_string(name.as_bytes());
+                    return aux::ret(state, vec![name_v]);
                 }
-            } else {
-                return aux::ret(state, vec![Value::Nil]);
             }
-
-            let name_v = state.new_string(name.as_bytes());
-            aux::ret(state, vec![name_v])
+        } else {
+            return aux::ret(state, vec![Value::NIL]);
         }
-        _ => aux::ret(state, vec![Value::Nil]),
+
+        let name_v = state.new_string(name.as_bytes());
+        aux::ret(state, vec![name_v])
+    } else {
+        aux::ret(state, vec![Value::NIL])
     }
 }
 
 // ============================================================================
-// debug.getlocal / debug.setlocal — スタブ（最小実装）。
+// debug.getlocal([thread,] level, n) / debug.setlocal([thread,] level, n, value)
 // ============================================================================
 
+/// フレーム `ci_idx` の `n` 番目のローカル変数を探す（本家 `findlocal`）。
+///
+/// 正の `n` は有効なローカル変数（名前は `Proto::local_vars` から）、名前の無いスロットは
+/// フレーム内に収まる限り `"(*temporary)"`。負の `n` は可変長引数（`"(*vararg)"`, 5.2 以降の拡張）。
+///
+/// 見つかれば名前と値の置き場所（VM スタックの絶対位置）を返す。可変長引数は `base` の直下にある
+/// （[`CallInfo::nvarargs`](crate::state::CallInfo::nvarargs)）。
+fn find_local(state: &LuaState, ci_idx: usize, n: i64) -> Option<(String, usize)> {
+    let ci = &state.call_info[ci_idx];
+    if n < 0 {
+        let idx = n.unsigned_abs() as usize - 1;
+        return (ci.lua_closure.is_some() && idx < ci.nvarargs)
+            .then(|| ("(*vararg)".to_string(), ci.base - ci.nvarargs + idx));
+    }
+    let n = n as usize;
+    if n == 0 {
+        return None;
+    }
+    let slot = ci.base + n - 1;
+    if let Some(k) = ci.lua_closure
+        && let Some(Closure::Lua(lc)) = state.global.heap.get_closure(k)
+        && let Some(name) = lc.proto().local_name(n, ci.current_pc)
+    {
+        return Some((name.to_string(), slot));
+    }
+    // 名前の無いスロット: 次のフレームの開始位置（最上位ならスタック末尾）までに収まるか。
+    let limit = state
+        .call_info
+        .get(ci_idx + 1)
+        .map_or(state.stack.len(), |next| next.func);
+    (limit.saturating_sub(ci.base) >= n).then(|| ("(*temporary)".to_string(), slot))
+}
+
+/// `[thread,] level, n` を読み、対象スレッド・level・`n`・level 引数の位置を返す。
+fn local_args(
+    state: &mut LuaState,
+    args: &[Value],
+    fname: &str,
+) -> LuaResult<(Option<ThreadKey>, i64, i64, usize)> {
+    let (thread, arg) = thread_arg(state, args);
+    let level = aux::check_int(state, args, arg, fname)?;
+    let n = aux::check_int(state, args, arg + 1, fname)?;
+    Ok((thread, level, n, arg))
+}
+
+/// 実行中のスレッドの `level` 番目のフレームの `n` 番目のローカル変数の名前と値を返し、
+/// `value` があれば書き込む。レベルが範囲外なら `None`。末尾呼び出しで消えたフレームや
+/// 該当する変数が無ければ `Some(None)`。level 0 は getlocal/setlocal 自身。
+fn access_local(
+    state: &mut LuaState,
+    level: i64,
+    n: i64,
+    value: Option<Value>,
+) -> Option<Option<(String, Value)>> {
+    let idx = match (level >= 0)
+        .then(|| stack_level(state, level as usize))
+        .flatten()?
+    {
+        StackLevel::Frame(idx) => idx,
+        StackLevel::TailCall => return Some(None),
+    };
+    let Some((name, slot)) = find_local(state, idx, n) else {
+        return Some(None);
+    };
+    let old = state.stack.get(slot).copied().unwrap_or(Value::NIL);
+    if let Some(v) = value
+        && let Some(s) = state.stack.get_mut(slot)
+    {
+        *s = v;
+    }
+    Some(Some((name, old)))
+}
+
 fn l_getlocal(state: &mut LuaState) -> LuaResult<i32> {
-    // 最小実装: 常に nil を返す。
-    aux::ret(state, vec![Value::Nil])
+    let args = aux::args_vec(state);
+    let (thread, level, n, arg) = local_args(state, &args, "getlocal")?;
+    let Some(found) = state
+        .with_thread(thread, |s| access_local(s, level, n, None))
+        .flatten()
+    else {
+        return Err(aux::arg_error(
+            state,
+            arg + 1,
+            "getlocal",
+            "level out of range",
+        ));
+    };
+    let Some((name, value)) = found else {
+        return aux::ret(state, vec![Value::NIL]);
+    };
+    let name = state.new_string(name.as_bytes());
+    aux::ret(state, vec![name, value])
 }
 
 fn l_setlocal(state: &mut LuaState) -> LuaResult<i32> {
-    // 最小実装: 常に nil を返す。
-    aux::ret(state, vec![Value::Nil])
+    let args = aux::args_vec(state);
+    let (thread, level, n, arg) = local_args(state, &args, "setlocal")?;
+    let value = aux::opt_value(&args, arg + 2);
+    let Some(found) = state
+        .with_thread(thread, |s| access_local(s, level, n, Some(value)))
+        .flatten()
+    else {
+        return Err(aux::arg_error(
+            state,
+            arg + 1,
+            "setlocal",
+            "level out of range",
+        ));
+    };
+    let Some((name, _)) = found else {
+        return aux::ret(state, vec![Value::NIL]);
+    };
+    let name = state.new_string(name.as_bytes());
+    aux::ret(state, vec![name])
 }
 
 // ============================================================================
-// debug.sethook / debug.gethook — no-op スタブ（フック機構未実装）。
+// debug.sethook([thread,] hook, mask [, count]) / debug.gethook([thread])
 // ============================================================================
 
+/// VM に設定する中継フック（本家 ldblib の `hookf`）。スレッドのフックに置いた Lua 関数を
+/// `(イベント名 [, 行番号])` で呼ぶ。
+fn hookf(state: &mut LuaState, event: HookEvent) -> LuaResult<()> {
+    let f = state.hook.data;
+    if f.as_closure().is_none() {
+        return Ok(());
+    }
+    let name = state.new_string(event.name().as_bytes());
+    let args = match event {
+        HookEvent::Line(line) => vec![name, Value::number(line as f64)],
+        _ => vec![name],
+    };
+    crate::vm::call(state, f, &args)?;
+    Ok(())
+}
+
+/// 先頭の省略可能な thread 引数を読み、対象スレッド（`None` はメインスレッド）と
+/// 残りの引数の開始位置を返す。省略時は実行中のスレッド。
+fn thread_arg(state: &LuaState, args: &[Value]) -> (Option<ThreadKey>, usize) {
+    match args.first().and_then(|v| v.as_thread()) {
+        Some(k) => (Some(k), 1),
+        None => (state.thread, 0),
+    }
+}
+
+/// マスク文字列（`"c"`/`"r"`/`"l"` の組み合わせ）をビット集合へ（本家 `makemask`）。
+fn make_mask(smask: &[u8], count: u32) -> u8 {
+    let mut mask = 0;
+    if smask.contains(&b'c') {
+        mask |= HOOK_MASK_CALL;
+    }
+    if smask.contains(&b'r') {
+        mask |= HOOK_MASK_RET;
+    }
+    if smask.contains(&b'l') {
+        mask |= HOOK_MASK_LINE;
+    }
+    if count > 0 {
+        mask |= HOOK_MASK_COUNT;
+    }
+    mask
+}
+
+/// ビット集合をマスク文字列へ（本家 `unmakemask`）。
+fn unmake_mask(mask: u8) -> String {
+    let mut s = String::new();
+    if mask & HOOK_MASK_CALL != 0 {
+        s.push('c');
+    }
+    if mask & HOOK_MASK_RET != 0 {
+        s.push('r');
+    }
+    if mask & HOOK_MASK_LINE != 0 {
+        s.push('l');
+    }
+    s
+}
+
 fn l_sethook(state: &mut LuaState) -> LuaResult<i32> {
-    // フック機構未実装: 何もしない。
+    let args = aux::args_vec(state);
+    let (thread, arg) = thread_arg(state, &args);
+    let func = aux::opt_value(&args, arg);
+    // 引数なし / nil はフック解除。
+    let (func, mask, count) = if func.is_nil() {
+        (Value::NIL, 0, 0)
+    } else {
+        let func = aux::check_function(state, &args, arg, "sethook")?;
+        let smask = aux::check_str_bytes(state, &args, arg + 1, "sethook")?;
+        let count = aux::opt_int(state, &args, arg + 2, "sethook", 0)?.max(0) as u32;
+        (func, make_mask(&smask, count), count)
+    };
+    let hook = if mask == 0 { None } else { Some(hookf as _) };
+    state.with_thread(thread, |s| {
+        s.set_hook(hook, mask, count);
+        if hook.is_some() {
+            s.hook.data = func;
+        }
+    });
     aux::ret0(state)
 }
 
 fn l_gethook(state: &mut LuaState) -> LuaResult<i32> {
-    // フック機構未実装: nil, "", 0 を返す（本家の返り値に倣う）。
-    let empty = state.new_string(b"");
-    aux::ret(state, vec![Value::Nil, empty, Value::Number(0.0)])
+    let args = aux::args_vec(state);
+    let (thread, _) = thread_arg(state, &args);
+    let hook = state.with_thread(thread, |s| s.hook).unwrap_or_default();
+    let func = match hook.func {
+        None => Value::NIL,
+        // Rust 側から設定されたフック。
+        Some(_) if hook.data.is_nil() => state.new_string(b"external hook"),
+        Some(_) => hook.data,
+    };
+    let mask = state.new_string(unmake_mask(hook.mask).as_bytes());
+    aux::ret(
+        state,
+        vec![func, mask, Value::number(hook.base_count as f64)],
+    )
+}
+
+// ============================================================================
+// debug.heapsnapshot(filename) — rua 拡張。ヒープのオブジェクトグラフを書き出す。
+// ============================================================================
+
+/// [`LuaState::heap_snapshot`] をファイルへ書き出す。拡張子が `.dot` なら Graphviz、
+/// それ以外は JSON。成功で true、失敗で nil とメッセージを返す（io ライブラリと同じ形）。
+fn l_heapsnapshot(state: &mut LuaState) -> LuaResult<i32> {
+    let args = aux::args_vec(state);
+    let name = aux::check_str_bytes(state, &args, 0, "heapsnapshot")?;
+    let filename = String::from_utf8_lossy(&name).into_owned();
+    let snapshot = state.heap_snapshot();
+    let out = if filename.ends_with(".dot") {
+        snapshot.to_dot()
+    } else {
+        snapshot.to_json()
+    };
+    match std::fs::write(&filename, out) {
+        Ok(()) => aux::ret(state, vec![Value::TRUE]),
+        Err(e) => {
+            let msg = state.new_string(format!("{}: {}", filename, e).as_bytes());
+            aux::ret(state, vec![Value::NIL, msg])
+        }
+    }
 }
@@ -306,16 +306,14 @@ fn get_file_metatable() -> Option<TableKey> {
 pub fn open(state: &mut LuaState) {
     // ファイルメソッドを保持するメタテーブルを作成する
     let file_mt = state.new_table();
-    let file_mt_k = match file_mt {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(file_mt_k) = file_mt.as_table() else {
+        return;
     };
 
     // ファイルオブジェクトのメソッドテーブル（__index に設定するテーブル）
     let methods_t = state.new_table();
-    let methods_k = match methods_t {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(methods_k) = methods_t.as_table() else {
+        return;
     };
 
     aux::register(state, methods_k, "read", file_read);
@@ -338,9 +336,8 @@ pub fn open(state: &mut LuaState) {
 
     // io ライブラリテーブルを作成
     let t = state.new_table();
-    let tk = match t {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(tk) = t.as_table() else {
+        return;
     };
 
     // io 関数を登録
@@ -378,16 +375,15 @@ fn make_file_userdata(state: &mut LuaState, handle: FileHandle) -> Value {
     if let Some(mt_k) = get_file_metatable() {
         ud.set_metatable(Some(GcHandle::Table(mt_k)));
     }
-    Value::GcRef(state.global.heap.alloc_userdata(ud))
+    Value::gc(state.global.heap.alloc_userdata(ud))
 }
 
 fn get_file_handle(state: &LuaState, v: Value) -> Option<FileHandleRef> {
-    match v {
-        Value::GcRef(GcHandle::Userdata(k)) => {
-            let ud = state.global.heap.get_userdata(k)?;
-            ud.data().downcast_ref::<FileHandleRef>().cloned()
-        }
-        _ => None,
+    if let Some(k) = v.as_userdata() {
+        let ud = state.global.heap.get_userdata(k)?;
+        ud.data().downcast_ref::<FileHandleRef>().cloned()
+    } else {
+        None
     }
 }
 
@@ -400,7 +396,7 @@ fn get_file_handle(state: &LuaState, v: Value) -> Option<FileHandleRef> {
 fn l_open(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let filename = aux::check_str_bytes(state, &args, 0, "open")?;
-    let mode_bytes = if matches!(aux::opt_value(&args, 1), Value::Nil) {
+    let mode_bytes = if aux::opt_value(&args, 1).is_nil() {
         b"r".to_vec()
     } else {
         aux::check_str_bytes(state, &args, 1, "open")?
@@ -427,7 +423,7 @@ fn l_open(state: &mut LuaState) -> LuaResult<i32> {
             _ => {
                 let msg =
                     state.new_string(format!("invalid mode '{}' in io.open", mode_str).as_bytes());
-                return aux::ret(state, vec![Value::Nil, msg]);
+                return aux::ret(state, vec![Value::NIL, msg]);
             }
         }
     }
@@ -451,7 +447,7 @@ fn l_open(state: &mut LuaState) -> LuaResult<i32> {
         }
         Err(e) => {
             let msg = state.new_string(format!("{}: {}", filename_str, e).as_bytes());
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
@@ -461,19 +457,19 @@ fn l_close(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let v = aux::opt_value(&args, 0);
 
-    if matches!(v, Value::Nil) {
+    if v.is_nil() {
         // デフォルト出力を閉じる（stdout は閉じない）
-        return aux::ret(state, vec![Value::Boolean(true)]);
+        return aux::ret(state, vec![Value::TRUE]);
     }
 
     match get_file_handle(state, v) {
         Some(fh) => {
             let closed = fh.borrow_mut().close();
-            aux::ret(state, vec![Value::Boolean(closed)])
+            aux::ret(state, vec![Value::boolean(closed)])
         }
         None => {
             let msg = state.new_string(b"file expected");
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
@@ -489,19 +485,17 @@ fn l_write(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let mut buf: Vec<u8> = Vec::new();
     for (i, v) in args.iter().enumerate() {
-        match v {
-            Value::GcRef(GcHandle::Str(k)) => {
-                buf.extend_from_slice(state.global.heap.get_str(*k).unwrap().as_bytes());
-            }
-            Value::Number(n) => buf.extend_from_slice(number_to_string(*n).as_bytes()),
-            other => {
-                return Err(aux::arg_error(
-                    state,
-                    i + 1,
-                    "write",
-                    &format!("string expected, got {}", other.type_of().name()),
-                ));
-            }
+        if let Some(k) = v.as_string() {
+            buf.extend_from_slice(state.global.heap.get_str(k).unwrap().as_bytes());
+        } else if let Some(n) = v.as_number() {
+            buf.extend_from_slice(number_to_string(n).as_bytes())
+        } else {
+            return Err(aux::arg_error(
+                state,
+                i + 1,
+                "write",
+                &format!("string expected, got {}", v.type_of().name()),
+            ));
         }
     }
     let stdout = std::io::stdout();
@@ -514,7 +508,7 @@ fn l_lines(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let v = aux::opt_value(&args, 0);
 
-    if matches!(v, Value::Nil) {
+    if v.is_nil() {
         // stdin から読む行イテレータ
         let iter = aux::make_native(state, lines_iter_stdin);
         return aux::ret(state, vec![iter]);
@@ -533,14 +527,14 @@ fn l_lines(state: &mut LuaState) -> LuaResult<i32> {
             // ファイルオブジェクトをテーブルに格納してイテレータを返す
             // テーブルに file を保存してクロージャ状態として使う
             let state_t = state.new_table();
-            if let Value::GcRef(GcHandle::Table(stk)) = state_t {
+            if let Some(stk) = state_t.as_table() {
                 let key = state.new_string(b"file");
                 if let Some(t) = state.global.heap.get_table_mut(stk) {
                     let _ = t.set(key, ud_val);
                 }
                 // __call メタメソッドを持つテーブルをイテレータ状態として返す
                 let mt = state.new_table();
-                if let Value::GcRef(GcHandle::Table(mtk)) = mt {
+                if let Some(mtk) = mt.as_table() {
                     aux::register(state, mtk, "__call", lines_iter_file);
                     if let Some(t) = state.global.heap.get_table_mut(stk) {
                         t.set_metatable(Some(GcHandle::Table(mtk)));
@@ -565,7 +559,7 @@ fn lines_iter_stdin(state: &mut LuaState) -> LuaResult<i32> {
     let mut line = String::new();
     let read = lock.read_line(&mut line).unwrap_or(0);
     if read == 0 {
-        return aux::ret(state, vec![Value::Nil]);
+        return aux::ret(state, vec![Value::NIL]);
     }
     while matches!(line.as_bytes().last(), Some(b'\n') | Some(b'\r')) {
         line.pop();
@@ -576,7 +570,7 @@ fn lines_iter_stdin(state: &mut LuaState) -> LuaResult<i32> {
 
 fn lines_iter_file_wrapper(state: &mut LuaState) -> LuaResult<i32> {
     // このアプローチは使わない（state_t の __call に委ねる）
-    aux::ret(state, vec![Value::Nil])
+    aux::ret(state, vec![Value::NIL])
 }
 
 /// `io.flush()`: stdout をフラッシュ。
@@ -591,34 +585,32 @@ fn l_input(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let v = aux::opt_value(&args, 0);
 
-    if matches!(v, Value::Nil) {
+    if v.is_nil() {
         // デフォルト入力（stdin）を返す
         let stdin_val = make_file_userdata(state, FileHandle::Stdin);
         return aux::ret(state, vec![stdin_val]);
     }
 
-    match v {
-        Value::GcRef(GcHandle::Str(_)) => {
-            // ファイル名が指定された場合はファイルを開く
-            let filename = aux::check_str_bytes(state, &args, 0, "input")?;
-            let filename_str = String::from_utf8_lossy(&filename).to_string();
-            match std::fs::File::open(&filename_str) {
-                Ok(file) => {
-                    let handle = FileHandle::File {
-                        file,
-                        closed: false,
-                    };
-                    let ud_val = make_file_userdata(state, handle);
-                    aux::ret(state, vec![ud_val])
-                }
-                Err(e) => Err(aux::rt_error(state, format!("{}: {}", filename_str, e))),
+    if v.as_string().is_some() {
+        // ファイル名が指定された場合はファイルを開く
+        let filename = aux::check_str_bytes(state, &args, 0, "input")?;
+        let filename_str = String::from_utf8_lossy(&filename).to_string();
+        match std::fs::File::open(&filename_str) {
+            Ok(file) => {
+                let handle = FileHandle::File {
+                    file,
+                    closed: false,
+                };
+                let ud_val = make_file_userdata(state, handle);
+                aux::ret(state, vec![ud_val])
             }
+            Err(e) => Err(aux::rt_error(state, format!("{}: {}", filename_str, e))),
         }
-        Value::GcRef(GcHandle::Userdata(_)) => {
-            // ファイルオブジェクトが指定された場合はそのまま返す
-            aux::ret(state, vec![v])
-        }
-        _ => Err(aux::arg_error(state, 1, "input", "string or file expected")),
+    } else if v.as_userdata().is_some() {
+        // ファイルオブジェクトが指定された場合はそのまま返す
+        aux::ret(state, vec![v])
+    } else {
+        Err(aux::arg_error(state, 1, "input", "string or file expected"))
     }
 }
 
@@ -627,35 +619,35 @@ fn l_output(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let v = aux::opt_value(&args, 0);
 
-    if matches!(v, Value::Nil) {
+    if v.is_nil() {
         // デフォルト出力（stdout）を返す
         let stdout_val = make_file_userdata(state, FileHandle::Stdout);
         return aux::ret(state, vec![stdout_val]);
     }
 
-    match v {
-        Value::GcRef(GcHandle::Str(_)) => {
-            let filename = aux::check_str_bytes(state, &args, 0, "output")?;
-            let filename_str = String::from_utf8_lossy(&filename).to_string();
-            match std::fs::File::create(&filename_str) {
-                Ok(file) => {
-                    let handle = FileHandle::File {
-                        file,
-                        closed: false,
-                    };
-                    let ud_val = make_file_userdata(state, handle);
-                    aux::ret(state, vec![ud_val])
-                }
-                Err(e) => Err(aux::rt_error(state, format!("{}: {}", filename_str, e))),
+    if v.as_string().is_some() {
+        let filename = aux::check_str_bytes(state, &args, 0, "output")?;
+        let filename_str = String::from_utf8_lossy(&filename).to_string();
+        match std::fs::File::create(&filename_str) {
+            Ok(file) => {
+                let handle = FileHandle::File {
+                    file,
+                    closed: false,
+                };
+                let ud_val = make_file_userdata(state, handle);
+                aux::ret(state, vec![ud_val])
             }
+            Err(e) => Err(aux::rt_error(state, format!("{}: {}", filename_str, e))),
         }
-        Value::GcRef(GcHandle::Userdata(_)) => aux::ret(state, vec![v]),
-        _ => Err(aux::arg_error(
+    } else if v.as_userdata().is_some() {
+        aux::ret(state, vec![v])
+    } else {
+        Err(aux::arg_error(
             state,
             1,
             "output",
             "string or file expected",
-        )),
+        ))
     }
 }
 
@@ -674,7 +666,7 @@ fn l_type(state: &mut LuaState) -> LuaResult<i32> {
                 aux::ret(state, vec![s])
             }
         }
-        None => aux::ret(state, vec![Value::Nil]),
+        None => aux::ret(state, vec![Value::NIL]),
     }
 }
 
@@ -698,11 +690,11 @@ fn read_from_handle_args(
         && fh.borrow().is_closed()
     {
         let msg = state.new_string(b"attempt to use a closed file");
-        return aux::ret(state, vec![Value::Nil, msg]);
+        return aux::ret(state, vec![Value::NIL, msg]);
     }
 
     // フォーマットが無い場合は "*l" がデフォルト
-    if args.len() <= fmt_start || matches!(aux::opt_value(args, fmt_start), Value::Nil) {
+    if args.len() <= fmt_start || aux::opt_value(args, fmt_start).is_nil() {
         // デフォルト: "*l" (1行読み込み)
         let result = if let Some(ref fh) = fh_opt {
             fh.borrow_mut().read_line()
@@ -724,10 +716,10 @@ fn read_from_handle_args(
         };
         match result {
             Ok(Some(data)) => {
-                let v = state.new_string(&data);
+                let v = state.new_string_from(data);
                 return aux::ret(state, vec![v]);
             }
-            Ok(None) => return aux::ret(state, vec![Value::Nil]),
+            Ok(None) => return aux::ret(state, vec![Value::NIL]),
             Err(e) => return Err(aux::rt_error(state, e.to_string())),
         }
     }
@@ -749,35 +741,33 @@ fn read_one_format(
     fmt_val: Value,
     fh_opt: Option<&FileHandleRef>,
 ) -> LuaResult<Value> {
-    let fmt = match fmt_val {
-        Value::GcRef(GcHandle::Str(k)) => state.global.heap.get_str(k).unwrap().as_bytes().to_vec(),
-        Value::Number(n) => {
-            // 数値の場合は n バイト読む
-            let count = n as usize;
-            let result = if let Some(fh) = fh_opt {
-                fh.borrow_mut().read_bytes(count)
-            } else {
-                let stdin = std::io::stdin();
-                let mut lock = stdin.lock();
-                let mut buf = vec![0u8; count];
-                match lock.read(&mut buf) {
-                    Ok(0) => Ok(None),
-                    Ok(n) => {
-                        buf.truncate(n);
-                        Ok(Some(buf))
-                    }
-                    Err(e) => Err(e),
+    let fmt = if let Some(k) = fmt_val.as_string() {
+        state.global.heap.get_str(k).unwrap().as_bytes().to_vec()
+    } else if let Some(n) = fmt_val.as_number() {
+        // 数値の場合は n バイト読む
+        let count = n as usize;
+        let result = if let Some(fh) = fh_opt {
+            fh.borrow_mut().read_bytes(count)
+        } else {
+            let stdin = std::io::stdin();
+            let mut lock = stdin.lock();
+            let mut buf = vec![0u8; count];
+            match lock.read(&mut buf) {
+                Ok(0) => Ok(None),
+                Ok(n) => {
+                    buf.truncate(n);
+                    Ok(Some(buf))
                 }
-            };
-            return match result {
-                Ok(Some(data)) => Ok(state.new_string(&data)),
-                Ok(None) => Ok(Value::Nil),
-                Err(e) => Err(aux::rt_error(state, e.to_string())),
-            };
-        }
-        _ => {
-            return Err(aux::rt_error(state, "invalid format in read"));
-        }
+                Err(e) => Err(e),
+            }
+        };
+        return match result {
+            Ok(Some(data)) => Ok(state.new_string_from(data)),
+            Ok(None) => Ok(Value::NIL),
+            Err(e) => Err(aux::rt_error(state, e.to_string())),
+        };
+    } else {
+        return Err(aux::rt_error(state, "invalid format in read"));
     };
 
     let f = fmt.strip_prefix(b"*").unwrap_or(&fmt);
@@ -813,8 +803,8 @@ fn read_one_format(
                 })
             };
             match result {
-                Ok(Some(data)) => Ok(state.new_string(&data)),
-                Ok(None) => Ok(Value::Nil),
+                Ok(Some(data)) => Ok(state.new_string_from(data)),
+                Ok(None) => Ok(Value::NIL),
                 Err(e) => Err(aux::rt_error(state, e.to_string())),
             }
         }
@@ -836,8 +826,8 @@ fn read_one_format(
                 }
             };
             match result {
-                Ok(Some(n)) => Ok(Value::Number(n)),
-                Ok(None) => Ok(Value::Nil),
+                Ok(Some(n)) => Ok(Value::number(n)),
+                Ok(None) => Ok(Value::NIL),
                 Err(e) => Err(aux::rt_error(state, e.to_string())),
             }
         }
@@ -852,7 +842,7 @@ fn read_one_format(
                 lock.read_to_end(&mut buf).map(|_| buf)
             };
             match result {
-                Ok(data) => Ok(state.new_string(&data)),
+                Ok(data) => Ok(state.new_string_from(data)),
                 Err(e) => Err(aux::rt_error(state, e.to_string())),
             }
         }
@@ -881,18 +871,18 @@ fn file_read(state: &mut LuaState) -> LuaResult<i32> {
 
     if fh_opt.borrow().is_closed() {
         let msg = state.new_string(b"attempt to use a closed file");
-        return aux::ret(state, vec![Value::Nil, msg]);
+        return aux::ret(state, vec![Value::NIL, msg]);
     }
 
     // フォーマット引数が無い場合はデフォルト "*l"
-    if fmt_args.is_empty() || matches!(fmt_args[0], Value::Nil) {
+    if fmt_args.is_empty() || fmt_args[0].is_nil() {
         let result = fh_opt.borrow_mut().read_line();
         return match result {
             Ok(Some(data)) => {
-                let v = state.new_string(&data);
+                let v = state.new_string_from(data);
                 aux::ret(state, vec![v])
             }
-            Ok(None) => aux::ret(state, vec![Value::Nil]),
+            Ok(None) => aux::ret(state, vec![Value::NIL]),
             Err(e) => Err(aux::rt_error(state, e.to_string())),
         };
     }
@@ -922,24 +912,22 @@ fn file_write(state: &mut LuaState) -> LuaResult<i32> {
 
     if fh.borrow().is_closed() {
         let msg = state.new_string(b"attempt to use a closed file");
-        return aux::ret(state, vec![Value::Nil, msg]);
+        return aux::ret(state, vec![Value::NIL, msg]);
     }
 
     let mut buf: Vec<u8> = Vec::new();
     for (i, v) in args[1..].iter().enumerate() {
-        match v {
-            Value::GcRef(GcHandle::Str(k)) => {
-                buf.extend_from_slice(state.global.heap.get_str(*k).unwrap().as_bytes());
-            }
-            Value::Number(n) => buf.extend_from_slice(number_to_string(*n).as_bytes()),
-            other => {
-                return Err(aux::arg_error(
-                    state,
-                    i + 2,
-                    "write",
-                    &format!("string expected, got {}", other.type_of().name()),
-                ));
-            }
+        if let Some(k) = v.as_string() {
+            buf.extend_from_slice(state.global.heap.get_str(k).unwrap().as_bytes());
+        } else if let Some(n) = v.as_number() {
+            buf.extend_from_slice(number_to_string(n).as_bytes())
+        } else {
+            return Err(aux::arg_error(
+                state,
+                i + 2,
+                "write",
+                &format!("string expected, got {}", v.type_of().name()),
+            ));
         }
     }
 
@@ -947,7 +935,7 @@ fn file_write(state: &mut LuaState) -> LuaResult<i32> {
         Ok(()) => aux::ret(state, vec![file_val]),
         Err(e) => {
             let msg = state.new_string(e.to_string().as_bytes());
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
@@ -963,7 +951,7 @@ fn file_close(state: &mut LuaState) -> LuaResult<i32> {
     match get_file_handle(state, file_val) {
         Some(fh) => {
             fh.borrow_mut().close();
-            aux::ret(state, vec![Value::Boolean(true)])
+            aux::ret(state, vec![Value::TRUE])
         }
         None => Err(aux::arg_error(state, 1, "close", "file expected")),
     }
@@ -981,14 +969,14 @@ fn file_lines(state: &mut LuaState) -> LuaResult<i32> {
         Some(_) => {
             // ファイルをテーブルに格納してイテレータを返す
             let state_t = state.new_table();
-            if let Value::GcRef(GcHandle::Table(stk)) = state_t {
+            if let Some(stk) = state_t.as_table() {
                 let fkey = state.new_string(b"file");
                 if let Some(t) = state.global.heap.get_table_mut(stk) {
                     let _ = t.set(fkey, file_val);
                 }
                 // __call でイテレータを実装
                 let mt = state.new_table();
-                if let Value::GcRef(GcHandle::Table(mtk)) = mt {
+                if let Some(mtk) = mt.as_table() {
                     aux::register(state, mtk, "__call", lines_iter_file);
                     if let Some(t) = state.global.heap.get_table_mut(stk) {
                         t.set_metatable(Some(GcHandle::Table(mtk)));
@@ -1006,34 +994,33 @@ fn lines_iter_file(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let state_t = aux::opt_value(&args, 0);
 
-    let file_val = match state_t {
-        Value::GcRef(GcHandle::Table(k)) => {
-            let fkey = state.new_string(b"file");
-            state
-                .global
-                .heap
-                .get_table(k)
-                .map(|t| t.get(&fkey))
-                .unwrap_or(Value::Nil)
-        }
-        _ => return aux::ret(state, vec![Value::Nil]),
+    let file_val = if let Some(k) = state_t.as_table() {
+        let fkey = state.new_string(b"file");
+        state
+            .global
+            .heap
+            .get_table(k)
+            .map(|t| t.get(&fkey))
+            .unwrap_or(Value::NIL)
+    } else {
+        return aux::ret(state, vec![Value::NIL]);
     };
 
     match get_file_handle(state, file_val) {
         Some(fh) => {
             if fh.borrow().is_closed() {
-                return aux::ret(state, vec![Value::Nil]);
+                return aux::ret(state, vec![Value::NIL]);
             }
             match fh.borrow_mut().read_line() {
                 Ok(Some(data)) => {
-                    let v = state.new_string(&data);
+                    let v = state.new_string_from(data);
                     aux::ret(state, vec![v])
                 }
-                Ok(None) => aux::ret(state, vec![Value::Nil]),
+                Ok(None) => aux::ret(state, vec![Value::NIL]),
                 Err(e) => Err(aux::rt_error(state, e.to_string())),
             }
         }
-        None => aux::ret(state, vec![Value::Nil]),
+        None => aux::ret(state, vec![Value::NIL]),
     }
 }
 
@@ -1054,7 +1041,7 @@ fn file_seek(state: &mut LuaState) -> LuaResult<i32> {
         return Err(aux::rt_error(state, "attempt to use a closed file"));
     }
 
-    let whence_bytes = if matches!(aux::opt_value(&args, 1), Value::Nil) {
+    let whence_bytes = if aux::opt_value(&args, 1).is_nil() {
         b"cur".to_vec()
     } else {
         aux::check_str_bytes(state, &args, 1, "seek")?
@@ -1071,10 +1058,10 @@ fn file_seek(state: &mut LuaState) -> LuaResult<i32> {
     };
 
     match fh.borrow_mut().seek(seek_from) {
-        Ok(pos) => aux::ret(state, vec![Value::Number(pos as f64)]),
+        Ok(pos) => aux::ret(state, vec![Value::number(pos as f64)]),
         Err(e) => {
             let msg = state.new_string(e.to_string().as_bytes());
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
@@ -1093,10 +1080,10 @@ fn file_flush(state: &mut LuaState) -> LuaResult<i32> {
     };
 
     match fh.borrow_mut().flush() {
-        Ok(()) => aux::ret(state, vec![Value::Boolean(true)]),
+        Ok(()) => aux::ret(state, vec![Value::TRUE]),
         Err(e) => {
             let msg = state.new_string(e.to_string().as_bytes());
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
@@ -1108,7 +1095,7 @@ fn file_setvbuf(state: &mut LuaState) -> LuaResult<i32> {
     if args.is_empty() {
         return Err(aux::arg_error(state, 1, "setvbuf", "file expected"));
     }
-    aux::ret(state, vec![Value::Boolean(true)])
+    aux::ret(state, vec![Value::TRUE])
 }
 
 /// `__tostring` メタメソッド: file オブジェクトの文字列表現。
@@ -20,9 +20,8 @@ thread_local! {
 
 pub fn open(state: &mut LuaState) {
     let m = state.new_table();
-    let mk = match m {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(mk) = m.as_table() else {
+        return;
     };
     aux::register(state, mk, "abs", l_abs);
     aux::register(state, mk, "ceil", l_ceil);
@@ -52,10 +51,10 @@ pub fn open(state: &mut LuaState) {
     aux::register(state, mk, "random", l_random);
     aux::register(state, mk, "randomseed", l_randomseed);
 
-    aux::set_field(state, mk, "pi", Value::Number(std::f64::consts::PI));
-    aux::set_field(state, mk, "huge", Value::Number(f64::INFINITY));
+    aux::set_field(state, mk, "pi", Value::number(std::f64::consts::PI));
+    aux::set_field(state, mk, "huge", Value::number(f64::INFINITY));
     // 本家: math.huge は HUGE_VAL（+inf）。最大/最小整数は無い。
-    aux::set_field(state, mk, "maxinteger", Value::Number(i64::MAX as f64));
+    aux::set_field(state, mk, "maxinteger", Value::number(i64::MAX as f64));
 
     // グローバルに math テーブルを設定。
     if let GcHandle::Table(g) = state.global.globals {
@@ -67,7 +66,7 @@ pub fn open(state: &mut LuaState) {
 fn unary(state: &mut LuaState, fname: &str, f: impl Fn(f64) -> f64) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let x = aux::check_number(state, &args, 0, fname)?;
-    aux::ret(state, vec![Value::Number(f(x))])
+    aux::ret(state, vec![Value::number(f(x))])
 }
 
 /// `math.frexp(x)`: x = m * 2^e（0.5 <= |m| < 1, または m=0/非有限はそのまま）。
@@ -91,14 +90,14 @@ fn l_ldexp(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let m = aux::check_number(state, &args, 0, "ldexp")?;
     let e = aux::check_int(state, &args, 1, "ldexp")?;
-    aux::ret(state, vec![Value::Number(m * 2.0f64.powi(e as i32))])
+    aux::ret(state, vec![Value::number(m * 2.0f64.powi(e as i32))])
 }
 
 fn l_frexp(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let x = aux::check_number(state, &args, 0, "frexp")?;
     let (m, e) = frexp(x);
-    aux::ret(state, vec![Value::Number(m), Value::Number(e as f64)])
+    aux::ret(state, vec![Value::number(m), Value::number(e as f64)])
 }
 
 fn l_abs(state: &mut LuaState) -> LuaResult<i32> {
@@ -160,7 +159,7 @@ fn l_pow(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let x = aux::check_number(state, &args, 0, "pow")?;
     let y = aux::check_number(state, &args, 1, "pow")?;
-    aux::ret(state, vec![Value::Number(x.powf(y))])
+    aux::ret(state, vec![Value::number(x.powf(y))])
 }
 
 fn l_fmod(state: &mut LuaState) -> LuaResult<i32> {
@@ -168,7 +167,7 @@ fn l_fmod(state: &mut LuaState) -> LuaResult<i32> {
     let x = aux::check_number(state, &args, 0, "fmod")?;
     let y = aux::check_number(state, &args, 1, "fmod")?;
     // C fmod: 符号は被除数に従う（Rust の % と同じ）。
-    aux::ret(state, vec![Value::Number(x % y)])
+    aux::ret(state, vec![Value::number(x % y)])
 }
 
 fn l_modf(state: &mut LuaState) -> LuaResult<i32> {
@@ -176,7 +175,7 @@ fn l_modf(state: &mut LuaState) -> LuaResult<i32> {
     let x = aux::check_number(state, &args, 0, "modf")?;
     let int_part = x.trunc();
     let frac = x - int_part;
-    aux::ret(state, vec![Value::Number(int_part), Value::Number(frac)])
+    aux::ret(state, vec![Value::number(int_part), Value::number(frac)])
 }
 
 fn l_max(state: &mut LuaState) -> LuaResult<i32> {
@@ -188,7 +187,7 @@ fn l_max(state: &mut LuaState) -> LuaResult<i32> {
             best = v;
         }
     }
-    aux::ret(state, vec![Value::Number(best)])
+    aux::ret(state, vec![Value::number(best)])
 }
 
 fn l_min(state: &mut LuaState) -> LuaResult<i32> {
@@ -200,7 +199,7 @@ fn l_min(state: &mut LuaState) -> LuaResult<i32> {
             best = v;
         }
     }
-    aux::ret(state, vec![Value::Number(best)])
+    aux::ret(state, vec![Value::number(best)])
 }
 
 // ---- 乱数 -------------------------------------------------------------------
@@ -225,14 +224,14 @@ fn l_random(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let r = next_rand();
     match args.len() {
-        0 => aux::ret(state, vec![Value::Number(r)]),
+        0 => aux::ret(state, vec![Value::number(r)]),
         1 => {
             let m = aux::check_int(state, &args, 0, "random")?;
             if m < 1 {
                 return Err(aux::arg_error(state, 1, "random", "interval is empty"));
             }
             let v = (r * m as f64).floor() as i64 + 1;
-            aux::ret(state, vec![Value::Number(v as f64)])
+            aux::ret(state, vec![Value::number(v as f64)])
         }
         _ => {
             let lo = aux::check_int(state, &args, 0, "random")?;
@@ -242,7 +241,7 @@ fn l_random(state: &mut LuaState) -> LuaResult<i32> {
             }
             let span = (hi - lo + 1) as f64;
             let v = lo + (r * span).floor() as i64;
-            aux::ret(state, vec![Value::Number(v as f64)])
+            aux::ret(state, vec![Value::number(v as f64)])
         }
     }
 }
@@ -20,9 +20,8 @@ thread_local! {
 
 pub fn open(state: &mut LuaState) {
     let t = state.new_table();
-    let tk = match t {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(tk) = t.as_table() else {
+        return;
     };
     aux::register(state, tk, "time", l_time);
     aux::register(state, tk, "clock", l_clock);
@@ -50,13 +49,21 @@ fn now_secs() -> f64 {
 /// `os.time([table])`: 引数なしは現在の Unix 時刻。テーブル指定は UTC として合成する。
 fn l_time(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
-    let secs = match aux::opt_value(&args, 0) {
-        Value::Nil => now_secs(),
-        Value::GcRef(GcHandle::Table(tk)) => {
+    let secs = {
+        let v = aux::opt_value(&args, 0);
+        if v.is_nil() {
+            now_secs()
+        } else if let Some(tk) = v.as_table() {
             let field = |state: &mut LuaState, name: &str, default: i64| -> i64 {
                 let k = state.new_string(name.as_bytes());
-                match state.global.heap.get_table(tk).map(|t| t.get(&k)) {
-                    Some(Value::Number(n)) => n as i64,
+                match state
+                    .global
+                    .heap
+                    .get_table(tk)
+                    .map(|t| t.get(&k))
+                    .and_then(|v| v.as_number())
+                {
+                    Some(n) => n as i64,
                     _ => default,
                 }
             };
@@ -68,16 +75,17 @@ fn l_time(state: &mut LuaState) -> LuaResult<i32> {
             let sec = field(state, "sec", 0);
             let days = days_from_civil(year, month, day);
             (days * 86400 + hour * 3600 + min * 60 + sec) as f64
+        } else {
+            return Err(aux::arg_error(state, 1, "time", "table expected"));
         }
-        _ => return Err(aux::arg_error(state, 1, "time", "table expected")),
     };
-    aux::ret(state, vec![Value::Number(secs)])
+    aux::ret(state, vec![Value::number(secs)])
 }
 
 /// `os.clock()`: プロセス開始からの経過秒（CPU 時間の近似として実時間を返す）。
 fn l_clock(state: &mut LuaState) -> LuaResult<i32> {
     let elapsed = CLOCK_START.with(|start| start.elapsed().map(|d| d.as_secs_f64()).unwrap_or(0.0));
-    aux::ret(state, vec![Value::Number(elapsed)])
+    aux::ret(state, vec![Value::number(elapsed)])
 }
 
 /// `os.difftime(t2, t1)`: 秒差。
@@ -85,7 +93,7 @@ fn l_difftime(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let t2 = aux::check_number(state, &args, 0, "difftime")?;
     let t1 = aux::opt_number(state, &args, 1, "difftime", 0.0)?;
-    aux::ret(state, vec![Value::Number(t2 - t1)])
+    aux::ret(state, vec![Value::number(t2 - t1)])
 }
 
 /// `os.getenv(name)`: 環境変数（無ければ nil）。
@@ -95,7 +103,7 @@ fn l_getenv(state: &mut LuaState) -> LuaResult<i32> {
     let name = String::from_utf8_lossy(&name).into_owned();
     let result = match std::env::var(&name) {
         Ok(v) => state.new_string(v.as_bytes()),
-        Err(_) => Value::Nil,
+        Err(_) => Value::NIL,
     };
     aux::ret(state, vec![result])
 }
@@ -114,24 +122,24 @@ fn l_tmpname(state: &mut LuaState) -> LuaResult<i32> {
 /// 引数なしの場合、シェルが利用可能なら true を返す。
 fn l_execute(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
-    match aux::opt_value(&args, 0) {
-        Value::Nil => aux::ret(state, vec![Value::Boolean(true)]),
-        _ => {
-            let cmd = aux::check_str_bytes(state, &args, 0, "execute")?;
-            let cmd_str = String::from_utf8_lossy(&cmd);
-            match std::process::Command::new("/bin/sh")
-                .arg("-c")
-                .arg(cmd_str.as_ref())
-                .status()
-            {
-                Ok(s) => {
-                    let code = s.code().unwrap_or(-1);
-                    aux::ret(state, vec![Value::Number(code as f64)])
-                }
-                Err(e) => {
-                    let msg = state.new_string(e.to_string().as_bytes());
-                    aux::ret(state, vec![Value::Nil, msg])
-                }
+    let v = aux::opt_value(&args, 0);
+    if v.is_nil() {
+        aux::ret(state, vec![Value::TRUE])
+    } else {
+        let cmd = aux::check_str_bytes(state, &args, 0, "execute")?;
+        let cmd_str = String::from_utf8_lossy(&cmd);
+        match std::process::Command::new("/bin/sh")
+            .arg("-c")
+            .arg(cmd_str.as_ref())
+            .status()
+        {
+            Ok(s) => {
+                let code = s.code().unwrap_or(-1);
+                aux::ret(state, vec![Value::number(code as f64)])
+            }
+            Err(e) => {
+                let msg = state.new_string(e.to_string().as_bytes());
+                aux::ret(state, vec![Value::NIL, msg])
             }
         }
     }
@@ -146,10 +154,10 @@ fn l_remove(state: &mut LuaState) -> LuaResult<i32> {
     let result =
         std::fs::remove_file(filename.as_ref()).or_else(|_| std::fs::remove_dir(filename.as_ref()));
     match result {
-        Ok(()) => aux::ret(state, vec![Value::Boolean(true)]),
+        Ok(()) => aux::ret(state, vec![Value::TRUE]),
         Err(e) => {
             let msg = state.new_string(e.to_string().as_bytes());
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
@@ -163,20 +171,25 @@ fn l_rename(state: &mut LuaState) -> LuaResult<i32> {
     let oldname = String::from_utf8_lossy(&oldname);
     let newname = String::from_utf8_lossy(&newname);
     match std::fs::rename(oldname.as_ref(), newname.as_ref()) {
-        Ok(()) => aux::ret(state, vec![Value::Boolean(true)]),
+        Ok(()) => aux::ret(state, vec![Value::TRUE]),
         Err(e) => {
             let msg = state.new_string(e.to_string().as_bytes());
-            aux::ret(state, vec![Value::Nil, msg])
+            aux::ret(state, vec![Value::NIL, msg])
         }
     }
 }
 
 fn l_exit(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
-    let code = match aux::opt_value(&args, 0) {
-        Value::Nil | Value::Boolean(true) => 0,
-        Value::Boolean(false) => 1,
-        _ => aux::check_int(state, &args, 0, "exit")? as i32,
+    let code = {
+        let v = aux::opt_value(&args, 0);
+        if v.is_nil() || v == Value::TRUE {
+            0
+        } else if v == Value::FALSE {
+            1
+        } else {
+            aux::check_int(state, &args, 0, "exit")? as i32
+        }
     };
     std::process::exit(code);
 }
@@ -199,11 +212,15 @@ struct Tm {
 
 fn l_date(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
-    let fmt = match aux::opt_value(&args, 0) {
-        Value::Nil => b"%c".to_vec(),
-        _ => aux::check_str_bytes(state, &args, 0, "date")?,
+    let fmt = {
+        let v = aux::opt_value(&args, 0);
+        if v.is_nil() {
+            b"%c".to_vec()
+        } else {
+            aux::check_str_bytes(state, &args, 0, "date")?
+        }
     };
-    let t = if matches!(aux::opt_value(&args, 1), Value::Nil) {
+    let t = if aux::opt_value(&args, 1).is_nil() {
         now_secs() as i64
     } else {
         aux::check_number(state, &args, 1, "date")? as i64
@@ -216,16 +233,16 @@ fn l_date(state: &mut LuaState) -> LuaResult<i32> {
     // "*t" はテーブルを返す。
     if fmt == b"*t" {
         let tbl = state.new_table();
-        if let Value::GcRef(GcHandle::Table(tk)) = tbl {
-            aux::set_field(state, tk, "year", Value::Number(tm.year as f64));
-            aux::set_field(state, tk, "month", Value::Number(tm.month as f64));
-            aux::set_field(state, tk, "day", Value::Number(tm.day as f64));
-            aux::set_field(state, tk, "hour", Value::Number(tm.hour as f64));
-            aux::set_field(state, tk, "min", Value::Number(tm.min as f64));
-            aux::set_field(state, tk, "sec", Value::Number(tm.sec as f64));
-            aux::set_field(state, tk, "wday", Value::Number((tm.wday + 1) as f64));
-            aux::set_field(state, tk, "yday", Value::Number(tm.yday as f64));
-            aux::set_field(state, tk, "isdst", Value::Boolean(false));
+        if let Some(tk) = tbl.as_table() {
+            aux::set_field(state, tk, "year", Value::number(tm.year as f64));
+            aux::set_field(state, tk, "month", Value::number(tm.month as f64));
+            aux::set_field(state, tk, "day", Value::number(tm.day as f64));
+            aux::set_field(state, tk, "hour", Value::number(tm.hour as f64));
+            aux::set_field(state, tk, "min", Value::number(tm.min as f64));
+            aux::set_field(state, tk, "sec", Value::number(tm.sec as f64));
+            aux::set_field(state, tk, "wday", Value::number((tm.wday + 1) as f64));
+            aux::set_field(state, tk, "yday", Value::number(tm.yday as f64));
+            aux::set_field(state, tk, "isdst", Value::FALSE);
         }
         return aux::ret(state, vec![tbl]);
     }
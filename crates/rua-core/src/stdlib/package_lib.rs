@@ -24,9 +24,10 @@
 //!    モジュールが値を設定しなかった場合は `true` を格納する。
 //! 5. `package.loaded[modname]` を返す。
 
+use std::any::Any;
 use std::rc::Rc;
 
-use crate::compiler::compile;
+use crate::compiler::compile_with_lang;
 use crate::error::LuaResult;
 use crate::gc::{GcHandle, TableKey};
 use crate::state::LuaState;
@@ -45,9 +46,8 @@ const DEFAULT_CONFIG: &str = "/\n;\n?\n!\n-\n";
 /// package ライブラリと `require` をグローバル環境へ登録する。
 pub fn open(state: &mut LuaState) {
     let pkg = state.new_table();
-    let pk = match pkg {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(pk) = pkg.as_table() else {
+        return;
     };
 
     let loaded = state.new_table();
@@ -68,12 +68,12 @@ pub fn open(state: &mut LuaState) {
 
     // package.loaders 配列（searcher を順に登録）。
     let loaders = state.new_table();
-    if let Value::GcRef(GcHandle::Table(lk)) = loaders {
+    if let Some(lk) = loaders.as_table() {
         let preload_searcher = aux::make_native(state, searcher_preload);
         let lua_searcher = aux::make_native(state, searcher_lua);
         if let Some(t) = state.global.heap.get_table_mut(lk) {
-            let _ = t.set(Value::Number(1.0), preload_searcher);
-            let _ = t.set(Value::Number(2.0), lua_searcher);
+            let _ = t.set(Value::number(1.0), preload_searcher);
+            let _ = t.set(Value::number(2.0), lua_searcher);
         }
     }
     aux::set_field(state, pk, "loaders", loaders);
@@ -95,30 +95,38 @@ fn package_table(state: &mut LuaState) -> Option<TableKey> {
         _ => return None,
     };
     let key = state.new_string(b"package");
-    match state.global.heap.get_table(g).map(|t| t.get(&key)) {
-        Some(Value::GcRef(GcHandle::Table(pk))) => Some(pk),
-        _ => None,
-    }
+    state
+        .global
+        .heap
+        .get_table(g)
+        .map(|t| t.get(&key))
+        .and_then(|v| v.as_table())
 }
 
 /// `package` テーブルのフィールド（テーブル型）のキーを取得する。
 fn package_subtable(state: &mut LuaState, field: &str) -> Option<TableKey> {
     let pk = package_table(state)?;
     let key = state.new_string(field.as_bytes());
-    match state.global.heap.get_table(pk).map(|t| t.get(&key)) {
-        Some(Value::GcRef(GcHandle::Table(k))) => Some(k),
-        _ => None,
-    }
+    state
+        .global
+        .heap
+        .get_table(pk)
+        .map(|t| t.get(&key))
+        .and_then(|v| v.as_table())
 }
 
 /// `package` テーブルのフィールド（文字列型）のバイト列を取得する。
 fn package_str_field(state: &mut LuaState, field: &str) -> Option<Vec<u8>> {
     let pk = package_table(state)?;
     let key = state.new_string(field.as_bytes());
-    match state.global.heap.get_table(pk).map(|t| t.get(&key)) {
-        Some(Value::GcRef(GcHandle::Str(k))) => {
-            state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
-        }
+    match state
+        .global
+        .heap
+        .get_table(pk)
+        .map(|t| t.get(&key))
+        .and_then(|v| v.as_string())
+    {
+        Some(k) => state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec()),
         _ => None,
     }
 }
@@ -127,12 +135,13 @@ fn package_str_field(state: &mut LuaState, field: &str) -> Option<Vec<u8>> {
 ///
 /// メインチャンクは upvalue を持たない。成功で関数値、失敗で構文エラーメッセージを返す。
 fn load_chunk(state: &mut LuaState, src: &[u8], chunkname: &str) -> Result<Value, String> {
-    match compile(&mut state.global.heap, src, chunkname) {
+    let lang = state.global.lang;
+    match compile_with_lang(&mut state.global.heap, src, chunkname, lang) {
         Ok(proto) => {
             let env = state.global.globals;
             let closure = LuaClosure::new_with_env(Rc::new(proto), env);
             let h = state.global.heap.alloc_closure(Closure::Lua(closure));
-            Ok(Value::GcRef(h))
+            Ok(Value::gc(h))
         }
         Err(e) => Err(format!("{e}")),
     }
@@ -157,14 +166,14 @@ fn searcher_preload(state: &mut LuaState) -> LuaResult<i32> {
         let msg = state.new_string(b"\n\tno field package.preload");
         return aux::ret(state, vec![msg]);
     };
-    let key = state.new_string(&name);
+    let key = Value::gc(state.global.heap.intern_str(&name));
     let loader = state
         .global
         .heap
         .get_table(preload)
         .map(|t| t.get(&key))
-        .unwrap_or(Value::Nil);
-    if matches!(loader, Value::GcRef(GcHandle::Closure(_))) {
+        .unwrap_or(Value::NIL);
+    if loader.as_closure().is_some() {
         aux::ret(state, vec![loader])
     } else {
         let mut buf = b"\n\tno field package.preload['".to_vec();
@@ -235,7 +244,8 @@ fn searcher_lua(state: &mut LuaState) -> LuaResult<i32> {
 fn l_require(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let name = aux::check_str_bytes(state, &args, 0, "require")?;
-    let name_val = state.new_string(&name);
+    // package.loaded のキーに使うので、長い名前でもインターンしておく。
+    let name_val = Value::gc(state.global.heap.intern_str(&name));
 
     let Some(loaded_tk) = package_subtable(state, "loaded") else {
         return Err(aux::rt_error(state, "'package.loaded' is not a table"));
@@ -247,7 +257,7 @@ fn l_require(state: &mut LuaState) -> LuaResult<i32> {
         .heap
         .get_table(loaded_tk)
         .map(|t| t.get(&name_val))
-        .unwrap_or(Value::Nil);
+        .unwrap_or(Value::NIL);
     if cached.is_truthy() {
         return aux::ret(state, vec![cached]);
     }
@@ -266,29 +276,46 @@ fn l_require(state: &mut LuaState) -> LuaResult<i32> {
             .heap
             .get_table(loaders_tk)
             .map(|t| t.get_int(idx))
-            .unwrap_or(Value::Nil);
-        if matches!(searcher, Value::Nil) {
+            .unwrap_or(Value::NIL);
+        if searcher.is_nil() {
             return Err(aux::rt_error(state, errmsg));
         }
         let res = crate::vm::call(state, searcher, &[name_val])?;
-        match res.into_iter().next().unwrap_or(Value::Nil) {
-            f @ Value::GcRef(GcHandle::Closure(_)) => break f,
-            Value::GcRef(GcHandle::Str(k)) => {
-                if let Some(s) = state.global.heap.get_str(k) {
-                    errmsg.push_str(&String::from_utf8_lossy(s.as_bytes()));
-                }
-            }
-            _ => {}
+        let v = res.into_iter().next().unwrap_or(Value::NIL);
+        if v.as_closure().is_some() {
+            break v;
+        } else if let Some(k) = v.as_string()
+            && let Some(s) = state.global.heap.get_str(k)
+        {
+            errmsg.push_str(&String::from_utf8_lossy(s.as_bytes()));
         }
         idx += 1;
     };
 
-    // 3. ローダを modname 引数で実行。
-    let rets = crate::vm::call(state, loader, &[name_val])?;
-    let modval = rets.into_iter().next().unwrap_or(Value::Nil);
+    // 3. ローダを modname 引数で実行。ローダが yield したら require_cont で再開する。
+    // name_val は継続の ctx にも持つので、GC ルートとしてスタックにも積んでおく。
+    state.stack.push(name_val);
+    let rets = crate::vm::call_k(state, loader, &[name_val], require_cont, || {
+        Box::new((name_val, loaded_tk))
+    })?;
+    require_cont(state, Ok(rets), Box::new((name_val, loaded_tk)))
+}
+
+/// ローダ実行後の `require` の続き（手順 4・5）。ctx は `(modname, package.loaded)`。
+fn require_cont(
+    state: &mut LuaState,
+    result: LuaResult<Vec<Value>>,
+    ctx: Box<dyn Any>,
+) -> LuaResult<i32> {
+    let rets = result?;
+    let Ok(ctx) = ctx.downcast::<(Value, TableKey)>() else {
+        return aux::ret0(state);
+    };
+    let (name_val, loaded_tk) = *ctx;
+    let modval = rets.into_iter().next().unwrap_or(Value::NIL);
 
     // 4. 非 nil の戻り値は package.loaded[modname] に格納。
-    if !matches!(modval, Value::Nil)
+    if !modval.is_nil()
         && let Some(t) = state.global.heap.get_table_mut(loaded_tk)
     {
         let _ = t.set(name_val, modval);
@@ -300,12 +327,12 @@ fn l_require(state: &mut LuaState) -> LuaResult<i32> {
         .heap
         .get_table(loaded_tk)
         .map(|t| t.get(&name_val))
-        .unwrap_or(Value::Nil);
-    let result = if matches!(final_val, Value::Nil) {
+        .unwrap_or(Value::NIL);
+    let result = if final_val.is_nil() {
         if let Some(t) = state.global.heap.get_table_mut(loaded_tk) {
-            let _ = t.set(name_val, Value::Boolean(true));
+            let _ = t.set(name_val, Value::TRUE);
         }
-        Value::Boolean(true)
+        Value::TRUE
     } else {
         final_val
     };
@@ -3,6 +3,8 @@
 //! `len`/`sub`/`rep`/`upper`/`lower`/`byte`/`char`/`format`/`reverse` と、Lua パターンを使う
 //! `find`/`match`/`gmatch`/`gsub`。パターン照合は [`super::pattern`] が担う。
 
+use std::any::Any;
+
 use crate::error::LuaResult;
 use crate::gc::{GcHandle, TableKey};
 use crate::state::LuaState;
@@ -14,9 +16,8 @@ use super::pattern::{self, Cap, MatchState};
 
 pub fn open(state: &mut LuaState) {
     let s = state.new_table();
-    let sk = match s {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(sk) = s.as_table() else {
+        return;
     };
     aux::register(state, sk, "len", l_len);
     aux::register(state, sk, "sub", l_sub);
@@ -40,7 +41,7 @@ pub fn open(state: &mut LuaState) {
     // `createmetatable`）。これにより `("x"):upper()` / `s:match(p)` などのメソッド構文が
     // VM の `metatable_of`（string を参照）経由で `string` テーブルへ解決される。
     let mt = state.new_table();
-    if let Value::GcRef(GcHandle::Table(mtk)) = mt {
+    if let Some(mtk) = mt.as_table() {
         aux::set_field(state, mtk, "__index", s);
         state.global.string_metatable = Some(GcHandle::Table(mtk));
     }
@@ -53,7 +54,7 @@ pub fn open(state: &mut LuaState) {
 fn l_len(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let s = aux::check_str_bytes(state, &args, 0, "len")?;
-    aux::ret(state, vec![Value::Number(s.len() as f64)])
+    aux::ret(state, vec![Value::number(s.len() as f64)])
 }
 
 fn l_sub(state: &mut LuaState) -> LuaResult<i32> {
@@ -73,7 +74,7 @@ fn l_sub(state: &mut LuaState) -> LuaResult<i32> {
     } else {
         Vec::new()
     };
-    let v = state.new_string(&out);
+    let v = state.new_string_from(out);
     aux::ret(state, vec![v])
 }
 
@@ -81,13 +82,18 @@ fn l_rep(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let s = aux::check_str_bytes(state, &args, 0, "rep")?;
     let n = aux::check_int(state, &args, 1, "rep")?;
-    let mut out = Vec::new();
+    // 組み立てる前に結果の長さで上限を検査する（`string.rep('x', 1e10)` など）。
+    let total = s
+        .len()
+        .saturating_mul(n.max(0).try_into().unwrap_or(usize::MAX));
+    state.reserve_memory(total)?;
+    let mut out = Vec::with_capacity(total);
     let mut k = 0;
     while k < n {
         out.extend_from_slice(&s);
         k += 1;
     }
-    let v = state.new_string(&out);
+    let v = state.new_string_from(out);
     aux::ret(state, vec![v])
 }
 
@@ -95,7 +101,7 @@ fn l_upper(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let mut s = aux::check_str_bytes(state, &args, 0, "upper")?;
     s.make_ascii_uppercase();
-    let v = state.new_string(&s);
+    let v = state.new_string_from(s);
     aux::ret(state, vec![v])
 }
 
@@ -103,7 +109,7 @@ fn l_lower(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let mut s = aux::check_str_bytes(state, &args, 0, "lower")?;
     s.make_ascii_lowercase();
-    let v = state.new_string(&s);
+    let v = state.new_string_from(s);
     aux::ret(state, vec![v])
 }
 
@@ -111,7 +117,7 @@ fn l_reverse(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let mut s = aux::check_str_bytes(state, &args, 0, "reverse")?;
     s.reverse();
-    let v = state.new_string(&s);
+    let v = state.new_string_from(s);
     aux::ret(state, vec![v])
 }
 
@@ -130,7 +136,7 @@ fn l_byte(state: &mut LuaState) -> LuaResult<i32> {
     let mut out = Vec::new();
     let mut k = i;
     while k <= j {
-        out.push(Value::Number(s[(k - 1) as usize] as f64));
+        out.push(Value::number(s[(k - 1) as usize] as f64));
         k += 1;
     }
     aux::ret(state, out)
@@ -146,7 +152,7 @@ fn l_char(state: &mut LuaState) -> LuaResult<i32> {
         }
         out.push(c as u8);
     }
-    let v = state.new_string(&out);
+    let v = state.new_string_from(out);
     aux::ret(state, vec![v])
 }
 
@@ -253,7 +259,7 @@ fn l_format(state: &mut LuaState) -> LuaResult<i32> {
             }
         }
     }
-    let v = state.new_string(&out);
+    let v = state.new_string_from(out);
     aux::ret(state, vec![v])
 }
 
@@ -520,7 +526,7 @@ fn caps_to_values(state: &mut LuaState, src: &[u8], caps: &[Cap]) -> Vec<Value>
     caps.iter()
         .map(|c| match c {
             Cap::Str(start, len) => state.new_string(&src[*start..*start + *len]),
-            Cap::Pos(init) => Value::Number((*init as f64) + 1.0),
+            Cap::Pos(init) => Value::number((*init as f64) + 1.0),
         })
         .collect()
 }
@@ -548,12 +554,12 @@ fn find_or_match(state: &mut LuaState, find: bool) -> LuaResult<i32> {
             return aux::ret(
                 state,
                 vec![
-                    Value::Number((start + 1) as f64),
-                    Value::Number((start + p.len()) as f64),
+                    Value::number((start + 1) as f64),
+                    Value::number((start + p.len()) as f64),
                 ],
             );
         }
-        return aux::ret(state, vec![Value::Nil]);
+        return aux::ret(state, vec![Value::NIL]);
     }
 
     // パターン照合。
@@ -571,7 +577,7 @@ fn find_or_match(state: &mut LuaState, find: bool) -> LuaResult<i32> {
                 let caps = ms
                     .captures(s1, e, false)
                     .map_err(|er| aux::rt_error(state, er))?;
-                let mut out = vec![Value::Number((s1 + 1) as f64), Value::Number(e as f64)];
+                let mut out = vec![Value::number((s1 + 1) as f64), Value::number(e as f64)];
                 out.extend(caps_to_values(state, &s, &caps));
                 return aux::ret(state, out);
             } else {
@@ -587,7 +593,7 @@ fn find_or_match(state: &mut LuaState, find: bool) -> LuaResult<i32> {
         }
         s1 += 1;
     }
-    aux::ret(state, vec![Value::Nil])
+    aux::ret(state, vec![Value::NIL])
 }
 
 fn l_find(state: &mut LuaState) -> LuaResult<i32> {
@@ -619,18 +625,16 @@ fn l_gmatch(state: &mut LuaState) -> LuaResult<i32> {
     let pval = state.new_string(&p);
     // 状態テーブル {s, p, pos=0} を作り、__call = gmatch_aux のメタテーブルを付ける。
     let tbl = state.new_table();
-    let tk = match tbl {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => unreachable!(),
+    let Some(tk) = tbl.as_table() else {
+        unreachable!()
     };
     aux::set_field(state, tk, "s", sval);
     aux::set_field(state, tk, "p", pval);
-    aux::set_field(state, tk, "pos", Value::Number(0.0));
+    aux::set_field(state, tk, "pos", Value::number(0.0));
 
     let mt = state.new_table();
-    let mtk = match mt {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => unreachable!(),
+    let Some(mtk) = mt.as_table() else {
+        unreachable!()
     };
     aux::register(state, mtk, "__call", gmatch_aux);
     if let Some(t) = state.global.heap.get_table_mut(tk) {
@@ -657,7 +661,7 @@ fn gmatch_aux(state: &mut LuaState) -> LuaResult<i32> {
         match ms.do_match(src, 0).map_err(|e| aux::rt_error(state, e))? {
             Some(e) => {
                 let newstart = if e == src { e + 1 } else { e };
-                aux::set_field(state, selfk, "pos", Value::Number(newstart as f64));
+                aux::set_field(state, selfk, "pos", Value::number(newstart as f64));
                 let caps = ms
                     .captures(src, e, true)
                     .map_err(|er| aux::rt_error(state, er))?;
@@ -671,8 +675,14 @@ fn gmatch_aux(state: &mut LuaState) -> LuaResult<i32> {
 
 fn field_bytes(state: &mut LuaState, tk: TableKey, name: &str) -> Vec<u8> {
     let kv = state.new_string(name.as_bytes());
-    match state.global.heap.get_table(tk).map(|t| t.get(&kv)) {
-        Some(Value::GcRef(GcHandle::Str(sk))) => state
+    match state
+        .global
+        .heap
+        .get_table(tk)
+        .map(|t| t.get(&kv))
+        .and_then(|v| v.as_string())
+    {
+        Some(sk) => state
             .global
             .heap
             .get_str(sk)
@@ -684,10 +694,13 @@ fn field_bytes(state: &mut LuaState, tk: TableKey, name: &str) -> Vec<u8> {
 
 fn field_num(state: &mut LuaState, tk: TableKey, name: &str) -> f64 {
     let key = state.new_string(name.as_bytes());
-    match state.global.heap.get_table(tk).map(|t| t.get(&key)) {
-        Some(Value::Number(n)) => n,
-        _ => 0.0,
-    }
+    state
+        .global
+        .heap
+        .get_table(tk)
+        .map(|t| t.get(&key))
+        .and_then(|v| v.as_number())
+        .unwrap_or(0.0)
 }
 
 // ---- gsub -------------------------------------------------------------------
@@ -697,74 +710,134 @@ fn l_gsub(state: &mut LuaState) -> LuaResult<i32> {
     let src = aux::check_str_bytes(state, &args, 0, "gsub")?;
     let pat = aux::check_str_bytes(state, &args, 1, "gsub")?;
     let repl = aux::opt_value(&args, 2);
-    let max_s = if matches!(aux::opt_value(&args, 3), Value::Nil) {
+    let max_s = if aux::opt_value(&args, 3).is_nil() {
         (src.len() + 1) as i64
     } else {
         aux::check_int(state, &args, 3, "gsub")?
     };
     // 置換種別の検査。
-    match repl {
-        Value::GcRef(GcHandle::Str(_))
-        | Value::Number(_)
-        | Value::GcRef(GcHandle::Table(_))
-        | Value::GcRef(GcHandle::Closure(_)) => {}
-        _ => {
-            return Err(aux::arg_error(
-                state,
-                3,
-                "gsub",
-                "string/function/table expected",
-            ));
-        }
+    if repl.as_string().is_some()
+        || repl.is_number()
+        || repl.as_table().is_some()
+        || repl.as_closure().is_some()
+    {
+    } else {
+        return Err(aux::arg_error(
+            state,
+            3,
+            "gsub",
+            "string/function/table expected",
+        ));
     }
 
     let anchor = pat.first() == Some(&b'^');
-    let pat_start = if anchor { 1 } else { 0 };
-    let mut out: Vec<u8> = Vec::new();
-    let mut n = 0i64;
-    let mut s = 0usize;
-    loop {
-        if n >= max_s {
-            break;
+    gsub_run(
+        state,
+        GsubState {
+            src,
+            pat,
+            repl,
+            max_s,
+            anchor,
+            out: Vec::new(),
+            n: 0,
+            s: 0,
+        },
+    )
+}
+
+/// `gsub` の途中状態。置換関数が yield したら継続の ctx として保存する。
+struct GsubState {
+    src: Vec<u8>,
+    pat: Vec<u8>,
+    repl: Value,
+    max_s: i64,
+    anchor: bool,
+    out: Vec<u8>,
+    /// 置換した回数。
+    n: i64,
+    /// 次の照合開始位置。
+    s: usize,
+}
+
+impl GsubState {
+    /// 照合の終わり `e`（`None` はマッチ無し）から次の開始位置へ進める。
+    /// 続けて照合するなら `true`。
+    fn advance(&mut self, e: Option<usize>) -> bool {
+        match e {
+            Some(e) if e > self.s => self.s = e, // 非空マッチ: 進める
+            _ if self.s < self.src.len() => {
+                self.out.push(self.src[self.s]);
+                self.s += 1;
+            }
+            _ => return false,
         }
-        let mut ms = MatchState::new(&src, &pat);
+        !self.anchor
+    }
+}
+
+fn gsub_run(state: &mut LuaState, mut st: GsubState) -> LuaResult<i32> {
+    let pat_start = if st.anchor { 1 } else { 0 };
+    while st.n < st.max_s {
+        let mut ms = MatchState::new(&st.src, &st.pat);
         ms.reset();
         let e = ms
-            .do_match(s, pat_start)
+            .do_match(st.s, pat_start)
             .map_err(|er| aux::rt_error(state, er))?;
-        match e {
-            Some(e) => {
-                n += 1;
-                add_value(state, &mut out, &ms, &src, s, e, repl)?;
-                if e > s {
-                    s = e; // 非空マッチ: 進める
-                } else if s < src.len() {
-                    out.push(src[s]);
-                    s += 1;
-                } else {
-                    break;
-                }
-            }
-            None => {
-                if s < src.len() {
-                    out.push(src[s]);
-                    s += 1;
-                } else {
-                    break;
-                }
+        if let Some(e) = e {
+            st.n += 1;
+            if st.repl.as_closure().is_some() {
+                // 関数置換: キャプチャを引数に呼ぶ。yield したら gsub_cont で再開する。
+                let caps = ms
+                    .captures(st.s, e, true)
+                    .map_err(|er| aux::rt_error(state, er))?;
+                let cap_vals = caps_to_values(state, &st.src, &caps);
+                let repl = st.repl;
+                let mut slot = Some(st);
+                let res = crate::vm::call_k(state, repl, &cap_vals, gsub_cont, || {
+                    Box::new((slot.take().expect("gsub state"), e))
+                })?;
+                st = slot.expect("gsub state");
+                let v = res.into_iter().next().unwrap_or(Value::NIL);
+                append_repl_result(state, &mut st.out, &st.src, st.s, e, v)?;
+            } else {
+                add_value(state, &mut st.out, &ms, &st.src, st.s, e, st.repl)?;
             }
         }
-        if anchor {
+        if !st.advance(e) {
             break;
         }
     }
+    gsub_finish(state, st)
+}
+
+/// 置換関数が yield した `gsub` の継続。ctx は途中状態と照合の終わり。
+fn gsub_cont(
+    state: &mut LuaState,
+    result: LuaResult<Vec<Value>>,
+    ctx: Box<dyn Any>,
+) -> LuaResult<i32> {
+    let v = result?.into_iter().next().unwrap_or(Value::NIL);
+    let Ok(ctx) = ctx.downcast::<(GsubState, usize)>() else {
+        return aux::ret0(state);
+    };
+    let (mut st, e) = *ctx;
+    append_repl_result(state, &mut st.out, &st.src, st.s, e, v)?;
+    if st.advance(Some(e)) {
+        gsub_run(state, st)
+    } else {
+        gsub_finish(state, st)
+    }
+}
+
+fn gsub_finish(state: &mut LuaState, mut st: GsubState) -> LuaResult<i32> {
     // 残りを追加。
-    out.extend_from_slice(&src[s.min(src.len())..]);
-    let res = state.new_string(&out);
-    aux::ret(state, vec![res, Value::Number(n as f64)])
+    st.out.extend_from_slice(&st.src[st.s.min(st.src.len())..]);
+    let res = state.new_string_from(st.out);
+    aux::ret(state, vec![res, Value::number(st.n as f64)])
 }
 
-/// gsub の 1 マッチ分の置換値を `out` へ追加する（本家 `add_value`）。
+/// gsub の 1 マッチ分の文字列・テーブルによる置換値を `out` へ追加する（本家 `add_value`）。
 fn add_value(
     state: &mut LuaState,
     out: &mut Vec<u8>,
@@ -777,40 +850,31 @@ fn add_value(
     let caps = ms
         .captures(s, e, true)
         .map_err(|er| aux::rt_error(state, er))?;
-    match repl {
-        Value::GcRef(GcHandle::Str(_)) | Value::Number(_) => {
-            // 文字列置換: %0=全体, %1..=キャプチャ。
-            let news = match repl {
-                Value::GcRef(GcHandle::Str(k)) => {
-                    state.global.heap.get_str(k).unwrap().as_bytes().to_vec()
-                }
-                Value::Number(num) => number_to_string(num).into_bytes(),
-                _ => unreachable!(),
-            };
-            add_s(state, out, &news, src, s, e, &caps)?;
-            Ok(())
-        }
-        Value::GcRef(GcHandle::Table(tk)) => {
-            // 第1キャプチャでテーブルを引く。
-            let key = caps_to_values(state, src, &caps[..1.min(caps.len())])
-                .into_iter()
-                .next()
-                .unwrap_or(Value::Nil);
-            let v = state
-                .global
-                .heap
-                .get_table(tk)
-                .map(|t| t.get(&key))
-                .unwrap_or(Value::Nil);
-            append_repl_result(state, out, src, s, e, v)
-        }
-        Value::GcRef(GcHandle::Closure(_)) => {
-            let cap_vals = caps_to_values(state, src, &caps);
-            let res = crate::vm::call(state, repl, &cap_vals)?;
-            let v = res.into_iter().next().unwrap_or(Value::Nil);
-            append_repl_result(state, out, src, s, e, v)
-        }
-        _ => unreachable!(),
+    if repl.as_string().is_some() || repl.is_number() {
+        // 文字列置換: %0=全体, %1..=キャプチャ。
+        let news = match repl.as_string() {
+            Some(k) => state.global.heap.get_str(k).unwrap().as_bytes().to_vec(),
+            None => number_to_string(repl.as_number().unwrap_or_default()).into_bytes(),
+        };
+        add_s(state, out, &news, src, s, e, &caps)?;
+        Ok(())
+    } else if let Some(tk) = repl.as_table() {
+        // 第1キャプチャでテーブルを引く。
+        let key = caps_to_values(state, src, &caps[..1.min(caps.len())])
+            .into_iter()
+            .next()
+            .unwrap_or(Value::NIL);
+        let key = state.global.heap.table_key(key);
+        let v = state
+            .global
+            .heap
+            .get_table(tk)
+            .map(|t| t.get(&key))
+            .unwrap_or(Value::NIL);
+        append_repl_result(state, out, src, s, e, v)
+    } else {
+        // 関数置換は yield に備えて gsub_run が直接呼ぶ。
+        unreachable!()
     }
 }
 
@@ -823,24 +887,21 @@ fn append_repl_result(
     e: usize,
     v: Value,
 ) -> LuaResult<()> {
-    match v {
-        Value::Nil | Value::Boolean(false) => {
-            out.extend_from_slice(&src[s..e]); // 元のテキストを保持
-            Ok(())
-        }
-        Value::GcRef(GcHandle::Str(k)) => {
-            let bytes = state.global.heap.get_str(k).unwrap().as_bytes().to_vec();
-            out.extend_from_slice(&bytes);
-            Ok(())
-        }
-        Value::Number(num) => {
-            out.extend_from_slice(number_to_string(num).as_bytes());
-            Ok(())
-        }
-        other => Err(aux::rt_error(
+    if !v.is_truthy() {
+        out.extend_from_slice(&src[s..e]); // 元のテキストを保持
+        Ok(())
+    } else if let Some(k) = v.as_string() {
+        let bytes = state.global.heap.get_str(k).unwrap().as_bytes().to_vec();
+        out.extend_from_slice(&bytes);
+        Ok(())
+    } else if let Some(num) = v.as_number() {
+        out.extend_from_slice(number_to_string(num).as_bytes());
+        Ok(())
+    } else {
+        Err(aux::rt_error(
             state,
-            format!("invalid replacement value (a {})", other.type_of().name()),
-        )),
+            format!("invalid replacement value (a {})", v.type_of().name()),
+        ))
     }
 }
 
@@ -1,21 +1,23 @@
 //! table ライブラリ（本家 `ltablib.c` 相当）。担当: **lua-stdlib**。
 //!
 //! `insert`/`remove`/`concat`/`sort`/`maxn`/`getn`。`sort` の比較関数は Lua 関数を
-//! VM 経由でコールバックする。
+//! VM 経由でコールバックする（比較関数の yield は [`crate::vm::call_k`] の継続で再開する）。
+
+use std::any::Any;
 
 use crate::error::LuaResult;
 use crate::gc::{GcHandle, TableKey};
 use crate::state::LuaState;
 use crate::value::Value;
 use crate::value::convert::number_to_string;
+use crate::vm::interp::reserve_insert;
 
 use super::aux;
 
 pub fn open(state: &mut LuaState) {
     let t = state.new_table();
-    let tk = match t {
-        Value::GcRef(GcHandle::Table(k)) => k,
-        _ => return,
+    let Some(tk) = t.as_table() else {
+        return;
     };
     aux::register(state, tk, "insert", l_insert);
     aux::register(state, tk, "remove", l_remove);
@@ -41,7 +43,7 @@ fn table_len(state: &LuaState, tk: TableKey) -> i64 {
 
 fn set_int(state: &mut LuaState, tk: TableKey, i: i64, v: Value) {
     if let Some(t) = state.global.heap.get_table_mut(tk) {
-        let _ = t.set(Value::Number(i as f64), v);
+        let _ = t.set(Value::number(i as f64), v);
     }
 }
 
@@ -52,14 +54,14 @@ fn get_int(state: &LuaState, tk: TableKey, i: i64) -> Value {
             .heap
             .get_table(tk)
             .map(|t| t.get_int(i as usize))
-            .unwrap_or(Value::Nil)
+            .unwrap_or(Value::NIL)
     } else {
         state
             .global
             .heap
             .get_table(tk)
-            .map(|t| t.get(&Value::Number(i as f64)))
-            .unwrap_or(Value::Nil)
+            .map(|t| t.get(&Value::number(i as f64)))
+            .unwrap_or(Value::NIL)
     }
 }
 
@@ -74,6 +76,7 @@ fn l_insert(state: &mut LuaState) -> LuaResult<i32> {
     match args.len() {
         2 => {
             // table.insert(t, v): 末尾に追加。
+            reserve_insert(state, tk, Value::number((n + 1) as f64), args[1])?;
             set_int(state, tk, n + 1, args[1]);
         }
         3 => {
@@ -82,6 +85,12 @@ fn l_insert(state: &mut LuaState) -> LuaResult<i32> {
             if pos < 1 || pos > n + 1 {
                 return Err(aux::arg_error(state, 2, "insert", "position out of bounds"));
             }
+            let last = if pos <= n {
+                get_int(state, tk, n)
+            } else {
+                args[2]
+            };
+            reserve_insert(state, tk, Value::number((n + 1) as f64), last)?;
             let mut i = n;
             while i >= pos {
                 let v = get_int(state, tk, i);
@@ -107,12 +116,12 @@ fn l_remove(state: &mut LuaState) -> LuaResult<i32> {
     let pos = aux::opt_int(state, &args, 1, "remove", n)?;
     if n == 0 && (args.len() < 2 || pos == 0) {
         // 空テーブル: nil を返す。
-        return aux::ret(state, vec![Value::Nil]);
+        return aux::ret(state, vec![Value::NIL]);
     }
     if n + 1 == pos {
         // 末尾の次を消す（実質 nil 返す）。
         let v = get_int(state, tk, pos);
-        set_int(state, tk, pos, Value::Nil);
+        set_int(state, tk, pos, Value::NIL);
         return aux::ret(state, vec![v]);
     }
     if pos < 1 || pos > n + 1 {
@@ -125,7 +134,7 @@ fn l_remove(state: &mut LuaState) -> LuaResult<i32> {
         set_int(state, tk, i, v);
         i += 1;
     }
-    set_int(state, tk, n, Value::Nil);
+    set_int(state, tk, n, Value::NIL);
     aux::ret(state, vec![removed])
 }
 
@@ -136,13 +145,13 @@ fn l_remove(state: &mut LuaState) -> LuaResult<i32> {
 fn l_concat(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let tk = aux::check_table(state, &args, 0, "concat")?;
-    let sep = if matches!(aux::opt_value(&args, 1), Value::Nil) {
+    let sep = if aux::opt_value(&args, 1).is_nil() {
         Vec::new()
     } else {
         aux::check_str_bytes(state, &args, 1, "concat")?
     };
     let i = aux::opt_int(state, &args, 2, "concat", 1)?;
-    let j = if matches!(aux::opt_value(&args, 3), Value::Nil) {
+    let j = if aux::opt_value(&args, 3).is_nil() {
         table_len(state, tk)
     } else {
         aux::check_int(state, &args, 3, "concat")?
@@ -151,28 +160,27 @@ fn l_concat(state: &mut LuaState) -> LuaResult<i32> {
     let mut idx = i;
     while idx <= j {
         let v = get_int(state, tk, idx);
-        let piece = match v {
-            Value::GcRef(GcHandle::Str(k)) => {
-                state.global.heap.get_str(k).unwrap().as_bytes().to_vec()
-            }
-            Value::Number(num) => number_to_string(num).into_bytes(),
-            other => {
-                return Err(aux::rt_error(
-                    state,
-                    format!(
-                        "invalid value (at index {idx}) in table for 'concat' ({})",
-                        other.type_of().name()
-                    ),
-                ));
-            }
-        };
-        out.extend_from_slice(&piece);
+        if let Some(s) = v.as_string().and_then(|k| state.global.heap.get_str(k)) {
+            out.extend_from_slice(s.as_bytes());
+        } else if let Some(num) = v.as_number() {
+            out.extend_from_slice(number_to_string(num).as_bytes());
+        } else {
+            return Err(aux::rt_error(
+                state,
+                format!(
+                    "invalid value (at index {idx}) in table for 'concat' ({})",
+                    v.type_of().name()
+                ),
+            ));
+        }
         if idx < j {
             out.extend_from_slice(&sep);
         }
+        // 上限は組み立て中に検査する（確保済みの `out` も使用量とみなす）。
+        state.reserve_memory(out.len())?;
         idx += 1;
     }
-    let s = state.new_string(&out);
+    let s = state.new_string_from(out);
     aux::ret(state, vec![s])
 }
 
@@ -185,56 +193,114 @@ fn l_sort(state: &mut LuaState) -> LuaResult<i32> {
     let tk = aux::check_table(state, &args, 0, "sort")?;
     let comp = aux::opt_value(&args, 1);
     let n = table_len(state, tk);
-    // 要素を取り出す。
+    // 要素を取り出す。比較関数が表を書き換えて GC が走っても回収されないよう、
+    // 作業中の要素はスタックにも積んでおく（ネイティブ関数のローカルは GC ルート外）。
     let mut elems: Vec<Value> = Vec::with_capacity(n as usize);
     for i in 1..=n {
         elems.push(get_int(state, tk, i));
     }
-    // マージソート（比較関数は失敗しうるので手書き）。
-    let sorted = merge_sort(state, elems, comp)?;
-    // 書き戻し。
-    for (i, v) in sorted.into_iter().enumerate() {
-        set_int(state, tk, i as i64 + 1, v);
-    }
-    aux::ret0(state)
+    state.stack.extend_from_slice(&elems);
+    let width = 1;
+    sort_run(
+        state,
+        SortState {
+            tk,
+            comp,
+            dst: Vec::with_capacity(elems.len()),
+            ri: width.min(elems.len()),
+            src: elems,
+            width,
+            lo: 0,
+            li: 0,
+        },
+    )
 }
 
-/// `a < b` を Lua 比較関数 or 既定（lua_lt）で判定する。
-fn less(state: &mut LuaState, comp: Value, a: Value, b: Value) -> LuaResult<bool> {
-    if matches!(comp, Value::Nil) {
-        aux::lua_lt(state, a, b)
-    } else {
-        let res = crate::vm::call(state, comp, &[a, b])?;
-        Ok(res.into_iter().next().unwrap_or(Value::Nil).is_truthy())
-    }
+/// ボトムアップのマージソートの途中状態。
+///
+/// 比較関数が yield したら継続の ctx として保存し、resume 後に同じ比較の続きから再開する。
+struct SortState {
+    tk: TableKey,
+    comp: Value,
+    /// 幅 `width` の整列済みの並びが連なった列。
+    src: Vec<Value>,
+    /// マージ結果の出力先。
+    dst: Vec<Value>,
+    width: usize,
+    /// マージ中の左の並びの先頭。
+    lo: usize,
+    /// 左・右の並びの次の要素。
+    li: usize,
+    ri: usize,
 }
 
-fn merge_sort(state: &mut LuaState, mut v: Vec<Value>, comp: Value) -> LuaResult<Vec<Value>> {
-    let len = v.len();
-    if len <= 1 {
-        return Ok(v);
-    }
-    let mid = len / 2;
-    let right = v.split_off(mid);
-    let left = merge_sort(state, v, comp)?;
-    let right = merge_sort(state, right, comp)?;
-    // マージ。
-    let mut out = Vec::with_capacity(len);
-    let mut li = 0;
-    let mut ri = 0;
-    while li < left.len() && ri < right.len() {
-        // 安定性: right[ri] < left[li] のときのみ right を先に出す。
-        if less(state, comp, right[ri], left[li])? {
-            out.push(right[ri]);
-            ri += 1;
+impl SortState {
+    /// 比較結果に従い、右（`right_first`）か左の並びの次の要素を出力する。
+    fn take(&mut self, right_first: bool) {
+        if right_first {
+            self.dst.push(self.src[self.ri]);
+            self.ri += 1;
         } else {
-            out.push(left[li]);
-            li += 1;
+            self.dst.push(self.src[self.li]);
+            self.li += 1;
+        }
+    }
+}
+
+fn sort_run(state: &mut LuaState, mut st: SortState) -> LuaResult<i32> {
+    let n = st.src.len();
+    while st.width < n {
+        while st.lo < n {
+            let mid = (st.lo + st.width).min(n);
+            let hi = (st.lo + 2 * st.width).min(n);
+            while st.li < mid && st.ri < hi {
+                // 安定性: right < left のときのみ right を先に出す。
+                let (right, left) = (st.src[st.ri], st.src[st.li]);
+                let right_first = if st.comp.is_nil() {
+                    aux::lua_lt(state, right, left)?
+                } else {
+                    let comp = st.comp;
+                    let mut slot = Some(st);
+                    let res = crate::vm::call_k(state, comp, &[right, left], sort_cont, || {
+                        Box::new(slot.take().expect("sort state"))
+                    })?;
+                    st = slot.expect("sort state");
+                    res.first().is_some_and(|v| v.is_truthy())
+                };
+                st.take(right_first);
+            }
+            st.dst.extend_from_slice(&st.src[st.li..mid]);
+            st.dst.extend_from_slice(&st.src[st.ri..hi]);
+            st.lo = hi;
+            st.li = hi;
+            st.ri = (hi + st.width).min(n);
         }
+        std::mem::swap(&mut st.src, &mut st.dst);
+        st.dst.clear();
+        st.width *= 2;
+        st.lo = 0;
+        st.li = 0;
+        st.ri = st.width.min(n);
     }
-    out.extend_from_slice(&left[li..]);
-    out.extend_from_slice(&right[ri..]);
-    Ok(out)
+    // 書き戻し。
+    for (i, v) in st.src.iter().enumerate() {
+        set_int(state, st.tk, i as i64 + 1, *v);
+    }
+    aux::ret0(state)
+}
+
+/// 比較関数が yield した `sort` の継続。比較結果を反映してマージを続ける。
+fn sort_cont(
+    state: &mut LuaState,
+    result: LuaResult<Vec<Value>>,
+    ctx: Box<dyn Any>,
+) -> LuaResult<i32> {
+    let right_first = result?.first().is_some_and(|v| v.is_truthy());
+    let Ok(mut st) = ctx.downcast::<SortState>() else {
+        return aux::ret0(state);
+    };
+    st.take(right_first);
+    sort_run(state, *st)
 }
 
 // ============================================================================
@@ -246,7 +312,7 @@ fn l_maxn(state: &mut LuaState) -> LuaResult<i32> {
     let tk = aux::check_table(state, &args, 0, "maxn")?;
     // 全キーを next で走査し、最大の正の数値キーを探す。
     let mut max = 0.0f64;
-    let mut key = Value::Nil;
+    let mut key = Value::NIL;
     loop {
         let nxt = state
             .global
@@ -255,7 +321,7 @@ fn l_maxn(state: &mut LuaState) -> LuaResult<i32> {
             .and_then(|t| t.next(&key).ok().flatten());
         match nxt {
             Some((k, _)) => {
-                if let Value::Number(n) = k
+                if let Some(n) = k.as_number()
                     && n > max
                 {
                     max = n;
@@ -265,12 +331,12 @@ fn l_maxn(state: &mut LuaState) -> LuaResult<i32> {
             None => break,
         }
     }
-    aux::ret(state, vec![Value::Number(max)])
+    aux::ret(state, vec![Value::number(max)])
 }
 
 fn l_getn(state: &mut LuaState) -> LuaResult<i32> {
     let args = aux::args_vec(state);
     let tk = aux::check_table(state, &args, 0, "getn")?;
     let n = table_len(state, tk);
-    aux::ret(state, vec![Value::Number(n as f64)])
+    aux::ret(state, vec![Value::number(n as f64)])
 }
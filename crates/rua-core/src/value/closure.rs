@@ -5,7 +5,7 @@
 //!
 //! # upvalue のオープン/クローズ（本家 `UpVal`）
 //! upvalue は、捕捉元のローカル変数がまだスタック上に生きている間は **open**
-//! （[`UpvalueState::Open`]、スタックスロットの絶対インデックスを指す）であり、
+//! （[`UpvalueState::Open`]、捕捉元スレッドのスタックスロットの絶対インデックスを指す）であり、
 //! その変数がスコープを抜けると **closed**（[`UpvalueState::Closed`]、値を自身へコピー）になる。
 //! 同一スコープの同一ローカルを捕捉する複数クロージャは同じ [`Upvalue`]（`Rc<RefCell<..>>`）を共有し、
 //! 一方の書き換えが他方へ反映される（本家のセマンティクスに一致）。
@@ -13,7 +13,7 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use crate::gc::{GcHandle, Trace, Tracer};
+use crate::gc::{GcHandle, HeapSize, ThreadKey, Trace, Tracer};
 use crate::state::NativeFn;
 use crate::value::Value;
 use crate::vm::proto::Proto;
@@ -21,8 +21,9 @@ use crate::vm::proto::Proto;
 /// upvalue の状態（open/closed）。
 #[derive(Debug)]
 pub enum UpvalueState {
-    /// 捕捉元ローカルがまだスタック上にある。値はスタックの**絶対**インデックスで参照する。
-    Open(usize),
+    /// 捕捉元ローカルがまだスタック上にある。値は捕捉元スレッド（`None` はメインスレッド）の
+    /// スタックの**絶対**インデックスで参照する。
+    Open(Option<ThreadKey>, usize),
     /// スコープを抜けて閉じられた upvalue。値を自身が保持する。
     Closed(Value),
 }
@@ -44,8 +45,8 @@ pub enum Closure {
 pub struct LuaClosure {
     /// 実行するプロトタイプ（命令列・定数表・子 proto・デバッグ情報）。
     proto: Rc<Proto>,
-    /// 捕捉した upvalue 群（`proto.num_upvalues` 個）。
-    upvalues: Vec<Upvalue>,
+    /// 捕捉した upvalue 群（`proto.num_upvalues` 個）。実行中のフレームは複製せずに共有する。
+    upvalues: Rc<[Upvalue]>,
     /// 関数の環境テーブル（本家 Lua 5.1 の `LClosure.g.env`）。
     /// `GetGlobal`/`SetGlobal` はこのテーブルに対して index_get/index_set する。
     /// デフォルトはグローバル環境テーブル。
@@ -53,13 +54,17 @@ pub struct LuaClosure {
 }
 
 impl LuaClosure {
-    /// プロトタイプからクロージャを作る（upvalue は呼び出し側が [`Self::push_upvalue`] で束ねる）。
+    /// upvalue を持たないプロトタイプ（メインチャンク等）からクロージャを作る。
     /// env にはグローバル環境テーブルのハンドルを渡す。
     pub fn new_with_env(proto: Rc<Proto>, env: GcHandle) -> Self {
-        let cap = proto.num_upvalues as usize;
+        Self::with_upvalues(proto, env, Vec::new())
+    }
+
+    /// 捕捉済みの upvalue 群を束ねてクロージャを作る（`CLOSURE` 命令から呼ぶ）。
+    pub fn with_upvalues(proto: Rc<Proto>, env: GcHandle, upvalues: Vec<Upvalue>) -> Self {
         LuaClosure {
             proto,
-            upvalues: Vec::with_capacity(cap),
+            upvalues: upvalues.into(),
             env,
         }
     }
@@ -69,8 +74,8 @@ impl LuaClosure {
         &self.proto
     }
 
-    /// 束ねた upvalue 群。
-    pub fn upvalues(&self) -> &[Upvalue] {
+    /// 束ねた upvalue 群。VM のフレームは `Rc` を複製して参照する（セルの配列はコピーしない）。
+    pub fn upvalues(&self) -> &Rc<[Upvalue]> {
         &self.upvalues
     }
 
@@ -79,11 +84,6 @@ impl LuaClosure {
         self.upvalues.get(i)
     }
 
-    /// upvalue を 1 つ束ねる（`CLOSURE` 直後の捕捉疑似命令処理から呼ぶ）。
-    pub fn push_upvalue(&mut self, uv: Upvalue) {
-        self.upvalues.push(uv);
-    }
-
     /// 関数の環境テーブルハンドルを返す。
     pub fn env(&self) -> GcHandle {
         self.env
@@ -164,6 +164,24 @@ impl std::fmt::Debug for NativeClosure {
     }
 }
 
+impl HeapSize for Closure {
+    /// proto は複数のクロージャで共有されるため含めない（[`crate::gc::Heap`] が別途 1 回だけ数える）。
+    /// upvalue セルは共有されうるが、簡単のため参照するクロージャごとに数える。
+    fn heap_size(&self) -> usize {
+        let cells = match self {
+            Closure::Lua(c) => {
+                c.upvalues.len() * std::mem::size_of::<Upvalue>()
+                    // Rc の確保単位 = 強/弱カウンタ + セル本体。
+                    + c.upvalues.len()
+                        * (2 * std::mem::size_of::<usize>()
+                            + std::mem::size_of::<RefCell<UpvalueState>>())
+            }
+            Closure::Native(c) => c.upvalues.capacity() * std::mem::size_of::<Value>(),
+        };
+        std::mem::size_of::<Closure>() + cells
+    }
+}
+
 impl Trace for Closure {
     fn trace(&self, tracer: &mut Tracer) {
         match self {
@@ -172,10 +190,13 @@ impl Trace for Closure {
                 c.proto.trace_constants(tracer);
                 // 環境テーブルを mark（setfenv で別テーブルに差し替えられる場合があるため必須）。
                 tracer.mark(c.env);
-                // closed upvalue が保持する値を mark（open は捕捉元スタックが別途ルート）。
-                for uv in &c.upvalues {
-                    if let UpvalueState::Closed(v) = &*uv.borrow() {
-                        tracer.mark_value(v);
+                // closed upvalue が保持する値を mark。open なら捕捉元のスタックを持つ
+                // コルーチンを生かす（メインスレッドのスタックは別途ルート）。
+                for uv in c.upvalues.iter() {
+                    match &*uv.borrow() {
+                        UpvalueState::Closed(v) => tracer.mark_value(v),
+                        UpvalueState::Open(Some(k), _) => tracer.mark(GcHandle::Thread(*k)),
+                        UpvalueState::Open(None, _) => {}
                     }
                 }
             }
@@ -2,7 +2,7 @@
 //!
 //! Lua 5.1 の値型は `nil / boolean / number(double) / string / table / function /
 //! userdata / lightuserdata / thread`。このうち string/table/function/userdata/thread は
-//! GC 管理オブジェクトであり、[`Value::GcRef`] が型別アリーナ上のハンドルで参照する。
+//! GC 管理オブジェクトであり、[`Value`] は型別アリーナ上のハンドル（[`GcHandle`]）を詰めて参照する。
 //!
 //! 型の **骨格** は lua-runtime が用意し、各 GC オブジェクトの本実装（テーブルのハッシュ部、
 //! 文字列メタ情報、クロージャの命令列など）は lua-vm / lua-frontend が埋める。
@@ -14,32 +14,59 @@ pub mod table;
 pub mod thread;
 pub mod userdata;
 
+use std::fmt;
 use std::os::raw::c_void;
 
-use crate::gc::GcHandle;
+use crate::gc::arena::ArenaKey;
+use crate::gc::{ClosureKey, GcHandle, StringKey, TableKey, ThreadKey, UserdataKey};
 
-/// Lua の値（本家 `TValue`）。
+/// Lua の値（本家 `TValue`）。NaN-boxing で 8 バイトの `u64` に詰める。
 ///
-/// `f64`・`bool`・ポインタ・[`GcHandle`] はいずれも `Copy` なので `Value` も `Copy`。
-/// これにより VM スタック上を安価に移動できる（本家の値セマンティクスに一致）。
+/// # 表現
+/// - number: `f64` のビット列そのまま。`NaN` は正の quiet NaN 1 種類に正規化する。
+/// - それ以外: 上位 16 ビットが `0xFFF8 + タグ`（正規化後の number には現れない負の NaN の空間）、
+///   下位 48 ビットがペイロード。
+///
+/// | タグ | 型 | ペイロード |
+/// |------|----|------------|
+/// | 0 | nil | 0 |
+/// | 1 | boolean | 0 / 1 |
+/// | 2 | lightuserdata | ポインタの下位 48 ビット（取り出し時に符号拡張） |
+/// | 3..=7 | string / table / function / userdata / thread | [`GcHandle::key_bits`] |
+///
+/// 中身を直接見ずに、構築は [`Value::number`]・[`Value::table`] など、取り出しは
+/// [`Value::as_number`]・[`Value::as_table`] などのアクセサを使う。`unsafe` は使わない。
 ///
 /// # 等価性
-/// `PartialEq` は Lua の生（raw）等価性に対応させる予定だが、GC 文字列の内容比較は
-/// インターン（同値 → 同一ハンドル）で吸収されるため、ハンドル一致で内容一致を判定できる。
+/// `PartialEq` はタグとペイロード（number は値）の一致。短い文字列はインターン（同値 → 同一ハンドル）
+/// されるためこれで足りるが、長い文字列は同値でも別ハンドルになりうるので、Lua の生（raw）等価性は
+/// [`crate::gc::Heap::raw_equal`] で判定する。
 /// メタメソッド `__eq` を考慮した等価性は lua-vm が別途実装する。
-#[derive(Debug, Clone, Copy, Default)]
-pub enum Value {
-    /// `nil`
-    #[default]
-    Nil,
-    /// `boolean`
-    Boolean(bool),
-    /// `number` — Lua 5.1 は整数型を持たず全数値が double。
-    Number(f64),
-    /// `lightuserdata` — GC 管理外の生ポインタ。値として比較・運搬される。
-    LightUserData(*mut c_void),
-    /// GC 管理オブジェクト（string/table/function/userdata/thread）への参照。
-    GcRef(GcHandle),
+#[derive(Clone, Copy)]
+pub struct Value(u64);
+
+const TAG_BASE: u64 = 0xFFF8;
+const PAYLOAD_BITS: u32 = 48;
+const PAYLOAD_MASK: u64 = (1 << PAYLOAD_BITS) - 1;
+const CANONICAL_NAN: u64 = 0x7FF8_0000_0000_0000;
+
+const TAG_NIL: u64 = 0;
+const TAG_BOOLEAN: u64 = 1;
+const TAG_LIGHTUSERDATA: u64 = 2;
+const TAG_STRING: u64 = 3;
+const TAG_TABLE: u64 = 4;
+const TAG_CLOSURE: u64 = 5;
+const TAG_USERDATA: u64 = 6;
+const TAG_THREAD: u64 = 7;
+
+const fn boxed(tag: u64, payload: u64) -> Value {
+    Value(((TAG_BASE + tag) << PAYLOAD_BITS) | payload)
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::NIL
+    }
 }
 
 /// Lua の基本型（本家 `LUA_T*` 定数, `lua_type` の戻り値に相当）。
@@ -75,48 +102,207 @@ impl LuaType {
 }
 
 impl Value {
-    /// 本家 `lua_type` 相当。GcRef はハンドルの判別子だけで型が分かる（本体デリファレンス不要）。
+    /// `nil`
+    pub const NIL: Value = boxed(TAG_NIL, 0);
+    /// `true`
+    pub const TRUE: Value = boxed(TAG_BOOLEAN, 1);
+    /// `false`
+    pub const FALSE: Value = boxed(TAG_BOOLEAN, 0);
+
+    // ---- 構築 ---------------------------------------------------------------
+
+    /// `boolean`
+    pub const fn boolean(b: bool) -> Value {
+        boxed(TAG_BOOLEAN, b as u64)
+    }
+
+    /// `number` — Lua 5.1 は整数型を持たず全数値が double。
+    pub const fn number(n: f64) -> Value {
+        if n.is_nan() {
+            Value(CANONICAL_NAN)
+        } else {
+            Value(n.to_bits())
+        }
+    }
+
+    /// `lightuserdata` — GC 管理外の生ポインタ。値として比較・運搬される。
+    ///
+    /// 下位 48 ビットだけを保持する（x86-64/AArch64 のユーザー空間アドレスは収まる, LuaJIT と同じ制約）。
+    pub fn light_userdata(p: *mut c_void) -> Value {
+        boxed(TAG_LIGHTUSERDATA, p as usize as u64 & PAYLOAD_MASK)
+    }
+
+    /// GC 管理オブジェクト（string/table/function/userdata/thread）への参照。
+    pub fn gc(h: GcHandle) -> Value {
+        let tag = match h {
+            GcHandle::Str(_) => TAG_STRING,
+            GcHandle::Table(_) => TAG_TABLE,
+            GcHandle::Closure(_) => TAG_CLOSURE,
+            GcHandle::Userdata(_) => TAG_USERDATA,
+            GcHandle::Thread(_) => TAG_THREAD,
+        };
+        boxed(tag, h.key_bits())
+    }
+
+    pub fn string(k: StringKey) -> Value {
+        boxed(TAG_STRING, k.to_bits())
+    }
+
+    pub fn table(k: TableKey) -> Value {
+        boxed(TAG_TABLE, k.to_bits())
+    }
+
+    pub fn closure(k: ClosureKey) -> Value {
+        boxed(TAG_CLOSURE, k.to_bits())
+    }
+
+    pub fn userdata(k: UserdataKey) -> Value {
+        boxed(TAG_USERDATA, k.to_bits())
+    }
+
+    pub fn thread(k: ThreadKey) -> Value {
+        boxed(TAG_THREAD, k.to_bits())
+    }
+
+    /// ビット表現（テーブルのハッシュキー用）。
+    pub(crate) fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    /// [`Value::to_bits`] の逆。
+    pub(crate) fn from_bits(bits: u64) -> Value {
+        Value(bits)
+    }
+
+    // ---- 判定・取り出し -----------------------------------------------------
+
+    /// number 以外ならタグ。
+    #[inline]
+    fn tag(self) -> Option<u64> {
+        let hi = self.0 >> PAYLOAD_BITS;
+        (hi >= TAG_BASE).then(|| hi - TAG_BASE)
+    }
+
+    /// タグが `tag` ならペイロード。
+    #[inline]
+    fn payload_if(self, tag: u64) -> Option<u64> {
+        (self.0 >> PAYLOAD_BITS == TAG_BASE + tag).then_some(self.0 & PAYLOAD_MASK)
+    }
+
+    /// 本家 `lua_type` 相当。タグだけで型が分かる（本体デリファレンス不要）。
     pub fn type_of(&self) -> LuaType {
-        match self {
-            Value::Nil => LuaType::Nil,
-            Value::Boolean(_) => LuaType::Boolean,
-            Value::Number(_) => LuaType::Number,
-            Value::LightUserData(_) => LuaType::LightUserData,
-            Value::GcRef(h) => match h {
-                GcHandle::Str(_) => LuaType::String,
-                GcHandle::Table(_) => LuaType::Table,
-                GcHandle::Closure(_) => LuaType::Function,
-                GcHandle::Userdata(_) => LuaType::Userdata,
-                GcHandle::Thread(_) => LuaType::Thread,
-            },
+        match self.tag() {
+            None => LuaType::Number,
+            Some(TAG_NIL) => LuaType::Nil,
+            Some(TAG_BOOLEAN) => LuaType::Boolean,
+            Some(TAG_LIGHTUSERDATA) => LuaType::LightUserData,
+            Some(TAG_STRING) => LuaType::String,
+            Some(TAG_TABLE) => LuaType::Table,
+            Some(TAG_CLOSURE) => LuaType::Function,
+            Some(TAG_USERDATA) => LuaType::Userdata,
+            Some(_) => LuaType::Thread,
         }
     }
 
+    #[inline]
+    pub fn is_nil(&self) -> bool {
+        self.0 == Value::NIL.0
+    }
+
+    #[inline]
+    pub fn is_number(&self) -> bool {
+        self.tag().is_none()
+    }
+
     /// Lua の真偽（false と nil のみ偽, それ以外は真）。本家 `lua_toboolean` のコア規則。
+    #[inline]
     pub fn is_truthy(&self) -> bool {
-        !matches!(self, Value::Nil | Value::Boolean(false))
+        self.0 != Value::NIL.0 && self.0 != Value::FALSE.0
+    }
+
+    #[inline]
+    pub fn as_boolean(&self) -> Option<bool> {
+        self.payload_if(TAG_BOOLEAN).map(|b| b != 0)
+    }
+
+    #[inline]
+    pub fn as_number(&self) -> Option<f64> {
+        self.is_number().then(|| f64::from_bits(self.0))
+    }
+
+    pub fn as_light_userdata(&self) -> Option<*mut c_void> {
+        self.payload_if(TAG_LIGHTUSERDATA)
+            .map(|p| (((p << 16) as i64 >> 16) as usize) as *mut c_void)
     }
 
     /// GC 参照ならそのハンドルを返す（GC ルート収集や型別アクセスの入口）。
     pub fn as_gc(&self) -> Option<GcHandle> {
-        match self {
-            Value::GcRef(h) => Some(*h),
-            _ => None,
+        let bits = self.0 & PAYLOAD_MASK;
+        Some(match self.tag()? {
+            TAG_STRING => GcHandle::Str(StringKey::from_bits(bits)),
+            TAG_TABLE => GcHandle::Table(TableKey::from_bits(bits)),
+            TAG_CLOSURE => GcHandle::Closure(ClosureKey::from_bits(bits)),
+            TAG_USERDATA => GcHandle::Userdata(UserdataKey::from_bits(bits)),
+            TAG_THREAD => GcHandle::Thread(ThreadKey::from_bits(bits)),
+            _ => return None,
+        })
+    }
+
+    #[inline]
+    pub fn as_string(&self) -> Option<StringKey> {
+        self.payload_if(TAG_STRING).map(StringKey::from_bits)
+    }
+
+    #[inline]
+    pub fn as_table(&self) -> Option<TableKey> {
+        self.payload_if(TAG_TABLE).map(TableKey::from_bits)
+    }
+
+    #[inline]
+    pub fn as_closure(&self) -> Option<ClosureKey> {
+        self.payload_if(TAG_CLOSURE).map(ClosureKey::from_bits)
+    }
+
+    #[inline]
+    pub fn as_userdata(&self) -> Option<UserdataKey> {
+        self.payload_if(TAG_USERDATA).map(UserdataKey::from_bits)
+    }
+
+    #[inline]
+    pub fn as_thread(&self) -> Option<ThreadKey> {
+        self.payload_if(TAG_THREAD).map(ThreadKey::from_bits)
+    }
+}
+
+impl From<GcHandle> for Value {
+    fn from(h: GcHandle) -> Value {
+        Value::gc(h)
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(n) = self.as_number() {
+            write!(f, "Number({n:?})")
+        } else if let Some(b) = self.as_boolean() {
+            write!(f, "Boolean({b})")
+        } else if let Some(p) = self.as_light_userdata() {
+            write!(f, "LightUserData({p:?})")
+        } else if let Some(h) = self.as_gc() {
+            write!(f, "GcRef({h:?})")
+        } else {
+            f.write_str("Nil")
         }
     }
 }
 
 impl PartialEq for Value {
-    /// Lua の raw 等価（`rawequal`）相当。
-    /// number は値比較、GcRef はハンドル比較（文字列はインターンにより内容一致 ⇔ ハンドル一致）。
+    /// ハンドル単位の等価。長い文字列の内容比較は行わない（[`crate::gc::Heap::raw_equal`] を使う）。
+    /// number は値比較（`NaN ~= NaN`, `0 == -0`）、それ以外はタグとペイロードの一致。
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Value::Nil, Value::Nil) => true,
-            (Value::Boolean(a), Value::Boolean(b)) => a == b,
-            (Value::Number(a), Value::Number(b)) => a == b,
-            (Value::LightUserData(a), Value::LightUserData(b)) => std::ptr::eq(*a, *b),
-            (Value::GcRef(a), Value::GcRef(b)) => a == b,
-            _ => false,
+        match (self.as_number(), other.as_number()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.0 == other.0,
         }
     }
 }
@@ -1,31 +1,46 @@
 //! Lua 文字列（本家 `lstring.c` / `TString` 相当）。
 //!
-//! Lua 文字列は **不変なバイト列**（任意の `\0` を含みうる）であり、インターンされる。
-//! インターンは [`crate::gc::Heap::intern_str`] が担い、同値文字列は同一ハンドルになる。
+//! Lua 文字列は **不変なバイト列**（任意の `\0` を含みうる）。本家 5.2 以降と同じく、長さで 2 種に分ける。
+//!
+//! - **短い文字列**（[`MAX_SHORT_LEN`] バイト以下）: インターンする。同値文字列は同一ハンドルになり、
+//!   比較はハンドルの一致で済む。識別子・テーブルのキー・定数の大半はこちら。
+//! - **長い文字列**: インターンしない（生成時にハッシュを計算しない）。`..` や `table.concat` が作る
+//!   大きな中間文字列の生成を、バイト列の確保と複製だけにするため。等価比較は必要になった時点で内容を
+//!   比べる（[`crate::gc::Heap::raw_equal`]）。テーブルのキーに使われたときだけ、その時点で
+//!   インターンして同値の文字列を 1 つのオブジェクトへ寄せる（[`crate::gc::Heap::intern_key`]）。
+//!
+//! 生成は [`crate::gc::Heap::alloc_str`] が担い、長さに応じてどちらかを作る。
 //!
 //! # バッファ安定性
 //! 内容を `Box<[u8]>` で確保し、生成後は再確保しない。これにより将来の C API で
 //! `lua_tolstring` が返す `const char*` のポインタ安定性（ARCHITECTURE.md §5）を満たす土台となる。
-//!
-//! TODO(lua-vm): メタテーブル（文字列共通メタテーブル）参照、`..` 連結や `string.*` 用の補助。
 
-use std::hash::{Hash, Hasher};
+use crate::gc::HeapSize;
+
+/// インターンする文字列の最大長（本家 5.2 `LUAI_MAXSHORTLEN`）。これより長い文字列は
+/// 生成時にインターンしない。
+pub const MAX_SHORT_LEN: usize = 40;
 
-/// インターン済み Lua 文字列の本体。
+/// Lua 文字列の本体。
 #[derive(Debug, Clone)]
 pub struct LuaString {
     /// 不変バイト列。生成後に変更しない（安定バッファ）。
     bytes: Box<[u8]>,
-    /// 事前計算ハッシュ（本家 `TString` も hash を保持）。テーブルキー等で再利用する。
-    hash: u64,
+    /// インターナに登録済みか。短い文字列は常に、長い文字列はテーブルのキーに使われてから。
+    interned: bool,
 }
 
 impl LuaString {
-    /// バイト列から生成する（通常は [`crate::gc::Heap::intern_str`] 経由で呼ばれる）。
+    /// バイト列から生成する（通常は [`crate::gc::Heap::alloc_str`] 経由で呼ばれる）。
     pub fn new(bytes: &[u8]) -> Self {
+        Self::from_vec(bytes.to_vec())
+    }
+
+    /// 組み立て済みのバッファをそのまま本体にする（連結結果などの複製を避ける）。
+    pub fn from_vec(bytes: Vec<u8>) -> Self {
         LuaString {
-            bytes: bytes.into(),
-            hash: hash_bytes(bytes),
+            bytes: bytes.into_boxed_slice(),
+            interned: false,
         }
     }
 
@@ -34,11 +49,6 @@ impl LuaString {
         &self.bytes
     }
 
-    /// 事前計算済みハッシュ。
-    pub fn hash(&self) -> u64 {
-        self.hash
-    }
-
     /// バイト長（`#` 演算子や `string.len` の基礎）。
     pub fn len(&self) -> usize {
         self.bytes.len()
@@ -48,6 +58,20 @@ impl LuaString {
         self.bytes.is_empty()
     }
 
+    /// 長い文字列（生成時にインターンしない）か。
+    pub fn is_long(&self) -> bool {
+        self.bytes.len() > MAX_SHORT_LEN
+    }
+
+    /// インターナに登録済みか。登録済みの文字列どうしはハンドルの一致 ⇔ 内容の一致。
+    pub fn is_interned(&self) -> bool {
+        self.interned
+    }
+
+    pub(crate) fn set_interned(&mut self) {
+        self.interned = true;
+    }
+
     /// UTF-8 として妥当ならば `&str` を返す（表示・診断用途）。
     /// Lua 文字列は本来バイト列なので、不正 UTF-8 では `None`。
     pub fn as_str(&self) -> Option<&str> {
@@ -55,9 +79,8 @@ impl LuaString {
     }
 }
 
-/// 文字列ハッシュ。インターナのキー安定性のため決定的なハッシュを使う。
-fn hash_bytes(bytes: &[u8]) -> u64 {
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    bytes.hash(&mut hasher);
-    hasher.finish()
+impl HeapSize for LuaString {
+    fn heap_size(&self) -> usize {
+        std::mem::size_of::<LuaString>() + self.bytes.len()
+    }
 }
@@ -9,33 +9,128 @@
 //! - `NaN` キーは代入エラー。
 //! - 整数値の `number`（`floor(n)==n` かつ有限）は整数キーとして扱う。`2` と `2.0` は同一キー。
 //! - `-0.0` は `0.0` に正規化する。
-//! - 文字列キーはインターン済みハンドルで比較（同値 ⇔ 同一ハンドル）。
-
-use std::collections::HashMap;
+//! - 文字列キーはインターン済みハンドルで比較（同値 ⇔ 同一ハンドル）。長い文字列は格納時に
+//!   [`crate::gc::Heap::intern_key`]、参照時に [`crate::gc::Heap::table_key`] で正規化してから渡すこと。
+//!
+//! # 配列部の大きさ（本家 `computesizes`/`rehash`）
+//! 配列部の大きさは、ハッシュ部が満杯になって新しいキーを入れられないときにだけ決め直す。
+//! 全整数キーを `2^(i-1) < k <= 2^i` の区間ごとに数え、「`1..=n` の半数より多くが使われている」
+//! 最大の 2 冪 `n` を配列部の大きさにする。残りのキーの数からハッシュ部の大きさ（2 冪）を決め、
+//! 全エントリを入れ直す。後から連番になった整数キーもこの時点で配列部へ移る。
+//!
+//! # ハッシュ部（本家 `Node`）
+//! ハッシュ部は固定長のノード配列で、衝突はノード間の `next` リンク（チェーン）で繋ぐ
+//! （Brent の変形: 新しいキーの主位置を他のチェーンのノードが占めていれば、そちらを空き
+//! ノードへ追い出す）。空きノードは `lastfree` から下向きに探す。値を `nil` にしたノードは
+//! キーを残し（死んだキー）、再ハッシュまで再利用しない。そのため走査中の削除でも
+//! `next` を続けられ、`next` 自体は確保を伴わない（キー → ノード位置 → 次の位置）。
+//!
+//! # ノード位置とインラインキャッシュ
+//! VM は定数文字列キーの参照ごとに前回のノード位置（スロット）を覚えておき
+//! （[`Proto::cached_slot`](crate::vm::proto::Proto::cached_slot)）、次回はそのノードの
+//! キーが一致するかを確かめるだけで値を読み書きする（[`Table::get_slot`]/[`Table::set_slot`]）。
+//! 同じキーが 2 つのノードに載ることは無く、再ハッシュでノードが動いてもキーの照合で外れるので、
+//! キャッシュの明示的な無効化は要らない。メタメソッドは生きたキーの読み書きには関わらないため、
+//! メタテーブルの変更も影響しない。
 
-use crate::gc::{GcHandle, Trace, Tracer};
+use crate::gc::{GcHandle, HeapSize, Trace, Tracer};
 use crate::value::Value;
 
-/// ハッシュ部のキー（[`Value`] を `Hash`/`Eq` 可能な形に正規化したもの）。
+/// 配列部の大きさの上限（`2^MAXBITS`, 本家 5.1 の `MAXBITS`）。
+const MAXBITS: usize = 26;
+const MAXASIZE: usize = 1 << MAXBITS;
+
+/// ハッシュ部のキー。正規化した [`Value`] のビット表現（NaN-boxing 済みなので型も含む）。
 ///
-/// 配列部に入らない number は [`HKey::Number`] にビットパターンで格納する
-/// （`-0.0`→`0.0` 正規化済み、`NaN` は格納しない）。
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum HKey {
-    Boolean(bool),
-    /// 整数値でない / 範囲外の number。正規化済み f64 のビットパターン。
-    Number(u64),
-    LightUserData(usize),
-    Gc(GcHandle),
+/// number は `-0.0`→`0.0` に正規化済みで、`NaN` は格納しない。文字列はインターン済みの
+/// ハンドルに正規化済みなのでビット一致 ⇔ 内容一致。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HKey(u64);
+
+impl HKey {
+    /// 整数キー。
+    fn int(i: usize) -> HKey {
+        HKey(Value::number(i as f64).to_bits())
+    }
+
+    fn gc(h: GcHandle) -> HKey {
+        HKey(Value::gc(h).to_bits())
+    }
+
+    fn value(self) -> Value {
+        Value::from_bits(self.0)
+    }
+
+    /// 配列部に入りうる整数キーなら、その添字（1 始まり）。
+    fn array_index(&self) -> Option<usize> {
+        self.value().as_number().and_then(num_to_array_index)
+    }
+
+    /// ノード配列上の主位置を決めるハッシュ値。
+    fn hash(&self) -> u64 {
+        // murmur3 の最終混合。下位ビットだけでノード位置を決めるため、上位ビットも混ぜる。
+        let x = (self.0 ^ (self.0 >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+        x ^ (x >> 33)
+    }
 }
 
-/// 正規化済みハッシュキー [`HKey`] を [`Value`] へ戻す（`next` 反復用）。
-fn hkey_to_value(hk: &HKey) -> Value {
-    match hk {
-        HKey::Boolean(b) => Value::Boolean(*b),
-        HKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
-        HKey::LightUserData(p) => Value::LightUserData(*p as *mut std::os::raw::c_void),
-        HKey::Gc(h) => Value::GcRef(*h),
+/// ハッシュ部のノード（本家 `Node`）。
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    /// `None` なら空きノード。値が `nil` で `Some` なら死んだキー。
+    key: Option<HKey>,
+    val: Value,
+    /// 同じチェーンの次のノード。
+    next: Option<usize>,
+}
+
+impl Node {
+    const FREE: Node = Node {
+        key: None,
+        val: Value::NIL,
+        next: None,
+    };
+}
+
+/// `k`（`>= 1`）以上の最小の 2 冪の指数（本家 `luaO_ceillog2`）。
+fn ceil_log2(k: usize) -> usize {
+    (usize::BITS - (k - 1).leading_zeros()) as usize
+}
+
+/// 整数キーの区間別の個数 `nums` と配列部候補の総数 `narray` から、配列部の大きさと
+/// そこに入るキーの数を決める（本家 `computesizes`）。
+fn compute_sizes(nums: &[usize; MAXBITS + 1], narray: usize) -> (usize, usize) {
+    let mut a = 0;
+    let mut na = 0;
+    let mut n = 0;
+    let mut twotoi = 1usize;
+    for &c in nums {
+        if twotoi / 2 >= narray {
+            break;
+        }
+        if c > 0 {
+            a += c;
+            if a > twotoi / 2 {
+                n = twotoi;
+                na = a;
+            }
+        }
+        if a == narray {
+            break;
+        }
+        twotoi *= 2;
+    }
+    (n, na)
+}
+
+/// 配列部候補の整数キーなら `nums` に数えて 1 を返す（本家 `countint`）。
+fn count_int(hk: &HKey, nums: &mut [usize; MAXBITS + 1]) -> usize {
+    match hk.array_index() {
+        Some(k) if k <= MAXASIZE => {
+            nums[ceil_log2(k)] += 1;
+            1
+        }
+        _ => 0,
     }
 }
 
@@ -58,43 +153,32 @@ fn num_to_array_index(n: f64) -> Option<usize> {
     }
 }
 
-/// number を正規化したビットパターンへ（`-0.0`→`0.0`）。`NaN` は `None`。
-fn norm_num_bits(n: f64) -> Option<u64> {
-    if n.is_nan() {
-        None
-    } else if n == 0.0 {
-        Some(0.0f64.to_bits())
-    } else {
-        Some(n.to_bits())
-    }
-}
-
 fn classify_key(key: &Value) -> KeyClass {
-    match key {
-        Value::Nil => KeyClass::Invalid,
-        Value::Boolean(b) => KeyClass::Hash(HKey::Boolean(*b)),
-        Value::Number(n) => {
-            if let Some(i) = num_to_array_index(*n) {
-                KeyClass::ArrayIndex(i)
-            } else {
-                match norm_num_bits(*n) {
-                    Some(bits) => KeyClass::Hash(HKey::Number(bits)),
-                    None => KeyClass::Invalid,
-                }
-            }
-        }
-        Value::LightUserData(p) => KeyClass::Hash(HKey::LightUserData(*p as usize)),
-        Value::GcRef(h) => KeyClass::Hash(HKey::Gc(*h)),
+    if key.is_nil() {
+        return KeyClass::Invalid;
+    }
+    let Some(n) = key.as_number() else {
+        return KeyClass::Hash(HKey(key.to_bits()));
+    };
+    if let Some(i) = num_to_array_index(n) {
+        KeyClass::ArrayIndex(i)
+    } else if n.is_nan() {
+        KeyClass::Invalid
+    } else {
+        // `-0.0` と `0.0` は同じキー。
+        KeyClass::Hash(HKey(Value::number(n + 0.0).to_bits()))
     }
 }
 
 /// Lua テーブル。
 #[derive(Debug, Default)]
 pub struct Table {
-    /// 配列部（キー `1..=array.len()` を `array[i-1]` に格納）。末尾の `nil` は border 計算で扱う。
+    /// 配列部（キー `1..=array.len()` を `array[i-1]` に格納）。穴（`nil`）を含みうる。
     array: Vec<Value>,
-    /// ハッシュ部。配列部に入らないキー。
-    hash: HashMap<HKey, Value>,
+    /// ハッシュ部のノード配列（長さは 0 または 2 冪）。配列部に入らないキー。
+    node: Vec<Node>,
+    /// 空きノード探索の開始位置（これより後ろに空きは無い）。
+    lastfree: usize,
     /// メタテーブル（無ければ `None`）。
     metatable: Option<GcHandle>,
 }
@@ -104,13 +188,14 @@ impl Table {
         Table::default()
     }
 
-    /// 配列部/ハッシュ部の初期容量を予約して作る（`NEWTABLE` のサイズヒント用）。
+    /// 配列部/ハッシュ部の大きさを指定して作る（`NEWTABLE` のサイズヒント用, 本家 `luaH_new`）。
     pub fn with_capacity(narray: usize, nhash: usize) -> Self {
-        Table {
-            array: Vec::with_capacity(narray),
-            hash: HashMap::with_capacity(nhash),
-            metatable: None,
-        }
+        let mut t = Table {
+            array: vec![Value::NIL; narray],
+            ..Table::default()
+        };
+        t.set_node_vector(nhash);
+        t
     }
 
     /// メタテーブルを取得。
@@ -128,16 +213,9 @@ impl Table {
     /// キーに対応する値を返す（無ければ `nil`）。raw アクセス（`__index` 非経由）。
     pub fn get(&self, key: &Value) -> Value {
         match classify_key(key) {
-            KeyClass::ArrayIndex(i) if i <= self.array.len() => self.array[i - 1],
-            KeyClass::ArrayIndex(i) => {
-                // 配列範囲外の整数キーはハッシュ部にあるかもしれない。
-                self.hash
-                    .get(&HKey::Number((i as f64).to_bits()))
-                    .copied()
-                    .unwrap_or(Value::Nil)
-            }
-            KeyClass::Hash(hk) => self.hash.get(&hk).copied().unwrap_or(Value::Nil),
-            KeyClass::Invalid => Value::Nil,
+            KeyClass::ArrayIndex(i) => self.get_int(i),
+            KeyClass::Hash(hk) => self.get_node(&hk),
+            KeyClass::Invalid => Value::NIL,
         }
     }
 
@@ -146,33 +224,33 @@ impl Table {
         if i >= 1 && i <= self.array.len() {
             self.array[i - 1]
         } else if i >= 1 {
-            self.hash
-                .get(&HKey::Number((i as f64).to_bits()))
-                .copied()
-                .unwrap_or(Value::Nil)
+            // 配列範囲外の整数キーはハッシュ部にあるかもしれない。
+            self.get_node(&HKey::int(i))
         } else {
-            Value::Nil
+            Value::NIL
         }
     }
 
     /// キーに値を代入する（raw, `__newindex` 非経由）。`nil`/`NaN` キーは `Err` を返す。
     ///
-    /// `value` が `nil` の場合は削除に相当（配列部は穴になりうる）。
+    /// `value` が `nil` の場合は削除に相当（配列部は穴に、ハッシュ部は死んだキーになる）。
+    /// ヒープ上のテーブルへの書き込みは [`Heap::get_table_mut`](crate::gc::Heap::get_table_mut)
+    /// 経由で可変参照を得た時点でライトバリアが掛かっている（インクリメンタル GC の前提）。
     pub fn set(&mut self, key: Value, value: Value) -> Result<(), TableKeyError> {
         match classify_key(&key) {
+            KeyClass::ArrayIndex(i) if i <= self.array.len() => {
+                self.array[i - 1] = value;
+                Ok(())
+            }
             KeyClass::ArrayIndex(i) => {
-                self.set_array_index(i, value);
+                self.set_node(HKey::int(i), value);
                 Ok(())
             }
             KeyClass::Hash(hk) => {
-                if matches!(value, Value::Nil) {
-                    self.hash.remove(&hk);
-                } else {
-                    self.hash.insert(hk, value);
-                }
+                self.set_node(hk, value);
                 Ok(())
             }
-            KeyClass::Invalid => Err(if matches!(key, Value::Nil) {
+            KeyClass::Invalid => Err(if key.is_nil() {
                 TableKeyError::NilKey
             } else {
                 TableKeyError::NanKey
@@ -180,34 +258,243 @@ impl Table {
         }
     }
 
-    /// 整数キーでの代入。配列部の伸長とハッシュ部からの巻き取りを行う。
-    fn set_array_index(&mut self, i: usize, value: Value) {
-        let len = self.array.len();
-        if i <= len {
-            self.array[i - 1] = value;
-            return;
+    /// `key` へ非 `nil` の値を代入したときに新たに確保するバイト数の見積もり。
+    ///
+    /// 既存のキー・空いた主位置・空きノードに収まるなら 0、再ハッシュが要るなら作り直す
+    /// 配列部とノード配列の大きさ。メモリ上限を確保前に検査するために使う
+    /// （[`Heap::table_insert_growth`](crate::gc::Heap::table_insert_growth)）。
+    pub fn insert_growth(&self, key: &Value) -> usize {
+        let hk = match classify_key(key) {
+            KeyClass::ArrayIndex(i) if i <= self.array.len() => return 0,
+            KeyClass::ArrayIndex(i) => HKey::int(i),
+            KeyClass::Hash(hk) => hk,
+            KeyClass::Invalid => return 0,
+        };
+        if !self.node.is_empty()
+            && (self.find_node(&hk).is_some()
+                || self.node[self.main_position(&hk)].val.is_nil()
+                || self.node[..self.lastfree]
+                    .iter()
+                    .rev()
+                    .any(|n| n.key.is_none()))
+        {
+            return 0;
+        }
+        let (nasize, nhsize) = self.rehash_sizes(&hk);
+        let node_len = if nhsize == 0 {
+            0
+        } else {
+            1 << ceil_log2(nhsize)
+        };
+        nasize.saturating_sub(self.array.len()) * std::mem::size_of::<Value>()
+            + node_len * std::mem::size_of::<Node>()
+    }
+
+    // ---- インラインキャッシュ（定数文字列キー）-----------------------------
+
+    /// 文字列キー `key` のノード位置と値（死んだキーなら `nil`）。キャッシュの充填用。
+    pub fn find_slot(&self, key: GcHandle) -> Option<(usize, Value)> {
+        let n = self.find_node(&HKey::gc(key))?;
+        Some((n, self.node[n].val))
+    }
+
+    /// ノード `slot` がキー `key` のものならその値（死んだキーなら `nil`）。
+    #[inline]
+    pub fn get_slot(&self, slot: usize, key: GcHandle) -> Option<Value> {
+        match self.node.get(slot) {
+            Some(Node {
+                key: Some(k), val, ..
+            }) if *k == HKey::gc(key) => Some(*val),
+            _ => None,
+        }
+    }
+
+    /// ノード `slot` がキー `key` の生きたエントリなら、値を `value`（非 `nil`）に書き換えて
+    /// `true` を返す。それ以外（キャッシュ外れ・削除になる代入）は何もせず `false`。
+    #[inline]
+    pub fn set_slot(&mut self, slot: usize, key: GcHandle, value: Value) -> bool {
+        if value.is_nil() {
+            return false;
+        }
+        match self.node.get_mut(slot) {
+            Some(Node {
+                key: Some(k), val, ..
+            }) if *k == HKey::gc(key) && !val.is_nil() => {
+                *val = value;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // ---- ハッシュ部（本家 `mainposition`/`luaH_newkey`/`rehash`）-----------
+
+    /// キーの主位置（ノード配列が空でないこと）。
+    fn main_position(&self, hk: &HKey) -> usize {
+        hk.hash() as usize & (self.node.len() - 1)
+    }
+
+    /// キーが載っているノード（死んだキーを含む）。
+    fn find_node(&self, hk: &HKey) -> Option<usize> {
+        if self.node.is_empty() {
+            return None;
+        }
+        let mut n = self.main_position(hk);
+        loop {
+            if self.node[n].key.as_ref() == Some(hk) {
+                return Some(n);
+            }
+            n = self.node[n].next?;
+        }
+    }
+
+    fn get_node(&self, hk: &HKey) -> Value {
+        self.find_node(hk).map_or(Value::NIL, |n| self.node[n].val)
+    }
+
+    /// ハッシュ部のキーへ代入する。新しいキーならノードを割り当てる（`nil` なら何もしない）。
+    fn set_node(&mut self, hk: HKey, value: Value) {
+        if let Some(n) = self.find_node(&hk) {
+            self.node[n].val = value;
+        } else if !value.is_nil() {
+            self.new_key(hk, value);
+        }
+    }
+
+    /// 再ハッシュ後の入れ直し: 配列部に入る整数キーは配列部へ、それ以外はハッシュ部へ。
+    fn set_hkey(&mut self, hk: HKey, value: Value) {
+        match hk.array_index() {
+            Some(i) if i <= self.array.len() => self.array[i - 1] = value,
+            _ => self.set_node(hk, value),
+        }
+    }
+
+    /// 表に無いキーを挿入する（本家 `luaH_newkey`）。
+    ///
+    /// 主位置が空いていればそこへ置く。塞がっていれば空きノードを取り、主位置の住人が
+    /// 自分の主位置に居なければ住人を空きノードへ追い出し、居ればこちらが空きノードに入って
+    /// 住人のチェーンに繋がる。空きノードが無ければ再ハッシュしてから入れ直す。
+    fn new_key(&mut self, hk: HKey, value: Value) {
+        if self.node.is_empty() {
+            self.rehash(&hk);
+            return self.set_hkey(hk, value);
         }
-        if i == len + 1 {
-            if matches!(value, Value::Nil) {
-                // 末尾の次に nil を書く: ハッシュ部にあれば消す、無ければ何もしない。
-                self.hash.remove(&HKey::Number((i as f64).to_bits()));
-                return;
+        let mut mp = self.main_position(&hk);
+        if !self.node[mp].val.is_nil() {
+            let Some(free) = self.free_position() else {
+                self.rehash(&hk);
+                return self.set_hkey(hk, value);
+            };
+            let occupant = self.node[mp].key.expect("live node has a key");
+            let other = self.main_position(&occupant);
+            if other != mp {
+                // 住人は他のチェーンから来ている: 直前のノードを空きノードへ付け替えて移す。
+                let mut prev = other;
+                while self.node[prev].next != Some(mp) {
+                    prev = self.node[prev].next.expect("occupant is on its chain");
+                }
+                self.node[prev].next = Some(free);
+                self.node[free] = self.node[mp];
+                self.node[mp].next = None;
+                self.node[mp].val = Value::NIL;
+            } else {
+                // 住人は自分の主位置に居る: 新しいキーは空きノードに入れてチェーンに繋ぐ。
+                self.node[free].next = self.node[mp].next;
+                self.node[mp].next = Some(free);
+                mp = free;
             }
-            // 配列末尾へ追加し、後続の整数キーをハッシュ部から巻き取る。
-            self.array.push(value);
-            let mut next = self.array.len() + 1;
-            while let Some(v) = self.hash.remove(&HKey::Number((next as f64).to_bits())) {
-                self.array.push(v);
-                next += 1;
+        }
+        self.node[mp].key = Some(hk);
+        self.node[mp].val = value;
+    }
+
+    /// 空きノードを `lastfree` から下向きに探す（本家 `getfreepos`）。
+    fn free_position(&mut self) -> Option<usize> {
+        while self.lastfree > 0 {
+            self.lastfree -= 1;
+            if self.node[self.lastfree].key.is_none() {
+                return Some(self.lastfree);
             }
-            return;
         }
-        // 連番でない整数キーはハッシュ部へ。
-        let hk = HKey::Number((i as f64).to_bits());
-        if matches!(value, Value::Nil) {
-            self.hash.remove(&hk);
+        None
+    }
+
+    /// `size` 個以上のキーが入る 2 冪の長さでノード配列を作り直す（本家 `setnodevector`）。
+    fn set_node_vector(&mut self, size: usize) {
+        self.node = if size == 0 {
+            Vec::new()
         } else {
-            self.hash.insert(hk, value);
+            vec![Node::FREE; 1 << ceil_log2(size)]
+        };
+        self.lastfree = self.node.len();
+    }
+
+    /// 配列部の使用数を区間別に数える（本家 `numusearray`）。
+    fn num_use_array(&self, nums: &mut [usize; MAXBITS + 1]) -> usize {
+        let mut ause = 0;
+        let mut i = 1;
+        let mut ttlg = 1;
+        for slot in nums.iter_mut() {
+            let lim = ttlg.min(self.array.len());
+            if i > lim {
+                break;
+            }
+            let lc = self.array[i - 1..lim]
+                .iter()
+                .filter(|v| !v.is_nil())
+                .count();
+            i = lim + 1;
+            *slot += lc;
+            ause += lc;
+            ttlg *= 2;
+        }
+        ause
+    }
+
+    /// 新しいキー `extra` を入れるために配列部/ハッシュ部の大きさを決め直す（本家 `rehash`）。
+    fn rehash(&mut self, extra: &HKey) {
+        let (nasize, nhsize) = self.rehash_sizes(extra);
+        self.resize(nasize, nhsize);
+    }
+
+    /// 再ハッシュ後の配列部の長さとハッシュ部のキー数（本家 `rehash` の前半）。
+    fn rehash_sizes(&self, extra: &HKey) -> (usize, usize) {
+        let mut nums = [0usize; MAXBITS + 1];
+        let mut nasize = self.num_use_array(&mut nums);
+        let mut totaluse = nasize;
+        for n in &self.node {
+            if let (Some(k), false) = (&n.key, n.val.is_nil()) {
+                nasize += count_int(k, &mut nums);
+                totaluse += 1;
+            }
+        }
+        nasize += count_int(extra, &mut nums);
+        totaluse += 1;
+        let (nasize, na) = compute_sizes(&nums, nasize);
+        (nasize, totaluse - na)
+    }
+
+    /// 配列部を `nasize` に、ハッシュ部を `nhsize` 個分にして全エントリを入れ直す
+    /// （本家 `resize`）。死んだキーはここで消える。
+    fn resize(&mut self, nasize: usize, nhsize: usize) {
+        let old_node = std::mem::take(&mut self.node);
+        self.set_node_vector(nhsize);
+        if nasize > self.array.len() {
+            self.array.resize(nasize, Value::NIL);
+        } else if nasize < self.array.len() {
+            // 縮む部分はハッシュ部へ移す。
+            let tail = self.array.split_off(nasize);
+            self.array.shrink_to_fit();
+            for (i, v) in tail.into_iter().enumerate() {
+                if !v.is_nil() {
+                    self.set_node(HKey::int(nasize + i + 1), v);
+                }
+            }
+        }
+        for n in old_node.iter().rev() {
+            if let (Some(k), false) = (n.key, n.val.is_nil()) {
+                self.set_hkey(k, n.val);
+            }
         }
     }
 
@@ -217,12 +504,12 @@ impl Table {
     /// 配列部を二分探索、そうでなければハッシュ部を非有界探索する。
     pub fn length(&self) -> usize {
         let mut j = self.array.len();
-        if j > 0 && matches!(self.array[j - 1], Value::Nil) {
+        if j > 0 && self.array[j - 1].is_nil() {
             // 配列部に穴がある: array[i] != nil かつ array[j] == nil となる境界を二分探索。
             let mut i = 0;
             while j - i > 1 {
                 let m = (i + j) / 2;
-                if matches!(self.array[m - 1], Value::Nil) {
+                if self.array[m - 1].is_nil() {
                     j = m;
                 } else {
                     i = m;
@@ -231,7 +518,7 @@ impl Table {
             return i;
         }
         // 配列部は穴なし。ハッシュ部に続きがあるか非有界探索する。
-        if self.hash.is_empty() {
+        if self.node.is_empty() {
             return j;
         }
         self.unbound_search(j)
@@ -241,12 +528,12 @@ impl Table {
     fn unbound_search(&self, mut i: usize) -> usize {
         let mut j = i + 1;
         // t[j] != nil の間、区間を倍々で広げる。
-        while !matches!(self.get_int(j), Value::Nil) {
+        while !self.get_int(j).is_nil() {
             i = j;
             if j > usize::MAX / 2 {
                 // 異常に大きい: 線形探索にフォールバック。
                 let mut k = 1;
-                while !matches!(self.get_int(k), Value::Nil) {
+                while !self.get_int(k).is_nil() {
                     k += 1;
                 }
                 return k - 1;
@@ -256,7 +543,7 @@ impl Table {
         // [i, j) を二分探索。
         while j - i > 1 {
             let m = (i + j) / 2;
-            if matches!(self.get_int(m), Value::Nil) {
+            if self.get_int(m).is_nil() {
                 j = m;
             } else {
                 i = m;
@@ -274,8 +561,10 @@ impl Table {
     /// - `Ok(None)`: もう要素が無い（反復終了）。
     /// - `Err(())`: `key` がテーブルに存在しない（本家 "invalid key to 'next'"）。
     ///
-    /// 反復順は「配列部（昇順, `nil` を飛ばす）→ ハッシュ部（`HashMap` の反復順）」。
-    /// 反復中にテーブルを変更しなければ順序は安定する（本家と同じ契約）。
+    /// 反復順は「配列部（昇順, `nil` を飛ばす）→ ハッシュ部（ノード配列の順）」。
+    /// 配列部とノード配列を 1 本の添字で数え、`key` の位置の次から値が非 `nil` のものを探すだけなので
+    /// 確保は伴わない。反復中に新しいキーを加えなければ順序は安定する
+    /// （既存フィールドへの `nil` 代入は可, 本家と同じ契約）。
     ///
     /// NOTE(lua-stdlib): `pairs`/`next`/`table.maxn` 実装のため lua-stdlib が追加した
     /// 補助メソッド。ハッシュ部反復の公開口が他に無いため。owner（lua-vm）レビュー希望。
@@ -283,47 +572,35 @@ impl Table {
     /// `Err(())` は「キー不在」のみを表す単純なシグナルのため、専用エラー型は設けない。
     #[allow(clippy::result_unit_err)]
     pub fn next(&self, key: &Value) -> Result<Option<(Value, Value)>, ()> {
-        // 配列部の `idx`（0 始まり）以降で最初の非 nil を返すヘルパ。
-        let first_array_from = |start: usize| -> Option<(Value, Value)> {
-            for idx in start..self.array.len() {
-                if !matches!(self.array[idx], Value::Nil) {
-                    return Some((Value::Number((idx + 1) as f64), self.array[idx]));
-                }
+        let start = self.find_index(key)?;
+        let asize = self.array.len();
+        for i in start..asize {
+            if !self.array[i].is_nil() {
+                return Ok(Some((Value::number((i + 1) as f64), self.array[i])));
             }
-            None
-        };
-        let first_hash = || -> Option<(Value, Value)> {
-            self.hash.iter().next().map(|(k, v)| (hkey_to_value(k), *v))
-        };
-
-        match key {
-            Value::Nil => Ok(first_array_from(0).or_else(first_hash)),
-            _ => match classify_key(key) {
-                KeyClass::ArrayIndex(i) if i <= self.array.len() => {
-                    Ok(first_array_from(i).or_else(first_hash))
-                }
-                KeyClass::ArrayIndex(i) => {
-                    // 配列範囲外の整数キー: ハッシュ部にある想定。
-                    self.hash_next(HKey::Number((i as f64).to_bits()))
-                }
-                KeyClass::Hash(hk) => self.hash_next(hk),
-                KeyClass::Invalid => Err(()),
-            },
         }
+        let from = start.saturating_sub(asize);
+        Ok(self.node[from.min(self.node.len())..]
+            .iter()
+            .find(|n| !n.val.is_nil())
+            .map(|n| (n.key.expect("live node has a key").value(), n.val)))
     }
 
-    /// ハッシュ部で `hk` の次のエントリを返す（`hk` 不在なら `Err`）。
-    fn hash_next(&self, hk: HKey) -> Result<Option<(Value, Value)>, ()> {
-        let mut found = false;
-        for (k, v) in self.hash.iter() {
-            if found {
-                return Ok(Some((hkey_to_value(k), *v)));
-            }
-            if *k == hk {
-                found = true;
-            }
+    /// `next` の走査を再開する位置（本家 `findindex` の次の添字）。配列部が `0..asize`、
+    /// ノードが `asize..` に続く。`key` が表に無ければ `Err`。
+    fn find_index(&self, key: &Value) -> Result<usize, ()> {
+        if key.is_nil() {
+            return Ok(0);
         }
-        if found { Ok(None) } else { Err(()) }
+        let hk = match classify_key(key) {
+            KeyClass::ArrayIndex(i) if i <= self.array.len() => return Ok(i),
+            KeyClass::ArrayIndex(i) => HKey::int(i),
+            KeyClass::Hash(hk) => hk,
+            KeyClass::Invalid => return Err(()),
+        };
+        // 死んだキーも見つかるので、走査中に値を消されても続けられる。
+        let n = self.find_node(&hk).ok_or(())?;
+        Ok(self.array.len() + n + 1)
     }
 
     /// 配列部への参照。
@@ -335,6 +612,24 @@ impl Table {
     pub fn array_mut(&mut self) -> &mut Vec<Value> {
         &mut self.array
     }
+
+    /// 全エントリ（配列部の非 `nil` を含む）を列挙する。順序は `next` と同じ。
+    ///
+    /// weak table の走査・掃除（[`crate::gc`]）用。
+    pub fn entries(&self) -> impl Iterator<Item = (Value, Value)> + '_ {
+        let array = self
+            .array
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| !v.is_nil())
+            .map(|(i, v)| (Value::number((i + 1) as f64), *v));
+        let node = self
+            .node
+            .iter()
+            .filter(|n| !n.val.is_nil())
+            .filter_map(|n| Some((n.key?.value(), n.val)));
+        array.chain(node)
+    }
 }
 
 /// テーブルキーの無効値エラー（VM がランタイムエラーへ昇格する）。
@@ -346,16 +641,29 @@ pub enum TableKeyError {
     NanKey,
 }
 
+impl HeapSize for Table {
+    /// 配列部・ハッシュ部は確保済み容量で数える（本家 `sizenode`/`sizearray` 相当）。
+    fn heap_size(&self) -> usize {
+        std::mem::size_of::<Table>()
+            + self.array.capacity() * std::mem::size_of::<Value>()
+            + self.node.capacity() * std::mem::size_of::<Node>()
+    }
+}
+
 impl Trace for Table {
     fn trace(&self, tracer: &mut Tracer) {
         for v in &self.array {
             tracer.mark_value(v);
         }
-        for (k, v) in &self.hash {
-            if let HKey::Gc(h) = k {
-                tracer.mark(*h);
+        // 死んだキーは辿らない（本家と同じ。回収後のハンドルは世代付きなので誤一致しない）。
+        for n in &self.node {
+            if n.val.is_nil() {
+                continue;
             }
-            tracer.mark_value(v);
+            if let Some(k) = n.key {
+                tracer.mark_value(&k.value());
+            }
+            tracer.mark_value(&n.val);
         }
         if let Some(mt) = self.metatable {
             tracer.mark(mt);
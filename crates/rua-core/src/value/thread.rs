@@ -1,10 +1,11 @@
 //! Lua コルーチン（スレッド）型（本家 `lua_State` のコルーチン表現に相当）。
 //!
 //! コルーチンは `coroutine.create` で生成され GC ヒープ（[`crate::gc::Heap`]）に格納される。
-//! 実行状態（スタック・コールフレーム）をスライスとして保存し、`coroutine.resume` で
-//! メインスレッドの `LuaState` へ積み戻して再開する（スタック・スライシング方式）。
+//! 各コルーチンは自分の実行スタック（[`ThreadStack`]）を持ち、`coroutine.resume` で
+//! [`LuaState::switch_thread`](crate::state::LuaState::switch_thread) により実行中のスレッドと
+//! 入れ替えて実行する。
 
-use crate::state::CallInfo;
+use crate::state::ThreadStack;
 use crate::value::Value;
 
 /// コルーチンの実行状態。
@@ -28,10 +29,9 @@ pub struct LuaThread {
     /// 初回 resume で呼ぶ関数（`coroutine.create(f)` の `f`）。
     /// 初回 resume 後は `None` に。
     pub body: Option<Value>,
-    /// 中断時のスタック保存領域（`state.stack` のコルーチン部分）。
-    pub saved_stack: Vec<Value>,
-    /// 中断時のコールフレーム保存領域（`state.call_info` のコルーチン部分）。
-    pub saved_call_info: Vec<CallInfo>,
+    /// 実行スタック。中断中はこのコルーチン自身のもの。実行中（と、別のコルーチンを resume して
+    /// いる間）は、入れ替えたこのコルーチンを resume した側のスレッドのもの。
+    pub stack: ThreadStack,
 }
 
 impl LuaThread {
@@ -40,8 +40,7 @@ impl LuaThread {
         LuaThread {
             status: ThreadStatus::Suspended,
             body: Some(body),
-            saved_stack: Vec::new(),
-            saved_call_info: Vec::new(),
+            stack: ThreadStack::default(),
         }
     }
 }
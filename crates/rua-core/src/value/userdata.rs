@@ -7,11 +7,13 @@
 //! `lua_newuserdata` が返す生ポインタの安定性は、本体を個別 box 化し、スタック生存値で
 //! ルート保持することで満たす。第一マイルストーンでは C へポインタを渡さないため Rust 値で保持する。
 //!
-//! TODO(lua-vm/lua-capi): C 互換の生バイト userdata 表現、`__gc` finalizer の起動。
+//! メタテーブルに `__gc` を持つ userdata は、回収時に finalizer が呼ばれる（[`crate::gc`] 参照）。
+//!
+//! TODO(lua-vm/lua-capi): C 互換の生バイト userdata 表現。
 
 use std::any::Any;
 
-use crate::gc::{GcHandle, Trace, Tracer};
+use crate::gc::{GcHandle, HeapSize, Trace, Tracer};
 
 /// full userdata。
 pub struct Userdata {
@@ -21,6 +23,11 @@ pub struct Userdata {
     metatable: Option<GcHandle>,
     /// 環境テーブル（本家 userdata の env）。
     env: Option<GcHandle>,
+    /// finalizer 登録順の通し番号。finalizer を登録の逆順に呼ぶために [`crate::gc::Heap`] が
+    /// 確保時と `__gc` 付きメタテーブルの設定時（[`crate::gc::Heap::set_userdata_metatable`]）に振る。
+    serial: u64,
+    /// finalizer 呼び出し対象として分離済みか（本家 `FINALIZEDBIT`）。`__gc` は高々 1 回しか呼ばない。
+    finalized: bool,
 }
 
 impl Userdata {
@@ -29,6 +36,8 @@ impl Userdata {
             data,
             metatable: None,
             env: None,
+            serial: 0,
+            finalized: false,
         }
     }
 
@@ -55,6 +64,23 @@ impl Userdata {
     pub fn set_env(&mut self, env: Option<GcHandle>) {
         self.env = env;
     }
+
+    pub(crate) fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    pub(crate) fn set_serial(&mut self, serial: u64) {
+        self.serial = serial;
+    }
+
+    /// finalizer 呼び出し対象として分離済みか。
+    pub fn is_finalized(&self) -> bool {
+        self.finalized
+    }
+
+    pub(crate) fn set_finalized(&mut self) {
+        self.finalized = true;
+    }
 }
 
 impl std::fmt::Debug for Userdata {
@@ -66,6 +92,12 @@ impl std::fmt::Debug for Userdata {
     }
 }
 
+impl HeapSize for Userdata {
+    fn heap_size(&self) -> usize {
+        std::mem::size_of::<Userdata>() + std::mem::size_of_val(self.data.as_ref())
+    }
+}
+
 impl Trace for Userdata {
     fn trace(&self, tracer: &mut Tracer) {
         if let Some(mt) = self.metatable {
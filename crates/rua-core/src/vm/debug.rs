@@ -0,0 +1,217 @@
+//! デバッグ情報の解析（本家 `ldebug.c` の `getobjname`/`getfuncname` 相当）。担当: **lua-vm**。
+//!
+//! バイトコードを遡って、レジスタの値がどこから来たか（ローカル変数・グローバル・フィールド・
+//! upvalue・メソッド）を推定する。`debug.getinfo` の `name`/`namewhat` に使う。
+//!
+//! 本家 5.1 の `symbexec` は命令列を記号実行するが、ここでは 5.2 の `findsetreg` と同じく
+//! 先頭から線形に走査して「`lastpc` より前で最後にそのレジスタへ書いた命令」を求める。
+//! 前方ジャンプの飛び先より手前の書き込みは条件付きなので採用しない。
+
+use crate::gc::Heap;
+use crate::state::LuaState;
+use crate::value::closure::Closure;
+
+use super::opcode::{OpCode, index_k, is_k};
+use super::proto::Proto;
+
+/// `lastpc` 番目の命令より前で、最後に `reg` へ書き込んだ命令の pc（本家 5.2 `findsetreg`）。
+pub fn find_set_reg(proto: &Proto, lastpc: usize, reg: usize) -> Option<usize> {
+    let mut setreg = None;
+    // 前方ジャンプの飛び先のうち最も遠いもの。これより手前の書き込みは条件付き。
+    let mut jmptarget = 0;
+    for (pc, instr) in proto.code.iter().enumerate().take(lastpc) {
+        let Some(op) = instr.opcode() else {
+            continue;
+        };
+        let a = instr.a() as usize;
+        let change = match op {
+            OpCode::LoadNil => a <= reg && reg <= instr.b() as usize,
+            OpCode::TForLoop => reg >= a + 2,
+            OpCode::Call | OpCode::TailCall => reg >= a,
+            OpCode::Jmp => {
+                let dest = (pc as i64 + 1 + instr.sbx() as i64) as usize;
+                if pc < dest && dest <= lastpc && dest > jmptarget {
+                    jmptarget = dest;
+                }
+                false
+            }
+            _ => op.sets_a() && reg == a,
+        };
+        if change {
+            setreg = if pc < jmptarget { None } else { Some(pc) };
+        }
+    }
+    setreg
+}
+
+/// スタックレベルが指すもの（本家 `lua_getstack` の結果）。
+pub enum StackLevel {
+    /// 実在するフレーム（`call_info` の添字）。
+    Frame(usize),
+    /// 末尾呼び出しで消えたフレーム（本家の `(tail call)`）。
+    TailCall,
+}
+
+/// スタックレベル `level` を解決する（本家 `lua_getstack`）。level 0 は実行中のネイティブ関数
+/// （`debug.*` 自身）。末尾呼び出しを行ったフレームの下には、その回数だけ消えたフレームが並ぶ。
+pub fn stack_level(state: &LuaState, level: usize) -> Option<StackLevel> {
+    let mut level = level as i64;
+    for idx in (0..state.call_info.len()).rev() {
+        if level == 0 {
+            return Some(StackLevel::Frame(idx));
+        }
+        level -= 1 + state.call_info[idx].tail_calls as i64;
+        if level < 0 {
+            return Some(StackLevel::TailCall);
+        }
+    }
+    None
+}
+
+/// スタックトレースバック文字列（本家 5.1 `db_errorfb`）。`level` 番目のフレームから列挙する。
+///
+/// 各行は `\tshort_src:line: in function 'name'` の形で、名前が分からなければ
+/// `in main chunk` / `in function <src:line>` / `?` になる。深いスタックは先頭 12 段と末尾 10 段だけを
+/// 残し、間を `...` で省略する。
+pub fn traceback(state: &LuaState, level: usize) -> String {
+    const LEVELS1: usize = 12; // 先頭に表示する段数
+    const LEVELS2: usize = 10; // 末尾に表示する段数
+    // 段数（末尾呼び出しで消えた分を含む）を一度だけ数え、省略時は末尾 LEVELS2 段へ直接飛ぶ。
+    let depth: usize = state
+        .call_info
+        .iter()
+        .map(|ci| 1 + ci.tail_calls as usize)
+        .sum();
+    let mut out = String::from("stack traceback:");
+    let mut level = level;
+    let mut first_part = true;
+    while let Some(frame) = stack_level(state, level) {
+        level += 1;
+        if level > LEVELS1 && first_part {
+            first_part = false;
+            if level + LEVELS2 < depth {
+                out.push_str("\n\t...");
+                level = depth - LEVELS2;
+                continue;
+            }
+        }
+        out.push_str("\n\t");
+        out.push_str(&frame_description(state, frame));
+    }
+    out
+}
+
+/// トレースバック 1 行分のフレーム説明。
+fn frame_description(state: &LuaState, frame: StackLevel) -> String {
+    let StackLevel::Frame(idx) = frame else {
+        return "(tail call): ?".to_string();
+    };
+    let ci = &state.call_info[idx];
+    let lua_proto = ci
+        .lua_closure
+        .and_then(|ck| match state.global.heap.get_closure(ck) {
+            Some(Closure::Lua(lc)) => Some(lc.proto()),
+            _ => None,
+        });
+    let mut s = match lua_proto {
+        Some(proto) => super::interp::short_src(proto.source.as_deref()),
+        None => "[C]".to_string(),
+    };
+    s.push(':');
+    if lua_proto.is_some() && ci.current_line > 0 {
+        s.push_str(&format!("{}:", ci.current_line));
+    }
+    match (func_name(state, idx), lua_proto) {
+        (Some((_, name)), _) => s.push_str(&format!(" in function '{name}'")),
+        (None, Some(proto)) if proto.line_defined == 0 => s.push_str(" in main chunk"),
+        (None, Some(proto)) => s.push_str(&format!(
+            " in function <{}:{}>",
+            super::interp::short_src(proto.source.as_deref()),
+            proto.line_defined
+        )),
+        (None, None) => s.push_str(" ?"),
+    }
+    s
+}
+
+/// `pc` 番目の命令の時点でレジスタ `reg` が持つ値の名前と種別（本家 `getobjname`）。
+///
+/// 種別は `"local"`/`"global"`/`"field"`/`"upvalue"`/`"method"`。推定できなければ `None`。
+pub fn obj_name(
+    heap: &Heap,
+    proto: &Proto,
+    pc: usize,
+    reg: usize,
+) -> Option<(&'static str, String)> {
+    if let Some(name) = proto.local_name(reg + 1, pc) {
+        return Some(("local", name.to_string()));
+    }
+    let set_pc = find_set_reg(proto, pc, reg)?;
+    let instr = proto.code[set_pc];
+    match instr.opcode()? {
+        OpCode::GetGlobal => {
+            let name = const_name(heap, proto, instr.bx())?;
+            Some(("global", name))
+        }
+        OpCode::Move => {
+            let (a, b) = (instr.a() as usize, instr.b() as usize);
+            if b < a {
+                obj_name(heap, proto, set_pc, b)
+            } else {
+                None
+            }
+        }
+        OpCode::GetTable => Some(("field", rk_name(heap, proto, instr.c()))),
+        OpCode::GetUpval => {
+            let name = proto
+                .upvalue_names
+                .get(instr.b() as usize)
+                .cloned()
+                .unwrap_or_else(|| "?".to_string());
+            Some(("upvalue", name))
+        }
+        OpCode::SelfOp => Some(("method", rk_name(heap, proto, instr.c()))),
+        _ => None,
+    }
+}
+
+/// フレーム `ci_idx` で実行中の関数の名前と種別（本家 `getfuncname`）。
+///
+/// 呼び出し元が Lua 関数で、その現在の命令が `CALL`/`TAILCALL`/`TFORLOOP` のときだけ分かる。
+/// 末尾呼び出しされた関数やメタメソッド・ネイティブ関数から呼ばれた関数は `None`。
+pub fn func_name(state: &LuaState, ci_idx: usize) -> Option<(&'static str, String)> {
+    if state.call_info.get(ci_idx)?.tail_calls > 0 {
+        return None;
+    }
+    let caller = state.call_info.get(ci_idx.checked_sub(1)?)?;
+    let Some(Closure::Lua(lc)) = state.global.heap.get_closure(caller.lua_closure?) else {
+        return None;
+    };
+    let proto = lc.proto();
+    let instr = *proto.code.get(caller.current_pc)?;
+    match instr.opcode()? {
+        OpCode::Call | OpCode::TailCall | OpCode::TForLoop => obj_name(
+            &state.global.heap,
+            proto,
+            caller.current_pc,
+            instr.a() as usize,
+        ),
+        _ => None,
+    }
+}
+
+/// 文字列定数 `K(idx)` の内容（文字列でなければ `None`）。
+fn const_name(heap: &Heap, proto: &Proto, idx: u32) -> Option<String> {
+    let k = proto.constants.get(idx as usize)?.as_string()?;
+    let s = heap.get_str(k)?;
+    Some(String::from_utf8_lossy(s.as_bytes()).into_owned())
+}
+
+/// RK オペランドが文字列定数ならその内容、そうでなければ `"?"`（本家 `kname`）。
+fn rk_name(heap: &Heap, proto: &Proto, rk: u32) -> String {
+    if is_k(rk) {
+        const_name(heap, proto, index_k(rk)).unwrap_or_else(|| "?".to_string())
+    } else {
+        "?".to_string()
+    }
+}
@@ -4,19 +4,30 @@
 //! 算術・比較・連結のメタメソッド解決、`__index`/`__newindex`、`CALL`/`RETURN` の
 //! フレーム操作、数値/汎用 for、upvalue の open/close を実装する。
 //!
-//! # 呼び出しモデル
-//! `CALL` は Lua 関数に対して Rust 再帰（[`call`] → [`execute`]）でフレームを積む。
-//! 各 [`execute`] 呼び出しが 1 フレームを管理し、`base` を起点にレジスタ `R(i)` を
-//! `state.stack[base + i]` に対応づける。再帰深度は [`MAX_CALL_DEPTH`] で保護する
-//! （Lua の "stack overflow" 相当）。エラーは [`LuaResult`] の `Err` で伝播し、
+//! # 呼び出しモデル（本家 5.1 `luaV_execute` の `reentry`）
+//! Lua 関数から Lua 関数への `CALL` は Rust 再帰せず、同じディスパッチループの中で
+//! [`CallInfo`] を積んでレジスタ窓（`base`/`proto`/`pc` …）を切り替える。呼び出し元の実行状態は
+//! 自フレームの [`CallInfo::lua_frame`] へ退避し、`RETURN` で降ろして復元する。`base` を起点に
+//! レジスタ `R(i)` を `state.stack[base + i]` に対応づける。
+//!
+//! ネイティブ関数・メタメソッド・`pcall` などからの再入だけが [`call`] を通って Rust 再帰し、
+//! その深さを [`MAX_C_CALLS`] で制限する（"C stack overflow"）。Lua 同士の再帰の深さは
+//! スタックの大きさ（[`GlobalState::stack_limit`](crate::state::GlobalState::stack_limit)）で
+//! 制限する（"stack overflow"）。エラーは [`LuaResult`] の `Err` で伝播し、
 //! 最寄りの [`crate::state::call::pcall`] 境界がスタックを巻き戻す。
 
+use std::any::Any;
 use std::cell::RefCell;
 use std::rc::Rc;
-
-use crate::error::{LuaError, LuaResult};
-use crate::gc::GcHandle;
-use crate::state::{CallInfo, LuaFrameState, LuaState};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use crate::error::{InterruptReason, LuaError, LuaResult};
+use crate::gc::{GcHandle, ThreadKey};
+use crate::state::{
+    CallInfo, Continuation, ContinuationFn, DEADLINE_CHECK_INTERVAL, HOOK_MASK_CALL,
+    HOOK_MASK_COUNT, HOOK_MASK_LINE, HOOK_MASK_RET, HookEvent, LuaFrameState, LuaState,
+};
 use crate::value::closure::{Closure, LuaClosure, Upvalue, UpvalueState};
 use crate::value::convert::{number_to_string, str_to_number};
 use crate::value::table::Table;
@@ -25,22 +36,22 @@ use crate::value::{LuaType, Value};
 use super::opcode::{self, LFIELDS_PER_FLUSH, OpCode};
 use super::proto::Proto;
 
-/// Lua 関数/ネイティブ呼び出しの最大ネスト深度。本家 Lua 5.1 の `LUAI_MAXCALLS`
+/// [`call`] の最大ネスト深度（Rust 再帰の深さ）。本家 Lua 5.1 の `LUAI_MAXCCALLS`
 /// （`luaconf.h` の既定値 200）に合わせる。
 ///
-/// rua の VM はネストした呼び出しを **Rust の関数再帰**として実行するため、無限/過大な
-/// Lua 再帰を放置すると OS/Rust スタックを溢れさせ、**プロセスがクラッシュ**
-/// （abort/segfault = 互換性方針上の最悪結果）してしまう。この上限に達した時点で
-/// `call()` 冒頭が捕捉可能な `"stack overflow"` エラーへ変換し、クラッシュを防ぐ。
+/// Lua 同士の呼び出しはディスパッチループ内でフレームを積むため数えない。ネイティブ関数・
+/// メタメソッド・`pcall` 等を経由した再入は **Rust の関数再帰**になり、放置すると OS/Rust
+/// スタックを溢れさせて**プロセスがクラッシュ**（abort/segfault = 互換性方針上の最悪結果）する。
+/// この上限に達した時点で `call()` 冒頭が捕捉可能な `"C stack overflow"` エラーへ変換する。
 ///
 /// 値のトレードオフ:
-/// - 小さすぎる → 正当だが深い再帰で誤って `stack overflow`（偽陽性）。
+/// - 小さすぎる → 正当だが深いメタメソッド/`pcall` の入れ子で誤ってエラー（偽陽性）。
 /// - 大きすぎる → 上限到達前に実 Rust スタックが先に溢れ、捕捉不能なクラッシュになる。
 ///
 /// PUC-Rio が検証済みの既定値 **200** を採用する（本家との挙動一致のため。実測でも
-/// 深さ 200 の非末尾再帰は `pcall` で `stack overflow` を捕捉でき、クラッシュしないことを
-/// 確認済み, 2026-06-14）。値を変更する場合は本コメントと根拠を必ず更新すること。
-pub const MAX_CALL_DEPTH: usize = 200;
+/// 深さ 200 の再入は `pcall` で捕捉でき、クラッシュしないことを確認済み, 2026-06-14）。
+/// 値を変更する場合は本コメントと根拠を必ず更新すること。
+pub const MAX_C_CALLS: usize = 200;
 
 /// `__index`/`__newindex` チェーンを辿る最大回数（本家 `MAXTAGLOOP`）。
 const MAXTAGLOOP: usize = 100;
@@ -57,89 +68,181 @@ pub fn run(state: &mut LuaState, proto: Rc<Proto>, args: &[Value]) -> LuaResult<
     let env = state.global.globals;
     let closure = LuaClosure::new_with_env(proto, env);
     let h = state.global.heap.alloc_closure(Closure::Lua(closure));
-    call(state, Value::GcRef(h), args)
+    call(state, Value::gc(h), args)
 }
 
-/// 中断済みコルーチンフレームを `resume_args` で再開する。
+/// 中断済みコルーチンを `resume_args` で再開する。
 ///
-/// `state.stack[stack_base..]` および `state.call_info[ci_base..]` には
-/// コルーチンの保存済みフレームが積まれていること。各フレームの `CallInfo.lua_frame`
-/// が保存された実行状態を持つ（tail call パススルーの場合は `None`）。
-pub fn resume_execute(
-    state: &mut LuaState,
-    _stack_base: usize,
-    ci_base: usize,
-    resume_args: Vec<Value>,
-) -> LuaResult<Vec<Value>> {
-    let mut current_vals = resume_args;
+/// `state` の実行スタックはコルーチンのものに切り替えてあること
+/// （[`LuaState::switch_thread`](crate::state::LuaState::switch_thread)）。
+/// 上のフレームから順に再開し、戻り値を 1 つ下のフレームへ渡していく。
+///
+/// - Lua フレーム: `CallInfo.lua_frame` の実行状態から、中断した命令の残りを終えて続きを実行する
+///   （ネイティブ関数へ TAILCALL して yield したフレームは `None` で、値をそのまま下へ渡す）。
+/// - ネイティブ関数フレーム: [`call_k`]/[`pcall_k`] で置いた継続を呼ぶ。
+///
+/// 再開後に起きたエラーは、[`pcall_k`] の継続を持つフレームがあればそこで捕捉する。
+pub fn resume_execute(state: &mut LuaState, resume_args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let mut current: LuaResult<Vec<Value>> = Ok(resume_args);
 
     loop {
-        if state.call_info.len() <= ci_base {
-            return Ok(current_vals);
+        let vals = match current {
+            Ok(vals) => vals,
+            Err(e) => recover(state, e)?,
+        };
+        if state.call_info.is_empty() {
+            return Ok(vals);
         }
         let ci_idx = state.call_info.len() - 1;
+        let (base, func) = (state.call_info[ci_idx].base, state.call_info[ci_idx].func);
 
-        // lua_frame が None のフレームはネイティブへの TAILCALL が yield したもの。
-        // このフレームには再開点がないのでポップして外側フレームへ値を渡す。
-        if state.call_info[ci_idx].lua_frame.is_none() {
-            let base = state.call_info[ci_idx].base;
-            state.call_info.pop();
-            state.stack.truncate(base);
+        if let Some(k) = state.call_info[ci_idx].continuation.take() {
+            current = (k.func)(state, Ok(vals), k.ctx)
+                .and_then(|n| native_return(state, n))
+                .map(|n| take_results(state, func, n));
             continue;
         }
 
-        let frame = state.call_info[ci_idx].lua_frame.take().unwrap();
+        // lua_frame が None のフレームは、yield したネイティブ関数自身か、ネイティブへの
+        // TAILCALL が yield したもの。再開点がないのでポップして外側フレームへ値を渡す。
+        let Some(mut frame) = state.call_info[ci_idx].lua_frame.take() else {
+            state.call_info.pop();
+            state.stack.truncate(func);
+            current = Ok(vals);
+            continue;
+        };
+
+        let (top, pc) = match finish_op(state, base, &mut frame, &vals) {
+            Ok(v) => v,
+            Err(e) => {
+                current = Err(e);
+                continue;
+            }
+        };
         let LuaFrameState {
-            resume_call_pc,
             proto,
             upvals,
-            varargs,
             open,
-            top,
             env,
-        } = *frame;
-        let base = state.call_info[ci_idx].base;
-
-        // yield を発生させた CALL 命令を再読みして結果レジスタへ current_vals を配置。
-        let call_instr = proto.code[resume_call_pc];
-        let call_a = call_instr.a() as usize;
-        let want = if call_instr.c() == 0 {
-            current_vals.len()
-        } else {
-            call_instr.c() as usize - 1
-        };
-        let mut restored_top = top;
-        for i in 0..want {
-            set_reg(
-                state,
-                base + call_a + i,
-                current_vals.get(i).copied().unwrap_or(Value::Nil),
-            );
+            ..
+        } = frame;
+        // このフレームの RETURN がフレームを降ろし、戻り値を `func` 以降に置く。
+        current = execute_inner(state, base, proto, upvals, open, top, pc, env)
+            .map(|n| take_results(state, func, n));
+    }
+}
+
+/// 再開後に起きたエラーを、[`pcall_k`] の継続を持つ最も内側のフレームまで巻き戻して
+/// その継続へ渡す（本家 5.2 `recover`）。そのようなフレームが無ければエラーを返す。
+fn recover(state: &mut LuaState, mut e: LuaError) -> LuaResult<Vec<Value>> {
+    loop {
+        match &e {
+            LuaError::Yield(_) => return Err(e),
+            LuaError::Interrupted(r) if !r.catchable() => return Err(e),
+            _ => {}
         }
-        if call_instr.c() == 0 {
-            restored_top = base + call_a + current_vals.len();
+        let Some(idx) = (0..state.call_info.len()).rev().find(|&i| {
+            state.call_info[i]
+                .continuation
+                .as_ref()
+                .is_some_and(|k| k.protected_depth.is_some())
+        }) else {
+            return Err(e);
+        };
+        close_frame_upvals(state, idx + 1);
+        state.call_info.truncate(idx + 1);
+        let Some(k) = state.call_info[idx].continuation.take() else {
+            return Err(e);
+        };
+        state
+            .stack
+            .truncate(k.protected_depth.unwrap_or(state.stack.len()));
+        let func = state.call_info[idx].func;
+        match (k.func)(state, Err(e), k.ctx)
+            .and_then(|n| native_return(state, n))
+            .map(|n| take_results(state, func, n))
+        {
+            Ok(vals) => return Ok(vals),
+            Err(next) => e = next,
         }
+    }
+}
 
-        match execute_inner(
-            state,
-            base,
-            proto,
-            upvals,
-            varargs,
-            open,
-            restored_top,
-            resume_call_pc + 1,
-            env,
-        ) {
-            Ok(vals) => {
-                // このフレームが正常終了。CI とスタックをポップして外側フレームへ。
-                state.call_info.pop();
-                state.stack.truncate(base);
-                current_vals = vals;
+/// 再開したフレームで、yield で中断していた命令の残りを行う（本家 5.2 `luaV_finishOp`）。
+///
+/// `vals` は中断の原因になった呼び出し（関数・メタメソッド・イテレータ）の戻り値。
+/// 再開時の `(top, pc)` を返す。`CONCAT` の残りが再び yield したら実行状態を保存し直す。
+fn finish_op(
+    state: &mut LuaState,
+    base: usize,
+    frame: &mut LuaFrameState,
+    vals: &[Value],
+) -> LuaResult<(usize, usize)> {
+    let pc = frame.resume_call_pc;
+    let instr = frame.proto.code[pc];
+    let a = instr.a() as usize;
+    let top = base + frame.proto.max_stack_size as usize;
+    let res = vals.first().copied().unwrap_or(Value::NIL);
+    match instr.opcode() {
+        Some(OpCode::Call) => Ok((place_results(state, base, &frame.proto, pc, vals), pc + 1)),
+        Some(
+            OpCode::GetGlobal
+            | OpCode::GetTable
+            | OpCode::SelfOp
+            | OpCode::Add
+            | OpCode::Sub
+            | OpCode::Mul
+            | OpCode::Div
+            | OpCode::Mod
+            | OpCode::Pow
+            | OpCode::Unm
+            | OpCode::Len,
+        ) => {
+            set_reg(state, base + a, res);
+            Ok((top, pc + 1))
+        }
+        Some(OpCode::Eq | OpCode::Lt | OpCode::Le) => {
+            let cond = res.is_truthy() != frame.le_by_lt;
+            // 条件が A と一致しなければ直後の JMP を飛ばす。
+            let next = if cond != (a != 0) { pc + 2 } else { pc + 1 };
+            Ok((top, next))
+        }
+        Some(OpCode::Concat) => {
+            // 保存した top は結果を置くレジスタ R(i)。R(B)..R(i) の連結を続ける。
+            let bb = instr.b() as usize;
+            let mut i = frame.top - base;
+            set_reg(state, base + i, res);
+            match concat_from(state, base, (bb, &mut i), res, &frame.proto, pc) {
+                Ok(acc) => set_reg(state, base + a, acc),
+                Err(LuaError::Yield(vals)) => {
+                    let mut saved = frame.clone();
+                    saved.top = base + i;
+                    if let Some(ci) = state.call_info.last_mut() {
+                        ci.lua_frame = Some(saved);
+                    }
+                    return Err(LuaError::Yield(vals));
+                }
+                Err(e) => return Err(e),
             }
-            Err(LuaError::Yield(vals)) => return Err(LuaError::Yield(vals)),
-            Err(e) => return Err(e),
+            Ok((top, pc + 1))
         }
+        Some(OpCode::TForLoop) => {
+            for i in 0..instr.c() as usize {
+                set_reg(
+                    state,
+                    base + a + 3 + i,
+                    vals.get(i).copied().unwrap_or(Value::NIL),
+                );
+            }
+            if res.is_nil() {
+                Ok((top, pc + 2))
+            } else {
+                set_reg(state, base + a + 2, res);
+                Ok((top, pc + 1))
+            }
+        }
+        // SETTABLE/SETGLOBAL: メタメソッドの結果は使わない。
+        _ => Ok((top, pc + 1)),
     }
 }
 
@@ -147,34 +250,177 @@ pub fn resume_execute(
 ///
 /// Lua クロージャ・ネイティブ関数・`__call` メタメソッドを持つ値に対応する。
 /// 返り値は呼び出しの全戻り値列。
+///
+/// Rust 再帰の入口なので、ネスト深度を [`MAX_C_CALLS`] で制限する（本家 `luaD_call` の `nCcalls`）。
+///
+/// 呼び出した関数の yield はここを越えられない（呼び出し元の Rust コードに再開点が無いため）。
+/// 積まれたフレームを降ろして `"attempt to yield across a C-call boundary"` エラーにする。
+/// yield を許すネイティブ関数は [`call_k`]/[`pcall_k`] を使う。
 pub fn call(state: &mut LuaState, func: Value, args: &[Value]) -> LuaResult<Vec<Value>> {
-    if state.call_info.len() >= MAX_CALL_DEPTH {
-        return Err(rt_err(state, "stack overflow".to_string()));
-    }
-    match func {
-        Value::GcRef(GcHandle::Closure(k)) => {
-            let is_lua = matches!(state.global.heap.get_closure(k), Some(Closure::Lua(_)));
-            if is_lua {
-                call_lua(state, k, args)
-            } else {
-                call_native(state, k, args)
-            }
+    let (depth, ci_len) = (state.stack.len(), state.call_info.len());
+    match call_yieldable(state, func, args) {
+        Err(LuaError::Yield(_)) => {
+            close_frame_upvals(state, ci_len);
+            state.call_info.truncate(ci_len);
+            state.stack.truncate(depth);
+            Err(rt_err(
+                state,
+                "attempt to yield across a C-call boundary".to_string(),
+            ))
         }
-        _ => {
-            // __call メタメソッド: handler(func, args...)
-            let mm = get_metamethod(state, func, b"__call");
-            if matches!(mm, Value::Nil) {
-                Err(type_err(state, "call", func))
-            } else {
-                let mut newargs = Vec::with_capacity(args.len() + 1);
-                newargs.push(func);
-                newargs.extend_from_slice(args);
-                call(state, mm, &newargs)
+        r => r,
+    }
+}
+
+/// yield をそのまま呼び出し元へ伝播する [`call`]。呼び出し元が再開点を保存できる場合
+/// （VM の命令・[`call_k`]・コルーチンの初回 resume）に使う。
+pub(crate) fn call_yieldable(
+    state: &mut LuaState,
+    func: Value,
+    args: &[Value],
+) -> LuaResult<Vec<Value>> {
+    if state.n_ccalls >= MAX_C_CALLS {
+        return Err(rt_err(state, "C stack overflow".to_string()));
+    }
+    state.n_ccalls += 1;
+    let r = call_value(state, func, args);
+    state.n_ccalls -= 1;
+    r
+}
+
+/// yield 可能な呼び出し（本家 5.2 `lua_callk`）。ネイティブ関数の本体または継続から呼ぶ。
+///
+/// 呼んだ関数がそのまま戻れば戻り値を返す。yield したら `ctx()` で作った処理状態と継続 `k` を
+/// 呼び出し元ネイティブ関数のフレームに置いて `Err(LuaError::Yield)` を返すので、ネイティブ関数は
+/// それを `?` でそのまま返すこと。resume 時には呼んだ関数の戻り値で `k` が呼ばれる。
+pub fn call_k(
+    state: &mut LuaState,
+    func: Value,
+    args: &[Value],
+    k: ContinuationFn,
+    ctx: impl FnOnce() -> Box<dyn Any>,
+) -> LuaResult<Vec<Value>> {
+    let ci = state.call_info.len().saturating_sub(1);
+    match call_yieldable(state, func, args) {
+        Err(LuaError::Yield(vals)) => {
+            set_continuation(state, ci, k, ctx(), None);
+            Err(LuaError::Yield(vals))
+        }
+        r => r,
+    }
+}
+
+/// yield 可能な保護呼び出し（本家 5.2 `lua_pcallk`）。
+///
+/// 外側の `Err` は呼び出し元へそのまま返すもの（yield と、捕捉させない実行の打ち切り）。
+/// 内側の `Result` が呼び出しの結果で、エラーならスタックは呼び出し前に巻き戻してある。
+/// yield 後に起きたエラーも、resume 時に同じく巻き戻してから `k` へ `Err` で渡す。
+pub fn pcall_k(
+    state: &mut LuaState,
+    func: Value,
+    args: &[Value],
+    k: ContinuationFn,
+    ctx: impl FnOnce() -> Box<dyn Any>,
+) -> LuaResult<LuaResult<Vec<Value>>> {
+    let ci = state.call_info.len().saturating_sub(1);
+    let depth = state.stack.len();
+    state.protected_calls += 1;
+    let result = crate::state::call::pcall(state, |s| call_yieldable(s, func, args));
+    state.protected_calls -= 1;
+    match result {
+        Ok(v) => Ok(Ok(v)),
+        Err(LuaError::Yield(vals)) => {
+            set_continuation(state, ci, k, ctx(), Some(depth));
+            Err(LuaError::Yield(vals))
+        }
+        Err(e @ LuaError::Interrupted(r)) if !r.catchable() => Err(e),
+        Err(e) => Ok(Err(e)),
+    }
+}
+
+fn set_continuation(
+    state: &mut LuaState,
+    ci: usize,
+    func: ContinuationFn,
+    ctx: Box<dyn Any>,
+    protected_depth: Option<usize>,
+) {
+    if let Some(ci) = state.call_info.get_mut(ci) {
+        ci.continuation = Some(Continuation {
+            func,
+            ctx,
+            protected_depth,
+        });
+    }
+}
+
+/// `func` を `args` で呼び、全戻り値を返す。関数と引数をスタック末尾に積んで [`call_at`] へ渡す。
+fn call_value(state: &mut LuaState, func: Value, args: &[Value]) -> LuaResult<Vec<Value>> {
+    let at = state.stack.len();
+    state.stack.push(func);
+    state.stack.extend_from_slice(args);
+    let n = call_at(state, at)?;
+    Ok(take_results(state, at, n))
+}
+
+/// `stack[func]` の関数を `stack[func + 1..]` の引数で呼ぶ（本家 `luaD_call`）。
+///
+/// 戻り値は `stack[func..func + n]` に置かれ、スタックはその直後で切り詰められる。返り値は `n`。
+/// Lua 関数の実行中に起きたエラーはフレームとスタックを `func` まで降ろしてから返す
+/// （yield はフレームを残したまま伝播する）。
+fn call_at(state: &mut LuaState, func: usize) -> LuaResult<usize> {
+    let (key, is_lua) = match resolve_call(state, func) {
+        Ok(r) => r,
+        Err(e) => {
+            state.stack.truncate(func);
+            return Err(e);
+        }
+    };
+    if !is_lua {
+        return call_native(state, key, func);
+    }
+    let ci_len = state.call_info.len();
+    let call = match push_lua_frame(state, key, func) {
+        Ok(call) => call,
+        Err(e) => {
+            state.stack.truncate(func);
+            return Err(e);
+        }
+    };
+    let result = hook(state, HookEvent::Call).and_then(|()| execute(state, call));
+    match result {
+        Ok(n) => Ok(n),
+        Err(LuaError::Yield(vals)) => {
+            // コルーチン yield: CI とスタックフレームを保持したまま伝播する。
+            // resume がコルーチンの実行スタックごと保存する。
+            Err(LuaError::Yield(vals))
+        }
+        Err(e) => {
+            // フレームを降ろす前の、エラー発生時点のコールスタックを記録する。
+            // スクリプトの保護呼び出しが捕捉するエラーでは作らない。
+            let caught = state.protected_calls > 0
+                && !matches!(e, LuaError::Interrupted(r) if !r.catchable());
+            if state.traceback_on_error && state.error_traceback.is_none() && !caught {
+                state.error_traceback = Some(crate::vm::debug::traceback(state, 0));
             }
+            // コルーチン本体のフレームは、終了したコルーチンの実行スタックとして resume が残す。
+            if ci_len == 0 && state.thread.is_some() {
+                return Err(e);
+            }
+            // ループ内で積んだ呼び出し先のフレームもまとめて降ろす。
+            state.call_info.truncate(ci_len);
+            state.stack.truncate(func);
+            Err(e)
         }
     }
 }
 
+/// `stack[func..func + n]` に置かれた戻り値を取り出し、スタックを `func` まで降ろす。
+fn take_results(state: &mut LuaState, func: usize, n: usize) -> Vec<Value> {
+    debug_assert_eq!(state.stack.len(), func + n);
+    state.stack.split_off(func)
+}
+
 // ============================================================================
 // 公開ヘルパ（lua-stdlib 連携）
 // ============================================================================
@@ -211,7 +457,7 @@ pub fn where_string(state: &LuaState, level: usize) -> String {
         return String::new();
     };
     let ci = &state.call_info[idx];
-    match &ci.source {
+    match state.frame_source(ci) {
         Some(src) if ci.current_line > 0 => format!("{src}:{}: ", ci.current_line),
         _ => String::new(),
     }
@@ -221,603 +467,917 @@ pub fn where_string(state: &LuaState, level: usize) -> String {
 // 呼び出し（Lua / ネイティブ）
 // ============================================================================
 
-fn call_native(
-    state: &mut LuaState,
-    key: crate::gc::ClosureKey,
-    args: &[Value],
-) -> LuaResult<Vec<Value>> {
-    let func = match state.global.heap.get_closure(key) {
+/// `stack[func]` の関数値を呼べるクロージャに解決する（本家 `tryfuncTM`）。
+///
+/// 関数でない値は `__call` メタメソッドを `func` の位置に差し込み、元の値を第 1 引数にずらす
+/// （1 段だけ。メタメソッドが関数でなければエラー）。返り値はクロージャのキーと Lua 関数か否か。
+fn resolve_call(state: &mut LuaState, func: usize) -> LuaResult<(crate::gc::ClosureKey, bool)> {
+    let f = state.stack[func];
+    let callee = if f.as_closure().is_some() {
+        f
+    } else {
+        get_metamethod(state, f, b"__call")
+    };
+    let Some(key) = callee.as_closure() else {
+        return Err(type_err(state, "call", f));
+    };
+    if callee != f {
+        state.stack.insert(func, callee);
+    }
+    let is_lua = matches!(state.global.heap.get_closure(key), Some(Closure::Lua(_)));
+    Ok((key, is_lua))
+}
+
+/// ネイティブ関数を呼ぶ（本家 `luaD_precall` の C 関数側）。引数は `stack[func + 1..]` に積んであること。
+///
+/// 戻り値は `stack[func..func + n]` に置かれ、`n` を返す。
+fn call_native(state: &mut LuaState, key: crate::gc::ClosureKey, func: usize) -> LuaResult<usize> {
+    let f = match state.global.heap.get_closure(key) {
         Some(Closure::Native(nc)) => nc.func(),
         _ => return Err(rt_err(state, "internal: not a native closure".to_string())),
     };
-    let base = state.stack.len();
-    for a in args {
-        state.stack.push(*a);
-    }
     // native_closure にキーを記録する。NativeFn 本体が
     // `state.current_native_closure()` でキーを取得し、upvalue へアクセスできる
     // （本家 lua_upvalueindex 相当, 第二マイルストーン C API 対応）。
     state.call_info.push(CallInfo {
-        base,
-        func: base,
+        base: func + 1,
+        func,
         expected_results: 0,
-        source: None,
         current_line: 0,
+        current_pc: 0,
         native_closure: Some(key),
+        lua_closure: None,
+        nvarargs: 0,
         lua_frame: None,
         env: None,
+        tail_calls: 0,
+        continuation: None,
     });
-    let r = func(state);
-    match r {
-        Ok(nres) => {
-            let nres = nres.max(0) as usize;
-            let total = state.stack.len();
-            let start = total.saturating_sub(nres);
-            let results = state.stack[start..total].to_vec();
-            state.call_info.pop();
-            state.stack.truncate(base);
-            Ok(results)
-        }
-        Err(LuaError::Yield(vals)) => {
-            // Yield: ネイティブフレームをクリーンアップしてから伝播する。
-            // これにより上位の execute_inner の CALL ハンドラが正しい CallInfo を参照できる。
-            state.call_info.pop();
-            state.stack.truncate(base);
-            Err(LuaError::Yield(vals))
-        }
-        Err(e) => Err(e),
-    }
+    hook(state, HookEvent::Call)?;
+    // エラーと yield ではフレームを残す。call_k 経由の yield は再開点を持つフレームを、
+    // 自身の yield（coroutine.yield 等）は再開点の無いフレームを残し（本家 5.1 と同じく
+    // 中断中のトレースバックに現れる）、後者は resume 時に降ろして resume の値を戻り値にする。
+    let nres = f(state)?;
+    native_return(state, nres)
 }
 
-fn call_lua(
+/// [`call_native`] を C 呼び出しの入れ子深度（[`MAX_C_CALLS`]）に数えて呼ぶ。
+/// VM の `CALL`/`TAILCALL` からネイティブ関数を呼ぶときに使う。
+fn call_native_counted(
     state: &mut LuaState,
     key: crate::gc::ClosureKey,
-    args: &[Value],
-) -> LuaResult<Vec<Value>> {
-    let (proto, upvals, env) = match state.global.heap.get_closure(key) {
-        Some(Closure::Lua(lc)) => (lc.proto().clone(), lc.upvalues().to_vec(), lc.env()),
-        _ => return Err(rt_err(state, "internal: not a Lua closure".to_string())),
+    func: usize,
+) -> LuaResult<usize> {
+    if state.n_ccalls >= MAX_C_CALLS {
+        return Err(rt_err(state, "C stack overflow".to_string()));
+    }
+    state.n_ccalls += 1;
+    let r = call_native(state, key, func);
+    state.n_ccalls -= 1;
+    r
+}
+
+/// ネイティブ関数（または継続）が `nres` 個の戻り値を積んで戻ったときの後始末。
+/// フレームを降ろし、戻り値を `func` 以降へ詰めて個数を返す。
+fn native_return(state: &mut LuaState, nres: i32) -> LuaResult<usize> {
+    hook(state, HookEvent::Return)?;
+    // 安全点: 戻り値はまだスタック上、フレームも積まれたまま（本家 lapi の checkGC 相当）。
+    state.check_gc()?;
+    let total = state.stack.len();
+    let ci = state
+        .call_info
+        .pop()
+        .expect("native frame is on the call stack");
+    let n = (nres.max(0) as usize).min(total.saturating_sub(ci.base));
+    state.stack.copy_within(total - n..total, ci.func);
+    state.stack.truncate(ci.func + n);
+    Ok(n)
+}
+
+// ============================================================================
+// デバッグフック（本家 `ldo.c` の `luaD_callhook` / `ldebug.c` の `luaG_traceexec`）
+// ============================================================================
+
+/// `event` が有効ならフック関数を呼ぶ。フック実行中は呼ばない（フック内の呼び出しは無視）。
+///
+/// 発生元のフレームは `call_info` の末尾にあり、フック関数からはレベル 2 に見える
+/// （`debug.getinfo(2)`）。
+fn hook(state: &mut LuaState, event: HookEvent) -> LuaResult<()> {
+    let bit = match event {
+        HookEvent::Call => HOOK_MASK_CALL,
+        HookEvent::Return | HookEvent::TailReturn => HOOK_MASK_RET,
+        HookEvent::Line(_) => HOOK_MASK_LINE,
+        HookEvent::Count => HOOK_MASK_COUNT,
+    };
+    if state.hook.mask & bit == 0 || state.hook.running {
+        return Ok(());
+    }
+    let Some(f) = state.hook.func else {
+        return Ok(());
     };
+    state.hook.running = true;
+    let r = f(state, event);
+    state.hook.running = false;
+    r
+}
 
-    let nparams = proto.num_params as usize;
-    let maxstack = proto.max_stack_size as usize;
-    let base = state.stack.len();
+/// フレーム `ci_index` からの復帰フック。`Return` の後、末尾呼び出しで消えたフレームの分だけ
+/// `TailReturn` を発生させる（本家 `callrethooks`）。
+fn return_hooks(state: &mut LuaState, ci_index: usize) -> LuaResult<()> {
+    if state.hook.mask & HOOK_MASK_RET == 0 {
+        return Ok(());
+    }
+    hook(state, HookEvent::Return)?;
+    let tail_calls = state.call_info.get(ci_index).map_or(0, |ci| ci.tail_calls);
+    for _ in 0..tail_calls {
+        hook(state, HookEvent::TailReturn)?;
+    }
+    Ok(())
+}
+
+/// 命令の実行前に count / line フックを発生させる（本家 `luaG_traceexec`）。
+///
+/// line は関数の先頭、新しい行への移動、後方ジャンプ（ループの次の周回）で発生する。
+fn trace_exec(state: &mut LuaState, proto: &Proto, pc: usize, old_pc: usize) -> LuaResult<()> {
+    if state.hook.mask & HOOK_MASK_COUNT != 0 {
+        state.hook.count = state.hook.count.saturating_sub(1);
+        if state.hook.count == 0 {
+            state.hook.count = state.hook.base_count;
+            hook(state, HookEvent::Count)?;
+        }
+    }
+    if state.hook.mask & HOOK_MASK_LINE != 0 {
+        let line = proto.line_at(pc);
+        if pc == 0 || pc <= old_pc || line != proto.line_at(old_pc) {
+            hook(state, HookEvent::Line(line))?;
+        }
+    }
+    Ok(())
+}
+
+/// 積んだ Lua フレームの実行に必要な状態（[`push_lua_frame`] の戻り値）。
+struct LuaCall {
+    base: usize,
+    proto: Rc<Proto>,
+    upvals: Rc<[Upvalue]>,
+    env: GcHandle,
+}
+
+/// VM 内の呼び出し準備（[`precall`]）の結果。
+enum PreCall {
+    /// Lua 関数のフレームを積んだ。ディスパッチループがそのまま実行を切り替える。
+    Lua(LuaCall),
+    /// ネイティブ関数を呼び終えた。戻り値は `stack[func..func + n]`。
+    Native(usize),
+}
 
-    // 固定引数を R(0..nparams) に配置。
-    for i in 0..nparams {
-        state.stack.push(args.get(i).copied().unwrap_or(Value::Nil));
+/// `stack[func]` の関数を `stack[func + 1..]` の引数で呼ぶ準備をする（本家 `luaD_precall`）。
+///
+/// Lua 関数はフレームを積むだけで実行しない（Rust 再帰しない）。ネイティブ関数はその場で呼ぶ。
+fn precall(state: &mut LuaState, func: usize) -> LuaResult<PreCall> {
+    let (key, is_lua) = resolve_call(state, func)?;
+    if is_lua {
+        push_lua_frame(state, key, func).map(PreCall::Lua)
+    } else {
+        call_native_counted(state, key, func).map(PreCall::Native)
     }
-    // 残りのレジスタを nil で確保。
-    state.stack.resize(base + maxstack.max(nparams), Value::Nil);
+}
 
-    // 可変長引数（`...`）。
-    let varargs: Vec<Value> = if proto.is_vararg && args.len() > nparams {
-        args[nparams..].to_vec()
+/// Lua クロージャの実行に必要な `(proto, upvalue, env)`。upvalue 配列はクロージャと共有する。
+fn lua_closure_parts(
+    state: &mut LuaState,
+    key: crate::gc::ClosureKey,
+) -> LuaResult<(Rc<Proto>, Rc<[Upvalue]>, GcHandle)> {
+    match state.global.heap.get_closure(key) {
+        Some(Closure::Lua(lc)) => Ok((lc.proto().clone(), lc.upvalues().clone(), lc.env())),
+        _ => Err(rt_err(state, "internal: not a Lua closure".to_string())),
+    }
+}
+
+/// `stack[func + 1..]` の実引数を `proto` の仮引数に合わせ、レジスタ領域を確保する
+/// （本家 `luaD_precall` + `adjust_varargs`）。
+///
+/// 固定引数を超えた実引数は、可変長引数関数なら固定引数をその上へ移して `base` の直下に残し、
+/// そうでなければ捨てる。返り値は `(base, 可変長引数の個数)`。レジスタ領域が
+/// [`GlobalState::stack_limit`](crate::state::GlobalState::stack_limit) を超えるなら
+/// `"stack overflow"` を返す。
+fn adjust_args(state: &mut LuaState, proto: &Proto, func: usize) -> LuaResult<(usize, usize)> {
+    let nparams = proto.num_params as usize;
+    let frame_size = (proto.max_stack_size as usize).max(nparams);
+    let nargs = state.stack.len() - (func + 1);
+    let (base, nvarargs) = if proto.is_vararg && nargs > nparams {
+        (func + 1 + nargs, nargs - nparams)
     } else {
-        Vec::new()
+        (func + 1, 0)
     };
+    if base + frame_size > state.global.stack_limit {
+        return Err(stack_overflow(state));
+    }
+    if nvarargs > 0 {
+        // 固定引数を可変長引数の上（新しい base）へ移し、元の位置は nil にする。
+        state.stack.extend_from_within(func + 1..func + 1 + nparams);
+        state.stack[func + 1..func + 1 + nparams].fill(Value::NIL);
+    }
+    // 余った実引数を捨て、残りのレジスタを nil で確保。
+    state.stack.truncate(base + nparams);
+    state.stack.resize(base + frame_size, Value::NIL);
+    Ok((base, nvarargs))
+}
 
+/// Lua クロージャのフレームを積む（本家 `luaD_precall` の Lua 関数側）。
+///
+/// 引数は `stack[func + 1..]` に積んであること。[`adjust_args`] でレジスタ領域を確保し、
+/// [`CallInfo`] を積む。レジスタ領域が上限を超えるならフレームは積まない。
+fn push_lua_frame(
+    state: &mut LuaState,
+    key: crate::gc::ClosureKey,
+    func: usize,
+) -> LuaResult<LuaCall> {
+    let (proto, upvals, env) = lua_closure_parts(state, key)?;
+    let (base, nvarargs) = adjust_args(state, &proto, func)?;
     state.call_info.push(CallInfo {
         base,
-        func: base,
+        func,
         expected_results: 0,
-        source: Some(short_src(proto.source.as_deref())),
         current_line: proto.line_defined,
+        current_pc: 0,
         native_closure: None,
+        lua_closure: Some(key),
+        nvarargs,
         lua_frame: None,
         env: Some(env),
+        tail_calls: 0,
+        continuation: None,
     });
+    Ok(LuaCall {
+        base,
+        proto,
+        upvals,
+        env,
+    })
+}
 
-    let result = execute(state, base, proto, upvals, varargs, env);
+/// 実行中のフレームを降ろし、`stack[src..src + n]` の戻り値をその関数の位置へ詰める
+/// （本家 `luaD_poscall`）。スタックは戻り値の直後で切り詰める。
+fn poscall(state: &mut LuaState, src: usize, n: usize) {
+    let ci = state
+        .call_info
+        .pop()
+        .expect("returning frame is on the call stack");
+    state.stack.copy_within(src..src + n, ci.func);
+    state.stack.truncate(ci.func + n);
+}
 
-    match result {
-        Ok(v) => {
-            state.call_info.pop();
-            state.stack.truncate(base);
-            Ok(v)
-        }
-        Err(LuaError::Yield(vals)) => {
-            // コルーチン yield: CI とスタックフレームを保持したまま伝播する。
-            // l_resume がこれらを saved_call_info / saved_stack に移す。
-            Err(LuaError::Yield(vals))
-        }
-        Err(e) => {
-            state.call_info.pop();
-            state.stack.truncate(base);
-            Err(e)
-        }
-    }
+/// ループ内で呼んだ関数（`n` 個の戻り値を [`poscall`] 済み）から、フレーム `ci_idx` へ戻る。
+///
+/// 呼び出し元が `CALL` 時に退避した実行状態を取り出し、戻り値を結果レジスタに揃える。
+/// 戻り値は呼び出し元の `base` と実行状態（`top` は結果配置後の値、`resume_call_pc` は
+/// 呼び出した `CALL` 命令）。
+fn resume_caller(state: &mut LuaState, ci_idx: usize, n: usize) -> (usize, LuaFrameState) {
+    let ci = &mut state.call_info[ci_idx];
+    let mut frame = ci.lua_frame.take().expect("caller state is saved at CALL");
+    let base = ci.base;
+    frame.top = adjust_results(state, base, &frame.proto, frame.resume_call_pc, n);
+    (base, frame)
+}
+
+/// `CALL` 命令（`proto.code[call_pc]`）の結果レジスタ `R(A)..` に置かれた `n` 個の戻り値を揃え、
+/// 新しい `top` を返す（本家 `luaD_poscall` の `wanted` 調整）。
+///
+/// `C == 0`（可変個）なら全戻り値を残して `top` をその直後に、そうでなければ `C - 1` 個に
+/// 揃えて（不足は nil）`top` をレジスタ領域の末尾に戻す。
+fn adjust_results(
+    state: &mut LuaState,
+    base: usize,
+    proto: &Proto,
+    call_pc: usize,
+    n: usize,
+) -> usize {
+    let instr = proto.code[call_pc];
+    let ra = base + instr.a() as usize;
+    let frame_top = base + proto.max_stack_size as usize;
+    let want = if instr.c() == 0 {
+        n
+    } else {
+        instr.c() as usize - 1
+    };
+    state.stack.truncate(ra + want);
+    state.stack.resize((ra + want).max(frame_top), Value::NIL);
+    if instr.c() == 0 { ra + n } else { frame_top }
+}
+
+/// yield から再開した `CALL` の戻り値 `results` を結果レジスタへ置き、新しい `top` を返す。
+fn place_results(
+    state: &mut LuaState,
+    base: usize,
+    proto: &Proto,
+    call_pc: usize,
+    results: &[Value],
+) -> usize {
+    let ra = base + proto.code[call_pc].a() as usize;
+    state.stack.resize(ra, Value::NIL);
+    state.stack.extend_from_slice(results);
+    adjust_results(state, base, proto, call_pc, results.len())
 }
 
 // ============================================================================
 // 命令ディスパッチループ
 // ============================================================================
 
-fn execute(
-    state: &mut LuaState,
-    base: usize,
-    proto: Rc<Proto>,
-    upvals: Vec<Upvalue>,
-    varargs: Vec<Value>,
-    env: GcHandle,
-) -> LuaResult<Vec<Value>> {
-    let initial_top = base + proto.max_stack_size as usize;
-    execute_inner(
-        state,
+fn execute(state: &mut LuaState, call: LuaCall) -> LuaResult<usize> {
+    let LuaCall {
         base,
         proto,
         upvals,
-        varargs,
-        Vec::new(),
-        initial_top,
-        0,
         env,
-    )
+    } = call;
+    let initial_top = base + proto.max_stack_size as usize;
+    execute_inner(state, base, proto, upvals, Vec::new(), initial_top, 0, env)
 }
 
 /// コルーチン再開用エントリ。保存済みの open upvalue リスト・top・pc から実行を続ける。
+///
+/// 入口のフレーム（呼び出し時点の `call_info` 末尾）から `RETURN` したらフレームを降ろし、
+/// 戻り値をその関数の位置（[`CallInfo::func`]）以降に置いて個数を返す。
+/// ループ内で積んだ Lua フレームは、エラー時には積んだまま返す（呼び出し側が降ろす）。
+/// ただしそれらのフレームの open upvalue はここで閉じる（本家 `luaD_pcall` の `luaF_close`）。
 #[allow(clippy::too_many_arguments)]
 fn execute_inner(
     state: &mut LuaState,
     base: usize,
-    mut proto: Rc<Proto>,
-    mut upvals: Vec<Upvalue>,
-    mut varargs: Vec<Value>,
+    proto: Rc<Proto>,
+    upvals: Rc<[Upvalue]>,
     saved_open: Vec<(usize, Upvalue)>,
     saved_top: usize,
     saved_pc: usize,
+    env: GcHandle,
+) -> LuaResult<usize> {
+    let entry_ci = state.call_info.len().saturating_sub(1);
+    let mut open = saved_open;
+    let r = dispatch(
+        state, base, proto, upvals, &mut open, saved_top, saved_pc, env,
+    );
+    if let Err(e) = &r
+        && !matches!(e, LuaError::Yield(_))
+    {
+        close_upvals(state, &mut open, 0);
+        close_frame_upvals(state, entry_ci);
+    }
+    r
+}
+
+/// [`execute_inner`] の命令ディスパッチループ。`open` は実行中フレームの open upvalue リスト。
+#[allow(clippy::too_many_arguments)]
+fn dispatch(
+    state: &mut LuaState,
+    mut base: usize,
+    mut proto: Rc<Proto>,
+    mut upvals: Rc<[Upvalue]>,
+    open: &mut Vec<(usize, Upvalue)>,
+    saved_top: usize,
+    saved_pc: usize,
     mut env: GcHandle,
-) -> LuaResult<Vec<Value>> {
-    // このフレームの CallInfo インデックスを記録する。ネストした呼び出しが
-    // Yield したとき、last_mut() ではなくこのインデックスで自フレームを参照する。
-    let my_ci_index = state.call_info.len().saturating_sub(1);
-    let mut first_entry = true;
-    'reenter: loop {
-        let (mut open, mut top, mut pc) = if first_entry {
-            first_entry = false;
-            (saved_open.clone(), saved_top, saved_pc)
-        } else {
-            (Vec::new(), base + proto.max_stack_size as usize, 0)
-        };
+) -> LuaResult<usize> {
+    // 入口フレームの CallInfo インデックス。ここからの RETURN で Rust の呼び出し元へ返る。
+    let entry_ci = state.call_info.len().saturating_sub(1);
+    // 実行中フレームの CallInfo インデックス。ネストした呼び出しが Yield したとき、
+    // last_mut() ではなくこのインデックスで自フレームを参照する。
+    let mut my_ci_index = entry_ci;
+    let mut top = saved_top;
+    let mut pc = saved_pc;
+    // 直前に実行した命令の pc（line フックの判定用, 本家 `oldpc`）。
+    let mut hook_pc = saved_pc.saturating_sub(1);
+
+    loop {
+        let instr = proto.code[pc];
+        let cur_pc = pc;
+        pc += 1;
 
-        // このフレームの CallInfo に整形済みソース名を反映（TCO 再入時の差し替えにも対応）。
+        // `error()` の level 指定（luaL_where 相当）や `debug.getlocal` に備え、現在位置を CallInfo に記録する。
         if let Some(ci) = state.call_info.last_mut() {
-            ci.source = Some(short_src(proto.source.as_deref()));
+            ci.current_line = proto.line_at(cur_pc);
+            ci.current_pc = cur_pc;
         }
-
-        loop {
-            let instr = proto.code[pc];
-            let cur_pc = pc;
-            pc += 1;
-
-            // `error()` の level 指定（luaL_where 相当）に備え、現在行を CallInfo に記録する。
-            if let Some(ci) = state.call_info.last_mut() {
-                ci.current_line = proto.line_at(cur_pc);
+        if state.hook.mask & (HOOK_MASK_LINE | HOOK_MASK_COUNT) != 0 && !state.hook.running {
+            trace_exec(state, &proto, cur_pc, hook_pc)?;
+        }
+        hook_pc = cur_pc;
+        // 実行の打ち切り（命令数の上限・外部からの中断要求）。
+        if let Some(left) = state.instruction_budget {
+            if left == 0 {
+                return Err(LuaError::Interrupted(InterruptReason::InstructionLimit));
             }
-
-            let op = match instr.opcode() {
-                Some(op) => op,
-                None => return Err(err_at(state, &proto, cur_pc, "bad opcode".to_string())),
-            };
-            let a = instr.a() as usize;
-
-            match op {
-                OpCode::Move => {
-                    let v = reg(state, base, instr.b() as usize);
-                    set_reg(state, base + a, v);
-                }
-                OpCode::LoadK => {
-                    let v = proto.constants[instr.bx() as usize];
-                    set_reg(state, base + a, v);
-                }
-                OpCode::LoadBool => {
-                    set_reg(state, base + a, Value::Boolean(instr.b() != 0));
-                    if instr.c() != 0 {
-                        pc += 1;
+            state.instruction_budget = Some(left - 1);
+        }
+        // フラグは埋め込み側（`api::Lua::call`）へ戻るまで下ろさない。途中の finalizer や
+        // コルーチンが捨てても、次の命令でまた打ち切る。
+        if let Some(flag) = &state.interrupt
+            && flag.load(Ordering::Relaxed)
+        {
+            return Err(LuaError::Interrupted(InterruptReason::Requested));
+        }
+        if let Some(deadline) = &mut state.deadline {
+            deadline.countdown -= 1;
+            if deadline.countdown == 0 {
+                deadline.countdown = DEADLINE_CHECK_INTERVAL;
+                if Instant::now() >= deadline.at {
+                    // 捕捉できない期限は `call_with_deadline` へ戻るまで外さず、途中で捨てられても
+                    // 次の命令でまた打ち切る。捕捉できる期限はハンドラが動けるよう一度で外す。
+                    let catchable = deadline.catchable;
+                    if catchable {
+                        state.deadline = None;
+                    } else {
+                        deadline.countdown = 1;
                     }
+                    return Err(LuaError::Interrupted(InterruptReason::Deadline {
+                        catchable,
+                    }));
                 }
-                OpCode::LoadNil => {
-                    let b = instr.b() as usize;
-                    for r in a..=b {
-                        set_reg(state, base + r, Value::Nil);
+            }
+        }
+
+        let op = match instr.opcode() {
+            Some(op) => op,
+            None => return Err(err_at(state, &proto, cur_pc, "bad opcode".to_string())),
+        };
+        let a = instr.a() as usize;
+
+        // 呼び出し（関数・メタメソッド・イテレータ）が yield したら、命令 `cur_pc` の途中として
+        // 自フレームの実行状態を CallInfo に保存して上位へ伝播する（再開時は `finish_op` が
+        // 命令の残りを行う）。my_ci_index を使うことでネストした Lua 呼び出しが CI を保持したまま
+        // 伝播してきた場合でも正しい自フレームに保存できる。
+        macro_rules! yieldable {
+            ($e:expr) => {
+                yieldable!($e, top, false)
+            };
+            ($e:expr, $top:expr, $le_by_lt:expr) => {
+                match $e {
+                    Ok(v) => v,
+                    Err(LuaError::Yield(vals)) => {
+                        if let Some(ci) = state.call_info.get_mut(my_ci_index) {
+                            ci.lua_frame = Some(LuaFrameState {
+                                resume_call_pc: cur_pc,
+                                proto: proto.clone(),
+                                upvals: upvals.clone(),
+                                open: open.clone(),
+                                top: $top,
+                                env,
+                                le_by_lt: $le_by_lt,
+                            });
+                        }
+                        return Err(LuaError::Yield(vals));
                     }
+                    Err(e) => return Err(e),
                 }
-                OpCode::GetUpval => {
-                    let v = upval_get(state, &upvals[instr.b() as usize]);
-                    set_reg(state, base + a, v);
-                }
-                OpCode::SetUpval => {
-                    let v = reg(state, base, a);
-                    upval_set(state, &upvals[instr.b() as usize], v);
-                }
-                OpCode::GetGlobal => {
-                    let key = proto.constants[instr.bx() as usize];
-                    // setfenv による env 変更を反映するため CallInfo から都度読む。
-                    let cur_env = state
-                        .call_info
-                        .get(my_ci_index)
-                        .and_then(|ci| ci.env)
-                        .unwrap_or(env);
-                    let g = Value::GcRef(cur_env);
-                    let v = index_get(state, g, key, &proto, cur_pc)?;
-                    set_reg(state, base + a, v);
-                }
-                OpCode::SetGlobal => {
-                    let key = proto.constants[instr.bx() as usize];
-                    let v = reg(state, base, a);
-                    // setfenv による env 変更を反映するため CallInfo から都度読む。
-                    let cur_env = state
-                        .call_info
-                        .get(my_ci_index)
-                        .and_then(|ci| ci.env)
-                        .unwrap_or(env);
-                    let g = Value::GcRef(cur_env);
-                    index_set(state, g, key, v, &proto, cur_pc)?;
-                }
-                OpCode::GetTable => {
-                    let t = reg(state, base, instr.b() as usize);
-                    let k = rk(state, &proto, base, instr.c());
-                    let v = index_get(state, t, k, &proto, cur_pc)?;
-                    set_reg(state, base + a, v);
-                }
-                OpCode::SetTable => {
-                    let t = reg(state, base, a);
-                    let k = rk(state, &proto, base, instr.b());
-                    let v = rk(state, &proto, base, instr.c());
-                    index_set(state, t, k, v, &proto, cur_pc)?;
-                }
-                OpCode::NewTable => {
-                    let narray = fb2int(instr.b());
-                    let nhash = fb2int(instr.c());
-                    let h = state
-                        .global
-                        .heap
-                        .alloc_table(Table::with_capacity(narray, nhash));
-                    set_reg(state, base + a, Value::GcRef(h));
-                }
-                OpCode::SelfOp => {
-                    let t = reg(state, base, instr.b() as usize);
-                    set_reg(state, base + a + 1, t);
-                    let k = rk(state, &proto, base, instr.c());
-                    let v = index_get(state, t, k, &proto, cur_pc)?;
-                    set_reg(state, base + a, v);
+            };
+        }
+
+        match op {
+            OpCode::Move => {
+                let v = reg(state, base, instr.b() as usize);
+                set_reg(state, base + a, v);
+            }
+            OpCode::LoadK => {
+                let v = proto.constants[instr.bx() as usize];
+                set_reg(state, base + a, v);
+            }
+            OpCode::LoadBool => {
+                set_reg(state, base + a, Value::boolean(instr.b() != 0));
+                if instr.c() != 0 {
+                    pc += 1;
                 }
-                OpCode::Add
-                | OpCode::Sub
-                | OpCode::Mul
-                | OpCode::Div
-                | OpCode::Mod
-                | OpCode::Pow => {
-                    let b = rk(state, &proto, base, instr.b());
-                    let c = rk(state, &proto, base, instr.c());
-                    let v = arith(state, op, b, c, &proto, cur_pc)?;
-                    set_reg(state, base + a, v);
+            }
+            OpCode::LoadNil => {
+                let b = instr.b() as usize;
+                for r in a..=b {
+                    set_reg(state, base + r, Value::NIL);
                 }
-                OpCode::Unm => {
-                    let b = reg(state, base, instr.b() as usize);
-                    let v = match tonum(state, b) {
-                        Some(n) => Value::Number(-n),
-                        None => {
-                            let mm = get_metamethod(state, b, b"__unm");
-                            if matches!(mm, Value::Nil) {
-                                return Err(arith_err(state, &proto, cur_pc, b));
-                            }
-                            first(call(state, mm, &[b, b])?)
+            }
+            OpCode::GetUpval => {
+                let v = upval_get(state, &upvals[instr.b() as usize]);
+                set_reg(state, base + a, v);
+            }
+            OpCode::SetUpval => {
+                let v = reg(state, base, a);
+                upval_set(state, &upvals[instr.b() as usize], v);
+            }
+            OpCode::GetGlobal => {
+                let key = proto.constants[instr.bx() as usize];
+                // setfenv による env 変更を反映するため CallInfo から都度読む。
+                let cur_env = state
+                    .call_info
+                    .get(my_ci_index)
+                    .and_then(|ci| ci.env)
+                    .unwrap_or(env);
+                let g = Value::gc(cur_env);
+                let v = yieldable!(index_get(state, g, key, &proto, cur_pc, None));
+                set_reg(state, base + a, v);
+            }
+            OpCode::SetGlobal => {
+                let key = proto.constants[instr.bx() as usize];
+                let v = reg(state, base, a);
+                // setfenv による env 変更を反映するため CallInfo から都度読む。
+                let cur_env = state
+                    .call_info
+                    .get(my_ci_index)
+                    .and_then(|ci| ci.env)
+                    .unwrap_or(env);
+                let g = Value::gc(cur_env);
+                yieldable!(index_set(state, g, key, v, &proto, cur_pc, None));
+            }
+            OpCode::GetTable => {
+                let t = reg(state, base, instr.b() as usize);
+                let k = rk(state, &proto, base, instr.c());
+                let v = yieldable!(index_get(
+                    state,
+                    t,
+                    k,
+                    &proto,
+                    cur_pc,
+                    Some(instr.b() as usize)
+                ));
+                set_reg(state, base + a, v);
+            }
+            OpCode::SetTable => {
+                let t = reg(state, base, a);
+                let k = rk(state, &proto, base, instr.b());
+                let v = rk(state, &proto, base, instr.c());
+                yieldable!(index_set(state, t, k, v, &proto, cur_pc, Some(a)));
+            }
+            OpCode::NewTable => {
+                let narray = fb2int(instr.b());
+                let nhash = fb2int(instr.c());
+                let h = state
+                    .global
+                    .heap
+                    .alloc_table(Table::with_capacity(narray, nhash));
+                set_reg(state, base + a, Value::gc(h));
+                state.check_gc()?;
+            }
+            OpCode::SelfOp => {
+                let t = reg(state, base, instr.b() as usize);
+                set_reg(state, base + a + 1, t);
+                let k = rk(state, &proto, base, instr.c());
+                let v = yieldable!(index_get(
+                    state,
+                    t,
+                    k,
+                    &proto,
+                    cur_pc,
+                    Some(instr.b() as usize)
+                ));
+                set_reg(state, base + a, v);
+            }
+            OpCode::Add | OpCode::Sub | OpCode::Mul | OpCode::Div | OpCode::Mod | OpCode::Pow => {
+                let b = rk(state, &proto, base, instr.b());
+                let c = rk(state, &proto, base, instr.c());
+                let regs = (rk_reg(instr.b()), rk_reg(instr.c()));
+                let v = yieldable!(arith(state, op, (b, c), regs, &proto, cur_pc));
+                set_reg(state, base + a, v);
+            }
+            OpCode::Unm => {
+                let b = reg(state, base, instr.b() as usize);
+                let v = match tonum(state, b) {
+                    Some(n) => Value::number(-n),
+                    None => {
+                        let mm = get_metamethod(state, b, b"__unm");
+                        if mm.is_nil() {
+                            let rb = Some(instr.b() as usize);
+                            return Err(arith_err(state, &proto, cur_pc, b, rb));
                         }
-                    };
-                    set_reg(state, base + a, v);
-                }
-                OpCode::Not => {
-                    let b = reg(state, base, instr.b() as usize);
-                    set_reg(state, base + a, Value::Boolean(!b.is_truthy()));
-                }
-                OpCode::Len => {
-                    let b = reg(state, base, instr.b() as usize);
-                    let v = len_op(state, b, &proto, cur_pc)?;
-                    set_reg(state, base + a, v);
-                }
-                OpCode::Concat => {
-                    let bb = instr.b() as usize;
-                    let cc = instr.c() as usize;
-                    // 右結合で R(B)..R(B+1)..R(C) を連結。
-                    let mut acc = reg(state, base, cc);
-                    let mut i = cc;
-                    while i > bb {
-                        i -= 1;
-                        let left = reg(state, base, i);
-                        acc = concat_two(state, left, acc, &proto, cur_pc)?;
+                        first(yieldable!(call_yieldable(state, mm, &[b, b])))
                     }
-                    set_reg(state, base + a, acc);
+                };
+                set_reg(state, base + a, v);
+            }
+            OpCode::Not => {
+                let b = reg(state, base, instr.b() as usize);
+                set_reg(state, base + a, Value::boolean(!b.is_truthy()));
+            }
+            OpCode::Len => {
+                let b = reg(state, base, instr.b() as usize);
+                let v = yieldable!(len_op(state, b, &proto, cur_pc, instr.b() as usize));
+                set_reg(state, base + a, v);
+            }
+            OpCode::Concat => {
+                let bb = instr.b() as usize;
+                let cc = instr.c() as usize;
+                let mut i = cc;
+                let acc = reg(state, base, cc);
+                // yield したら、再開時に結果を置くレジスタ R(i) を top として保存する。
+                let acc = yieldable!(
+                    concat_from(state, base, (bb, &mut i), acc, &proto, cur_pc),
+                    base + i,
+                    false
+                );
+                set_reg(state, base + a, acc);
+                state.check_gc()?;
+            }
+            OpCode::Jmp => {
+                // A > 0 は Lua 5.2 の goto が出す upvalue の閉鎖（`R(A-1)` 以上）。
+                if a > 0 {
+                    close_upvals(state, open, base + a - 1);
                 }
-                OpCode::Jmp => {
-                    pc = (pc as i32 + instr.sbx()) as usize;
+                pc = (pc as i32 + instr.sbx()) as usize;
+            }
+            OpCode::Eq => {
+                let b = rk(state, &proto, base, instr.b());
+                let c = rk(state, &proto, base, instr.c());
+                let eq = yieldable!(values_equal(state, b, c));
+                if eq != (a != 0) {
+                    pc += 1;
                 }
-                OpCode::Eq => {
-                    let b = rk(state, &proto, base, instr.b());
-                    let c = rk(state, &proto, base, instr.c());
-                    let eq = values_equal(state, b, c)?;
-                    if eq != (a != 0) {
-                        pc += 1;
-                    }
+            }
+            OpCode::Lt => {
+                let b = rk(state, &proto, base, instr.b());
+                let c = rk(state, &proto, base, instr.c());
+                let lt = yieldable!(less_than(state, b, c, &proto, cur_pc));
+                if lt != (a != 0) {
+                    pc += 1;
                 }
-                OpCode::Lt => {
-                    let b = rk(state, &proto, base, instr.b());
-                    let c = rk(state, &proto, base, instr.c());
-                    let lt = less_than(state, b, c, &proto, cur_pc)?;
-                    if lt != (a != 0) {
-                        pc += 1;
+            }
+            OpCode::Le => {
+                let b = rk(state, &proto, base, instr.b());
+                let c = rk(state, &proto, base, instr.c());
+                let le = match le_primitive(state, b, c) {
+                    Some(le) => le,
+                    None => {
+                        let Some((mm, by_lt)) = le_metamethod(state, b, c) else {
+                            return Err(cmp_err(state, &proto, cur_pc, b, c));
+                        };
+                        // 本家 5.1: `__le` が無ければ `not (c < b)` を `__lt` で求める。
+                        let args = if by_lt { [c, b] } else { [b, c] };
+                        let r = yieldable!(call_yieldable(state, mm, &args), top, by_lt);
+                        first(r).is_truthy() != by_lt
                     }
+                };
+                if le != (a != 0) {
+                    pc += 1;
                 }
-                OpCode::Le => {
-                    let b = rk(state, &proto, base, instr.b());
-                    let c = rk(state, &proto, base, instr.c());
-                    let le = less_equal(state, b, c, &proto, cur_pc)?;
-                    if le != (a != 0) {
-                        pc += 1;
-                    }
+            }
+            OpCode::Test => {
+                let ra = reg(state, base, a);
+                if ra.is_truthy() != (instr.c() != 0) {
+                    pc += 1;
                 }
-                OpCode::Test => {
-                    let ra = reg(state, base, a);
-                    if ra.is_truthy() != (instr.c() != 0) {
-                        pc += 1;
-                    }
+            }
+            OpCode::TestSet => {
+                let rb = reg(state, base, instr.b() as usize);
+                if rb.is_truthy() == (instr.c() != 0) {
+                    set_reg(state, base + a, rb);
+                } else {
+                    pc += 1;
                 }
-                OpCode::TestSet => {
-                    let rb = reg(state, base, instr.b() as usize);
-                    if rb.is_truthy() == (instr.c() != 0) {
-                        set_reg(state, base + a, rb);
-                    } else {
-                        pc += 1;
-                    }
+            }
+            OpCode::Call => {
+                // 関数 R(A) と引数 R(A+1).. をそのまま呼び先のフレームとして渡す。
+                let func = base + a;
+                if instr.b() != 0 {
+                    top = func + instr.b() as usize;
                 }
-                OpCode::Call => {
-                    let nargs = if instr.b() == 0 {
-                        top - (base + a + 1)
-                    } else {
-                        instr.b() as usize - 1
-                    };
-                    let func = reg(state, base, a);
-                    let mut callargs = Vec::with_capacity(nargs);
-                    for i in 0..nargs {
-                        callargs.push(reg(state, base, a + 1 + i));
-                    }
-                    let r = call(state, func, &callargs);
-                    let results = match r {
-                        Ok(v) => v,
-                        Err(LuaError::Yield(vals)) => {
-                            // コルーチン yield: 自フレームの状態を CallInfo に保存して上位へ伝播。
-                            // my_ci_index を使うことでネストした Lua 呼び出しが CI を保持したまま
-                            // 伝播してきた場合でも正しい自フレームに保存できる。
-                            if let Some(ci) = state.call_info.get_mut(my_ci_index) {
-                                ci.lua_frame = Some(Box::new(LuaFrameState {
-                                    resume_call_pc: cur_pc,
-                                    proto: proto.clone(),
-                                    upvals: upvals.clone(),
-                                    varargs: varargs.clone(),
-                                    open: open.clone(),
-                                    top,
-                                    env,
-                                }));
-                            }
-                            return Err(LuaError::Yield(vals));
-                        }
-                        Err(e) => return Err(e),
-                    };
-                    let want = if instr.c() == 0 {
-                        results.len()
-                    } else {
-                        instr.c() as usize - 1
-                    };
-                    for i in 0..want {
-                        set_reg(
-                            state,
-                            base + a + i,
-                            results.get(i).copied().unwrap_or(Value::Nil),
-                        );
-                    }
-                    if instr.c() == 0 {
-                        top = base + a + results.len();
-                    } else {
+                check_callable(state, &proto, cur_pc, reg(state, base, a), a)?;
+                state.stack.truncate(top);
+                match yieldable!(precall(state, func)) {
+                    PreCall::Lua(call) => {
+                        // Lua 関数はこのループの中で実行する（Rust 再帰しない）。
+                        // 呼び出し元の実行状態を自フレームへ退避する（RETURN で復元）。
+                        state.call_info[my_ci_index].lua_frame = Some(LuaFrameState {
+                            resume_call_pc: cur_pc,
+                            proto: std::mem::replace(&mut proto, call.proto),
+                            upvals: std::mem::replace(&mut upvals, call.upvals),
+                            open: std::mem::take(open),
+                            top,
+                            env,
+                            le_by_lt: false,
+                        });
+                        my_ci_index = state.call_info.len() - 1;
+                        base = call.base;
+                        env = call.env;
                         top = base + proto.max_stack_size as usize;
+                        pc = 0;
+                        hook(state, HookEvent::Call)?;
+                        continue;
                     }
+                    PreCall::Native(n) => top = adjust_results(state, base, &proto, cur_pc, n),
+                }
+            }
+            OpCode::TailCall => {
+                let func = base + a;
+                if instr.b() != 0 {
+                    top = func + instr.b() as usize;
+                }
+                check_callable(state, &proto, cur_pc, reg(state, base, a), a)?;
+                // 現フレームの open upvalue を閉じてからフレームを明け渡す。
+                close_upvals(state, open, base);
+                state.stack.truncate(top);
+                let (k, is_lua) = resolve_call(state, func)?;
+
+                // 呼び先が Lua クロージャなら **フレームを再利用** して 'reenter（真の TCO）。
+                if is_lua {
+                    let (new_proto, new_upvals, new_env) = lua_closure_parts(state, k)?;
+                    // 関数と引数を自フレームの関数の位置へ下ろす。
+                    let ci_func = state.call_info[my_ci_index].func;
+                    let len = state.stack.len();
+                    state.stack.copy_within(func..len, ci_func);
+                    state.stack.truncate(ci_func + (len - func));
+                    let (new_base, nvarargs) = adjust_args(state, &new_proto, ci_func)?;
+                    let ci = &mut state.call_info[my_ci_index];
+                    ci.base = new_base;
+                    ci.lua_closure = Some(k);
+                    ci.nvarargs = nvarargs;
+                    ci.env = Some(new_env);
+                    ci.current_line = new_proto.line_defined;
+                    ci.current_pc = 0;
+                    ci.tail_calls += 1;
+                    base = new_base;
+                    top = base + new_proto.max_stack_size as usize;
+                    proto = new_proto;
+                    upvals = new_upvals;
+                    env = new_env;
+                    open.clear();
+                    pc = 0;
+                    hook(state, HookEvent::Call)?;
+                    continue;
                 }
-                OpCode::TailCall => {
-                    let nargs = if instr.b() == 0 {
-                        top - (base + a + 1)
-                    } else {
-                        instr.b() as usize - 1
-                    };
-                    let func = reg(state, base, a);
-                    let mut callargs = Vec::with_capacity(nargs);
-                    for i in 0..nargs {
-                        callargs.push(reg(state, base, a + 1 + i));
-                    }
-                    // 現フレームの open upvalue を閉じてからフレームを明け渡す。
-                    close_upvals(state, &mut open, base);
-
-                    // 呼び先が Lua クロージャなら **フレームを再利用** して 'reenter（真の TCO）。
-                    if let Value::GcRef(GcHandle::Closure(k)) = func
-                        && let Some(Closure::Lua(lc)) = state.global.heap.get_closure(k)
-                    {
-                        let new_proto = lc.proto().clone();
-                        let new_upvals = lc.upvalues().to_vec();
-                        let new_env = lc.env();
-                        let nparams = new_proto.num_params as usize;
-                        let maxstack = new_proto.max_stack_size as usize;
-                        let need = base + maxstack.max(nparams);
-
-                        // フレーム領域を新関数のレジスタ数に合わせて調整。
-                        if state.stack.len() < need {
-                            state.stack.resize(need, Value::Nil);
-                        } else {
-                            state.stack.truncate(need);
-                        }
-                        // 固定引数を配置し、余りのレジスタを nil で初期化。
-                        for i in 0..nparams {
-                            state.stack[base + i] = callargs.get(i).copied().unwrap_or(Value::Nil);
-                        }
-                        for i in nparams..(need - base) {
-                            state.stack[base + i] = Value::Nil;
-                        }
-                        varargs = if new_proto.is_vararg && callargs.len() > nparams {
-                            callargs[nparams..].to_vec()
-                        } else {
-                            Vec::new()
-                        };
-                        proto = new_proto;
-                        upvals = new_upvals;
-                        env = new_env;
-                        continue 'reenter;
-                    }
 
-                    // ネイティブ関数 / `__call`: 通常呼び出しで結果をそのまま返す。
-                    let results = call(state, func, &callargs)?;
-                    return Ok(results);
+                // ネイティブ関数: その場で呼び、結果をそのままこのフレームの戻り値にする。
+                // yield したらこのフレームは再開点を持たない（lua_frame = None）。resume の値が
+                // そのままこのフレームの戻り値になる。
+                let n = call_native_counted(state, k, func)?;
+                return_hooks(state, my_ci_index)?;
+                poscall(state, func, n);
+                if my_ci_index == entry_ci {
+                    return Ok(n);
                 }
-                OpCode::Return => {
-                    let n = if instr.b() == 0 {
-                        top - (base + a)
-                    } else {
-                        instr.b() as usize - 1
-                    };
-                    let mut rets = Vec::with_capacity(n);
-                    for i in 0..n {
-                        rets.push(reg(state, base, a + i));
-                    }
-                    close_upvals(state, &mut open, base);
-                    return Ok(rets);
+                my_ci_index -= 1;
+                let (caller_base, caller) = resume_caller(state, my_ci_index, n);
+                base = caller_base;
+                proto = caller.proto;
+                upvals = caller.upvals;
+                *open = caller.open;
+                top = caller.top;
+                pc = caller.resume_call_pc + 1;
+                hook_pc = caller.resume_call_pc;
+                env = caller.env;
+            }
+            OpCode::Return => {
+                let ra = base + a;
+                let n = if instr.b() == 0 {
+                    top - ra
+                } else {
+                    instr.b() as usize - 1
+                };
+                close_upvals(state, open, base);
+                return_hooks(state, my_ci_index)?;
+                poscall(state, ra, n);
+                if my_ci_index == entry_ci {
+                    return Ok(n);
                 }
-                OpCode::ForPrep => {
-                    let init = num_for(state, base, a, "initial", &proto, cur_pc)?;
-                    let _limit = num_for(state, base, a + 1, "limit", &proto, cur_pc)?;
-                    let step = num_for(state, base, a + 2, "step", &proto, cur_pc)?;
-                    set_reg(state, base + a, Value::Number(init - step));
+                // ループ内で呼んだ Lua 関数からの復帰: 呼び出し元の CALL の続きへ。
+                my_ci_index -= 1;
+                let (caller_base, caller) = resume_caller(state, my_ci_index, n);
+                base = caller_base;
+                proto = caller.proto;
+                upvals = caller.upvals;
+                *open = caller.open;
+                top = caller.top;
+                pc = caller.resume_call_pc + 1;
+                hook_pc = caller.resume_call_pc;
+                env = caller.env;
+            }
+            OpCode::ForPrep => {
+                let init = num_for(state, base, a, "initial", &proto, cur_pc)?;
+                let _limit = num_for(state, base, a + 1, "limit", &proto, cur_pc)?;
+                let step = num_for(state, base, a + 2, "step", &proto, cur_pc)?;
+                set_reg(state, base + a, Value::number(init - step));
+                pc = (pc as i32 + instr.sbx()) as usize;
+            }
+            OpCode::ForLoop => {
+                let idx = num_at(state, base, a) + num_at(state, base, a + 2);
+                let limit = num_at(state, base, a + 1);
+                let step = num_at(state, base, a + 2);
+                let cont = if step >= 0.0 {
+                    idx <= limit
+                } else {
+                    idx >= limit
+                };
+                if cont {
+                    set_reg(state, base + a, Value::number(idx));
+                    set_reg(state, base + a + 3, Value::number(idx));
                     pc = (pc as i32 + instr.sbx()) as usize;
                 }
-                OpCode::ForLoop => {
-                    let idx = num_at(state, base, a) + num_at(state, base, a + 2);
-                    let limit = num_at(state, base, a + 1);
-                    let step = num_at(state, base, a + 2);
-                    let cont = if step >= 0.0 {
-                        idx <= limit
-                    } else {
-                        idx >= limit
-                    };
-                    if cont {
-                        set_reg(state, base + a, Value::Number(idx));
-                        set_reg(state, base + a + 3, Value::Number(idx));
-                        pc = (pc as i32 + instr.sbx()) as usize;
-                    }
+            }
+            OpCode::TForLoop => {
+                let func = reg(state, base, a);
+                let s = reg(state, base, a + 1);
+                let ctrl = reg(state, base, a + 2);
+                let nresults = instr.c() as usize;
+                let results = yieldable!(call_yieldable(state, func, &[s, ctrl]));
+                for i in 0..nresults {
+                    set_reg(
+                        state,
+                        base + a + 3 + i,
+                        results.get(i).copied().unwrap_or(Value::NIL),
+                    );
                 }
-                OpCode::TForLoop => {
-                    let func = reg(state, base, a);
-                    let s = reg(state, base, a + 1);
-                    let ctrl = reg(state, base, a + 2);
-                    let nresults = instr.c() as usize;
-                    let results = call(state, func, &[s, ctrl])?;
-                    for i in 0..nresults {
-                        set_reg(
-                            state,
-                            base + a + 3 + i,
-                            results.get(i).copied().unwrap_or(Value::Nil),
-                        );
-                    }
-                    let first_res = reg(state, base, a + 3);
-                    if !matches!(first_res, Value::Nil) {
-                        set_reg(state, base + a + 2, first_res);
-                    } else {
-                        pc += 1;
-                    }
+                let first_res = reg(state, base, a + 3);
+                if !first_res.is_nil() {
+                    set_reg(state, base + a + 2, first_res);
+                } else {
+                    pc += 1;
                 }
-                OpCode::SetList => {
-                    let n = if instr.b() == 0 {
-                        top - (base + a + 1)
-                    } else {
-                        instr.b() as usize
-                    };
-                    let mut block = instr.c() as usize;
-                    if block == 0 {
-                        // 大きな C は次の命令ワードに格納される。
-                        block = proto.code[pc].raw() as usize;
-                        pc += 1;
+            }
+            OpCode::SetList => {
+                let n = if instr.b() == 0 {
+                    top - (base + a + 1)
+                } else {
+                    instr.b() as usize
+                };
+                let mut block = instr.c() as usize;
+                if block == 0 {
+                    // 大きな C は次の命令ワードに格納される。
+                    block = proto.code[pc].raw() as usize;
+                    pc += 1;
+                }
+                let tval = reg(state, base, a);
+                let Some(tk) = tval.as_table() else {
+                    return Err(err_at(
+                        state,
+                        &proto,
+                        cur_pc,
+                        "SETLIST on non-table".to_string(),
+                    ));
+                };
+                for i in 1..=n {
+                    let idx = (block - 1) * LFIELDS_PER_FLUSH as usize + i;
+                    let v = reg(state, base, a + i);
+                    reserve_insert(state, tk, Value::number(idx as f64), v)?;
+                    if let Some(t) = state.global.heap.get_table_mut(tk) {
+                        let _ = t.set(Value::number(idx as f64), v);
                     }
-                    let tval = reg(state, base, a);
-                    let tk = match tval {
-                        Value::GcRef(GcHandle::Table(k)) => k,
+                }
+                top = base + proto.max_stack_size as usize;
+            }
+            OpCode::Close => {
+                close_upvals(state, open, base + a);
+            }
+            OpCode::Closure => {
+                let child = proto.protos[instr.bx() as usize].clone();
+                let nup = child.num_upvalues as usize;
+                // 子クロージャは親の env を継承する（Lua 5.1 の規則）。
+                // setfenv 後の env を反映するため CallInfo から読む。
+                let child_env = state
+                    .call_info
+                    .get(my_ci_index)
+                    .and_then(|ci| ci.env)
+                    .unwrap_or(env);
+                let mut captured = Vec::with_capacity(nup);
+                for _ in 0..nup {
+                    let pseudo = proto.code[pc];
+                    pc += 1;
+                    match pseudo.opcode() {
+                        Some(OpCode::Move) => {
+                            let abs = base + pseudo.b() as usize;
+                            let uv = find_or_create_upval(open, state.thread, abs);
+                            captured.push(uv);
+                        }
+                        Some(OpCode::GetUpval) => {
+                            let uv = upvals[pseudo.b() as usize].clone();
+                            captured.push(uv);
+                        }
                         _ => {
                             return Err(err_at(
                                 state,
                                 &proto,
                                 cur_pc,
-                                "SETLIST on non-table".to_string(),
+                                "malformed CLOSURE upvalue capture".to_string(),
                             ));
                         }
-                    };
-                    for i in 1..=n {
-                        let idx = (block - 1) * LFIELDS_PER_FLUSH as usize + i;
-                        let v = reg(state, base, a + i);
-                        if let Some(t) = state.global.heap.get_table_mut(tk) {
-                            let _ = t.set(Value::Number(idx as f64), v);
-                        }
                     }
-                    top = base + proto.max_stack_size as usize;
                 }
-                OpCode::Close => {
-                    close_upvals(state, &mut open, base + a);
-                }
-                OpCode::Closure => {
-                    let child = proto.protos[instr.bx() as usize].clone();
-                    let nup = child.num_upvalues as usize;
-                    // 子クロージャは親の env を継承する（Lua 5.1 の規則）。
-                    // setfenv 後の env を反映するため CallInfo から読む。
-                    let child_env = state
-                        .call_info
-                        .get(my_ci_index)
-                        .and_then(|ci| ci.env)
-                        .unwrap_or(env);
-                    let mut newc = LuaClosure::new_with_env(child, child_env);
-                    for _ in 0..nup {
-                        let pseudo = proto.code[pc];
-                        pc += 1;
-                        match pseudo.opcode() {
-                            Some(OpCode::Move) => {
-                                let abs = base + pseudo.b() as usize;
-                                let uv = find_or_create_upval(&mut open, abs);
-                                newc.push_upvalue(uv);
-                            }
-                            Some(OpCode::GetUpval) => {
-                                let uv = upvals[pseudo.b() as usize].clone();
-                                newc.push_upvalue(uv);
-                            }
-                            _ => {
-                                return Err(err_at(
-                                    state,
-                                    &proto,
-                                    cur_pc,
-                                    "malformed CLOSURE upvalue capture".to_string(),
-                                ));
-                            }
-                        }
-                    }
-                    let h = state.global.heap.alloc_closure(Closure::Lua(newc));
-                    set_reg(state, base + a, Value::GcRef(h));
-                }
-                OpCode::Vararg => {
-                    let want = if instr.b() == 0 {
-                        varargs.len()
+                let newc = LuaClosure::with_upvalues(child, child_env, captured);
+                let h = state.global.heap.alloc_closure(Closure::Lua(newc));
+                set_reg(state, base + a, Value::gc(h));
+                state.check_gc()?;
+            }
+            OpCode::Vararg => {
+                // 可変長引数は base の直下 `stack[base - nvarargs..base]` にある。
+                let nvarargs = state.call_info[my_ci_index].nvarargs;
+                let want = if instr.b() == 0 {
+                    nvarargs
+                } else {
+                    instr.b() as usize - 1
+                };
+                for i in 0..want {
+                    let v = if i < nvarargs {
+                        state.stack[base - nvarargs + i]
                     } else {
-                        instr.b() as usize - 1
+                        Value::NIL
                     };
-                    for i in 0..want {
-                        set_reg(
-                            state,
-                            base + a + i,
-                            varargs.get(i).copied().unwrap_or(Value::Nil),
-                        );
-                    }
-                    if instr.b() == 0 {
-                        top = base + a + varargs.len();
-                    }
+                    set_reg(state, base + a + i, v);
+                }
+                if instr.b() == 0 {
+                    top = base + a + nvarargs;
                 }
             }
         }
@@ -830,13 +1390,13 @@ fn execute_inner(
 
 #[inline]
 fn reg(state: &LuaState, base: usize, i: usize) -> Value {
-    state.stack.get(base + i).copied().unwrap_or(Value::Nil)
+    state.stack.get(base + i).copied().unwrap_or(Value::NIL)
 }
 
 #[inline]
 fn set_reg(state: &mut LuaState, idx: usize, v: Value) {
     if idx >= state.stack.len() {
-        state.stack.resize(idx + 1, Value::Nil);
+        state.stack.resize(idx + 1, Value::NIL);
     }
     state.stack[idx] = v;
 }
@@ -852,46 +1412,59 @@ fn rk(state: &LuaState, proto: &Proto, base: usize, x: u32) -> Value {
 
 #[inline]
 fn num_at(state: &LuaState, base: usize, i: usize) -> f64 {
-    match reg(state, base, i) {
-        Value::Number(n) => n,
-        _ => f64::NAN,
-    }
+    reg(state, base, i).as_number().unwrap_or(f64::NAN)
 }
 
 fn upval_get(state: &LuaState, uv: &Upvalue) -> Value {
     match &*uv.borrow() {
-        UpvalueState::Open(idx) => state.stack.get(*idx).copied().unwrap_or(Value::Nil),
+        UpvalueState::Open(thread, idx) => state
+            .stack_of(*thread)
+            .and_then(|s| s.get(*idx))
+            .copied()
+            .unwrap_or(Value::NIL),
         UpvalueState::Closed(v) => *v,
     }
 }
 
 fn upval_set(state: &mut LuaState, uv: &Upvalue, v: Value) {
     let open_idx = match &*uv.borrow() {
-        UpvalueState::Open(idx) => Some(*idx),
+        UpvalueState::Open(thread, idx) => Some((*thread, *idx)),
         UpvalueState::Closed(_) => None,
     };
     match open_idx {
-        Some(idx) => set_reg(state, idx, v),
-        None => *uv.borrow_mut() = UpvalueState::Closed(v),
+        Some((thread, idx)) if thread == state.thread => set_reg(state, idx, v),
+        Some((thread, idx)) => state.set_stack_slot(thread, idx, v),
+        None => {
+            // closed upvalue のセルはヒープ外にあるため前進バリアを掛ける。
+            state.global.heap.barrier(v);
+            *uv.borrow_mut() = UpvalueState::Closed(v);
+        }
     }
 }
 
-fn find_or_create_upval(open: &mut Vec<(usize, Upvalue)>, abs: usize) -> Upvalue {
+fn find_or_create_upval(
+    open: &mut Vec<(usize, Upvalue)>,
+    thread: Option<ThreadKey>,
+    abs: usize,
+) -> Upvalue {
     for (idx, uv) in open.iter() {
         if *idx == abs {
             return uv.clone();
         }
     }
-    let uv = Rc::new(RefCell::new(UpvalueState::Open(abs)));
+    let uv = Rc::new(RefCell::new(UpvalueState::Open(thread, abs)));
     open.push((abs, uv.clone()));
     uv
 }
 
 /// `from_abs` 以上の絶対インデックスを指す open upvalue を閉じる。
-fn close_upvals(state: &LuaState, open: &mut Vec<(usize, Upvalue)>, from_abs: usize) {
+///
+/// 閉じた値はスタック（ルート）から外れるため、前進バリアを掛ける。
+fn close_upvals(state: &mut LuaState, open: &mut Vec<(usize, Upvalue)>, from_abs: usize) {
     open.retain(|(idx, uv)| {
         if *idx >= from_abs {
-            let v = state.stack.get(*idx).copied().unwrap_or(Value::Nil);
+            let v = state.stack.get(*idx).copied().unwrap_or(Value::NIL);
+            state.global.heap.barrier(v);
             *uv.borrow_mut() = UpvalueState::Closed(v);
             false
         } else {
@@ -900,20 +1473,36 @@ fn close_upvals(state: &LuaState, open: &mut Vec<(usize, Upvalue)>, from_abs: us
     });
 }
 
+/// `call_info[from_ci..]` のフレームが退避している open upvalue をすべて閉じる。
+///
+/// エラーで降ろすフレームと、終了したコルーチンのフレームに使う。値はスタックから読むので、
+/// スタックを切り詰める前に呼ぶこと。
+pub(crate) fn close_frame_upvals(state: &mut LuaState, from_ci: usize) {
+    for i in from_ci..state.call_info.len() {
+        let Some(frame) = state.call_info[i].lua_frame.as_mut() else {
+            continue;
+        };
+        let mut open = std::mem::take(&mut frame.open);
+        close_upvals(state, &mut open, 0);
+    }
+}
+
 // ============================================================================
 // 算術・比較・連結・長さ
 // ============================================================================
 
 /// 値を数値へ（number はそのまま、数値に見える文字列は変換）。
 fn tonum(state: &LuaState, v: Value) -> Option<f64> {
-    match v {
-        Value::Number(n) => Some(n),
-        Value::GcRef(GcHandle::Str(k)) => state
+    if let Some(n) = v.as_number() {
+        Some(n)
+    } else if let Some(k) = v.as_string() {
+        state
             .global
             .heap
             .get_str(k)
-            .and_then(|s| str_to_number(s.as_bytes())),
-        _ => None,
+            .and_then(|s| str_to_number(s.as_bytes()))
+    } else {
+        None
     }
 }
 
@@ -941,92 +1530,152 @@ fn arith_event(op: OpCode) -> &'static [u8] {
     }
 }
 
+/// 算術演算（数値同士は直接計算、それ以外は算術メタメソッド）。
+///
+/// `regs` は各オペランドのレジスタ番号（定数なら `None`）で、エラー時の変数名推定に使う。
 fn arith(
     state: &mut LuaState,
     op: OpCode,
-    b: Value,
-    c: Value,
+    (b, c): (Value, Value),
+    regs: (Option<usize>, Option<usize>),
     proto: &Proto,
     pc: usize,
 ) -> LuaResult<Value> {
     if let (Some(x), Some(y)) = (tonum(state, b), tonum(state, c)) {
-        return Ok(Value::Number(do_arith(op, x, y)));
+        return Ok(Value::number(do_arith(op, x, y)));
     }
     let event = arith_event(op);
     let mut mm = get_metamethod(state, b, event);
-    if matches!(mm, Value::Nil) {
+    if mm.is_nil() {
         mm = get_metamethod(state, c, event);
     }
-    if matches!(mm, Value::Nil) {
+    if mm.is_nil() {
         // 数値化できなかった側を報告する。
-        let culprit = if tonum(state, b).is_none() { b } else { c };
-        return Err(arith_err(state, proto, pc, culprit));
+        let (culprit, r) = if tonum(state, b).is_none() {
+            (b, regs.0)
+        } else {
+            (c, regs.1)
+        };
+        return Err(arith_err(state, proto, pc, culprit, r));
     }
-    Ok(first(call(state, mm, &[b, c])?))
+    Ok(first(call_yieldable(state, mm, &[b, c])?))
 }
 
-/// `..` の 2 値連結（string/number 同士は直結、それ以外は `__concat`）。
-fn concat_two(
+/// `CONCAT`: R(B)..R(i-1) と、R(i) 以降の連結結果 `acc` を右から連結する（本家 `luaV_concat`）。
+///
+/// 文字列か数値が続く区間は 1 つのバッファにまとめて連結し、文字列を 1 回だけ作る。
+/// それ以外の組は `__concat` で 2 つずつ連結する。途中結果は消費済みの R(i) へ書き戻し、
+/// `__concat` が Lua を呼び戻す間も GC ルートに載せておく。`i` は連結済みの左端で、
+/// yield したときの再開位置になる。
+fn concat_from(
     state: &mut LuaState,
-    a: Value,
-    b: Value,
+    base: usize,
+    (bb, i): (usize, &mut usize),
+    mut acc: Value,
     proto: &Proto,
     pc: usize,
 ) -> LuaResult<Value> {
-    if let (Some(mut x), Some(y)) = (stringable(state, a), stringable(state, b)) {
-        x.extend_from_slice(&y);
-        return Ok(Value::GcRef(state.global.heap.intern_str(&x)));
+    while *i > bb {
+        let left = reg(state, base, *i - 1);
+        if !(is_concatable(acc) && is_concatable(left)) {
+            *i -= 1;
+            acc = concat_two(state, (left, acc), *i, proto, pc)?;
+            set_reg(state, base + *i, acc);
+            continue;
+        }
+        let mut j = *i - 1;
+        while j > bb && is_concatable(reg(state, base, j - 1)) {
+            j -= 1;
+        }
+        let len = (j..*i)
+            .map(|r| reg(state, base, r))
+            .chain([acc])
+            .map(|v| concat_len(state, v))
+            .sum();
+        // 組み立てる前に上限を検査する（オペランドはすべてレジスタにある）。
+        state.reserve_memory(len)?;
+        let mut buf = Vec::with_capacity(len);
+        for r in j..*i {
+            append_concat(state, reg(state, base, r), &mut buf);
+        }
+        append_concat(state, acc, &mut buf);
+        acc = state.new_string_from(buf);
+        *i = j;
+        set_reg(state, base + j, acc);
     }
-    let mut mm = get_metamethod(state, a, b"__concat");
-    if matches!(mm, Value::Nil) {
-        mm = get_metamethod(state, b, b"__concat");
+    Ok(acc)
+}
+
+/// メタメソッド無しで連結できる値（文字列か数値）か。
+fn is_concatable(v: Value) -> bool {
+    v.is_number() || v.as_string().is_some()
+}
+
+/// 連結したときのバイト長の見積もり（数値は `%.14g` の最大長で見積もる）。
+fn concat_len(state: &LuaState, v: Value) -> usize {
+    match v.as_string().and_then(|k| state.global.heap.get_str(k)) {
+        Some(s) => s.len(),
+        None => 24,
     }
-    if matches!(mm, Value::Nil) {
-        let culprit = if stringable(state, a).is_none() { a } else { b };
-        return Err(concat_err(state, proto, pc, culprit));
+}
+
+/// 文字列か数値（`%.14g` 文字列化）を `buf` に追記する。
+fn append_concat(state: &LuaState, v: Value, buf: &mut Vec<u8>) {
+    if let Some(n) = v.as_number() {
+        buf.extend_from_slice(number_to_string(n).as_bytes());
+    } else if let Some(s) = v.as_string().and_then(|k| state.global.heap.get_str(k)) {
+        buf.extend_from_slice(s.as_bytes());
     }
-    Ok(first(call(state, mm, &[a, b])?))
 }
 
-/// 連結に使えるなら byte 列を返す（number は `%.14g` 文字列化）。
-fn stringable(state: &LuaState, v: Value) -> Option<Vec<u8>> {
-    match v {
-        Value::Number(n) => Some(number_to_string(n).into_bytes()),
-        Value::GcRef(GcHandle::Str(k)) => {
-            state.global.heap.get_str(k).map(|s| s.as_bytes().to_vec())
-        }
-        _ => None,
+/// 文字列・数値でない値を含む組 `a .. b` を `__concat` で連結する。
+fn concat_two(
+    state: &mut LuaState,
+    (a, b): (Value, Value),
+    reg_a: usize,
+    proto: &Proto,
+    pc: usize,
+) -> LuaResult<Value> {
+    let mut mm = get_metamethod(state, a, b"__concat");
+    if mm.is_nil() {
+        mm = get_metamethod(state, b, b"__concat");
+    }
+    if mm.is_nil() {
+        let (culprit, r) = if !is_concatable(a) {
+            (a, reg_a)
+        } else {
+            (b, reg_a + 1)
+        };
+        return Err(concat_err(state, proto, pc, culprit, r));
     }
+    Ok(first(call_yieldable(state, mm, &[a, b])?))
 }
 
-fn len_op(state: &mut LuaState, v: Value, proto: &Proto, pc: usize) -> LuaResult<Value> {
-    match v {
-        Value::GcRef(GcHandle::Str(k)) => {
-            let len = state.global.heap.get_str(k).map(|s| s.len()).unwrap_or(0);
-            Ok(Value::Number(len as f64))
-        }
-        Value::GcRef(GcHandle::Table(k)) => {
-            // Lua 5.1: テーブルの `#` はメタメソッドを参照せず border を返す。
-            let len = state
-                .global
-                .heap
-                .get_table(k)
-                .map(|t| t.length())
-                .unwrap_or(0);
-            Ok(Value::Number(len as f64))
-        }
-        _ => {
-            let mm = get_metamethod(state, v, b"__len");
-            if matches!(mm, Value::Nil) {
-                Err(err_at(
-                    state,
-                    proto,
-                    pc,
-                    format!("attempt to get length of a {} value", v.type_of().name()),
-                ))
-            } else {
-                Ok(first(call(state, mm, &[v])?))
-            }
+fn len_op(
+    state: &mut LuaState,
+    v: Value,
+    proto: &Proto,
+    pc: usize,
+    reg: usize,
+) -> LuaResult<Value> {
+    if let Some(k) = v.as_string() {
+        let len = state.global.heap.get_str(k).map(|s| s.len()).unwrap_or(0);
+        Ok(Value::number(len as f64))
+    } else if let Some(k) = v.as_table() {
+        // Lua 5.1: テーブルの `#` はメタメソッドを参照せず border を返す。
+        let len = state
+            .global
+            .heap
+            .get_table(k)
+            .map(|t| t.length())
+            .unwrap_or(0);
+        Ok(Value::number(len as f64))
+    } else {
+        let mm = get_metamethod(state, v, b"__len");
+        if mm.is_nil() {
+            Err(type_err_at(state, proto, pc, "get length of", v, Some(reg)))
+        } else {
+            Ok(first(call_yieldable(state, mm, &[v])?))
         }
     }
 }
@@ -1037,7 +1686,7 @@ fn len_op(state: &mut LuaState, v: Value, proto: &Proto, pc: usize) -> LuaResult
 /// 共有している場合のみ（本家 lvm.c `equalobj` 参照）。
 /// 片方のみにメタテーブルがある場合は `__eq` を呼ばない（raw 比較で false）。
 fn values_equal(state: &mut LuaState, a: Value, b: Value) -> LuaResult<bool> {
-    if a == b {
+    if state.global.heap.raw_equal(a, b) {
         return Ok(true);
     }
     // 型が違えば false（number と string も等しくない）。
@@ -1056,10 +1705,10 @@ fn values_equal(state: &mut LuaState, a: Value, b: Value) -> LuaResult<bool> {
     // 片方のみが __eq を持つ場合は false（raw 比較）。
     // Lua 5.1: 両者が同一の `__eq` を持つときのみ呼ぶ。
     // 片方のみ、または異なる `__eq` の場合は呼ばず raw 比較（false）。
-    if matches!(mm_a, Value::Nil) || mm_a != mm_b {
+    if mm_a.is_nil() || mm_a != mm_b {
         return Ok(false);
     }
-    Ok(first(call(state, mm_a, &[a, b])?).is_truthy())
+    Ok(first(call_yieldable(state, mm_a, &[a, b])?).is_truthy())
 }
 
 /// 比較メタメソッドを取得する。Lua 5.1 の規則:
@@ -1068,20 +1717,20 @@ fn values_equal(state: &mut LuaState, a: Value, b: Value) -> LuaResult<bool> {
 fn get_cmp_metamethod(state: &mut LuaState, a: Value, b: Value, event: &[u8]) -> Value {
     let mm_a = get_metamethod(state, a, event);
     let mm_b = get_metamethod(state, b, event);
-    match (matches!(mm_a, Value::Nil), matches!(mm_b, Value::Nil)) {
+    match (mm_a.is_nil(), mm_b.is_nil()) {
         (false, false) => {
             // 両方にある場合: 同じハンドラなら使う、違えば a 側を使う（本家準拠）。
             mm_a
         }
         (false, true) => {
             // a のみ: 片方だけなのでメタメソッドを使わない。
-            Value::Nil
+            Value::NIL
         }
         (true, false) => {
             // b のみ: 片方だけなのでメタメソッドを使わない。
-            Value::Nil
+            Value::NIL
         }
-        (true, true) => Value::Nil,
+        (true, true) => Value::NIL,
     }
 }
 
@@ -1092,46 +1741,41 @@ fn less_than(
     proto: &Proto,
     pc: usize,
 ) -> LuaResult<bool> {
-    if let (Value::Number(x), Value::Number(y)) = (a, b) {
+    if let (Some(x), Some(y)) = (a.as_number(), b.as_number()) {
         return Ok(x < y);
     }
-    if let (Value::GcRef(GcHandle::Str(ka)), Value::GcRef(GcHandle::Str(kb))) = (a, b) {
+    if let (Some(ka), Some(kb)) = (a.as_string(), b.as_string()) {
         let sa = state.global.heap.get_str(ka).unwrap().as_bytes();
         let sb = state.global.heap.get_str(kb).unwrap().as_bytes();
         return Ok(sa < sb);
     }
     let mm = get_cmp_metamethod(state, a, b, b"__lt");
-    if matches!(mm, Value::Nil) {
+    if mm.is_nil() {
         return Err(cmp_err(state, proto, pc, a, b));
     }
-    Ok(first(call(state, mm, &[a, b])?).is_truthy())
+    Ok(first(call_yieldable(state, mm, &[a, b])?).is_truthy())
 }
 
-fn less_equal(
-    state: &mut LuaState,
-    a: Value,
-    b: Value,
-    proto: &Proto,
-    pc: usize,
-) -> LuaResult<bool> {
-    if let (Value::Number(x), Value::Number(y)) = (a, b) {
-        return Ok(x <= y);
-    }
-    if let (Value::GcRef(GcHandle::Str(ka)), Value::GcRef(GcHandle::Str(kb))) = (a, b) {
-        let sa = state.global.heap.get_str(ka).unwrap().as_bytes();
-        let sb = state.global.heap.get_str(kb).unwrap().as_bytes();
-        return Ok(sa <= sb);
+/// 数値同士・文字列同士の `a <= b`（メタメソッドが要るなら `None`）。
+fn le_primitive(state: &LuaState, a: Value, b: Value) -> Option<bool> {
+    if let (Some(x), Some(y)) = (a.as_number(), b.as_number()) {
+        return Some(x <= y);
     }
+    let (ka, kb) = (a.as_string()?, b.as_string()?);
+    let sa = state.global.heap.get_str(ka)?.as_bytes();
+    let sb = state.global.heap.get_str(kb)?.as_bytes();
+    Some(sa <= sb)
+}
+
+/// `a <= b` に使うメタメソッド。`__le` が無ければ `__lt` を返し、2 つ目の値を true にする
+/// （呼び出し側が `not (b < a)` として使う）。どちらも無ければ `None`。
+fn le_metamethod(state: &mut LuaState, a: Value, b: Value) -> Option<(Value, bool)> {
     let mm = get_cmp_metamethod(state, a, b, b"__le");
-    if !matches!(mm, Value::Nil) {
-        return Ok(first(call(state, mm, &[a, b])?).is_truthy());
+    if !mm.is_nil() {
+        return Some((mm, false));
     }
-    // 本家 5.1: __le が無ければ `not (b < a)` を __lt で試す。
     let lt = get_cmp_metamethod(state, a, b, b"__lt");
-    if matches!(lt, Value::Nil) {
-        return Err(cmp_err(state, proto, pc, a, b));
-    }
-    Ok(!first(call(state, lt, &[b, a])?).is_truthy())
+    (!lt.is_nil()).then_some((lt, true))
 }
 
 // ============================================================================
@@ -1144,35 +1788,41 @@ fn index_get(
     key: Value,
     proto: &Proto,
     pc: usize,
+    mut reg: Option<usize>,
 ) -> LuaResult<Value> {
     for _ in 0..MAXTAGLOOP {
-        if let Value::GcRef(GcHandle::Table(k)) = t {
-            let raw = state
-                .global
-                .heap
-                .get_table(k)
-                .map(|tb| tb.get(&key))
-                .unwrap_or(Value::Nil);
-            if !matches!(raw, Value::Nil) {
+        if let Some(k) = t.as_table() {
+            let raw = match key.as_string() {
+                Some(s) => cached_raw_get(state, proto, pc, k, GcHandle::Str(s)),
+                None => state
+                    .global
+                    .heap
+                    .get_table(k)
+                    .map(|tb| tb.get(&key))
+                    .unwrap_or(Value::NIL),
+            };
+            if !raw.is_nil() {
                 return Ok(raw);
             }
             let mm = get_metamethod(state, t, b"__index");
-            if matches!(mm, Value::Nil) {
-                return Ok(Value::Nil);
+            if mm.is_nil() {
+                return Ok(Value::NIL);
             }
             if mm.type_of() == LuaType::Function {
-                return Ok(first(call(state, mm, &[t, key])?));
+                return Ok(first(call_yieldable(state, mm, &[t, key])?));
             }
             t = mm; // テーブル等: チェーンを継続
+            reg = None;
         } else {
             let mm = get_metamethod(state, t, b"__index");
-            if matches!(mm, Value::Nil) {
-                return Err(index_err(state, proto, pc, t));
+            if mm.is_nil() {
+                return Err(type_err_at(state, proto, pc, "index", t, reg));
             }
             if mm.type_of() == LuaType::Function {
-                return Ok(first(call(state, mm, &[t, key])?));
+                return Ok(first(call_yieldable(state, mm, &[t, key])?));
             }
             t = mm;
+            reg = None;
         }
     }
     Err(err_at(
@@ -1190,37 +1840,52 @@ fn index_set(
     val: Value,
     proto: &Proto,
     pc: usize,
+    mut reg: Option<usize>,
 ) -> LuaResult<()> {
     for _ in 0..MAXTAGLOOP {
-        if let Value::GcRef(GcHandle::Table(k)) = t {
-            let exists = state
-                .global
-                .heap
-                .get_table(k)
-                .map(|tb| !matches!(tb.get(&key), Value::Nil))
-                .unwrap_or(false);
+        if let Some(k) = t.as_table() {
+            let exists = match key.as_string().map(GcHandle::Str) {
+                Some(s) => {
+                    // 既存フィールドへの上書きはキャッシュしたスロットへ直接書く。
+                    let slot = proto.cached_slot(pc);
+                    if let Some(tb) = state.global.heap.get_table_mut(k)
+                        && tb.set_slot(slot, s, val)
+                    {
+                        return Ok(());
+                    }
+                    !cached_raw_get(state, proto, pc, k, s).is_nil()
+                }
+                None => state
+                    .global
+                    .heap
+                    .get_table(k)
+                    .map(|tb| !tb.get(&key).is_nil())
+                    .unwrap_or(false),
+            };
             if exists {
                 return raw_set(state, k, key, val, proto, pc);
             }
             let mm = get_metamethod(state, t, b"__newindex");
-            if matches!(mm, Value::Nil) {
+            if mm.is_nil() {
                 return raw_set(state, k, key, val, proto, pc);
             }
             if mm.type_of() == LuaType::Function {
-                call(state, mm, &[t, key, val])?;
+                call_yieldable(state, mm, &[t, key, val])?;
                 return Ok(());
             }
             t = mm;
+            reg = None;
         } else {
             let mm = get_metamethod(state, t, b"__newindex");
-            if matches!(mm, Value::Nil) {
-                return Err(index_err(state, proto, pc, t));
+            if mm.is_nil() {
+                return Err(type_err_at(state, proto, pc, "index", t, reg));
             }
             if mm.type_of() == LuaType::Function {
-                call(state, mm, &[t, key, val])?;
+                call_yieldable(state, mm, &[t, key, val])?;
                 return Ok(());
             }
             t = mm;
+            reg = None;
         }
     }
     Err(err_at(
@@ -1231,6 +1896,40 @@ fn index_set(
     ))
 }
 
+/// 文字列キー `s` での生の参照を、`pc` 番目の命令のインラインキャッシュ経由で行う。
+///
+/// キャッシュしたスロットが同じキーのエントリならハッシュを引かずに値を返す。外れたときは
+/// 通常の探索をしてスロットを記録し直す。スロットはテーブルごとではなくキーで照合するので、
+/// 再ハッシュやメタテーブルの変更があっても古いスロットが誤った値を返すことは無い。
+///
+/// テーブルのキーはインターン済みの文字列なので、見つからなかったときだけ、インターンしていない
+/// 長い文字列を同値のキーに置き換えて引き直す（[`Heap::table_key`](crate::gc::Heap::table_key)）。
+#[inline]
+fn cached_raw_get(
+    state: &LuaState,
+    proto: &Proto,
+    pc: usize,
+    t: crate::gc::TableKey,
+    s: GcHandle,
+) -> Value {
+    let Some(tb) = state.global.heap.get_table(t) else {
+        return Value::NIL;
+    };
+    if let Some(v) = tb.get_slot(proto.cached_slot(pc), s) {
+        return v;
+    }
+    match tb.find_slot(s) {
+        Some((slot, v)) => {
+            proto.cache_slot(pc, slot);
+            v
+        }
+        None => match state.global.heap.table_key(Value::gc(s)) {
+            canon if canon != Value::gc(s) => tb.get(&canon),
+            _ => Value::NIL,
+        },
+    }
+}
+
 fn raw_set(
     state: &mut LuaState,
     k: crate::gc::TableKey,
@@ -1239,6 +1938,8 @@ fn raw_set(
     proto: &Proto,
     pc: usize,
 ) -> LuaResult<()> {
+    reserve_insert(state, k, key, val)?;
+    let key = state.global.heap.intern_key(key);
     let res = state
         .global
         .heap
@@ -1256,28 +1957,50 @@ fn raw_set(
     }
 }
 
+/// テーブル `k` へ `key = val` を raw に代入する前に、伸長で増える分を確保できるか検査する。
+///
+/// キーと値はレジスタ（またはメタテーブル経由で到達できる位置）にあるので、緊急 GC してよい。
+pub(crate) fn reserve_insert(
+    state: &mut LuaState,
+    k: crate::gc::TableKey,
+    key: Value,
+    val: Value,
+) -> LuaResult<()> {
+    if val.is_nil() {
+        return Ok(());
+    }
+    match state.global.heap.table_insert_growth(k, key) {
+        0 => Ok(()),
+        growth => state.reserve_memory(growth),
+    }
+}
+
 // ============================================================================
 // メタテーブル
 // ============================================================================
 
 fn metatable_of(state: &LuaState, v: Value) -> Option<crate::gc::TableKey> {
-    let mt = match v {
-        Value::GcRef(GcHandle::Table(k)) => {
-            state.global.heap.get_table(k).and_then(|t| t.metatable())
-        }
-        Value::GcRef(GcHandle::Userdata(k)) => state
+    let mt = if let Some(k) = v.as_table() {
+        state.global.heap.get_table(k).and_then(|t| t.metatable())
+    } else if let Some(k) = v.as_userdata() {
+        state
             .global
             .heap
             .get_userdata(k)
-            .and_then(|u| u.metatable()),
+            .and_then(|u| u.metatable())
+    } else if v.as_string().is_some() {
         // 文字列は型ごとの共有メタテーブル（`global_State.string_metatable`）を参照する。
         // 本体の登録は lua-stdlib が string ライブラリ初期化時に行う。
-        Value::GcRef(GcHandle::Str(_)) => state.global.string_metatable,
+        state.global.string_metatable
+    } else if v.is_number() {
         // 数値・boolean・nil の型共有メタテーブル（debug.setmetatable で設定）。
-        Value::Number(_) => state.global.number_metatable,
-        Value::Boolean(_) => state.global.boolean_metatable,
-        Value::Nil => state.global.nil_metatable,
-        _ => None,
+        state.global.number_metatable
+    } else if v.as_boolean().is_some() {
+        state.global.boolean_metatable
+    } else if v.is_nil() {
+        state.global.nil_metatable
+    } else {
+        None
     };
     match mt {
         Some(GcHandle::Table(k)) => Some(k),
@@ -1288,15 +2011,15 @@ fn metatable_of(state: &LuaState, v: Value) -> Option<crate::gc::TableKey> {
 /// `v` のメタテーブルからイベント名のハンドラを取得（無ければ `Nil`）。
 fn get_metamethod(state: &mut LuaState, v: Value, event: &[u8]) -> Value {
     let Some(mtk) = metatable_of(state, v) else {
-        return Value::Nil;
+        return Value::NIL;
     };
-    let key = Value::GcRef(state.global.heap.intern_str(event));
+    let key = Value::gc(state.global.heap.intern_str(event));
     state
         .global
         .heap
         .get_table(mtk)
         .map(|t| t.get(&key))
-        .unwrap_or(Value::Nil)
+        .unwrap_or(Value::NIL)
 }
 
 // ============================================================================
@@ -1314,7 +2037,7 @@ fn num_for(
     let v = reg(state, base, i);
     match tonum(state, v) {
         Some(n) => {
-            set_reg(state, base + i, Value::Number(n));
+            set_reg(state, base + i, Value::number(n));
             Ok(n)
         }
         None => Err(err_at(
@@ -1333,7 +2056,7 @@ fn num_for(
 #[inline]
 fn first(mut results: Vec<Value>) -> Value {
     if results.is_empty() {
-        Value::Nil
+        Value::NIL
     } else {
         results.swap_remove(0)
     }
@@ -1349,6 +2072,12 @@ fn fb2int(x: u32) -> usize {
     }
 }
 
+/// `"stack overflow"`（本家 `luaD_growstack` の失敗）。実行中フレームの位置を前置する。
+fn stack_overflow(state: &mut LuaState) -> LuaError {
+    let msg = format!("{}stack overflow", where_string(state, 0));
+    rt_err(state, msg)
+}
+
 /// Lua 文字列値としての実行時エラーを作る（本家のエラーオブジェクトに一致）。
 fn rt_err(state: &mut LuaState, msg: String) -> LuaError {
     let v = state.new_string(msg.as_bytes());
@@ -1372,7 +2101,7 @@ fn err_at(state: &mut LuaState, proto: &Proto, pc: usize, msg: String) -> LuaErr
 /// - `@file`   → `file`（ファイル由来チャンク）
 /// - `=name`   → `name`（特殊な名前。コマンドライン等）
 /// - その他    → `[string "先頭行..."]`（`load`/`loadstring` の文字列チャンク）
-fn short_src(source: Option<&str>) -> String {
+pub(crate) fn short_src(source: Option<&str>) -> String {
     let Some(src) = source else {
         return "?".to_string();
     };
@@ -1399,34 +2128,59 @@ fn type_err(state: &mut LuaState, action: &str, v: Value) -> LuaError {
     )
 }
 
-fn arith_err(state: &mut LuaState, proto: &Proto, pc: usize, v: Value) -> LuaError {
-    err_at(
-        state,
-        proto,
-        pc,
-        format!(
-            "attempt to perform arithmetic on a {} value",
-            v.type_of().name()
-        ),
-    )
+/// 位置と変数の説明を付した型エラー（本家 `luaG_typeerror` 相当）。
+///
+/// `reg` は問題の値が置かれたレジスタ（定数や中間値なら `None`）。命令列から
+/// `global 'x'` / `local 'x'` / `field 'x'` / `upvalue 'x'` / `method 'x'` を推定できれば
+/// `attempt to index local 'x' (a nil value)` の形になる。
+fn type_err_at(
+    state: &mut LuaState,
+    proto: &Proto,
+    pc: usize,
+    action: &str,
+    v: Value,
+    reg: Option<usize>,
+) -> LuaError {
+    let t = v.type_of().name();
+    let name = reg.and_then(|r| crate::vm::debug::obj_name(&state.global.heap, proto, pc, r));
+    let msg = match name {
+        Some((kind, name)) => format!("attempt to {action} {kind} '{name}' (a {t} value)"),
+        None => format!("attempt to {action} a {t} value"),
+    };
+    err_at(state, proto, pc, msg)
 }
 
-fn concat_err(state: &mut LuaState, proto: &Proto, pc: usize, v: Value) -> LuaError {
-    err_at(
-        state,
-        proto,
-        pc,
-        format!("attempt to concatenate a {} value", v.type_of().name()),
-    )
+fn arith_err(
+    state: &mut LuaState,
+    proto: &Proto,
+    pc: usize,
+    v: Value,
+    reg: Option<usize>,
+) -> LuaError {
+    type_err_at(state, proto, pc, "perform arithmetic on", v, reg)
 }
 
-fn index_err(state: &mut LuaState, proto: &Proto, pc: usize, v: Value) -> LuaError {
-    err_at(
-        state,
-        proto,
-        pc,
-        format!("attempt to index a {} value", v.type_of().name()),
-    )
+fn concat_err(state: &mut LuaState, proto: &Proto, pc: usize, v: Value, reg: usize) -> LuaError {
+    type_err_at(state, proto, pc, "concatenate", v, Some(reg))
+}
+
+/// CALL/TAILCALL の呼び先が呼び出し可能か検査する（関数でも `__call` 持ちでもなければエラー）。
+fn check_callable(
+    state: &mut LuaState,
+    proto: &Proto,
+    pc: usize,
+    func: Value,
+    reg: usize,
+) -> LuaResult<()> {
+    if func.as_closure().is_some() || !get_metamethod(state, func, b"__call").is_nil() {
+        return Ok(());
+    }
+    Err(type_err_at(state, proto, pc, "call", func, Some(reg)))
+}
+
+/// RK オペランドがレジスタを指していればその番号を返す。
+fn rk_reg(x: u32) -> Option<usize> {
+    (!opcode::is_k(x)).then_some(x as usize)
 }
 
 fn cmp_err(state: &mut LuaState, proto: &Proto, pc: usize, a: Value, b: Value) -> LuaError {
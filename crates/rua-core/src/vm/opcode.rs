@@ -139,7 +139,7 @@ pub enum OpCode {
     Len,
     /// `R(A) := R(B).. ... ..R(C)`
     Concat,
-    /// `pc += sBx`
+    /// `pc += sBx; if (A) close all upvalues >= R(A-1)`（A は Lua 5.2 の goto のみが使う）
     Jmp,
     /// `if ((RK(B) == RK(C)) ~= A) then pc++`
     Eq,
@@ -195,6 +195,29 @@ impl OpCode {
         OP_MODES[self as usize]
     }
 
+    /// この命令が `R(A)` に書き込むか（本家 `testAMode`）。
+    ///
+    /// 複数レジスタに書く `LOADNIL`/`CALL`/`TFORLOOP` 等は先頭の `R(A)` だけを表す
+    /// （範囲は呼び出し側で扱う）。
+    pub fn sets_a(self) -> bool {
+        !matches!(
+            self,
+            OpCode::SetGlobal
+                | OpCode::SetUpval
+                | OpCode::SetTable
+                | OpCode::Jmp
+                | OpCode::Eq
+                | OpCode::Lt
+                | OpCode::Le
+                | OpCode::Test
+                | OpCode::TailCall
+                | OpCode::Return
+                | OpCode::TForLoop
+                | OpCode::SetList
+                | OpCode::Close
+        )
+    }
+
     /// 命令のニーモニック名（デバッグ/ダンプ用, `luac -l` 風）。
     pub fn name(self) -> &'static str {
         OP_NAMES[self as usize]
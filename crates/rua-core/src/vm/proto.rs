@@ -6,7 +6,7 @@
 //!
 //! # GC との関係
 //! `Proto` は GC アリーナではなく [`Rc`] で共有する（不変・循環なし）。ただし
-//! [`Proto::constants`] にはインターン済み Lua 文字列（[`Value::GcRef`]）が含まれうるため、
+//! [`Proto::constants`] にはインターン済み Lua 文字列（[`Value::gc`]）が含まれうるため、
 //! Proto を保持するクロージャの [`Trace`](crate::gc::Trace) 実装が
 //! [`Proto::trace_constants`] を呼んで定数表を mark する（さもないと誤回収される）。
 //!
@@ -15,9 +15,10 @@
 //! （`MOVE B` = 親レジスタ R(B) を捕捉 / `GETUPVAL B` = 親 upvalue[B] を捕捉）で
 //! upvalue を束ねる（本家 Lua 5.1 と同一方式）。
 
+use std::cell::{Cell, OnceCell};
 use std::rc::Rc;
 
-use crate::gc::Tracer;
+use crate::gc::{HeapSize, Tracer};
 use crate::value::Value;
 
 use super::opcode::Instruction;
@@ -62,6 +63,17 @@ pub struct Proto {
     pub upvalue_names: Vec<String>,
     /// ローカル変数情報（デバッグ用）。
     pub local_vars: Vec<LocalVar>,
+    /// 命令ごとのインラインキャッシュ（実行時に VM が書き込む。コンパイル結果には含まれない）。
+    pub inline_cache: InlineCache,
+}
+
+/// 定数文字列キーでテーブルを参照する命令（`GETTABLE`/`SETTABLE`/`GETGLOBAL`/`SETGLOBAL`/`SELF`）が
+/// 前回ヒットしたハッシュ部のスロット（[`Table::get_slot`](crate::value::table::Table::get_slot)）。
+///
+/// 初回の参照時に命令数分を確保する。スロットはキーの照合付きで使うので、値が古くても誤りにはならない。
+#[derive(Debug, Default)]
+pub struct InlineCache {
+    slots: OnceCell<Box<[Cell<u32>]>>,
 }
 
 impl Proto {
@@ -69,11 +81,50 @@ impl Proto {
         Proto::default()
     }
 
+    /// `pc` 番目の命令のインラインキャッシュのスロット（未設定なら `usize::MAX`）。
+    #[inline]
+    pub fn cached_slot(&self, pc: usize) -> usize {
+        self.cache_cells()
+            .get(pc)
+            .map_or(usize::MAX, |c| c.get() as usize)
+    }
+
+    /// `pc` 番目の命令のインラインキャッシュへスロットを記録する。
+    #[inline]
+    pub fn cache_slot(&self, pc: usize, slot: usize) {
+        if let (Some(c), Ok(slot)) = (self.cache_cells().get(pc), u32::try_from(slot)) {
+            c.set(slot);
+        }
+    }
+
+    fn cache_cells(&self) -> &[Cell<u32>] {
+        self.inline_cache
+            .slots
+            .get_or_init(|| vec![Cell::new(u32::MAX); self.code.len()].into_boxed_slice())
+    }
+
     /// `pc` 番目の命令に対応するソース行を返す（無ければ 0）。
     pub fn line_at(&self, pc: usize) -> u32 {
         self.line_info.get(pc).copied().unwrap_or(0)
     }
 
+    /// `pc` 番目の命令の時点で有効な `n` 番目（1 始まり）のローカル変数名
+    /// （本家 `luaF_getlocalname`）。有効なローカルは登録順にレジスタ `R(0)`, `R(1)`, … に対応する。
+    pub fn local_name(&self, mut n: usize, pc: usize) -> Option<&str> {
+        for lv in &self.local_vars {
+            if lv.start_pc as usize > pc {
+                break;
+            }
+            if pc < lv.end_pc as usize {
+                n = n.checked_sub(1)?;
+                if n == 0 {
+                    return Some(&lv.name);
+                }
+            }
+        }
+        None
+    }
+
     /// 定数表と子 proto の定数を再帰的に mark する。
     ///
     /// このメソッドはクロージャの [`Trace`](crate::gc::Trace) 実装から呼ばれ、
@@ -87,3 +138,28 @@ impl Proto {
         }
     }
 }
+
+impl HeapSize for Proto {
+    /// proto 本体（子 proto を除く）のおおよそのバイト数（本家 `luaF_freeproto` が解放する量）。
+    fn heap_size(&self) -> usize {
+        let strings: usize = self
+            .upvalue_names
+            .iter()
+            .map(|n| n.capacity())
+            .sum::<usize>()
+            + self
+                .local_vars
+                .iter()
+                .map(|v| v.name.capacity())
+                .sum::<usize>()
+            + self.source.as_ref().map_or(0, |s| s.capacity());
+        std::mem::size_of::<Proto>()
+            + self.code.capacity() * std::mem::size_of::<Instruction>()
+            + self.constants.capacity() * std::mem::size_of::<Value>()
+            + self.protos.capacity() * std::mem::size_of::<Rc<Proto>>()
+            + self.line_info.capacity() * std::mem::size_of::<u32>()
+            + self.upvalue_names.capacity() * std::mem::size_of::<String>()
+            + self.local_vars.capacity() * std::mem::size_of::<LocalVar>()
+            + strings
+    }
+}
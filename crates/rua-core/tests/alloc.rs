@@ -0,0 +1,71 @@
+//! 呼び出し規約のヒープ割り当てテスト。
+//!
+//! 引数・戻り値はスタック上で受け渡し、upvalue はクロージャと共有するため、可変長引数を
+//! 持たない Lua 関数の呼び出しはヒープ割り当てを行わない。割り当て回数を数える
+//! グローバルアロケータで、呼び出し回数を変えても割り当て回数が変わらないことを確かめる。
+//!
+//! アロケータはプロセス全体で共有するため、このファイルのテストは 1 つに保つこと。
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rua_core::api::{Function, Lua};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// `n` を設定して `chunk` を実行し、その間の割り当て回数を返す。
+///
+/// チャンクは事前にコンパイルしておき、コンパイルや API ハンドルのアンカー登録を数えない。
+fn allocations_for(lua: &mut Lua, chunk: &Function, n: f64) -> usize {
+    lua.set_global("n", n).unwrap();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let () = lua.call(chunk.clone(), ()).unwrap();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn lua_calls_without_varargs_do_not_allocate() {
+    let mut lua = Lua::new();
+    lua.load(
+        r#"
+        local function fib(k) if k < 2 then return k end return fib(k - 1) + fib(k - 2) end
+        local up = 0
+        local function add(a, b, c) up = up + 1; return a + b, c end
+        local function tail(k) if k == 0 then return up end return tail(k - 1) end
+        function run(n)
+          local s = 0
+          for i = 1, n do s = s + add(i, 1) end
+          return fib(18) + s + tail(n) + math.floor(n)
+        end
+        "#,
+    )
+    .exec()
+    .unwrap();
+    let chunk = lua.load("result = run(n)").into_function().unwrap();
+    // スタック・コールスタックの伸長を済ませておく。
+    allocations_for(&mut lua, &chunk, 100_000.0);
+    let few = allocations_for(&mut lua, &chunk, 10.0);
+    let many = allocations_for(&mut lua, &chunk, 100_000.0);
+    assert_eq!(few, many, "allocations grow with the number of calls");
+}
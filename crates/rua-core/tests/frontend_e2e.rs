@@ -18,10 +18,8 @@ fn run_src(state: &mut LuaState, src: &str) -> Vec<Value> {
 }
 
 fn num(v: &Value) -> f64 {
-    match v {
-        Value::Number(n) => *n,
-        other => panic!("expected number, got {other:?}"),
-    }
+    v.as_number()
+        .unwrap_or_else(|| panic!("expected number, got {v:?}"))
 }
 
 #[test]
@@ -228,9 +226,9 @@ fn hash_table_constructor_string_key_string_val_overflow() {
     };
     let r = run_src(&mut state, &src);
     // 戻り値は文字列なので GcRef になる。nil でないことだけ確認する。
-    assert!(!matches!(r[0], Value::Nil));
-    assert!(!matches!(r[1], Value::Nil));
-    assert!(!matches!(r[2], Value::Nil));
+    assert!(!r[0].is_nil());
+    assert!(!r[1].is_nil());
+    assert!(!r[2].is_nil());
 }
 
 /// 大量のハッシュフィールド（500 個）でもパニックしないことを確認する。
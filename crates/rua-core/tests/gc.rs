@@ -17,6 +17,28 @@ fn intern_returns_same_handle_for_equal_bytes() {
     assert_eq!(heap.live_object_count(), 2);
 }
 
+#[test]
+fn long_strings_are_interned_only_as_table_keys() {
+    let mut heap = Heap::new();
+    let bytes = [b'x'; 64];
+    let a = Value::gc(heap.alloc_str(&bytes));
+    let b = Value::gc(heap.alloc_str(&bytes));
+    assert_ne!(a, b, "長い文字列は生成時にインターンしない");
+    assert!(heap.raw_equal(a, b));
+    assert_eq!(heap.table_key(b), b, "正規ハンドルが無ければそのまま");
+
+    // キーに使った時点で a が正規ハンドルになり、b の参照はそれへ寄せられる。
+    assert_eq!(heap.intern_key(a), a);
+    assert_eq!(heap.intern_key(b), a);
+    assert_eq!(heap.table_key(b), a);
+
+    // b だけ回収しても a のインターナ登録は残る。
+    heap.collect([a.as_gc().unwrap()]);
+    assert_eq!(heap.live_object_count(), 1);
+    let c = Value::gc(heap.alloc_str(&bytes));
+    assert_eq!(heap.table_key(c), a);
+}
+
 #[test]
 fn sweep_collects_unreachable_objects() {
     let mut heap = Heap::new();
@@ -38,7 +60,7 @@ fn reachable_through_table_survives() {
     let mut heap = Heap::new();
     let s = heap.intern_str(b"value");
     let mut t = Table::new();
-    t.array_mut().push(Value::GcRef(s));
+    t.array_mut().push(Value::gc(s));
     let table = heap.alloc_table(t);
 
     // ルート = table のみ。s は table 経由で到達可能なので生存すべき。
@@ -57,11 +79,11 @@ fn cycles_are_collected() {
     heap.get_table_mut(k1)
         .unwrap()
         .array_mut()
-        .push(Value::GcRef(t2));
+        .push(Value::gc(t2));
     heap.get_table_mut(k2)
         .unwrap()
         .array_mut()
-        .push(Value::GcRef(t1));
+        .push(Value::gc(t1));
     assert_eq!(heap.live_object_count(), 2);
 
     // ルート空 → 循環していても両方回収される（参照カウントでは漏れるケース）。
@@ -77,4 +99,945 @@ fn stale_handle_access_is_safe() {
     heap.collect(std::iter::empty()); // s を回収
     // 解放済みハンドルでの get は panic せず None（世代不一致 / 空スロット）。
     assert!(heap.get_str(k).is_none());
+
+    // 空いたスロットを再利用しても世代が進むので、古いハンドルは新しい文字列を指さない。
+    let t = heap.intern_str(b"reused");
+    let GcHandle::Str(k2) = t else { panic!() };
+    assert_ne!(k, k2);
+    assert!(heap.get_str(k).is_none());
+    assert_eq!(heap.get_str(k2).unwrap().as_bytes(), b"reused");
+}
+
+#[test]
+fn value_is_nan_boxed_into_8_bytes() {
+    assert_eq!(std::mem::size_of::<Value>(), 8);
+
+    let mut heap = Heap::new();
+    let s = heap.intern_str(b"key");
+    let t = heap.alloc_table(Table::new());
+    let vs = Value::gc(s);
+    let vt = Value::gc(t);
+    assert_eq!(vs.as_gc(), Some(s));
+    assert_eq!(vt.as_gc(), Some(t));
+    assert!(vs.as_table().is_none() && vt.as_string().is_none());
+
+    // 数値は NaN・無限大・負のゼロも含めてそのまま往復し、他の型と取り違えない。
+    for n in [0.0, -0.0, 1.5, f64::INFINITY, f64::NEG_INFINITY, f64::MAX] {
+        let v = Value::number(n);
+        assert_eq!(v.as_number().map(f64::to_bits), Some(n.to_bits()));
+        assert!(v.as_gc().is_none());
+    }
+    let nan = Value::number(-f64::NAN);
+    assert!(nan.as_number().unwrap().is_nan());
+    assert!(nan.as_gc().is_none() && !nan.is_nil());
+    assert_ne!(nan, nan, "raw 等価でも NaN ~= NaN");
+    assert_eq!(Value::number(0.0), Value::number(-0.0));
+
+    let p = 0x7fff_1234_5678usize as *mut std::os::raw::c_void;
+    assert_eq!(Value::light_userdata(p).as_light_userdata(), Some(p));
+    assert!(!Value::FALSE.is_truthy() && !Value::NIL.is_truthy());
+    assert!(Value::number(0.0).is_truthy());
+}
+
+// ============================================================================
+// 自動起動（VM のセーフポイントから）
+// ============================================================================
+
+#[test]
+fn vm_allocation_loop_triggers_collection() {
+    let mut lua = rua_core::api::Lua::new();
+    lua.load(
+        "keep = {}
+         for i = 1, 20000 do
+           local t = { i }
+           if i % 1000 == 0 then keep[#keep + 1] = t end
+         end",
+    )
+    .exec()
+    .unwrap();
+    // 2 万個のテーブルを確保しても、自動 GC により生存数は閾値程度に抑えられる。
+    assert!(lua.state().global.heap.live_object_count() < 20000);
+    // 保持したテーブルは回収されていない。
+    let n: f64 = lua
+        .load("local s = 0 for _, t in ipairs(keep) do s = s + t[1] end return s")
+        .eval()
+        .unwrap();
+    assert_eq!(n, (1..=20).map(|i| (i * 1000) as f64).sum::<f64>());
+}
+
+#[test]
+fn collectgarbage_stop_and_restart() {
+    let mut lua = rua_core::api::Lua::new();
+    lua.load("collectgarbage('stop') for i = 1, 5000 do local t = {} end")
+        .exec()
+        .unwrap();
+    let stopped = lua.state().global.heap.live_object_count();
+    assert!(stopped >= 5000, "停止中は自動回収されない");
+    lua.load("collectgarbage('restart') collectgarbage('collect')")
+        .exec()
+        .unwrap();
+    assert!(lua.state().global.heap.live_object_count() < stopped);
+}
+
+#[test]
+fn dropped_api_handles_are_released() {
+    let mut lua = rua_core::api::Lua::new();
+    let kept = lua.create_table();
+    lua.load("collectgarbage()").exec().unwrap();
+    let base = lua.state().global.heap.live_object_count();
+
+    // Rust 側で作って捨てたハンドルはアンカーから外れ、回収される。
+    for _ in 0..5000 {
+        let t = lua.create_table();
+        let _copy = t.clone();
+    }
+    lua.load("collectgarbage()").exec().unwrap();
+    assert!(lua.state().global.heap.live_object_count() <= base + 1);
+
+    // 保持中のハンドルは回収されない。
+    lua.set(&kept, "x", 1.0).unwrap();
+    let x: f64 = lua.get(&kept, "x").unwrap();
+    assert_eq!(x, 1.0);
+}
+
+// ============================================================================
+// インクリメンタル GC とライトバリア
+// ============================================================================
+
+#[test]
+fn incremental_step_completes_cycle() {
+    use rua_core::gc::GcPhase;
+    use rua_core::gc::alloc::GcConfig;
+
+    let mut heap = Heap::new();
+    let root = heap.alloc_table(Table::new());
+    for _ in 0..100 {
+        heap.alloc_table(Table::new());
+    }
+    let config = GcConfig {
+        stepmul: 10, // 1 ステップ 6 オブジェクト
+        ..GcConfig::default()
+    };
+    let mut steps = 0;
+    while !heap.step(&config, vec![root]) {
+        steps += 1;
+    }
+    assert!(steps > 1, "小さな stepmul では複数ステップに分かれるべき");
+    assert_eq!(heap.phase(), GcPhase::Pause);
+    assert_eq!(heap.live_object_count(), 1);
+}
+
+#[test]
+fn string_interned_during_sweep_is_not_floating_garbage() {
+    use rua_core::gc::GcPhase;
+    use rua_core::gc::alloc::GcConfig;
+
+    let mut heap = Heap::new();
+    let root = heap.alloc_table(Table::new());
+    for _ in 0..100 {
+        heap.alloc_table(Table::new());
+    }
+    let config = GcConfig {
+        stepmul: 10,
+        ..GcConfig::default()
+    };
+    while heap.phase() != GcPhase::Sweep {
+        heap.step(&config, vec![root]);
+    }
+    // sweep 中に確保した文字列をインターナから引き直しても黒くはしない。
+    let late = heap.intern_str(b"late");
+    assert_eq!(heap.intern_str(b"late"), late);
+    while !heap.step(&config, vec![root]) {}
+    assert_eq!(heap.live_object_count(), 2, "このサイクルでは解放しない");
+
+    // 次のサイクルでは到達不能なので回収される。
+    heap.collect([root]);
+    assert_eq!(heap.live_object_count(), 1);
+}
+
+#[test]
+fn barrier_keeps_value_stored_into_black_table() {
+    use rua_core::gc::GcPhase;
+    use rua_core::gc::alloc::GcConfig;
+
+    let mut heap = Heap::new();
+    let root = heap.alloc_table(Table::new());
+    let GcHandle::Table(rk) = root else { panic!() };
+    let config = GcConfig {
+        stepmul: 2, // 1 ステップ 1 オブジェクト
+        ..GcConfig::default()
+    };
+    // root -> a -> b の鎖を作り、root だけ走査済み（黒）・a は灰の状態まで進める。
+    let b = heap.alloc_table(Table::new());
+    let mut a = Table::new();
+    a.array_mut().push(Value::gc(b));
+    let a = heap.alloc_table(a);
+    heap.get_table_mut(rk)
+        .unwrap()
+        .array_mut()
+        .push(Value::gc(a));
+    heap.step(&config, vec![root]); // ルートを灰に
+    heap.step(&config, vec![root]); // root を黒に
+    assert_eq!(heap.phase(), GcPhase::Propagate);
+
+    // サイクル途中で白い新オブジェクトを黒いテーブルへ格納する。
+    let late = heap.alloc_table(Table::new());
+    heap.get_table_mut(rk)
+        .unwrap()
+        .array_mut()
+        .push(Value::gc(late));
+    while !heap.step(&config, vec![root]) {}
+
+    let GcHandle::Table(lk) = late else { panic!() };
+    assert!(heap.get_table(lk).is_some(), "後退バリアにより回収されない");
+}
+
+#[test]
+fn collectgarbage_tuning_options() {
+    let mut lua = rua_core::api::Lua::new();
+    let (pause, old_pause, stepmul): (f64, f64, f64) = lua
+        .load(
+            "local p = collectgarbage('setpause', 150)
+             local q = collectgarbage('setpause', 200)
+             return p, q, collectgarbage('setstepmul', 400)",
+        )
+        .call(())
+        .unwrap();
+    assert_eq!((pause, old_pause, stepmul), (200.0, 150.0, 200.0));
+    let done: bool = lua
+        .load(
+            "for i = 1, 3000 do local t = {} end
+             for i = 1, 1000 do if collectgarbage('step') then return true end end
+             return false",
+        )
+        .eval()
+        .unwrap();
+    assert!(done, "step を繰り返せばサイクルが完了する");
+}
+
+// ============================================================================
+// weak table（__mode）
+// ============================================================================
+
+/// Lua スクリプトを実行し、最後の `return` 値（数値）を返す。
+fn eval_num(src: &str) -> f64 {
+    let mut lua = rua_core::api::Lua::new();
+    lua.load(src).eval().unwrap()
+}
+
+#[test]
+fn weak_keys_drop_collectable_keys() {
+    let n = eval_num(
+        "local lim = 50
+         local a = setmetatable({}, {__mode = 'k'})
+         for i = 1, lim do a[{}] = i end
+         for i = 1, lim do a[i] = i end
+         for i = 1, lim do local s = string.rep('@', i); a[s] = s .. '#' end
+         collectgarbage()
+         local n = 0
+         for k, v in pairs(a) do assert(k == v or k .. '#' == v); n = n + 1 end
+         return n",
+    );
+    assert_eq!(n, 100.0);
+}
+
+#[test]
+fn weak_values_drop_collectable_values() {
+    let n = eval_num(
+        "local a = setmetatable({}, {__mode = 'v'})
+         local keep = {}
+         a[1] = keep
+         -- 関数内で確保し、呼び出し元のレジスタに一時値を残さない。
+         local function fill() for i = 2, 20 do a[i] = {} end end
+         fill()
+         a.s = 'string values stay'
+         a.n = 42
+         collectgarbage()
+         local n = 0
+         for k, v in pairs(a) do n = n + 1 end
+         assert(a[1] == keep and a.s and a.n == 42)
+         return n",
+    );
+    assert_eq!(n, 3.0);
+}
+
+#[test]
+fn weak_kv_drops_both_sides() {
+    let n = eval_num(
+        "local a = setmetatable({}, {__mode = 'kv'})
+         local k, v = {}, {}
+         a[k] = {}
+         a[{}] = v
+         a[k] = nil
+         a[k] = v
+         a[{}] = {}
+         collectgarbage()
+         local n = 0
+         for _ in pairs(a) do n = n + 1 end
+         assert(a[k] == v)
+         return n",
+    );
+    assert_eq!(n, 1.0);
+}
+
+#[test]
+fn ephemeron_value_referring_to_own_key_is_collected() {
+    let n = eval_num(
+        "local a = setmetatable({}, {__mode = 'k'})
+         -- 値がキーだけを参照する循環（弱キーでも強参照の値経由で漏れないこと）。
+         for i = 1, 30 do local t = {}; a[t] = t end
+         for i = 1, 30 do local t = {}; a[t] = {t} end
+         -- キー同士の連鎖: 生きているキーから値経由で次のキーへ到達できる。
+         local head = {}
+         local k = head
+         for i = 1, 10 do local nxt = {}; a[k] = nxt; k = nxt end
+         collectgarbage()
+         local n = 0
+         for _ in pairs(a) do n = n + 1 end
+         return n",
+    );
+    assert_eq!(n, 10.0);
+}
+
+// 公式スイート gc.lua の weak table 節（5.1）と ephemeron 節（5.2）からの移植。
+// スイート本体はリポジトリに含めないため、該当ケースをここで回帰テストにする。
+
+/// `src` を既定（インクリメンタル）と世代別の両モードで実行する。
+fn run_gc_lua(src: &str) {
+    for mode in ["incremental", "generational"] {
+        let mut lua = rua_core::api::Lua::new();
+        lua.load(format!("collectgarbage('{mode}')\n{src}"))
+            .set_name("=gc.lua")
+            .exec()
+            .unwrap_or_else(|e| panic!("{mode}: {e}"));
+    }
+}
+
+#[test]
+fn gc_lua_weak_keys() {
+    run_gc_lua(
+        "local lim = 15
+         a = {}; setmetatable(a, {__mode = 'k'});
+         for i=1,lim do a[{}] = i end
+         for i=1,lim do a[i] = i end
+         for i=1,lim do local s=string.rep('@', i); a[s] = s..'#' end
+         collectgarbage()
+         local i = 0
+         for k,v in pairs(a) do assert(k==v or k..'#'==v); i=i+1 end
+         assert(i == 2*lim)",
+    );
+}
+
+#[test]
+fn gc_lua_weak_values() {
+    run_gc_lua(
+        "local lim = 15
+         a = {}; setmetatable(a, {__mode = 'v'});
+         a[1] = string.rep('b', 21)
+         collectgarbage()
+         assert(a[1])   -- strings are *values*
+         a[1] = nil
+         for i=1,lim do a[i] = {} end
+         for i=1,lim do a[i..'x'] = {} end
+         for i=1,lim do local t={}; a[t]=t end
+         for i=1,lim do a[i+lim]=i..'x' end
+         collectgarbage()
+         local i = 0
+         for k,v in pairs(a) do assert(k==v or k-lim..'x' == v); i=i+1 end
+         assert(i == 2*lim)",
+    );
+}
+
+#[test]
+fn gc_lua_weak_keys_and_values() {
+    run_gc_lua(
+        "local lim = 15
+         a = {}; setmetatable(a, {__mode = 'vk'});
+         local x, y, z = {}, {}, {}
+         a[1], a[2], a[3] = x, y, z
+         a[string.rep('$', 11)] = string.rep('$', 11)
+         for i=4,lim do a[i] = {} end
+         for i=1,lim do a[{}] = i end
+         for i=1,lim do local t={}; a[t]=t end
+         collectgarbage()
+         assert(next(a) ~= nil)
+         local i = 0
+         for k,v in pairs(a) do
+           assert((k == 1 and v == x) or
+                  (k == 2 and v == y) or
+                  (k == 3 and v == z) or k==v);
+           i = i+1
+         end
+         assert(i == 4)
+         x,y,z=nil
+         collectgarbage()
+         assert(next(a) == string.rep('$', 11))",
+    );
+}
+
+#[test]
+fn gc_lua_ephemerons() {
+    run_gc_lua(
+        "local mt = {__mode = 'k'}
+         a = {{10},{20},{30},{40}}; setmetatable(a, mt)
+         x = nil
+         for i = 1, 100 do local n = {}; a[n] = {k = {x}}; x = n end
+         collectgarbage()
+         local n = x
+         local i = 0
+         while n do n = a[n].k[1]; i = i + 1 end
+         assert(i == 100)
+         x = nil
+         collectgarbage()
+         for i = 1, 4 do assert(a[i][1] == i * 10); a[i] = nil end
+         assert(next(a) == nil)
+
+         local K = {}
+         a[K] = {}
+         for i=1,10 do a[K][i] = {}; a[a[K][i]] = setmetatable({}, mt) end
+         x = nil
+         for k,v in pairs(a) do x = v end
+         collectgarbage()
+         local i = 0
+         for k,v in pairs(a) do i = i + 1 end
+         assert(i == 11)
+         K = nil
+         x = nil
+         collectgarbage()
+         assert(next(a) == nil)",
+    );
+}
+
+#[test]
+fn weak_table_survives_incremental_steps() {
+    let n = eval_num(
+        "collectgarbage('setstepmul', 10)
+         local a = setmetatable({}, {__mode = 'k'})
+         local keep = {}
+         for i = 1, 200 do
+           local key = {}
+           a[key] = i
+           if i % 10 == 0 then keep[#keep + 1] = key end
+           for j = 1, 20 do local garbage = {} end
+         end
+         while not collectgarbage('step') do end
+         while not collectgarbage('step') do end
+         local n = 0
+         for k, v in pairs(a) do n = n + 1 end
+         for _, k in ipairs(keep) do assert(a[k]) end
+         return n",
+    );
+    assert_eq!(n, 20.0);
+}
+
+// ============================================================================
+// __gc finalizer
+// ============================================================================
+
+#[test]
+fn finalizer_runs_on_collect_in_reverse_creation_order() {
+    let mut lua = rua_core::api::Lua::new();
+    let order: String = lua
+        .load(
+            "log = {}
+             local function make(id)
+               local u = newproxy(true)
+               getmetatable(u).__gc = function() log[#log + 1] = id end
+             end
+             make('a') make('b') make('c')
+             collectgarbage()
+             return table.concat(log, ',')",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(order, "c,b,a");
+}
+
+#[test]
+fn finalizer_order_follows_metatable_registration() {
+    let mut lua = rua_core::api::Lua::new();
+    let order: String = lua
+        .load(
+            "log = {}
+             local function make(id)
+               return newproxy(false), {__gc = function() log[#log + 1] = id end}
+             end
+             local a, ma = make('a')
+             local b, mb = make('b')
+             -- b を先に登録する: 確保順ではなく登録の逆順（a, b）で呼ばれる。
+             debug.setmetatable(b, mb)
+             debug.setmetatable(a, ma)
+             a, b = nil, nil
+             collectgarbage()
+             return table.concat(log, ',')",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(order, "a,b");
+}
+
+#[test]
+fn finalizer_resurrects_object_once() {
+    let n = eval_num(
+        "local calls = 0
+         local function make()
+           local u = newproxy(true)
+           getmetatable(u).__gc = function(o) calls = calls + 1; saved = o end
+         end
+         make()
+         collectgarbage()
+         assert(calls == 1 and type(saved) == 'userdata')
+         -- 生き返った userdata はまだ使えるが、__gc は二度と呼ばれない。
+         assert(getmetatable(saved).__gc)
+         saved = nil
+         collectgarbage()
+         collectgarbage()
+         return calls",
+    );
+    assert_eq!(n, 1.0);
+}
+
+#[test]
+fn finalized_userdata_is_cleared_from_weak_values() {
+    let n = eval_num(
+        "local wv = setmetatable({}, {__mode = 'v'})
+         local wk = setmetatable({}, {__mode = 'k'})
+         local u = newproxy(true)
+         getmetatable(u).__gc = function(o) keep = o end
+         wv[1] = u
+         wk[u] = 1
+         u = nil
+         collectgarbage()
+         -- 値側の参照は消え、キー側は finalizer 実行のため残る（本家 5.1 と同じ）。
+         assert(wv[1] == nil and wk[keep] == 1)
+         return 1",
+    );
+    assert_eq!(n, 1.0);
+}
+
+#[test]
+fn finalizer_error_does_not_escape_collect() {
+    let n = eval_num(
+        "do
+           local u = newproxy(true)
+           getmetatable(u).__gc = function() error('boom') end
+         end
+         collectgarbage()
+         return 7",
+    );
+    assert_eq!(n, 7.0);
+}
+
+static CLOSED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+fn count_close(state: &mut rua_core::state::LuaState) -> rua_core::error::LuaResult<i32> {
+    let _ = state;
+    CLOSED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    Ok(0)
+}
+
+#[test]
+fn finalizers_run_when_lua_is_dropped() {
+    let mut lua = rua_core::api::Lua::new();
+    lua.register_fn("on_close", count_close).unwrap();
+    lua.load(
+        "live = newproxy(true)
+         getmetatable(live).__gc = on_close
+         local other = newproxy(live) -- メタテーブル共有
+         held = { other }",
+    )
+    .exec()
+    .unwrap();
+    assert_eq!(CLOSED.load(std::sync::atomic::Ordering::SeqCst), 0);
+    drop(lua);
+    assert_eq!(CLOSED.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
+
+// ============================================================================
+// メモリ会計
+// ============================================================================
+
+#[test]
+fn bytes_in_use_tracks_table_growth_and_collection() {
+    let mut heap = Heap::new();
+    let base = heap.bytes_in_use();
+    let t = heap.alloc_table(Table::new());
+    let GcHandle::Table(k) = t else { panic!() };
+    let empty = heap.bytes_in_use();
+    assert!(empty > base);
+    {
+        let table = heap.get_table_mut(k).unwrap();
+        for i in 0..1000 {
+            table.array_mut().push(Value::number(i as f64));
+        }
+    }
+    let grown = heap.bytes_in_use();
+    assert!(grown >= empty + 1000 * std::mem::size_of::<Value>());
+    heap.collect([]);
+    assert_eq!(
+        heap.bytes_in_use(),
+        base,
+        "回収したテーブルの分が差し引かれる"
+    );
+}
+
+#[test]
+fn collectgarbage_count_reports_kilobytes() {
+    let mut lua = rua_core::api::Lua::new();
+    let (kb, rem, info): (f64, f64, f64) = lua
+        .load(
+            "collectgarbage('stop')
+             local kb, rem = collectgarbage('count')
+             return kb, rem, gcinfo()",
+        )
+        .call(())
+        .unwrap();
+    let bytes = lua.state().global.heap.bytes_in_use() as f64;
+    assert_eq!(rem, kb * 1024.0 % 1024.0);
+    assert_eq!(info, kb.floor());
+    // 戻り値の確保分だけずれうるので近似で比較する。
+    assert!((kb * 1024.0 - bytes).abs() < 4096.0);
+    let before: f64 = lua.load("return collectgarbage('count')").eval().unwrap();
+    let after: f64 = lua
+        .load("big = {} for i = 1, 10000 do big[i] = i end return collectgarbage('count')")
+        .eval()
+        .unwrap();
+    assert!(after - before > 10000.0 * 8.0 / 1024.0);
+}
+
+// ============================================================================
+// メモリ上限
+// ============================================================================
+
+#[test]
+fn memory_limit_raises_catchable_error() {
+    let mut lua = rua_core::api::Lua::new();
+    lua.set_memory_limit(Some(lua.used_memory() + 256 * 1024));
+    let (ok, msg): (bool, String) = lua
+        .load(
+            "return pcall(function()
+               local t = {}
+               for i = 1, 1e6 do t[i] = { i } end
+             end)",
+        )
+        .call(())
+        .unwrap();
+    assert!(!ok);
+    assert_eq!(msg, "not enough memory");
+    // 保護外では LuaError::Memory がそのまま返る。
+    let err = lua
+        .load("local s = {} for i = 1, 1e6 do s[i] = 'x' .. i end")
+        .exec()
+        .unwrap_err();
+    assert!(matches!(err, rua_core::error::LuaError::Memory), "{err:?}");
+    // 失敗した確保のゴミは次の回収で解放され、状態は引き続き使える。
+    lua.load("collectgarbage()").exec().unwrap();
+    assert!(lua.used_memory() <= lua.memory_limit().unwrap());
+}
+
+#[test]
+fn memory_limit_checked_before_large_allocations() {
+    let mut lua = rua_core::api::Lua::new();
+    let limit = lua.used_memory() + 256 * 1024;
+    lua.set_memory_limit(Some(limit));
+    // 上限を大きく越える確保は行う前に拒否され、使用量は上限を越えない。
+    for chunk in [
+        "return pcall(string.rep, 'x', 1e10)",
+        "local t = {} for i = 1, 100 do t[i] = ('x'):rep(1000) end
+         return pcall(table.concat, t, ('y'):rep(3000))",
+        "local s = ('x'):rep(200 * 1024) return pcall(function() return s .. s end)",
+        "local t = {} return pcall(function() for i = 1, 1e6 do rawset(t, i, true) end end)",
+        "local t = {} return pcall(function() for i = 1, 1e6 do table.insert(t, i) end end)",
+    ] {
+        let (ok, msg): (bool, String) = lua.load(chunk).call(()).expect(chunk);
+        assert!(!ok, "{chunk}");
+        assert_eq!(msg, "not enough memory", "{chunk}");
+        assert!(lua.used_memory() <= limit, "{chunk}");
+        lua.load("collectgarbage()").exec().unwrap();
+    }
+}
+
+#[test]
+fn memory_limit_collects_garbage_before_failing() {
+    let mut lua = rua_core::api::Lua::new();
+    lua.set_memory_limit(Some(lua.used_memory() + 256 * 1024));
+    // 生存量は小さいので、停止中でも上限到達時の回収で収まる。
+    let n: f64 = lua
+        .load(
+            "collectgarbage('stop')
+             local n = 0
+             for i = 1, 20000 do local t = { i, i, i, i } n = n + #t end
+             return n",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(n, 80000.0);
+}
+
+// ============================================================================
+// ヒープスナップショット
+// ============================================================================
+
+/// `_G.cache` に保持したテーブルのハンドル。
+fn cache_handle(lua: &mut rua_core::api::Lua) -> GcHandle {
+    let t: rua_core::api::Table = lua.get_global("cache").unwrap();
+    t.handle()
+}
+
+#[test]
+fn heap_snapshot_records_edges_and_retaining_path() {
+    let mut lua = rua_core::api::Lua::new();
+    lua.load("cache = { {}, { payload = string.rep('x', 100) } }")
+        .exec()
+        .unwrap();
+    let cache = cache_handle(&mut lua);
+    let snap = lua.state().heap_snapshot();
+    let obj = snap.get(cache).expect("cache は到達可能");
+    assert_eq!(obj.kind, "table");
+    assert!(obj.size > 0);
+    let second = obj.edges.iter().find(|e| e.name == "[2]").unwrap().to;
+    assert_eq!(snap.retaining_path(second), ["_G", "cache", "[2]"]);
+    let payload = snap
+        .get(second)
+        .unwrap()
+        .edges
+        .iter()
+        .find(|e| e.name == "payload");
+    assert_eq!(snap.get(payload.unwrap().to).unwrap().kind, "string");
+
+    let json = snap.to_json();
+    assert!(json.starts_with("{\"objects\":["));
+    assert!(json.contains("\"root\":\"_G\""));
+    let dot = snap.to_dot();
+    assert!(dot.starts_with("digraph heap {"));
+    assert!(dot.contains("[label=\"cache\"]"));
+}
+
+#[test]
+fn heap_snapshot_diff_reports_growth() {
+    let mut lua = rua_core::api::Lua::new();
+    lua.load("cache = {}").exec().unwrap();
+    let before = lua.state().heap_snapshot();
+    lua.load("for i = 1, 100 do cache[i] = { i } end")
+        .exec()
+        .unwrap();
+    let after = lua.state().heap_snapshot();
+    let diff = after.diff(&before);
+
+    let tables = diff.kinds.iter().find(|k| k.kind == "table").unwrap();
+    assert_eq!(tables.count_after - tables.count_before, 100);
+    assert_eq!(diff.kinds[0].kind, "table", "最も増えた型が先頭");
+    assert!(
+        diff.added
+            .iter()
+            .any(|e| e.path == ["_G", "cache", "[100]"])
+    );
+    let cache = cache_handle(&mut lua);
+    let grown = diff.grown.iter().find(|e| e.handle == cache).unwrap();
+    assert!(grown.size_after > grown.size_before);
+    assert!(diff.to_string().contains("_G -> cache"));
+}
+
+#[test]
+fn debug_heapsnapshot_writes_json_and_dot() {
+    let dir = std::env::temp_dir();
+    let json = dir.join(format!("rua-heap-{}.json", std::process::id()));
+    let dot = dir.join(format!("rua-heap-{}.dot", std::process::id()));
+    let mut lua = rua_core::api::Lua::new();
+    lua.set_global("json_path", json.to_str().unwrap()).unwrap();
+    lua.set_global("dot_path", dot.to_str().unwrap()).unwrap();
+    let ok: bool = lua
+        .load("return debug.heapsnapshot(json_path) and debug.heapsnapshot(dot_path)")
+        .eval()
+        .unwrap();
+    assert!(ok);
+    assert!(
+        std::fs::read_to_string(&json)
+            .unwrap()
+            .contains("\"type\":\"table\"")
+    );
+    assert!(
+        std::fs::read_to_string(&dot)
+            .unwrap()
+            .starts_with("digraph heap")
+    );
+    let _ = std::fs::remove_file(json);
+    let _ = std::fs::remove_file(dot);
+    let (ok, msg): (Option<bool>, String) = lua
+        .load("return debug.heapsnapshot('/nonexistent-dir/x.json')")
+        .call(())
+        .unwrap();
+    assert_eq!(ok, None);
+    assert!(msg.starts_with("/nonexistent-dir/x.json: "));
+}
+
+// ============================================================================
+// GC 統計とサイクル通知
+// ============================================================================
+
+#[test]
+fn gc_stats_count_freed_objects_per_arena() {
+    let mut heap = Heap::new();
+    let kept = heap.alloc_table(Table::new());
+    let _ = heap.alloc_table(Table::new());
+    let _ = heap.alloc_table(Table::new());
+    let _ = heap.intern_str(b"garbage");
+    let before = heap.bytes_in_use();
+    heap.collect([kept]);
+    let stats = heap.stats();
+    assert_eq!(stats.cycles, 1);
+    assert_eq!(stats.freed.tables, 2);
+    assert_eq!(stats.freed.strings, 1);
+    assert_eq!(stats.freed.total(), 3);
+    assert_eq!(stats.bytes_freed as usize, before - heap.bytes_in_use());
+    assert_eq!(stats.pauses, 1);
+    assert_eq!(stats.total_pause, stats.last_pause);
+}
+
+#[test]
+fn gc_callback_fires_at_cycle_start_and_end() {
+    use rua_core::gc::stats::GcEvent;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut lua = rua_core::api::Lua::new();
+    let log = events.clone();
+    lua.set_gc_callback(move |event, stats| log.borrow_mut().push((event, stats.cycles)));
+    lua.load("collectgarbage() collectgarbage()")
+        .exec()
+        .unwrap();
+    let cycles = lua.gc_stats().cycles;
+    assert_eq!(
+        events.borrow().as_slice(),
+        [
+            (GcEvent::CycleStart, cycles - 2),
+            (GcEvent::CycleEnd, cycles - 1),
+            (GcEvent::CycleStart, cycles - 1),
+            (GcEvent::CycleEnd, cycles),
+        ]
+    );
+    lua.remove_gc_callback();
+    lua.load("collectgarbage()").exec().unwrap();
+    assert_eq!(events.borrow().len(), 4);
+}
+
+#[test]
+fn collectgarbage_stats_returns_table() {
+    let mut lua = rua_core::api::Lua::new();
+    let (cycles, tables, pause): (f64, f64, f64) = lua
+        .load(
+            "collectgarbage('stop')
+             for i = 1, 100 do local t = {} end
+             collectgarbage()
+             local s = collectgarbage('stats')
+             return s.cycles, s.freed.tables, s.max_pause",
+        )
+        .call(())
+        .unwrap();
+    assert!(cycles >= 1.0);
+    // 最後の 1 個はレジスタに残りうる。
+    assert!(tables >= 99.0);
+    assert!(pause >= 0.0);
+    assert_eq!(cycles, lua.gc_stats().cycles as f64);
+}
+
+// ============================================================================
+// 世代別モード
+// ============================================================================
+
+/// 世代別モードの設定。小さなヒープでも major に切り替わらないよう major の閾値を上げる。
+fn generational() -> rua_core::gc::alloc::GcConfig {
+    use rua_core::gc::alloc::{GcConfig, GcMode};
+    GcConfig {
+        mode: GcMode::Generational,
+        major_multiplier: 100_000,
+        ..GcConfig::default()
+    }
+}
+
+#[test]
+fn generational_minor_frees_young_and_promotes_survivors() {
+    let config = generational();
+    let mut heap = Heap::new();
+    let root = heap.alloc_table(Table::new());
+    // 最初のステップは major。生存物は古くなる。
+    assert!(heap.step(&config, vec![root]));
+    assert!(heap.is_generational());
+    assert!(heap.is_old(root));
+    assert_eq!(heap.stats().minor_cycles, 0);
+
+    let young = heap.alloc_table(Table::new());
+    for _ in 0..10 {
+        heap.alloc_table(Table::new());
+    }
+    assert!(!heap.is_old(young));
+    heap.step(&config, vec![root, young]);
+    assert_eq!(heap.stats().minor_cycles, 1);
+    assert!(heap.is_old(young));
+    assert_eq!(heap.live_object_count(), 2);
+}
+
+#[test]
+fn generational_remembers_old_table_pointing_to_young() {
+    let config = generational();
+    let mut heap = Heap::new();
+    let root = heap.alloc_table(Table::new());
+    let GcHandle::Table(rk) = root else { panic!() };
+    heap.step(&config, vec![root]);
+
+    // 古い root に若いテーブルを格納する。minor は root を辿らないため、バリアが必要。
+    let child = heap.alloc_table(Table::new());
+    heap.get_table_mut(rk)
+        .unwrap()
+        .array_mut()
+        .push(Value::gc(child));
+    heap.step(&config, vec![root]);
+    assert_eq!(heap.stats().minor_cycles, 1);
+    let GcHandle::Table(ck) = child else { panic!() };
+    assert!(
+        heap.get_table(ck).is_some(),
+        "古いテーブルから参照される若いテーブルが回収された"
+    );
+    assert!(heap.is_old(child));
+
+    // 古いオブジェクトは minor では残り、major で回収される。
+    heap.get_table_mut(rk).unwrap().array_mut().clear();
+    heap.step(&config, vec![root]);
+    assert!(heap.get_table(ck).is_some());
+    heap.collect([root]);
+    assert!(heap.get_table(ck).is_none());
+}
+
+#[test]
+fn generational_mode_via_collectgarbage() {
+    let mut lua = rua_core::api::Lua::new();
+    let (prev, back, minors, n): (String, String, f64, f64) = lua
+        .load(
+            "local prev = collectgarbage('generational')
+             local keep = {}
+             local weak = setmetatable({}, {__mode = 'k'})
+             collectgarbage()
+             for i = 1, 2000 do
+               keep[i] = {i}
+               weak[{}] = true
+               if i % 200 == 0 then collectgarbage('step') end
+             end
+             local n = 0
+             for i = 1, #keep do n = n + keep[i][1] end
+             collectgarbage()
+             local w = 0
+             for _ in pairs(weak) do w = w + 1 end
+             assert(w <= 1, w)
+             local back = collectgarbage('incremental')
+             collectgarbage()
+             return prev, back, collectgarbage('stats').minor_cycles, n",
+        )
+        .call(())
+        .unwrap();
+    assert_eq!(prev, "incremental");
+    assert_eq!(back, "generational");
+    assert!(minors >= 10.0);
+    assert_eq!(n, 2000.0 * 2001.0 / 2.0);
+    assert!(!lua.state().global.heap.is_generational());
 }
@@ -4,7 +4,7 @@
 //! 基本動作と、Rust 関数を Lua から呼ぶ往復を検証する。
 
 use rua_core::api::{Lua, Value};
-use rua_core::error::LuaResult;
+use rua_core::error::{LuaError, LuaResult};
 use rua_core::state::LuaState;
 
 // ============================================================================
@@ -89,7 +89,7 @@ fn native_add(state: &mut LuaState) -> LuaResult<i32> {
     let args = args_vec(state);
     let a = check_number(state, &args, 0, "native_add")?;
     let b = check_number(state, &args, 1, "native_add")?;
-    ret(state, vec![rua_core::value::Value::Number(a + b)])
+    ret(state, vec![rua_core::value::Value::number(a + b)])
 }
 
 #[test]
@@ -133,8 +133,8 @@ fn register_fn_multiple_calls() {
 fn create_table_set_get() {
     let mut lua = Lua::new();
     let t = lua.create_table();
-    lua.set(t, "key", "value").unwrap();
-    let v: String = lua.get(t, "key").unwrap();
+    lua.set(&t, "key", "value").unwrap();
+    let v: String = lua.get(&t, "key").unwrap();
     assert_eq!(v, "value");
 }
 
@@ -142,7 +142,7 @@ fn create_table_set_get() {
 fn table_accessible_from_lua() {
     let mut lua = Lua::new();
     let t = lua.create_table();
-    lua.set(t, "x", 99.0f64).unwrap();
+    lua.set(&t, "x", 99.0f64).unwrap();
     lua.set_global("mytable", t).unwrap();
 
     let n: f64 = lua.load("return mytable.x").eval().unwrap();
@@ -212,3 +212,498 @@ fn chunk_into_function_and_call() {
     // チャンクに渡した引数が `...` として返る。
     assert!(!result.is_empty());
 }
+
+// ============================================================================
+// 呼び出しの深さ（スタック上限 / C 呼び出し上限）
+// ============================================================================
+
+#[test]
+fn deep_lua_recursion_runs_without_native_recursion() {
+    let mut lua = Lua::new();
+    let n: f64 = lua
+        .load(
+            "local function depth(n) if n == 0 then return 0 end return 1 + depth(n - 1) end \
+             return depth(20000)",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(n, 20000.0);
+}
+
+#[test]
+fn runaway_recursion_reports_stack_overflow() {
+    let mut lua = Lua::new();
+    lua.set_stack_limit(10_000);
+    let msg: String = lua
+        .load(
+            "local function f() return 1 + f() end \
+             local ok, err = pcall(f) assert(not ok) return err",
+        )
+        .eval()
+        .unwrap();
+    assert!(msg.contains("stack overflow"), "{msg}");
+    // 上限内の再帰は引き続き動く。
+    let n: f64 = lua
+        .load(
+            "local function g(n) if n == 0 then return 0 end return 1 + g(n - 1) end return g(100)",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(n, 100.0);
+}
+
+#[test]
+fn recursive_metamethod_hits_c_stack_limit() {
+    // メタメソッド経由の呼び出しはネイティブ再帰になる。デバッグビルドのフレームは大きいので、
+    // テストスレッド既定（2 MiB）ではなく本家の C スタック相当の余裕を持たせて実行する。
+    let run = || {
+        let mut lua = Lua::new();
+        lua.load(
+            "local t = setmetatable({}, {}) \
+             getmetatable(t).__index = function(t, k) return t[k] end \
+             local ok, err = pcall(function() return t.x end) assert(not ok) return err",
+        )
+        .eval::<String>()
+        .unwrap()
+    };
+    let msg = std::thread::Builder::new()
+        .stack_size(16 << 20)
+        .spawn(run)
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(msg.contains("C stack overflow"), "{msg}");
+}
+
+#[test]
+fn coroutine_yields_across_nested_lua_frames() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local function inner(x) return coroutine.yield(x) + 1 end \
+             local function outer(x) return inner(x * 2) * 10 end \
+             local co = coroutine.create(outer) \
+             local _, a = coroutine.resume(co, 5) \
+             local _, b = coroutine.resume(co, 7) \
+             return a .. ' ' .. b .. ' ' .. coroutine.status(co)",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(s, "10 80 dead");
+}
+
+#[test]
+fn coroutine_keeps_its_own_stack_across_resume_sites() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local shared = 0 \
+             local co = coroutine.wrap(function() \
+               local a, b = 'A', 'B' \
+               local x = coroutine.yield() \
+               shared = shared + 1 \
+               return a .. b .. x .. shared \
+             end) \
+             co() \
+             local function deeper(...) return co(...) end \
+             return deeper('x', 2, 3)",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(s, "ABx1");
+}
+
+#[test]
+fn coroutine_running_status_and_traceback() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local log = {} \
+             local co \
+             co = coroutine.create(function() \
+               log[#log + 1] = tostring(coroutine.running() == co) \
+               local inner = coroutine.create(function() \
+                 log[#log + 1] = coroutine.status(co) \
+                 log[#log + 1] = select(2, coroutine.resume(co)) \
+               end) \
+               coroutine.resume(inner) \
+               coroutine.yield() \
+             end) \
+             coroutine.resume(co) \
+             log[#log + 1] = select(2, pcall(coroutine.yield)) \
+             log[#log + 1] = debug.traceback(co) \
+             return table.concat(log, '|')",
+        )
+        .eval()
+        .unwrap();
+    // 中断中のコルーチンのトレースバックは、そのコルーチンのフレーム（yield 自身を含む）だけを列挙する。
+    let (log, tb) = s.split_once("|stack traceback:\n\t").unwrap();
+    assert_eq!(
+        log,
+        "true|normal|cannot resume non-suspended coroutine|\
+         attempt to yield from outside a coroutine"
+    );
+    let (top, body) = tb.split_once("\n\t").unwrap();
+    assert_eq!(top, "[C]: in function 'yield'");
+    assert!(body.ends_with(":1>") && !body.contains('\n'), "{tb}");
+}
+
+#[test]
+fn coroutine_dead_by_error_keeps_frames_and_closes_upvalues() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local co = coroutine.create(function()\n\
+               local y = {'in co'}\n\
+               g = function() return y[1] end\n\
+               error('boom')\n\
+             end)\n\
+             local _, err = coroutine.resume(co)\n\
+             local a, b, c = {}, {}, {}\n\
+             pcall(function() local p = {'in pcall'} h = function() return p[1] end error() end)\n\
+             local s = coroutine.create(function() local z = {'suspended'} k = function() return z[1] end coroutine.yield() end)\n\
+             coroutine.resume(s)\n\
+             s = nil\n\
+             collectgarbage()\n\
+             return table.concat({err, g(), h(), k(), debug.traceback(co)}, '|')",
+        )
+        .set_name("=t")
+        .eval()
+        .unwrap();
+    // エラーで終了したコルーチンも本家 5.1 どおりフレームをトレースバックに残す。
+    // 捕捉されていたローカルは、エラーやスレッドの回収の後もクロージャから読める。
+    assert_eq!(
+        s,
+        "t:4: boom|in co|in pcall|suspended|stack traceback:\n\
+         \t[C]: in function 'error'\n\
+         \tt:4: in function <t:1>"
+    );
+}
+
+#[test]
+fn coroutine_yields_across_metamethods() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local mt = {} \
+             mt.__index = function(t, k) return coroutine.yield(k) end \
+             mt.__lt = function(a, b) return coroutine.yield('lt') end \
+             mt.__concat = function(a, b) return coroutine.yield('concat') end \
+             local co = coroutine.wrap(function() \
+               local o = setmetatable({}, mt) \
+               local v = o.foo \
+               local le = o <= o \
+               return v .. ' ' .. tostring(le) .. ' ' .. ('a' .. o .. 'c') \
+             end) \
+             local log = {} \
+             local r = co(nil) \
+             for _, x in ipairs({'bar', true, 'X'}) do log[#log + 1] = r; r = co(x) end \
+             return table.concat(log, ',') .. ' | ' .. r",
+        )
+        .eval()
+        .unwrap();
+    // __le が無いので `o <= o` は `not (o < o)`。
+    assert_eq!(s, "foo,lt,concat | bar false aX");
+}
+
+#[test]
+fn coroutine_yields_across_stdlib_callbacks() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local co = coroutine.wrap(function() \
+               local ok, v = pcall(function() return coroutine.yield('p') end) \
+               local ok2, e = pcall(function() coroutine.yield('e') error('boom', 0) end) \
+               local t = {3, 1, 2} \
+               table.sort(t, function(a, b) return coroutine.yield(a < b) end) \
+               local g = string.gsub('a b', '%w', function(w) return coroutine.yield(w) end) \
+               return table.concat({tostring(ok), v, tostring(ok2), e, \
+                                    table.concat(t), g}, ' ') \
+             end) \
+             local r = co(nil) \
+             while type(r) ~= 'string' or #r < 4 do \
+               if r == 'p' then r = co('P') \
+               elseif type(r) == 'string' then r = co(r:upper()) \
+               else r = co(r) end \
+             end \
+             return r",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(s, "true P false boom 123 A B");
+}
+
+/// 引数の関数を `call_k` で呼び、結果を 2 倍して返す（yield 可能な NativeFn）。
+fn native_double_call(state: &mut LuaState) -> LuaResult<i32> {
+    use rua_core::stdlib::aux::args_vec;
+    let args = args_vec(state);
+    let f = args.first().copied().unwrap_or(rua_core::value::Value::NIL);
+    let rets = rua_core::vm::call_k(state, f, &[], double_cont, || Box::new(()))?;
+    double_cont(state, Ok(rets), Box::new(()))
+}
+
+fn double_cont(
+    state: &mut LuaState,
+    result: LuaResult<Vec<rua_core::value::Value>>,
+    _ctx: Box<dyn std::any::Any>,
+) -> LuaResult<i32> {
+    use rua_core::stdlib::aux::ret;
+    let n = result?.first().and_then(|v| v.as_number()).unwrap_or(0.0);
+    ret(state, vec![rua_core::value::Value::number(n * 2.0)])
+}
+
+#[test]
+fn native_function_yields_with_continuation() {
+    let mut lua = Lua::new();
+    lua.register_fn("double_call", native_double_call).unwrap();
+    let s: String = lua
+        .load(
+            "local co = coroutine.create(function() \
+               return double_call(function() return coroutine.yield('in') + 1 end) \
+             end) \
+             local _, a = coroutine.resume(co, nil) \
+             local _, b = coroutine.resume(co, 20) \
+             return a .. ' ' .. b .. ' ' .. coroutine.status(co)",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(s, "in 42 dead");
+}
+
+#[test]
+fn yield_through_plain_call_is_an_error() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local mt = {__tostring = function() return coroutine.yield() end} \
+             local co = coroutine.create(function() \
+               return tostring(setmetatable({}, mt)) \
+             end) \
+             local ok, e = coroutine.resume(co) \
+             return tostring(ok) .. ' ' .. e",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(s, "false attempt to yield across a C-call boundary");
+}
+
+// ============================================================================
+// 実行の打ち切り（命令数の上限 / 中断フラグ）
+// ============================================================================
+
+#[test]
+fn instruction_limit_stops_runaway_loop() {
+    use rua_core::error::InterruptReason;
+
+    let mut lua = Lua::new();
+    lua.set_instruction_limit(Some(10_000));
+    // pcall や coroutine.resume の中でも捕捉されずに埋め込み側まで届く。
+    for src in [
+        "while true do end",
+        "pcall(function() while true do end end)",
+        "coroutine.resume(coroutine.create(function() while true do end end))",
+    ] {
+        lua.set_instruction_limit(Some(10_000));
+        let err = lua.load(src).exec().unwrap_err();
+        assert!(
+            matches!(
+                err,
+                LuaError::Interrupted(InterruptReason::InstructionLimit)
+            ),
+            "{src}: {err:?}"
+        );
+        assert_eq!(lua.instructions_remaining(), Some(0));
+    }
+    // 上限を外せば同じ状態で実行を続けられる。
+    lua.set_instruction_limit(None);
+    let n: f64 = lua.load("return 1 + 1").eval().unwrap();
+    assert_eq!(n, 2.0);
+}
+
+#[test]
+fn interrupt_flag_stops_running_script() {
+    use rua_core::error::InterruptReason;
+    use std::sync::atomic::Ordering;
+
+    let mut lua = Lua::new();
+    let flag = lua.interrupt_handle();
+    let stopper = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        flag.store(true, Ordering::Relaxed);
+    });
+    let err = lua
+        .load("while true do pcall(function() end) end")
+        .exec()
+        .unwrap_err();
+    stopper.join().unwrap();
+    assert!(
+        matches!(err, LuaError::Interrupted(InterruptReason::Requested)),
+        "{err:?}"
+    );
+    // フラグはエラーが戻った時点で下りるので、次の実行は普通に動く。
+    assert!(!lua.interrupt_handle().load(Ordering::Relaxed));
+    lua.load("x = 1").exec().unwrap();
+}
+
+#[test]
+fn interrupt_flag_is_not_swallowed_by_finalizer() {
+    use rua_core::error::InterruptReason;
+    use std::sync::atomic::Ordering;
+
+    let mut lua = Lua::new();
+    let flag = lua.interrupt_handle();
+    let stopper = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        flag.store(true, Ordering::Relaxed);
+    });
+    // `__gc` の中で打ち切られても、finalizer の保護呼び出しで捨てられずに埋め込み側まで届く。
+    let err = lua
+        .load(
+            "local p = newproxy(true)
+             getmetatable(p).__gc = function() while true do end end
+             p = nil
+             collectgarbage()
+             return 'escaped'",
+        )
+        .eval::<String>()
+        .unwrap_err();
+    stopper.join().unwrap();
+    assert!(
+        matches!(err, LuaError::Interrupted(InterruptReason::Requested)),
+        "{err:?}"
+    );
+    assert!(!lua.interrupt_handle().load(Ordering::Relaxed));
+    lua.load("x = 1").exec().unwrap();
+}
+
+#[test]
+fn deadline_stops_script_through_pcall() {
+    use rua_core::error::InterruptReason;
+    use std::time::Duration;
+
+    let mut lua = Lua::new();
+    let err = lua
+        .load("pcall(function() while true do end end) return 'unreachable'")
+        .exec_with_deadline(Duration::from_millis(20))
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            LuaError::Interrupted(InterruptReason::Deadline { catchable: false })
+        ),
+        "{err:?}"
+    );
+    // 期限は呼び出しの間だけ有効。期限内に終わる呼び出しは普通に値を返す。
+    assert!(lua.state().deadline.is_none());
+    let n: f64 = lua
+        .load("local s = 0 for i = 1, 100 do s = s + i end return s")
+        .eval_with_deadline(Duration::from_secs(60))
+        .unwrap();
+    assert_eq!(n, 5050.0);
+}
+
+#[test]
+fn deadline_is_not_swallowed_by_finalizer() {
+    use rua_core::error::InterruptReason;
+    use std::time::Duration;
+
+    const SPIN_IN_GC: &str = "local p = newproxy(true)
+         getmetatable(p).__gc = function() while true do end end
+         p = nil";
+    let mut lua = Lua::new();
+    let err = lua
+        .load(format!("{SPIN_IN_GC} collectgarbage() return 'escaped'"))
+        .eval_with_deadline::<String>(Duration::from_millis(50))
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            LuaError::Interrupted(InterruptReason::Deadline { catchable: false })
+        ),
+        "{err:?}"
+    );
+    // 捕捉できる期限切れも finalizer では捨てず、GC を起こした箇所の pcall へ届く。
+    lua.set_catchable_timeouts(true);
+    let msg: String = lua
+        .load(format!(
+            "{SPIN_IN_GC} local ok, err = pcall(collectgarbage) return err"
+        ))
+        .eval_with_deadline(Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(msg, "execution timed out");
+}
+
+#[test]
+fn catchable_timeout_is_seen_by_script_pcall() {
+    use std::time::{Duration, Instant};
+
+    let mut lua = Lua::new();
+    lua.set_catchable_timeouts(true);
+    let msg: String = lua
+        .load("local ok, err = pcall(function() while true do end end) return err")
+        .eval_with_deadline(Instant::now() + Duration::from_millis(20))
+        .unwrap();
+    assert_eq!(msg, "execution timed out");
+}
+
+// ============================================================================
+// debug ライブラリ
+// ============================================================================
+
+#[test]
+fn getlocal_negative_index_reads_and_writes_varargs() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local function f(a, ...) \
+               local n1, v1 = debug.getlocal(1, -1) \
+               local n2, v2 = debug.getlocal(1, -2) \
+               local none = debug.getlocal(1, -3) \
+               debug.setlocal(1, -2, 'changed') \
+               local _, second = ... \
+               return n1 .. '=' .. v1 .. ' ' .. n2 .. '=' .. v2 .. ' ' .. tostring(none) .. ' ' .. second \
+             end \
+             return f(0, 'x', 'y')",
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(s, "(*vararg)=x (*vararg)=y nil changed");
+}
+
+#[test]
+fn debug_functions_inspect_suspended_coroutine() {
+    let mut lua = Lua::new();
+    let s: String = lua
+        .load(
+            "local co = coroutine.create(function(a, b)\n\
+               local c = a + b\n\
+               coroutine.yield()\n\
+               return c\n\
+             end)\n\
+             coroutine.resume(co, 1, 2)\n\
+             local log = {}\n\
+             for level = 0, 1 do\n\
+               local info = debug.getinfo(co, level, 'Sl')\n\
+               log[#log + 1] = info.what .. ':' .. info.currentline\n\
+             end\n\
+             log[#log + 1] = tostring(debug.getinfo(co, 2))\n\
+             local name, value = debug.getlocal(co, 1, 3)\n\
+             log[#log + 1] = name .. '=' .. value\n\
+             log[#log + 1] = debug.setlocal(co, 1, 3, 40)\n\
+             log[#log + 1] = select(2, pcall(debug.getlocal, co, 2, 1))\n\
+             local n = 0\n\
+             debug.sethook(co, function() n = n + 1 end, 'l')\n\
+             log[#log + 1] = select(2, debug.gethook(co)) .. ',' .. tostring(debug.gethook())\n\
+             log[#log + 1] = select(2, coroutine.resume(co))\n\
+             log[#log + 1] = n\n\
+             return table.concat(log, '|')",
+        )
+        .eval()
+        .unwrap();
+    // level 0 は中断中のコルーチンの yield、level 1 がそれを呼んだ関数。
+    // フックはそのコルーチンにだけ掛かる。
+    assert_eq!(
+        s,
+        "C:-1|Lua:3|nil|c=3|c|bad argument #2 to 'getlocal' (level out of range)|l,nil|40|1"
+    );
+}
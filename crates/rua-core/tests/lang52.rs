@@ -0,0 +1,125 @@
+//! Lua 5.2 互換モード（`Lang::Lua52`）の動作テスト。
+//!
+//! `goto`/ラベルの実行結果（ループ・continue・upvalue の閉じ方）、5.2 の文字列エスケープ、
+//! 既定の 5.1 モードでは受理しないこと、`GlobalState::lang` が `load` に伝わることを検証する。
+
+use rua_core::api::{Lang, Lua};
+use rua_core::error::LuaError;
+
+fn eval52(src: &str) -> String {
+    let mut lua = Lua::new();
+    lua.load(src).set_lang(Lang::Lua52).eval().unwrap()
+}
+
+fn error52(src: &str) -> String {
+    let mut lua = Lua::new();
+    match lua.load(src).set_name("=t").set_lang(Lang::Lua52).exec() {
+        Err(LuaError::Syntax(msg)) => msg,
+        other => panic!("expected a syntax error, got {other:?}"),
+    }
+}
+
+#[test]
+fn goto_loops_and_continue() {
+    let out = eval52(
+        r#"
+        local t = {}
+        local i = 1
+        ::top::
+        if i <= 3 then t[#t + 1] = i; i = i + 1; goto top end
+        for j = 1, 6 do
+          if j % 2 == 0 then goto continue end
+          t[#t + 1] = "o" .. j
+          ::continue::
+        end
+        while true do
+          for k = 1, 10 do
+            if k == 2 then goto out end
+          end
+        end
+        ::out::
+        local n = 0
+        repeat
+          local x = n
+          n = n + 1
+          if x == 0 then goto continue end
+          t[#t + 1] = "r" .. x
+          ::continue::
+        until x >= 2
+        return table.concat(t, ",")
+        "#,
+    );
+    assert_eq!(out, "1,2,3,o1,o3,o5,r1,r2");
+}
+
+#[test]
+fn backward_goto_gives_each_iteration_a_fresh_local() {
+    // 5.2 の仕様どおり、ラベルの手前へ戻る goto は捕捉された local を閉じる。
+    let out = eval52(
+        r#"
+        local fs, i = {}, 1
+        ::again::
+        local x = i * 10
+        fs[i] = function() return x end
+        i = i + 1
+        if i <= 3 then goto again end
+        return fs[1]() .. " " .. fs[2]() .. " " .. fs[3]()
+        "#,
+    );
+    assert_eq!(out, "10 20 30");
+}
+
+#[test]
+fn lua52_string_escapes() {
+    let out = eval52(
+        r#"return "\x41\z
+                            B\u{48}\u{20AC}""#,
+    );
+    assert_eq!(out, "AB\u{48}\u{20AC}");
+}
+
+#[test]
+fn goto_errors_name_the_lines() {
+    assert_eq!(
+        error52("goto nowhere"),
+        "t:1: no visible label 'nowhere' for <goto> at line 1"
+    );
+    assert_eq!(
+        error52("do\n goto l\n local a\n ::l:: print(a)\nend"),
+        "t:4: <goto l> at line 2 jumps into the scope of local 'a'"
+    );
+    // repeat 本体の末尾ラベルは `until` の条件式と同じスコープなので、末尾でも local を飛び越せない。
+    assert_eq!(
+        error52("repeat\n goto c\n local x = 1\n ::c::\nuntil x"),
+        "t:4: <goto c> at line 2 jumps into the scope of local 'x'"
+    );
+    assert_eq!(
+        error52("do ::l:: end\n::l::\n::l::"),
+        "t:3: label 'l' already defined on line 2"
+    );
+}
+
+#[test]
+fn default_language_is_lua51() {
+    let mut lua = Lua::new();
+    // 5.1 では goto はただの名前。
+    let n: f64 = lua.load("local goto = 5 return goto").eval().unwrap();
+    assert_eq!(n, 5.0);
+    assert!(lua.load("::l::").exec().is_err());
+    assert!(
+        lua.load(r#"return "\x41""#)
+            .set_lang(Lang::Lua51)
+            .exec()
+            .is_ok()
+    );
+}
+
+#[test]
+fn global_lang_applies_to_load() {
+    let mut lua = Lua::new();
+    let src = r#"return assert(loadstring("local i = 0 ::l:: i = i + 1 if i < 4 then goto l end return i"))()"#;
+    assert!(lua.load(src).exec().is_err());
+    lua.state_mut().global.lang = Lang::Lua52;
+    let n: f64 = lua.load(src).eval().unwrap();
+    assert_eq!(n, 4.0);
+}
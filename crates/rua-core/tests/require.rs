@@ -6,7 +6,6 @@
 use std::rc::Rc;
 
 use rua_core::compiler::compile;
-use rua_core::gc::GcHandle;
 use rua_core::state::LuaState;
 use rua_core::stdlib;
 use rua_core::value::Value;
@@ -25,18 +24,18 @@ fn run_src(state: &mut LuaState, src: &str) -> Vec<Value> {
 }
 
 fn as_num(v: Value) -> f64 {
-    match v {
-        Value::Number(n) => n,
-        _ => panic!("expected number, got {v:?}"),
+    if let Some(n) = v.as_number() {
+        n
+    } else {
+        panic!("expected number, got {v:?}")
     }
 }
 
 fn as_string(state: &LuaState, v: Value) -> String {
-    match v {
-        Value::GcRef(GcHandle::Str(k)) => {
-            String::from_utf8_lossy(state.global.heap.get_str(k).unwrap().as_bytes()).into_owned()
-        }
-        _ => panic!("expected string, got {v:?}"),
+    if let Some(k) = v.as_string() {
+        String::from_utf8_lossy(state.global.heap.get_str(k).unwrap().as_bytes()).into_owned()
+    } else {
+        panic!("expected string, got {v:?}")
     }
 }
 
@@ -92,10 +91,7 @@ fn require_via_preload_and_cache() {
     );
     assert_eq!(as_num(r[0]), 42.0);
     assert_eq!(as_string(&s, r[1]), "mymod");
-    assert!(
-        matches!(r[2], Value::Boolean(true)),
-        "cached module identity"
-    );
+    assert_eq!(r[2], Value::TRUE, "cached module identity");
     // ローダは一度だけ呼ばれる（2 回目はキャッシュ）。
     assert_eq!(as_num(r[3]), 1.0);
 }
@@ -112,9 +108,9 @@ fn require_preload_without_return_uses_true() {
     "#,
     );
     // 戻り値なしのモジュールは true がキャッシュ・返却される。
-    assert!(matches!(r[0], Value::Boolean(true)));
-    assert!(matches!(r[1], Value::Boolean(true)));
-    assert!(matches!(r[2], Value::Boolean(true)));
+    assert_eq!(r[0], Value::TRUE);
+    assert_eq!(r[1], Value::TRUE);
+    assert_eq!(r[2], Value::TRUE);
 }
 
 #[test]
@@ -160,7 +156,7 @@ fn require_dotted_name_maps_to_subdir() {
         dir.display()
     );
     let r = run_src(&mut s, &src);
-    assert!(matches!(r[0], Value::Boolean(true)));
+    assert_eq!(r[0], Value::TRUE);
 
     std::fs::remove_dir_all(&dir).ok();
 }
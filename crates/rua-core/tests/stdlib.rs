@@ -22,22 +22,23 @@ fn sval(state: &mut LuaState, s: &str) -> Value {
 }
 
 fn num(n: f64) -> Value {
-    Value::Number(n)
+    Value::number(n)
 }
 
 fn global(state: &mut LuaState, name: &str) -> Value {
     let k = state.new_string(name.as_bytes());
     match state.global.globals {
         GcHandle::Table(tk) => state.global.heap.get_table(tk).unwrap().get(&k),
-        _ => Value::Nil,
+        _ => Value::NIL,
     }
 }
 
 fn field(state: &mut LuaState, t: Value, name: &str) -> Value {
     let k = state.new_string(name.as_bytes());
-    match t {
-        Value::GcRef(GcHandle::Table(tk)) => state.global.heap.get_table(tk).unwrap().get(&k),
-        _ => Value::Nil,
+    if let Some(tk) = t.as_table() {
+        state.global.heap.get_table(tk).unwrap().get(&k)
+    } else {
+        Value::NIL
     }
 }
 
@@ -55,31 +56,31 @@ fn call_lib(state: &mut LuaState, lib: &str, name: &str, args: &[Value]) -> Vec<
 }
 
 fn as_string(state: &LuaState, v: Value) -> String {
-    match v {
-        Value::GcRef(GcHandle::Str(k)) => {
-            String::from_utf8_lossy(state.global.heap.get_str(k).unwrap().as_bytes()).into_owned()
-        }
-        _ => panic!("expected string, got {v:?}"),
+    if let Some(k) = v.as_string() {
+        String::from_utf8_lossy(state.global.heap.get_str(k).unwrap().as_bytes()).into_owned()
+    } else {
+        panic!("expected string, got {v:?}")
     }
 }
 
 fn as_num(v: Value) -> f64 {
-    match v {
-        Value::Number(n) => n,
-        _ => panic!("expected number, got {v:?}"),
+    if let Some(n) = v.as_number() {
+        n
+    } else {
+        panic!("expected number, got {v:?}")
     }
 }
 
 fn new_array(state: &mut LuaState, items: &[Value]) -> Value {
     let t = state.new_table();
-    if let Value::GcRef(GcHandle::Table(tk)) = t {
+    if let Some(tk) = t.as_table() {
         for (i, v) in items.iter().enumerate() {
             let _ = state
                 .global
                 .heap
                 .get_table_mut(tk)
                 .unwrap()
-                .set(Value::Number((i + 1) as f64), *v);
+                .set(Value::number((i + 1) as f64), *v);
         }
     }
     t
@@ -90,18 +91,18 @@ fn new_array(state: &mut LuaState, items: &[Value]) -> Value {
 #[test]
 fn type_and_tostring() {
     let mut s = new_state();
-    let r = call_g(&mut s, "type", &[Value::Number(1.0)]);
+    let r = call_g(&mut s, "type", &[Value::number(1.0)]);
     assert_eq!(as_string(&s, r[0]), "number");
-    let nilv = call_g(&mut s, "type", &[Value::Nil]);
+    let nilv = call_g(&mut s, "type", &[Value::NIL]);
     assert_eq!(as_string(&s, nilv[0]), "nil");
-    let bv = call_g(&mut s, "type", &[Value::Boolean(true)]);
+    let bv = call_g(&mut s, "type", &[Value::TRUE]);
     assert_eq!(as_string(&s, bv[0]), "boolean");
 
-    let r = call_g(&mut s, "tostring", &[Value::Number(1.5)]);
+    let r = call_g(&mut s, "tostring", &[Value::number(1.5)]);
     assert_eq!(as_string(&s, r[0]), "1.5");
-    let r = call_g(&mut s, "tostring", &[Value::Nil]);
+    let r = call_g(&mut s, "tostring", &[Value::NIL]);
     assert_eq!(as_string(&s, r[0]), "nil");
-    let r = call_g(&mut s, "tostring", &[Value::Boolean(false)]);
+    let r = call_g(&mut s, "tostring", &[Value::FALSE]);
     assert_eq!(as_string(&s, r[0]), "false");
 }
 
@@ -117,12 +118,12 @@ fn tonumber_variants() {
     assert_eq!(as_num(r[0]), 3.5);
 
     let arg = sval(&mut s, "ff");
-    let r = call_g(&mut s, "tonumber", &[arg, Value::Number(16.0)]);
+    let r = call_g(&mut s, "tonumber", &[arg, Value::number(16.0)]);
     assert_eq!(as_num(r[0]), 255.0);
 
     let arg = sval(&mut s, "hello");
     let r = call_g(&mut s, "tonumber", &[arg]);
-    assert!(matches!(r[0], Value::Nil));
+    assert!(r[0].is_nil());
 }
 
 #[test]
@@ -134,9 +135,9 @@ fn select_works() {
         "select",
         &[
             hash,
-            Value::Number(1.0),
-            Value::Number(2.0),
-            Value::Number(3.0),
+            Value::number(1.0),
+            Value::number(2.0),
+            Value::number(3.0),
         ],
     );
     assert_eq!(as_num(r[0]), 3.0);
@@ -145,10 +146,10 @@ fn select_works() {
         &mut s,
         "select",
         &[
-            Value::Number(2.0),
-            Value::Number(10.0),
-            Value::Number(20.0),
-            Value::Number(30.0),
+            Value::number(2.0),
+            Value::number(10.0),
+            Value::number(20.0),
+            Value::number(30.0),
         ],
     );
     assert_eq!(r.len(), 2);
@@ -160,17 +161,13 @@ fn select_works() {
 fn assert_and_pcall() {
     let mut s = new_state();
     // assert(true, ...) returns its args.
-    let r = call_g(
-        &mut s,
-        "assert",
-        &[Value::Number(5.0), Value::Boolean(true)],
-    );
+    let r = call_g(&mut s, "assert", &[Value::number(5.0), Value::TRUE]);
     assert_eq!(as_num(r[0]), 5.0);
 
     // pcall(assert, false) -> (false, "assertion failed!")
     let assertf = global(&mut s, "assert");
-    let r = call_g(&mut s, "pcall", &[assertf, Value::Boolean(false)]);
-    assert_eq!(r[0], Value::Boolean(false));
+    let r = call_g(&mut s, "pcall", &[assertf, Value::FALSE]);
+    assert_eq!(r[0], Value::FALSE);
     assert_eq!(as_string(&s, r[1]), "assertion failed!");
 }
 
@@ -180,16 +177,16 @@ fn rawequal_rawget_rawset() {
     let t = new_array(&mut s, &[]);
     let k = sval(&mut s, "x");
     // rawset(t, "x", 7)
-    call_g(&mut s, "rawset", &[t, k, Value::Number(7.0)]);
+    call_g(&mut s, "rawset", &[t, k, Value::number(7.0)]);
     // rawget(t, "x") == 7
     let r = call_g(&mut s, "rawget", &[t, k]);
     assert_eq!(as_num(r[0]), 7.0);
     // rawequal(t, t) is true; rawequal(t, other) false
     let r = call_g(&mut s, "rawequal", &[t, t]);
-    assert_eq!(r[0], Value::Boolean(true));
+    assert_eq!(r[0], Value::TRUE);
     let other = new_array(&mut s, &[]);
     let r = call_g(&mut s, "rawequal", &[t, other]);
-    assert_eq!(r[0], Value::Boolean(false));
+    assert_eq!(r[0], Value::FALSE);
 }
 
 #[test]
@@ -199,16 +196,16 @@ fn pairs_iterates_all() {
     // pairs(t) -> (next, t, nil)
     let triplet = call_g(&mut s, "pairs", &[t]);
     let nextf = triplet[0];
-    let mut key = Value::Nil;
+    let mut key = Value::NIL;
     let mut count = 0;
     let mut sum = 0.0;
     loop {
         let r = vm::call(&mut s, nextf, &[t, key]).unwrap();
-        if r.is_empty() || matches!(r[0], Value::Nil) {
+        if r.is_empty() || r[0].is_nil() {
             break;
         }
         key = r[0];
-        if let Value::Number(n) = r[1] {
+        if let Some(n) = r[1].as_number() {
             sum += n;
         }
         count += 1;
@@ -222,16 +219,16 @@ fn pairs_iterates_all() {
 fn state_table_with_mixed(state: &mut LuaState) -> Value {
     let t = new_array(
         state,
-        &[Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)],
+        &[Value::number(1.0), Value::number(2.0), Value::number(3.0)],
     );
-    if let Value::GcRef(GcHandle::Table(tk)) = t {
+    if let Some(tk) = t.as_table() {
         let k = state.new_string(b"k");
         let _ = state
             .global
             .heap
             .get_table_mut(tk)
             .unwrap()
-            .set(k, Value::Number(100.0));
+            .set(k, Value::number(100.0));
     }
     t
 }
@@ -239,10 +236,10 @@ fn state_table_with_mixed(state: &mut LuaState) -> Value {
 #[test]
 fn ipairs_stops_at_hole() {
     let mut s = new_state();
-    let t = new_array(&mut s, &[Value::Number(10.0), Value::Number(20.0)]);
+    let t = new_array(&mut s, &[Value::number(10.0), Value::number(20.0)]);
     let triplet = call_g(&mut s, "ipairs", &[t]);
     let iter = triplet[0];
-    let mut i = Value::Number(0.0);
+    let mut i = Value::number(0.0);
     let mut seen = Vec::new();
     loop {
         let r = vm::call(&mut s, iter, &[t, i]).unwrap();
@@ -388,7 +385,7 @@ fn string_match_and_anchors() {
     let str_ = sval(&mut s, "xyz");
     let pat = sval(&mut s, "%d+");
     let r = call_lib(&mut s, "string", "match", &[str_, pat]);
-    assert!(matches!(r[0], Value::Nil));
+    assert!(r[0].is_nil());
 }
 
 #[test]
@@ -427,7 +424,7 @@ fn string_gmatch_iterates() {
     let iter = triplet[0];
     let mut out = Vec::new();
     loop {
-        let r = vm::call(&mut s, iter, &[Value::Nil, Value::Nil]).unwrap();
+        let r = vm::call(&mut s, iter, &[Value::NIL, Value::NIL]).unwrap();
         if r.is_empty() {
             break;
         }
@@ -442,8 +439,8 @@ fn string_gmatch_iterates() {
 #[test]
 fn table_insert_remove() {
     let mut s = new_state();
-    let t = new_array(&mut s, &[Value::Number(1.0), Value::Number(2.0)]);
-    call_lib(&mut s, "table", "insert", &[t, Value::Number(3.0)]);
+    let t = new_array(&mut s, &[Value::number(1.0), Value::number(2.0)]);
+    call_lib(&mut s, "table", "insert", &[t, Value::number(3.0)]);
     let r = call_lib(&mut s, "table", "getn", &[t]);
     assert_eq!(as_num(r[0]), 3.0);
 
@@ -458,9 +455,10 @@ fn table_insert_remove() {
 }
 
 fn field_int(state: &mut LuaState, t: Value, i: usize) -> Value {
-    match t {
-        Value::GcRef(GcHandle::Table(tk)) => state.global.heap.get_table(tk).unwrap().get_int(i),
-        _ => Value::Nil,
+    if let Some(tk) = t.as_table() {
+        state.global.heap.get_table(tk).unwrap().get_int(i)
+    } else {
+        Value::NIL
     }
 }
 
@@ -481,7 +479,7 @@ fn table_sort_default_and_custom() {
     let mut s = new_state();
     let t = new_array(
         &mut s,
-        &[Value::Number(3.0), Value::Number(1.0), Value::Number(2.0)],
+        &[Value::number(3.0), Value::number(1.0), Value::number(2.0)],
     );
     call_lib(&mut s, "table", "sort", &[t]);
     assert_eq!(as_num(field_int(&mut s, t, 1)), 1.0);
@@ -492,9 +490,9 @@ fn table_sort_default_and_custom() {
 #[test]
 fn table_maxn() {
     let mut s = new_state();
-    let t = new_array(&mut s, &[Value::Number(10.0), Value::Number(20.0)]);
-    if let Value::GcRef(GcHandle::Table(tk)) = t {
-        state_set(&mut s, tk, 100.0, Value::Number(5.0));
+    let t = new_array(&mut s, &[Value::number(10.0), Value::number(20.0)]);
+    if let Some(tk) = t.as_table() {
+        state_set(&mut s, tk, 100.0, Value::number(5.0));
     }
     let r = call_lib(&mut s, "table", "maxn", &[t]);
     assert_eq!(as_num(r[0]), 100.0);
@@ -506,7 +504,7 @@ fn state_set(state: &mut LuaState, tk: rua_core::gc::TableKey, key: f64, v: Valu
         .heap
         .get_table_mut(tk)
         .unwrap()
-        .set(Value::Number(key), v);
+        .set(Value::number(key), v);
 }
 
 // ---- math -------------------------------------------------------------------
@@ -557,14 +555,14 @@ fn os_date_utc_and_difftime() {
 
     // time/clock は数値。
     let r = call_lib(&mut s, "os", "time", &[]);
-    assert!(matches!(r[0], Value::Number(_)));
+    assert!(r[0].is_number());
     let r = call_lib(&mut s, "os", "clock", &[]);
-    assert!(matches!(r[0], Value::Number(_)));
+    assert!(r[0].is_number());
 
     // getenv: 未設定は nil。
     let key = sval(&mut s, "RUA_DEFINITELY_NOT_SET_98765");
     let r = call_lib(&mut s, "os", "getenv", &[key]);
-    assert!(matches!(r[0], Value::Nil));
+    assert!(r[0].is_nil());
 }
 
 #[test]
@@ -608,19 +606,19 @@ fn os_remove_and_rename() {
     let src = sval(&mut s, src_path);
     let dst = sval(&mut s, dst_path);
     let r = call_lib(&mut s, "os", "rename", &[src, dst]);
-    assert_eq!(r[0], Value::Boolean(true));
+    assert_eq!(r[0], Value::TRUE);
     assert!(std::path::Path::new(dst_path).exists());
     assert!(!std::path::Path::new(src_path).exists());
 
     // os.remove: リネーム後のファイルを削除。
     let dst = sval(&mut s, dst_path);
     let r = call_lib(&mut s, "os", "remove", &[dst]);
-    assert_eq!(r[0], Value::Boolean(true));
+    assert_eq!(r[0], Value::TRUE);
     assert!(!std::path::Path::new(dst_path).exists());
 
     // os.remove: 存在しないファイルは nil + エラーメッセージ。
     let missing = sval(&mut s, "/tmp/rua_nonexistent_file_xyz_99999");
     let r = call_lib(&mut s, "os", "remove", &[missing]);
-    assert!(matches!(r[0], Value::Nil));
-    assert!(matches!(r[1], Value::GcRef(_)));
+    assert!(r[0].is_nil());
+    assert!(r[1].as_gc().is_some());
 }
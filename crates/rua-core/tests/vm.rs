@@ -27,9 +27,10 @@ fn proto(code: Vec<Instruction>, consts: Vec<Value>, max_stack: u8) -> Rc<Proto>
 }
 
 fn num(v: &Value) -> f64 {
-    match v {
-        Value::Number(n) => *n,
-        other => panic!("expected number, got {other:?}"),
+    if let Some(n) = v.as_number() {
+        n
+    } else {
+        panic!("expected number, got {v:?}")
     }
 }
 
@@ -42,7 +43,7 @@ fn arithmetic_and_return() {
         Instruction::abc(OpCode::Add, 0, rk_as_k(0), 1),          // R0 = 10 + R1 = 50
         Instruction::abc(OpCode::Return, 0, 2, 0),                // return R0
     ];
-    let consts = vec![Value::Number(10.0), Value::Number(20.0), Value::Number(2.0)];
+    let consts = vec![Value::number(10.0), Value::number(20.0), Value::number(2.0)];
     let p = proto(code, consts, 2);
 
     let mut state = LuaState::new();
@@ -64,12 +65,12 @@ fn table_set_get_and_length() {
         Instruction::abc(OpCode::Return, 1, 3, 0),   // return R1, R2
     ];
     let consts = vec![
-        Value::Number(1.0),
-        Value::Number(10.0),
-        Value::Number(2.0),
-        Value::Number(20.0),
-        Value::Number(3.0),
-        Value::Number(30.0),
+        Value::number(1.0),
+        Value::number(10.0),
+        Value::number(2.0),
+        Value::number(20.0),
+        Value::number(3.0),
+        Value::number(30.0),
     ];
     let p = proto(code, consts, 3);
 
@@ -79,6 +80,41 @@ fn table_set_get_and_length() {
     assert_eq!(num(&res[1]), 20.0, "t[2]");
 }
 
+#[test]
+fn table_rehash_moves_integer_keys_into_array_part() {
+    let n = |i: usize| Value::number(i as f64);
+
+    // 逆順に埋めた整数キーも、再ハッシュで配列部へ移る。
+    let mut t = Table::new();
+    for i in (1..=100).rev() {
+        t.set(n(i), n(i)).unwrap();
+    }
+    assert!(t.array().len() >= 64, "array part: {}", t.array().len());
+    assert_eq!(t.length(), 100);
+
+    // 本家 5.1 と同じ border: {1, 2, nil, 4} は配列部に収まり #t == 4。
+    let mut t = Table::new();
+    for i in [1, 2, 4] {
+        t.set(n(i), n(i)).unwrap();
+    }
+    assert_eq!(t.array().len(), 4);
+    assert_eq!(t.length(), 4);
+
+    // 走査しながら消しても next は最後まで辿れる。
+    let mut t = Table::new();
+    for i in 1..=50 {
+        t.set(n(i * 3), n(i)).unwrap();
+    }
+    let (mut key, mut sum) = (Value::NIL, 0.0);
+    while let Some((k, v)) = t.next(&key).unwrap() {
+        sum += num(&v);
+        t.set(k, Value::NIL).unwrap();
+        key = k;
+    }
+    assert_eq!(sum, 1275.0);
+    assert!(t.next(&Value::NIL).unwrap().is_none());
+}
+
 #[test]
 fn numeric_for_loop_sum() {
     // local s=0; for i=1,5 do s=s+i end; return s   => 15
@@ -94,7 +130,7 @@ fn numeric_for_loop_sum() {
         Instruction::asbx(OpCode::ForLoop, 1, -2), // loop back to body
         Instruction::abc(OpCode::Return, 0, 2, 0), // return R0
     ];
-    let consts = vec![Value::Number(0.0), Value::Number(1.0), Value::Number(5.0)];
+    let consts = vec![Value::number(0.0), Value::number(1.0), Value::number(5.0)];
     // 注意: 先頭 LOADK は abx で作り直す。
     let mut code = code;
     code[0] = Instruction::abx(OpCode::LoadK, 0, 0);
@@ -122,7 +158,7 @@ fn closure_counter_shares_upvalue() {
             Instruction::abc(OpCode::SetUpval, 0, 0, 0),
             Instruction::abc(OpCode::Return, 0, 2, 0),
         ],
-        constants: vec![Value::Number(1.0)],
+        constants: vec![Value::number(1.0)],
         num_upvalues: 1,
         max_stack_size: 1,
         source: Some("test".to_string()),
@@ -153,7 +189,7 @@ fn closure_counter_shares_upvalue() {
             Instruction::abc(OpCode::Call, 2, 1, 2),
             Instruction::abc(OpCode::Return, 2, 2, 0),
         ],
-        constants: vec![Value::Number(0.0)],
+        constants: vec![Value::number(0.0)],
         protos: vec![child],
         max_stack_size: 3,
         source: Some("test".to_string()),
@@ -175,11 +211,12 @@ fn call_native_function() {
     fn double(state: &mut LuaState) -> rua_core::error::LuaResult<i32> {
         // 引数は呼び出しフレームの base から積まれている。
         let base = state.call_info.last().unwrap().base;
-        let x = match state.stack.get(base) {
-            Some(Value::Number(n)) => *n,
-            _ => 0.0,
-        };
-        state.stack.push(Value::Number(x * 2.0));
+        let x = state
+            .stack
+            .get(base)
+            .and_then(|v| v.as_number())
+            .unwrap_or(0.0);
+        state.stack.push(Value::number(x * 2.0));
         Ok(1)
     }
 
@@ -188,7 +225,7 @@ fn call_native_function() {
         .global
         .heap
         .alloc_closure(Closure::Native(NativeClosure::new(double)));
-    let res = call(&mut state, Value::GcRef(nat), &[Value::Number(21.0)]).unwrap();
+    let res = call(&mut state, Value::gc(nat), &[Value::number(21.0)]).unwrap();
     assert_eq!(num(&res[0]), 42.0);
 }
 
@@ -209,7 +246,7 @@ fn index_metamethod_fallback() {
         .heap
         .get_table_mut(fk)
         .unwrap()
-        .set(answer_key, Value::Number(42.0))
+        .set(answer_key, Value::number(42.0))
         .unwrap();
 
     // metatable: { __index = fallback }
@@ -223,7 +260,7 @@ fn index_metamethod_fallback() {
         .heap
         .get_table_mut(mtk)
         .unwrap()
-        .set(index_key, Value::GcRef(fallback))
+        .set(index_key, Value::gc(fallback))
         .unwrap();
 
     // t = {} with metatable mt
@@ -255,7 +292,7 @@ fn index_metamethod_fallback() {
     let env = state.global.globals;
     let closure = Closure::Lua(rua_core::value::closure::LuaClosure::new_with_env(p, env));
     let ch = state.global.heap.alloc_closure(closure);
-    let res = call(&mut state, Value::GcRef(ch), &[Value::GcRef(t)]).unwrap();
+    let res = call(&mut state, Value::gc(ch), &[Value::gc(t)]).unwrap();
     assert_eq!(num(&res[0]), 42.0);
 }
 
@@ -281,18 +318,18 @@ fn string_concat() {
         Instruction::abc(OpCode::Concat, 0, 0, 2),
         Instruction::abc(OpCode::Return, 0, 2, 0),
     ];
-    let p2 = proto(code, vec![foo, bar, Value::Number(42.0)], 3);
+    let p2 = proto(code, vec![foo, bar, Value::number(42.0)], 3);
     let _ = p;
 
     let res = run(&mut state, p2, &[]).unwrap();
-    match res[0] {
-        Value::GcRef(GcHandle::Str(k)) => {
-            assert_eq!(
-                state.global.heap.get_str(k).unwrap().as_bytes(),
-                b"foobar42"
-            );
-        }
-        other => panic!("expected string, got {other:?}"),
+    let v = res[0];
+    if let Some(k) = v.as_string() {
+        assert_eq!(
+            state.global.heap.get_str(k).unwrap().as_bytes(),
+            b"foobar42"
+        );
+    } else {
+        panic!("expected string, got {v:?}")
     }
 }
 
@@ -317,7 +354,7 @@ fn deep_tail_recursion_does_not_overflow() {
             Instruction::abc(OpCode::Return, 4, 0, 0), // （TAILCALL 後の定型 RETURN, 実行されない）
             Instruction::abc(OpCode::Return, 1, 2, 0), // return acc
         ],
-        constants: vec![Value::Number(0.0), Value::Number(1.0), fname],
+        constants: vec![Value::number(0.0), Value::number(1.0), fname],
         num_params: 2,
         max_stack_size: 7,
         source: Some("@tco".to_string()),
@@ -337,8 +374,8 @@ fn deep_tail_recursion_does_not_overflow() {
         ],
         constants: vec![
             state.new_string(b"f"),
-            Value::Number(100000.0),
-            Value::Number(0.0),
+            Value::number(100000.0),
+            Value::number(0.0),
         ],
         protos: vec![f_proto],
         max_stack_size: 3,
@@ -357,11 +394,11 @@ fn string_indexing_uses_string_metatable() {
     // GETTABLE / SELF が文字列メタテーブル経由で解決されることを検証。
     fn upper(state: &mut LuaState) -> rua_core::error::LuaResult<i32> {
         let base = state.call_info.last().unwrap().base;
-        let s = match state.stack.get(base) {
-            Some(Value::GcRef(GcHandle::Str(k))) => state
+        let s = match state.stack.get(base).and_then(|v| v.as_string()) {
+            Some(k) => state
                 .global
                 .heap
-                .get_str(*k)
+                .get_str(k)
                 .unwrap()
                 .as_bytes()
                 .to_ascii_uppercase(),
@@ -389,7 +426,7 @@ fn string_indexing_uses_string_metatable() {
         .heap
         .get_table_mut(slk)
         .unwrap()
-        .set(upper_key, Value::GcRef(upper_fn))
+        .set(upper_key, Value::gc(upper_fn))
         .unwrap();
 
     // string メタテーブル = { __index = string_lib }
@@ -403,7 +440,7 @@ fn string_indexing_uses_string_metatable() {
         .heap
         .get_table_mut(mtk)
         .unwrap()
-        .set(index_key, Value::GcRef(string_lib))
+        .set(index_key, Value::gc(string_lib))
         .unwrap();
     state.global.string_metatable = Some(str_mt);
 
@@ -426,12 +463,12 @@ fn string_indexing_uses_string_metatable() {
     let closure = Closure::Lua(rua_core::value::closure::LuaClosure::new_with_env(p, env));
     let ch = state.global.heap.alloc_closure(closure);
     let arg = state.new_string(b"hi");
-    let res = call(&mut state, Value::GcRef(ch), &[arg]).unwrap();
-    match res[0] {
-        Value::GcRef(GcHandle::Str(k)) => {
-            assert_eq!(state.global.heap.get_str(k).unwrap().as_bytes(), b"HI");
-        }
-        other => panic!("expected string, got {other:?}"),
+    let res = call(&mut state, Value::gc(ch), &[arg]).unwrap();
+    let v = res[0];
+    if let Some(k) = v.as_string() {
+        assert_eq!(state.global.heap.get_str(k).unwrap().as_bytes(), b"HI");
+    } else {
+        panic!("expected string, got {v:?}")
     }
 }
 
@@ -446,7 +483,7 @@ fn error_message_strips_chunk_prefix() {
             Instruction::abc(OpCode::Add, 1, 0, rk_as_k(0)),
             Instruction::abc(OpCode::Return, 1, 2, 0),
         ],
-        constants: vec![Value::Number(1.0)],
+        constants: vec![Value::number(1.0)],
         max_stack_size: 2,
         source: Some("@myscript.lua".to_string()),
         line_info: vec![1, 2, 2],
@@ -454,7 +491,10 @@ fn error_message_strips_chunk_prefix() {
     });
 
     let err = run(&mut state, p, &[]).unwrap_err();
-    let rua_core::error::LuaError::Runtime(Value::GcRef(GcHandle::Str(k))) = err else {
+    let Some(k) = (match &err {
+        rua_core::error::LuaError::Runtime(v) => v.as_string(),
+        _ => None,
+    }) else {
         panic!("expected runtime string error, got {err:?}");
     };
     let msg =
@@ -494,7 +534,7 @@ fn where_string_reports_caller_line() {
         .heap
         .get_table_mut(gk)
         .unwrap()
-        .set(gname, Value::GcRef(probe_fn))
+        .set(gname, Value::gc(probe_fn))
         .unwrap();
 
     // proto: 命令0 は GETGLOBAL(行10), 命令1 は CALL(行20), 命令2 RETURN
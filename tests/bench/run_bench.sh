@@ -8,10 +8,12 @@
 #   tests/bench/run_bench.sh
 #   RUA_LUA_BIN=$(command -v lua5.1) LUAJIT_BIN=$(command -v luajit) tests/bench/run_bench.sh
 #   REPS=7 tests/bench/run_bench.sh    # 反復回数（既定 5）
+#   RUA_GC=generational tests/bench/run_bench.sh  # rua の GC モード（incremental / generational）
 set -eu
 cd "$(dirname "$0")"
 ROOT="$(cd ../.. && pwd)"
 REPS="${REPS:-5}"
+RUA_GC="${RUA_GC:-incremental}"
 
 # --- rua バイナリの決定（無ければ release ビルド） ---
 RUA_BIN="${RUA_BIN:-$ROOT/target/release/rua}"
@@ -19,6 +21,12 @@ if [ ! -x "$RUA_BIN" ]; then
   echo "rua release バイナリが無いのでビルドします..." >&2
   ( cd "$ROOT" && cargo build --release -p rua-cli >/dev/null )
 fi
+# GC モードは with_gc.lua で collectgarbage(<mode>) を呼んでからスクリプトを実行して切り替える。
+case "$RUA_GC" in
+  incremental|generational) ;;
+  *) echo "RUA_GC は incremental か generational: $RUA_GC" >&2; exit 2 ;;
+esac
+RUA_CMD=("$RUA_BIN" with_gc.lua "$RUA_GC")
 
 # --- 比較対象の検出 ---
 LUA_BIN="${RUA_LUA_BIN:-}"
@@ -32,15 +40,16 @@ if [ -z "$LUAJIT" ] && command -v luajit >/dev/null 2>&1; then
   LUAJIT="$(command -v luajit)"
 fi
 
-# 中央値時間(秒)を返す。$1=実行コマンドのプレフィックス, $2=スクリプト
+# 中央値時間(秒)を返す。$1=スクリプト, $2...=実行コマンド
 median_time() {
-  local runner="$1" script="$2"
+  local script="$1"
+  shift
   local times=()
   for _ in $(seq 1 "$REPS"); do
     local start end
     start=$(date +%s.%N)
     # 出力は捨てる。失敗したら "x" を返す。
-    if ! $runner "$script" >/dev/null 2>&1; then echo "x"; return; fi
+    if ! "$@" "$script" >/dev/null 2>&1; then echo "x"; return; fi
     end=$(date +%s.%N)
     times+=("$(awk "BEGIN{print $end-$start}")")
   done
@@ -48,7 +57,7 @@ median_time() {
 }
 
 echo "=== rua ベンチ比較 (REPS=$REPS, 中央値秒) ==="
-echo "rua    : $RUA_BIN"
+echo "rua    : $RUA_BIN (gc: $RUA_GC)"
 echo "lua5.1 : ${LUA_BIN:-(なし)}"
 echo "luajit : ${LUAJIT:-(なし)}"
 printf '\n%-18s %10s %10s %10s\n' "script" "rua" "lua5.1" "luajit"
@@ -56,9 +65,9 @@ printf '%-18s %10s %10s %10s\n' "------" "---" "------" "------"
 
 for script in scripts/*.lua; do
   name="$(basename "$script")"
-  t_rua=$(median_time "$RUA_BIN run" "$script")
+  t_rua=$(median_time "$script" "${RUA_CMD[@]}")
   t_lua="-"; t_jit="-"
-  [ -n "$LUA_BIN" ] && t_lua=$(median_time "$LUA_BIN" "$script")
-  [ -n "$LUAJIT" ] && t_jit=$(median_time "$LUAJIT" "$script")
+  [ -n "$LUA_BIN" ] && t_lua=$(median_time "$script" "$LUA_BIN")
+  [ -n "$LUAJIT" ] && t_jit=$(median_time "$script" "$LUAJIT")
   printf '%-18s %10s %10s %10s\n' "$name" "$t_rua" "$t_lua" "$t_jit"
 done
@@ -0,0 +1,13 @@
+-- ログ整形: 多項の `..` で行を組み立て、大きなバッファへ追記し続ける。
+local buf = ""
+local chunks = {}
+for i = 1, 20000 do
+  buf = buf .. "[" .. i .. "] level=" .. (i % 3) .. " msg=request handled in " .. i * 0.5 .. "ms\n"
+  if #buf > 65536 then
+    chunks[#chunks + 1] = buf
+    buf = ""
+  end
+end
+chunks[#chunks + 1] = buf
+local s = table.concat(chunks)
+print("len=" .. #s .. " chunks=" .. #chunks)
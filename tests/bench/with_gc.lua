@@ -0,0 +1,5 @@
+-- run_bench.sh 用: 自動 GC のモードを選んでからベンチスクリプトを実行する。
+-- 使い方: rua with_gc.lua <incremental|generational> <script>
+local mode, script = ...
+collectgarbage(mode)
+dofile(script)
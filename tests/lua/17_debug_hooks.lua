@@ -0,0 +1,82 @@
+-- debug.sethook / debug.gethook: call/return/line/count イベント
+
+local function sum(n)
+  local s = 0
+  for i = 1, n do
+    s = s + i
+  end
+  return s
+end
+
+-- line: sum の中で実行した行（後方ジャンプでは同じ行でも再度発生）
+local lines = {}
+debug.sethook(function(event, line)
+  if debug.getinfo(2, "f").func == sum then
+    lines[#lines + 1] = line
+  end
+end, "l")
+sum(2)
+debug.sethook()
+print("lines", table.concat(lines, " "))
+
+-- call/return: 5.1 では末尾呼び出しも "call"。戻りでは "return" の後に末尾呼び出しの回数だけ "tail return"
+local function down(n)
+  if n == 0 then return 0 end
+  return down(n - 1)
+end
+local events = {}
+debug.sethook(function(event)
+  local info = debug.getinfo(2, "S")
+  if info.what ~= "C" then
+    events[event] = (events[event] or 0) + 1
+  end
+end, "cr")
+down(3)
+debug.sethook()
+print("call", events["call"], "tail call", events["tail call"])
+print("return", events["return"], "tail return", events["tail return"])
+
+-- getinfo(2, "Sl") はフックを発生させた関数の位置を返す
+local where
+local function probe()
+  local x = 1
+  return x
+end
+debug.sethook(function(event, line)
+  if where == nil and debug.getinfo(2, "f").func == probe then
+    local info = debug.getinfo(2, "Sl")
+    where = info.short_src .. ":" .. info.currentline .. ":" .. line
+  end
+end, "l")
+probe()
+debug.sethook()
+print("where", where)
+
+-- count: 100 命令ごと
+local ticks = 0
+debug.sethook(function(event)
+  ticks = ticks + 1
+end, "", 100)
+for i = 1, 1000 do end
+debug.sethook()
+print("count", ticks >= 9 and ticks <= 11)
+
+-- gethook
+local function h() end
+debug.sethook(h, "cr", 5)
+local f, mask, count = debug.gethook()
+debug.sethook()
+print("gethook", f == h, mask, count)
+print("cleared", debug.gethook())
+
+-- フックの中のエラーは発生元の関数から伝播する
+local ok, err = pcall(function()
+  debug.sethook(function()
+    debug.sethook()
+    error("in hook")
+  end, "l")
+  local a = 1
+  return a
+end)
+print("hook error", ok, (string.gsub(err, "^.-:%d+: ", "")))
+print("after", debug.gethook())
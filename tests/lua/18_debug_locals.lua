@@ -0,0 +1,67 @@
+-- debug.getlocal / debug.setlocal
+
+-- 現在 pc で有効なローカルだけが見える
+local function locals(level)
+  local out = {}
+  local i = 1
+  while true do
+    local name, value = debug.getlocal(level + 1, i)
+    if name == nil then break end
+    if string.sub(name, 1, 1) ~= "(" then
+      out[#out + 1] = name .. "=" .. tostring(value)
+    end
+    i = i + 1
+  end
+  return table.concat(out, " ")
+end
+
+local function f(a, b)
+  local c = a + b
+  print("f", locals(1))
+  do
+    local d = c * 2
+    print("f.do", locals(1))
+  end
+  print("f.after", locals(1))
+  return c
+end
+f(1, 2)
+
+-- 呼び出し元のレベル
+local function inner()
+  local s = locals(2)
+  return s
+end
+local function outer(x)
+  local y = x .. "!"
+  local s = inner()
+  return s
+end
+print("outer", outer("hi"))
+
+-- setlocal は呼び出し元のレジスタを書き換える
+local function bump(name)
+  local i = 1
+  while true do
+    local n, v = debug.getlocal(2, i)
+    if n == nil then return nil end
+    if n == name then return debug.setlocal(2, i, v + 100) end
+    i = i + 1
+  end
+end
+local function g()
+  local counter = 1
+  local renamed = bump("counter")
+  return renamed, counter
+end
+print("setlocal", g())
+
+-- 名前の無いレジスタは (*temporary)
+local function t(a)
+  print("temp", (debug.getlocal(1, 2)))
+end
+t(1)
+
+-- 範囲外
+print("missing", debug.getlocal(1, 100))
+print("level", pcall(debug.getlocal, 100, 1))
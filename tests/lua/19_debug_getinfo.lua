@@ -0,0 +1,64 @@
+-- debug.getinfo: オプション n/S/l/u/f/L、レベル指定と関数指定、C 関数と末尾呼び出し
+
+local function show(t)
+  local keys = {}
+  for k in pairs(t) do keys[#keys + 1] = k end
+  table.sort(keys)
+  local out = {}
+  for _, k in ipairs(keys) do
+    local v = t[k]
+    if type(v) == "table" then
+      local ls = {}
+      for l in pairs(v) do ls[#ls + 1] = l end
+      table.sort(ls)
+      v = "{" .. table.concat(ls, ",") .. "}"
+    elseif type(v) == "function" then
+      v = "function"
+    end
+    out[#out + 1] = k .. "=" .. tostring(v)
+  end
+  return table.concat(out, " ")
+end
+
+-- 既定のオプション（flnSu）とレベル 2 の呼び出し元
+local function f()
+  print(show(debug.getinfo(1)))
+  print(show(debug.getinfo(2, "nSl")))
+end
+f()
+
+-- name / namewhat は呼び出し命令から推定する
+local t = { m = function(self) return debug.getinfo(1, "n") end }
+local i = t:m()
+print("method", i.name, i.namewhat)
+i = t.m(t)
+print("field", i.name, i.namewhat)
+function globalfn() return debug.getinfo(1, "n") end
+i = globalfn()
+print("global", i.name, i.namewhat)
+local function g() return debug.getinfo(1, "n") end
+i = g()
+print("local", i.name, i.namewhat)
+local up = g
+local function h()
+  local r = up()
+  return r
+end
+i = h()
+print("upvalue", i.name, i.namewhat)
+
+-- 関数を直接指定（C 関数・Lua 関数と activelines）
+print(show(debug.getinfo(print)))
+print(show(debug.getinfo(f, "SLu")))
+
+-- 末尾呼び出しで消えたフレーム
+local function tail2()
+  print(show(debug.getinfo(2, "nSlf")))
+  print("tail2", debug.getinfo(1, "n").name)
+end
+local function tail1() return tail2() end
+tail1()
+
+-- 不正なオプション・範囲外のレベル
+print(pcall(debug.getinfo, 1, "x"))
+print(debug.getinfo(100))
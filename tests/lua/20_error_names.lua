@@ -0,0 +1,27 @@
+-- 実行時エラーメッセージの変数名推定（global/local/field/upvalue/method）
+
+local function try(f)
+  local ok, msg = pcall(f)
+  print(ok, msg)
+end
+
+try(function() undefined_fn() end)
+try(function() local x; x.y = 1 end)
+try(function() return cfg.name end)
+try(function() local t = {} return t.a.b end)
+try(function() local t = {} t.x.y = 1 end)
+try(function() string.nothing() end)
+try(function() local u; return (function() return u + 1 end)() end)
+try(function() local t = {} t:foo() end)
+try(function() local f; f() end)
+try(function() local n = nil; return 1 + n end)
+try(function() local s; return "a" .. s end)
+try(function() return "a" .. missing end)
+try(function() local t; return #t end)
+
+-- 名前の付かないケース
+try(function() return 1 + nil end)
+try(function() return "a" .. {} end)
+try(function() return #nil end)
+try(function() return -{} end)
+try(function() return {} < 1 end)
@@ -0,0 +1,37 @@
+-- 定数キーのフィールド/メソッド参照（インラインキャッシュ）の無効化
+
+local function get(t) return t.x end
+local function set(t, v) t.x = v end
+
+-- 異なるテーブル・再ハッシュ・削除をまたいで同じ命令を使う
+local a, b = {x = 1}, {y = 0, x = 2}
+print(get(a), get(b), get(a))
+for i = 1, 100 do a["k" .. i] = i end
+print(get(a))
+set(a, 10); print(get(a))
+set(a, nil); print(get(a))
+set(a, 20); print(get(a), a.k50)
+
+-- 消したフィールドへの代入は __newindex を通る
+local log = {}
+local c = setmetatable({x = 1}, {__newindex = function(t, k, v) log[#log + 1] = k .. "=" .. tostring(v); rawset(t, k, v) end})
+set(c, 2); set(c, nil); set(c, 3)
+print(table.concat(log, " "), get(c))
+
+-- メソッド参照とメタテーブルの差し替え
+local A = {}; A.__index = A
+function A:name() return "A" end
+local B = {}; B.__index = B
+function B:name() return "B" end
+local o = setmetatable({}, A)
+local function call(obj) return obj:name() end
+print(call(o), call(o))
+setmetatable(o, B); print(call(o))
+function o:name() return "own" end
+print(call(o))
+o.name = nil; print(call(o))
+
+-- グローバル
+gv = 1
+local function g() return gv end
+print(g()); gv = nil; print(g()); gv = "back"; print(g())
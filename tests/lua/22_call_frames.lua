@@ -0,0 +1,73 @@
+-- 呼び出し規約（引数・戻り値のスタック上受け渡し、可変長引数、__call、末尾呼び出し、yield）
+
+-- 固定引数・余剰引数・不足引数
+local function f3(a, b, c) return a, b, c end
+print(f3(1))
+print(f3(1, 2, 3, 4, 5))
+print((f3(1, 2)))
+
+-- 可変長引数はフレーム内に置かれる
+local function va(a, ...)
+  local n = select('#', ...)
+  local x, y = ...
+  return a, n, x, y, ...
+end
+print(va(1))
+print(va(1, 2))
+print(va(1, nil, nil))
+print(va(1, 2, 3, 4))
+local function count(...) return select('#', ...) end
+print(count(va(1, 2, 3)))
+local t = {va(10, 20, 30)}
+print(#t, t[1], t[5])
+
+-- 多値の受け渡し（C == 0 / B == 0）
+local function many(n) local r = {} for i = 1, n do r[i] = i end return unpack(r) end
+print(count(many(50)))
+print(select(48, many(50)))
+
+-- __call（Lua 関数・ネイティブ関数）
+local callable = setmetatable({}, {__call = function(self, x, y) return x + y, self end})
+local s, self = callable(3, 4)
+print(s, self == callable)
+local pc = setmetatable({k = "v"}, {__call = rawget})
+print(pc("k"))
+print(pcall(setmetatable({}, {__call = 1})))
+
+-- 末尾呼び出し（Lua / ネイティブ / 可変長）
+local function loop(n, acc) if n == 0 then return acc end return loop(n - 1, acc + n) end
+print(loop(100000, 0))
+local function tva(n, ...) if n == 0 then return ... end return tva(n - 1, n, ...) end
+print(tva(5))
+local function tnative(...) return select('#', ...) end
+local function tn(...) return tnative(...) end
+print(tn(1, nil, 3))
+local function tc(x) return callable(x, x) end
+print((tc(21)))
+
+-- upvalue を共有するクロージャ
+local function counter()
+  local n = 0
+  return function() n = n + 1 return n end, function() return n end
+end
+local inc, get = counter()
+inc() inc()
+print(get())
+
+-- 可変長引数の関数から yield
+local co = coroutine.wrap(function(...)
+  local a, b = ...
+  local x = coroutine.yield(a + b, ...)
+  return x, select('#', ...), ...
+end)
+print(co(1, 2, 3))
+print(co("r"))
+
+-- 入れ子の Lua 呼び出し越しの yield
+local co2 = coroutine.create(function(a)
+  local function inner(x, ...) return coroutine.yield(x, ...) end
+  local function mid(...) local r = inner(...) return r, "mid" end
+  return mid(a, "extra")
+end)
+print(coroutine.resume(co2, "v"))
+print(coroutine.resume(co2, "back"))
@@ -0,0 +1,43 @@
+-- 長い文字列（インターンしない文字列）の等価性とテーブルキー
+
+local base = string.rep("ab", 30)
+local s1 = base .. "!"
+local s2 = string.rep("ab", 30) .. "!"
+local s3 = table.concat({base, "!"})
+print(#s1, s1 == s2, s2 == s3, rawequal(s1, s3), s1 == base)
+print(s1 < s2, s1 <= s2, base < s1)
+
+-- 別々に作った同値の長い文字列は同じキー
+local t = {}
+t[s1] = 1
+print(t[s2], t[s3], rawget(t, s2))
+t[s2] = 2
+print(t[s1])
+local n = 0
+for k, v in pairs(t) do n = n + 1; print(k == s3, v) end
+print(n, next(t, s3))
+rawset(t, s3, nil)
+print(t[s1], next(t))
+
+-- メタメソッドにも同じキーが届く
+local log = {}
+local p = setmetatable({}, {__index = function(_, k) return #k end,
+  __newindex = function(tt, k, v) log[#log + 1] = k == s1; rawset(tt, k, v) end})
+print(p[s2])
+p[s3] = true
+print(log[1], p[s1])
+
+-- string.gsub の置換テーブル
+local keys = {[s1] = "long", short = "s"}
+print((string.gsub(s2 .. " short", "%S+", keys)))
+
+-- 多項の連結（数値・__concat を含む）
+local x = 1.5
+local long = "a" .. x .. "b" .. 2 .. base .. 10 .. "c"
+print(long, #long)
+local C = setmetatable({}, {__concat = function(a, b)
+  return (type(a) == "table" and "<C>" or a) .. (type(b) == "table" and "<C>" or b)
+end})
+print("x" .. 1 .. C .. "y" .. 2, C .. base == "<C>" .. base)
+local ok, err = pcall(function() return "a" .. base .. {} end)
+print(ok, (string.match(err, "attempt.*")))